@@ -2,6 +2,7 @@
 mod cublaslt;
 mod layer_norm;
 mod linear;
+mod pooling;
 #[allow(dead_code, unused)]
 mod rms_norm;
 mod rotary;
@@ -9,6 +10,7 @@ mod rotary;
 pub use cublaslt::get_cublas_lt_wrapper;
 pub use layer_norm::LayerNorm;
 pub use linear::{HiddenAct, Linear};
+pub use pooling::{pool_needs_mask, pool_padded, pool_sequence};
 #[allow(unused_imports)]
 pub use rms_norm::RMSNorm;
 pub use rotary::{apply_rotary, get_cos_sin, get_inv_freqs, RopeScaling};
@@ -0,0 +1,93 @@
+use candle::{DType, Result, Tensor};
+use text_embeddings_backend_core::Pool;
+
+/// Whether padded models need the attention mask to apply `pool`
+pub fn pool_needs_mask(pool: &Pool) -> bool {
+    matches!(
+        pool,
+        Pool::Mean | Pool::Max | Pool::WeightedMean | Pool::MeanSqrtLen
+    )
+}
+
+/// Pool the [seq_len, hidden_size] embeddings of a single sequence to [1, hidden_size]
+pub fn pool_sequence(pool: &Pool, embeddings: &Tensor) -> Result<Tensor> {
+    let len = embeddings.dim(0)?;
+    match pool {
+        Pool::Mean => embeddings.sum_keepdim(0)? / (len as f64),
+        Pool::MeanSqrtLen => embeddings.sum_keepdim(0)? / (len as f64).sqrt(),
+        Pool::Max => embeddings.max_keepdim(0),
+        Pool::WeightedMean => {
+            // Token `i` has weight `i + 1`
+            let weights = Tensor::arange(1u32, len as u32 + 1, embeddings.device())?
+                .to_dtype(DType::F32)?
+                .unsqueeze(1)?;
+            let total = (len * (len + 1) / 2) as f64;
+
+            // Accumulate in f32 to avoid overflows with f16 embeddings
+            (embeddings
+                .to_dtype(DType::F32)?
+                .broadcast_mul(&weights)?
+                .sum_keepdim(0)?
+                / total)?
+                .to_dtype(embeddings.dtype())
+        }
+        _ => candle::bail!("`{pool}` pooling does not pool the token embeddings"),
+    }
+}
+
+/// Pool padded [batch_size, max_length, hidden_size] embeddings to [batch_size, hidden_size]
+///
+/// `attention_mask` is [batch_size, max_length, 1] and `None` if no member of the batch is
+/// padded. `input_lengths` is [batch_size, 1].
+pub fn pool_padded(
+    pool: &Pool,
+    outputs: &Tensor,
+    attention_mask: Option<&Tensor>,
+    input_lengths: &Tensor,
+) -> Result<Tensor> {
+    match pool {
+        Pool::Max => match attention_mask {
+            Some(attention_mask) => {
+                // Padded values can never be the maximum
+                let padding_bias = attention_mask.affine(1e4, -1e4)?;
+                outputs.broadcast_add(&padding_bias)?.max(1)
+            }
+            None => outputs.max(1),
+        },
+        Pool::Mean | Pool::MeanSqrtLen | Pool::WeightedMean => {
+            // Mask padded values
+            let outputs = match attention_mask {
+                Some(attention_mask) => outputs.broadcast_mul(attention_mask)?,
+                None => outputs.clone(),
+            };
+
+            match pool {
+                Pool::Mean => outputs.sum(1)?.broadcast_div(input_lengths),
+                Pool::MeanSqrtLen => outputs.sum(1)?.broadcast_div(&input_lengths.sqrt()?),
+                _ => {
+                    // Token `i` has weight `i + 1`
+                    let (_, max_length, _) = outputs.dims3()?;
+                    let weights = Tensor::arange(1u32, max_length as u32 + 1, outputs.device())?
+                        .to_dtype(DType::F32)?
+                        .reshape((1, max_length, 1))?;
+                    let weights = match attention_mask {
+                        Some(attention_mask) => {
+                            weights.broadcast_mul(&attention_mask.to_dtype(DType::F32)?)?
+                        }
+                        None => weights,
+                    };
+                    let total = weights.sum(1)?;
+
+                    // Accumulate in f32 to avoid overflows with f16 embeddings
+                    outputs
+                        .to_dtype(DType::F32)?
+                        .broadcast_mul(&weights)?
+                        .sum(1)?
+                        .broadcast_div(&total)?
+                        .to_dtype(outputs.dtype())
+                }
+            }
+        }
+        _ => candle::bail!("`{pool}` pooling does not pool the token embeddings"),
+    }
+}
@@ -5,11 +5,13 @@ mod compute_cap;
 mod flash_attn;
 mod layers;
 mod models;
+mod st_modules;
 
 #[cfg(feature = "cuda")]
 use crate::compute_cap::{
     compatible_compute_cap, get_compile_compute_cap, get_runtime_compute_cap,
 };
+use crate::layers::Linear;
 use crate::models::{
     BertConfig, BertModel, DistilBertConfig, DistilBertModel, GTEConfig, GTEModel, JinaBertModel,
     JinaCodeBertModel, MPNetConfig, MPNetModel, MistralConfig, Model, NomicBertModel, NomicConfig,
@@ -21,14 +23,14 @@ use crate::models::{
     FlashJinaCodeBertModel, FlashMistralModel, FlashNomicBertModel, FlashQwen2Model,
 };
 use anyhow::Context;
-use candle::{DType, Device};
+use candle::{DType, Device, Tensor};
 use candle_nn::VarBuilder;
 use nohash_hasher::BuildNoHashHasher;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::path::Path;
 use text_embeddings_backend_core::{
-    Backend, BackendError, Batch, Embedding, Embeddings, ModelType, Predictions,
+    load_st_modules, Backend, BackendError, Batch, Embedding, Embeddings, ModelType, Predictions,
 };
 
 /// This enum is needed to be able to differentiate between jina models that also use
@@ -68,6 +70,9 @@ enum Config {
 pub struct CandleBackend {
     device: Device,
     model: Box<dyn Model + Send>,
+    colbert_linear: Option<Linear>,
+    /// Sentence Transformers modules applied after pooling
+    st_modules: Vec<st_modules::Module>,
 }
 
 impl CandleBackend {
@@ -377,13 +382,54 @@ impl CandleBackend {
             }
         };
 
+        let colbert_linear = load_colbert_linear(model_path, dtype, &device).s()?;
+        if colbert_linear.is_some() {
+            tracing::info!("Loaded ColBERT head for multi-vector embeddings");
+        }
+
+        let st_modules = st_modules::load(load_st_modules(model_path)?, dtype, &device).s()?;
+
         Ok(Self {
             device,
             model: model?,
+            colbert_linear,
+            st_modules,
         })
     }
 }
 
+/// Load the optional ColBERT projection (e.g. BGE-M3 `colbert_linear`)
+fn load_colbert_linear(
+    model_path: &Path,
+    dtype: DType,
+    device: &Device,
+) -> candle::Result<Option<Linear>> {
+    let safetensors_path = model_path.join("colbert_linear.safetensors");
+    let pytorch_path = model_path.join("colbert_linear.pt");
+
+    let mut tensors: HashMap<String, Tensor> = if safetensors_path.exists() {
+        candle::safetensors::load(safetensors_path, device)?
+    } else if pytorch_path.exists() {
+        candle::pickle::read_all(pytorch_path)?
+            .into_iter()
+            .collect()
+    } else {
+        return Ok(None);
+    };
+
+    let weight = tensors
+        .remove("weight")
+        .ok_or_else(|| candle::Error::Msg("`colbert_linear` has no `weight`".to_string()))?
+        .to_device(device)?
+        .to_dtype(dtype)?;
+    let bias = tensors
+        .remove("bias")
+        .map(|bias| bias.to_device(device)?.to_dtype(dtype))
+        .transpose()?;
+
+    Ok(Some(Linear::new(weight, bias, None)))
+}
+
 impl Backend for CandleBackend {
     fn max_batch_size(&self) -> Option<usize> {
         // Limit max batch size to 4 on CPU
@@ -401,10 +447,22 @@ impl Backend for CandleBackend {
         self.model.is_padded()
     }
 
-    fn embed(&self, batch: Batch) -> Result<Embeddings, BackendError> {
+    fn supports_multivector(&self) -> bool {
+        self.colbert_linear.is_some()
+    }
+
+    fn embed(&self, mut batch: Batch) -> Result<Embeddings, BackendError> {
         let batch_size = batch.len();
         let pooled_indices = batch.pooled_indices.clone();
         let raw_indices = batch.raw_indices.clone();
+        let multivector_indices = std::mem::take(&mut batch.multivector_indices);
+
+        // Multi-vector embeddings are computed from the raw embeddings
+        if !multivector_indices.is_empty() {
+            batch.raw_indices.extend(&multivector_indices);
+            batch.raw_indices.sort_unstable();
+        }
+        let model_raw_indices = batch.raw_indices.clone();
 
         // Used for indexing in the raw_embeddings tensor
         let input_lengths: Vec<usize> = (0..batch.len())
@@ -419,22 +477,83 @@ impl Backend for CandleBackend {
         // Device => Host data transfer
         let pooled_embeddings = match pooled_embeddings {
             None => vec![],
-            Some(pooled_embeddings) => pooled_embeddings.to_dtype(DType::F32).e()?.to_vec2().e()?,
+            Some(pooled_embeddings) => st_modules::forward(&self.st_modules, pooled_embeddings)
+                .e()?
+                .to_dtype(DType::F32)
+                .e()?
+                .to_vec2()
+                .e()?,
         };
 
-        // This transfer is expensive...
-        let raw_embeddings = match raw_embeddings {
-            None => vec![],
-            Some(raw_embeddings) => raw_embeddings.to_dtype(DType::F32).e()?.to_vec2().e()?,
+        let (raw_embeddings, multivector_embeddings) = match raw_embeddings {
+            None => (vec![], vec![]),
+            // This transfer is expensive...
+            Some(raw_embeddings) if multivector_indices.is_empty() => (
+                raw_embeddings.to_dtype(DType::F32).e()?.to_vec2().e()?,
+                vec![],
+            ),
+            Some(raw_embeddings) => {
+                // Split the rows between raw and multi-vector requests
+                let mut raw_rows = Vec::new();
+                let mut multivector_rows = Vec::new();
+                let mut cumulative_length = 0;
+                for i in model_raw_indices.into_iter() {
+                    let length = input_lengths[i as usize];
+                    if multivector_indices.contains(&i) {
+                        // Skip the leading and trailing special tokens
+                        multivector_rows.extend(
+                            cumulative_length as u32 + 1..(cumulative_length + length) as u32 - 1,
+                        );
+                    } else {
+                        raw_rows
+                            .extend(cumulative_length as u32..(cumulative_length + length) as u32);
+                    }
+                    cumulative_length += length;
+                }
+
+                let raw = if raw_rows.is_empty() {
+                    vec![]
+                } else {
+                    let raw_rows_length = raw_rows.len();
+                    let raw_rows = Tensor::from_vec(raw_rows, raw_rows_length, &self.device).e()?;
+                    raw_embeddings
+                        .index_select(&raw_rows, 0)
+                        .e()?
+                        .to_dtype(DType::F32)
+                        .e()?
+                        .to_vec2()
+                        .e()?
+                };
+
+                let multivector = if multivector_rows.is_empty() {
+                    vec![]
+                } else {
+                    // Unwrap is safe here: multi-vector requests are rejected without a ColBERT head
+                    let colbert_linear = self.colbert_linear.as_ref().unwrap();
+
+                    let multivector_rows_length = multivector_rows.len();
+                    let multivector_rows =
+                        Tensor::from_vec(multivector_rows, multivector_rows_length, &self.device)
+                            .e()?;
+                    let multivector = colbert_linear
+                        .forward(&raw_embeddings.index_select(&multivector_rows, 0).e()?)
+                        .e()?
+                        .to_dtype(DType::F32)
+                        .e()?;
+
+                    // L2 normalization
+                    let norm = multivector.sqr().e()?.sum_keepdim(1).e()?.sqrt().e()?;
+                    multivector.broadcast_div(&norm).e()?.to_vec2().e()?
+                };
+
+                (raw, multivector)
+            }
         };
 
         let mut embeddings =
             HashMap::with_capacity_and_hasher(batch_size, BuildNoHashHasher::default());
         for (i, e) in pooled_indices.into_iter().zip(pooled_embeddings) {
-            embeddings.insert(
-                i as usize,
-                Embedding::Pooled(e, Vec::<(String, f32)>::new()),
-            );
+            embeddings.insert(i as usize, Embedding::Pooled(e, vec![]));
         }
 
         let mut cumulative_length = 0;
@@ -445,6 +564,23 @@ impl Backend for CandleBackend {
             cumulative_length += length;
         }
 
+        let mut cumulative_length = 0;
+        for i in multivector_indices.into_iter() {
+            let length = input_lengths[i as usize].saturating_sub(2);
+            let e = multivector_embeddings[cumulative_length..cumulative_length + length].to_vec();
+
+            let embedding = match embeddings.remove(&(i as usize)) {
+                Some(Embedding::Pooled(pooled, token_weights)) => Embedding::Hybrid {
+                    pooled,
+                    token_weights,
+                    multivector: e,
+                },
+                _ => Embedding::MultiVector(e),
+            };
+            embeddings.insert(i as usize, embedding);
+            cumulative_length += length;
+        }
+
         Ok(embeddings)
     }
 
@@ -1,4 +1,6 @@
-use crate::layers::{get_cublas_lt_wrapper, HiddenAct, LayerNorm, Linear};
+use crate::layers::{
+    get_cublas_lt_wrapper, pool_needs_mask, pool_padded, HiddenAct, LayerNorm, Linear,
+};
 use crate::models::Model;
 use candle::{DType, Device, IndexOp, Module, Result, Tensor, D};
 use candle_nn::{Embedding, VarBuilder};
@@ -483,9 +485,9 @@ impl DistilBertModel {
 
                 let (attention_bias, attention_mask) = match masking {
                     true => {
-                        // We only need the mask if we use mean pooling
+                        // We only need the mask if we pool all the tokens
                         // For CLS pooling, the bias is enough
-                        let attention_mask = if self.pool == Pool::Mean {
+                        let attention_mask = if pool_needs_mask(&self.pool) {
                             let attention_mask = Tensor::from_vec(
                                 attention_mask,
                                 (batch_size, max_length, 1),
@@ -571,21 +573,28 @@ impl DistilBertModel {
                 Pool::Cls => outputs.i((.., 0))?,
                 // Last token pooling is not supported for this model
                 Pool::LastToken => unreachable!(),
-                // Mean pooling
-                Pool::Mean => {
-                    if let Some(ref attention_mask) = attention_mask {
-                        let mut attention_mask = attention_mask.clone();
-
-                        if let Some(pooled_indices) = pooled_indices {
-                            // Select values in the batch
-                            attention_mask = attention_mask.index_select(&pooled_indices, 0)?;
-                        };
-
-                        // Mask padded values
-                        outputs = outputs.broadcast_mul(&attention_mask)?;
-                    }
-
-                    (outputs.sum(1)?.broadcast_div(&input_lengths))?
+                // Mean, Max, WeightedMean and MeanSqrtLen pooling
+                Pool::Mean | Pool::Max | Pool::WeightedMean | Pool::MeanSqrtLen => {
+                    let (attention_mask, input_lengths) = match pooled_indices {
+                        // Select values in the batch
+                        Some(pooled_indices) => (
+                            attention_mask
+                                .as_ref()
+                                .map(|attention_mask| {
+                                    attention_mask.index_select(&pooled_indices, 0)
+                                })
+                                .transpose()?,
+                            input_lengths.index_select(&pooled_indices, 0)?,
+                        ),
+                        None => (attention_mask.clone(), input_lengths.clone()),
+                    };
+
+                    pool_padded(
+                        &self.pool,
+                        &outputs,
+                        attention_mask.as_ref(),
+                        &input_lengths,
+                    )?
                 }
                 Pool::Splade => {
                     // Unwrap is safe here
@@ -1,5 +1,5 @@
 use crate::flash_attn::flash_attn_varlen;
-use crate::layers::{LayerNorm, Linear};
+use crate::layers::{pool_sequence, LayerNorm, Linear};
 use crate::models::bert::{
     BertClassificationHead, BertConfig, BertEmbeddings, BertSpladeHead, ClassificationHead,
     PositionEmbeddingType, RobertaClassificationHead,
@@ -436,8 +436,8 @@ impl FlashBertModel {
                         )
                     }
                 }
-                // Mean pooling
-                Pool::Mean => {
+                // Mean, Max, WeightedMean and MeanSqrtLen pooling
+                Pool::Mean | Pool::Max | Pool::WeightedMean | Pool::MeanSqrtLen => {
                     if batch_size > 1 {
                         // for each request that requires pooling
                         let results: Result<Vec<Tensor>> = batch
@@ -448,16 +448,15 @@ impl FlashBertModel {
                                 let start = batch.cumulative_seq_lengths[i];
                                 let len = batch.cumulative_seq_lengths[i + 1] - start;
 
-                                // Mean
                                 let embeddings = outputs.narrow(0, start as usize, len as usize)?;
-                                embeddings.sum_keepdim(0)? / (len as f64)
+                                pool_sequence(&self.pool, &embeddings)
                             })
                             .collect();
 
                         // Concatenate all results
                         Some(Tensor::cat(&results?, 0)?)
                     } else {
-                        Some((outputs.sum_keepdim(0)? / (batch.max_length as f64))?)
+                        Some(pool_sequence(&self.pool, &outputs)?)
                     }
                 }
                 Pool::Splade => {
@@ -1,5 +1,5 @@
 use crate::flash_attn::flash_attn_varlen;
-use crate::layers::{LayerNorm, Linear};
+use crate::layers::{pool_sequence, LayerNorm, Linear};
 use crate::models::distilbert::{
     DistilBertConfig, DistilBertEmbeddings, DistilBertMLP, DistilBertSpladeHead,
 };
@@ -300,8 +300,8 @@ impl FlashDistilBertModel {
                         .unsqueeze(0)?
                     }
                 }
-                // Mean pooling
-                Pool::Mean => {
+                // Mean, Max, WeightedMean and MeanSqrtLen pooling
+                Pool::Mean | Pool::Max | Pool::WeightedMean | Pool::MeanSqrtLen => {
                     if batch_size > 1 {
                         // for each request that requires pooling
                         let results: Result<Vec<Tensor>> = batch
@@ -312,16 +312,15 @@ impl FlashDistilBertModel {
                                 let start = batch.cumulative_seq_lengths[i];
                                 let len = batch.cumulative_seq_lengths[i + 1] - start;
 
-                                // Mean
                                 let embeddings = outputs.narrow(0, start as usize, len as usize)?;
-                                embeddings.sum_keepdim(0)? / (len as f64)
+                                pool_sequence(&self.pool, &embeddings)
                             })
                             .collect();
 
                         // Concatenate all results
                         Tensor::cat(&results?, 0)?
                     } else {
-                        (outputs.sum_keepdim(0)? / (batch.max_length as f64))?
+                        pool_sequence(&self.pool, &outputs)?
                     }
                 }
                 Pool::Splade => {
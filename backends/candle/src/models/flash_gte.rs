@@ -1,5 +1,5 @@
 use crate::flash_attn::flash_attn_varlen;
-use crate::layers::{get_cos_sin, get_inv_freqs, LayerNorm, Linear};
+use crate::layers::{get_cos_sin, get_inv_freqs, pool_sequence, LayerNorm, Linear};
 use crate::models::{GTEClassificationHead, GTEConfig, Model, PositionEmbeddingType, GTEMLP};
 use candle::{DType, Device, IndexOp, Result, Tensor};
 use candle_nn::{Embedding, Module, VarBuilder};
@@ -336,8 +336,8 @@ impl FlashGTEModel {
                         )
                     }
                 }
-                // Mean pooling
-                Pool::Mean => {
+                // Mean, Max, WeightedMean and MeanSqrtLen pooling
+                Pool::Mean | Pool::Max | Pool::WeightedMean | Pool::MeanSqrtLen => {
                     if batch_size > 1 {
                         // for each request that requires pooling
                         let results: Result<Vec<Tensor>> = batch
@@ -348,16 +348,15 @@ impl FlashGTEModel {
                                 let start = batch.cumulative_seq_lengths[i];
                                 let len = batch.cumulative_seq_lengths[i + 1] - start;
 
-                                // Mean
                                 let embeddings = outputs.narrow(0, start as usize, len as usize)?;
-                                embeddings.sum_keepdim(0)? / (len as f64)
+                                pool_sequence(&self.pool, &embeddings)
                             })
                             .collect();
 
                         // Concatenate all results
                         Some(Tensor::cat(&results?, 0)?)
                     } else {
-                        Some((outputs.sum_keepdim(0)? / (batch.max_length as f64))?)
+                        Some(pool_sequence(&self.pool, &outputs)?)
                     }
                 }
                 Pool::Splade => {
@@ -1,6 +1,6 @@
 use crate::alibi::alibi_head_slopes;
 use crate::flash_attn::flash_attn_varlen;
-use crate::layers::{HiddenAct, LayerNorm, Linear};
+use crate::layers::{pool_sequence, HiddenAct, LayerNorm, Linear};
 use crate::models::bert::PositionEmbeddingType;
 use crate::models::jina::JinaEmbeddings;
 use crate::models::{BertConfig, Model};
@@ -416,8 +416,8 @@ impl FlashJinaCodeBertModel {
                         )
                     }
                 }
-                // Mean pooling
-                Pool::Mean => {
+                // Mean, Max, WeightedMean and MeanSqrtLen pooling
+                Pool::Mean | Pool::Max | Pool::WeightedMean | Pool::MeanSqrtLen => {
                     if batch_size > 1 {
                         // for each request that requires pooling
                         let results: Result<Vec<Tensor>> = batch
@@ -428,16 +428,15 @@ impl FlashJinaCodeBertModel {
                                 let start = batch.cumulative_seq_lengths[i];
                                 let len = batch.cumulative_seq_lengths[i + 1] - start;
 
-                                // Mean
                                 let embeddings = outputs.narrow(0, start as usize, len as usize)?;
-                                embeddings.sum_keepdim(0)? / (len as f64)
+                                pool_sequence(&self.pool, &embeddings)
                             })
                             .collect();
 
                         // Concatenate all results
                         Some(Tensor::cat(&results?, 0)?)
                     } else {
-                        Some((outputs.sum_keepdim(0)? / (batch.max_length as f64))?)
+                        Some(pool_sequence(&self.pool, &outputs)?)
                     }
                 }
                 Pool::Splade => {
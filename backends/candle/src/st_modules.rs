@@ -0,0 +1,90 @@
+/// Sentence Transformers modules applied to the pooled embeddings
+use crate::layers::Linear;
+use candle::{DType, Device, Result, Tensor};
+use std::collections::HashMap;
+use text_embeddings_backend_core::{DenseActivation, DenseModule, StModule};
+
+pub(crate) enum Module {
+    Dense {
+        linear: Linear,
+        activation: DenseActivation,
+    },
+    Normalize,
+}
+
+pub(crate) fn load(modules: Vec<StModule>, dtype: DType, device: &Device) -> Result<Vec<Module>> {
+    modules
+        .into_iter()
+        .map(|module| match module {
+            StModule::Dense(dense) => load_dense(dense, dtype, device),
+            StModule::Normalize => Ok(Module::Normalize),
+        })
+        .collect()
+}
+
+fn load_dense(dense: DenseModule, dtype: DType, device: &Device) -> Result<Module> {
+    let mut tensors: HashMap<String, Tensor> =
+        if dense.weights_path.extension().and_then(|e| e.to_str()) == Some("safetensors") {
+            candle::safetensors::load(&dense.weights_path, device)?
+        } else {
+            candle::pickle::read_all(&dense.weights_path)?
+                .into_iter()
+                .collect()
+        };
+
+    let weight = tensors
+        .remove("linear.weight")
+        .ok_or_else(|| candle::Error::Msg("Dense module has no `linear.weight`".to_string()))?
+        .to_device(device)?
+        .to_dtype(dtype)?;
+    if weight.dims2()? != (dense.out_features, dense.in_features) {
+        return Err(candle::Error::Msg(format!(
+            "Dense weight has shape {:?} but the module maps {} to {} features",
+            weight.dims(),
+            dense.in_features,
+            dense.out_features
+        )));
+    }
+    let bias = if dense.bias {
+        let bias = tensors
+            .remove("linear.bias")
+            .ok_or_else(|| candle::Error::Msg("Dense module has no `linear.bias`".to_string()))?
+            .to_device(device)?
+            .to_dtype(dtype)?;
+        Some(bias)
+    } else {
+        None
+    };
+
+    Ok(Module::Dense {
+        linear: Linear::new(weight, bias, None),
+        activation: dense.activation,
+    })
+}
+
+/// Apply the modules to a [batch_size, hidden_size] tensor of pooled embeddings
+pub(crate) fn forward(modules: &[Module], mut embeddings: Tensor) -> Result<Tensor> {
+    for module in modules {
+        embeddings = match module {
+            Module::Dense { linear, activation } => {
+                let embeddings = linear.forward(&embeddings)?;
+                match activation {
+                    DenseActivation::Identity => embeddings,
+                    DenseActivation::Tanh => embeddings.tanh()?,
+                    DenseActivation::Sigmoid => candle_nn::ops::sigmoid(&embeddings)?,
+                    DenseActivation::Relu => embeddings.relu()?,
+                    DenseActivation::Gelu => embeddings.gelu_erf()?,
+                }
+            }
+            Module::Normalize => {
+                // Computed in f32 with the epsilon of `torch.nn.functional.normalize`, which
+                // would underflow in f16
+                let dtype = embeddings.dtype();
+                let embeddings = embeddings.to_dtype(DType::F32)?;
+                let norm = embeddings.sqr()?.sum_keepdim(1)?.sqrt()?.maximum(1e-12)?;
+                embeddings.broadcast_div(&norm)?.to_dtype(dtype)?
+            }
+        };
+    }
+    Ok(embeddings)
+}
@@ -1,9 +1,11 @@
 use anyhow::Result;
+use candle::{Device, Tensor};
 use hf_hub::api::sync::{ApiBuilder, ApiError, ApiRepo};
 use hf_hub::{Repo, RepoType};
 use insta::internals::YamlMatcher;
 use serde::{Deserialize, Serialize};
 use std::cmp::max;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use text_embeddings_backend_core::{Batch, Embedding, Embeddings};
@@ -95,18 +97,17 @@ pub fn sort_embeddings(embeddings: Embeddings) -> (Vec<Vec<f32>>, Vec<Vec<f32>>)
 
     for (_, embedding) in embeddings {
         match embedding {
-            Embedding::Pooled(e) => pooled_embeddings.push(e),
+            Embedding::Pooled(e, _) => pooled_embeddings.push(e),
             Embedding::All(e) => raw_embeddings.extend(e),
+            Embedding::Hybrid { pooled, .. } => pooled_embeddings.push(pooled),
+            Embedding::MultiVector(_) => {}
         }
     }
 
     (pooled_embeddings, raw_embeddings)
 }
 
-pub fn download_artifacts(
-    model_id: &'static str,
-    revision: Option<&'static str>,
-) -> Result<PathBuf> {
+fn api_repo(model_id: &'static str, revision: Option<&'static str>) -> ApiRepo {
     let mut builder = ApiBuilder::new().with_progress(false);
 
     if let Some(cache_dir) = std::env::var_os("HUGGINGFACE_HUB_CACHE") {
@@ -114,7 +115,7 @@ pub fn download_artifacts(
     }
 
     let api = builder.build().unwrap();
-    let api_repo = if let Some(revision) = revision {
+    if let Some(revision) = revision {
         api.repo(Repo::with_revision(
             model_id.to_string(),
             RepoType::Model,
@@ -122,7 +123,14 @@ pub fn download_artifacts(
         ))
     } else {
         api.repo(Repo::new(model_id.to_string(), RepoType::Model))
-    };
+    }
+}
+
+pub fn download_artifacts(
+    model_id: &'static str,
+    revision: Option<&'static str>,
+) -> Result<PathBuf> {
+    let api_repo = api_repo(model_id, revision);
 
     api_repo.get("config.json")?;
     api_repo.get("tokenizer.json")?;
@@ -179,6 +187,73 @@ fn download_safetensors(api: &ApiRepo) -> Result<Vec<PathBuf>, ApiError> {
     Ok(safetensors_files)
 }
 
+/// Download `modules.json` and the `Dense` modules at `dense_paths` next to the model files
+pub fn download_st_modules(
+    model_id: &'static str,
+    revision: Option<&'static str>,
+    dense_paths: &[&str],
+) -> Result<()> {
+    let api_repo = api_repo(model_id, revision);
+
+    api_repo.get("modules.json")?;
+    for path in dense_paths {
+        api_repo.get(&format!("{path}/config.json"))?;
+        if let Err(err) = api_repo.get(&format!("{path}/model.safetensors")) {
+            tracing::warn!("Could not download `{path}/model.safetensors`: {err}");
+            api_repo.get(&format!("{path}/pytorch_model.bin"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy of the model at `model_root` without its Sentence Transformers modules
+pub fn without_st_modules(model_root: &Path) -> Result<PathBuf> {
+    let root = std::env::temp_dir().join("tei-no-st-modules");
+    std::fs::create_dir_all(&root)?;
+    for name in ["config.json", "model.safetensors", "pytorch_model.bin"] {
+        if model_root.join(name).exists() {
+            std::fs::copy(model_root.join(name), root.join(name))?;
+        }
+    }
+    Ok(root)
+}
+
+/// Copy of the model at `model_root` with Sentence Transformers modules that scale the pooled
+/// embeddings by `scale` and end with a `Normalize` module
+pub fn with_st_modules(model_root: &Path, hidden_size: usize, scale: f32) -> Result<PathBuf> {
+    let root = std::env::temp_dir().join("tei-st-modules");
+    std::fs::create_dir_all(root.join("2_Dense"))?;
+    for name in ["config.json", "model.safetensors"] {
+        std::fs::copy(model_root.join(name), root.join(name))?;
+    }
+
+    std::fs::write(
+        root.join("modules.json"),
+        r#"[
+            {"idx": 0, "name": "0", "path": "", "type": "sentence_transformers.models.Transformer"},
+            {"idx": 1, "name": "1", "path": "1_Pooling", "type": "sentence_transformers.models.Pooling"},
+            {"idx": 2, "name": "2", "path": "2_Dense", "type": "sentence_transformers.models.Dense"},
+            {"idx": 3, "name": "3", "path": "3_Normalize", "type": "sentence_transformers.models.Normalize"}
+        ]"#,
+    )?;
+    std::fs::write(
+        root.join("2_Dense/config.json"),
+        format!(
+            r#"{{"in_features": {hidden_size}, "out_features": {hidden_size}, "bias": true, "activation_function": "torch.nn.modules.linear.Identity"}}"#
+        ),
+    )?;
+
+    let weight = (Tensor::eye(hidden_size, candle::DType::F32, &Device::Cpu)? * scale as f64)?;
+    let bias = Tensor::zeros(hidden_size, candle::DType::F32, &Device::Cpu)?;
+    let tensors = HashMap::from([
+        ("linear.weight".to_string(), weight),
+        ("linear.bias".to_string(), bias),
+    ]);
+    candle::safetensors::save(&tensors, root.join("2_Dense/model.safetensors"))?;
+
+    Ok(root)
+}
+
 pub fn relative_matcher() -> YamlMatcher<SnapshotScores> {
     YamlMatcher::new()
 }
@@ -235,6 +310,7 @@ pub fn load_tokenizer(model_root: &Path) -> Result<Tokenizer> {
 
 pub fn batch(encodings: Vec<Encoding>, pooled_indices: Vec<u32>, raw_indices: Vec<u32>) -> Batch {
     let mut input_ids = Vec::new();
+    let mut tokens = Vec::new();
     let mut token_type_ids = Vec::new();
     let mut position_ids = Vec::new();
     let mut cumulative_seq_lengths = Vec::with_capacity(encodings.len() + 1);
@@ -246,6 +322,7 @@ pub fn batch(encodings: Vec<Encoding>, pooled_indices: Vec<u32>, raw_indices: Ve
     for encoding in encodings.iter() {
         let encoding_length = encoding.len() as u32;
         input_ids.extend(encoding.get_ids().to_vec());
+        tokens.extend(encoding.get_tokens().to_vec());
         token_type_ids.extend(encoding.get_type_ids().to_vec());
         position_ids.extend(0..encoding_length);
         cumulative_length += encoding_length;
@@ -255,11 +332,14 @@ pub fn batch(encodings: Vec<Encoding>, pooled_indices: Vec<u32>, raw_indices: Ve
 
     Batch {
         input_ids,
+        tokens,
         token_type_ids,
         position_ids,
         cumulative_seq_lengths,
         max_length,
         pooled_indices,
         raw_indices,
+        multivector_indices: vec![],
+        lexical_indices: vec![],
     }
 }
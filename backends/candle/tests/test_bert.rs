@@ -2,7 +2,10 @@ mod common;
 
 use crate::common::{sort_embeddings, SnapshotEmbeddings, SnapshotScores};
 use anyhow::Result;
-use common::{batch, cosine_matcher, download_artifacts, load_tokenizer, relative_matcher};
+use common::{
+    batch, cosine_matcher, download_artifacts, load_tokenizer, relative_matcher, with_st_modules,
+    without_st_modules,
+};
 use text_embeddings_backend_candle::CandleBackend;
 use text_embeddings_backend_core::{Backend, ModelType, Pool};
 
@@ -66,6 +69,53 @@ fn test_mini() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[serial_test::serial]
+fn test_mini_st_modules() -> Result<()> {
+    let model_root = download_artifacts("sentence-transformers/all-MiniLM-L6-v2", None)?;
+    let tokenizer = load_tokenizer(&model_root)?;
+    let model_root = with_st_modules(&model_root, 384, 2.0)?;
+
+    let backend = CandleBackend::new(
+        &model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Mean),
+    )?;
+
+    let input_batch = || {
+        batch(
+            vec![
+                tokenizer.encode("What is Deep Learning?", true).unwrap(),
+                tokenizer.encode("Deep Learning is...", true).unwrap(),
+                tokenizer.encode("What is Deep Learning?", true).unwrap(),
+            ],
+            [0, 1, 2].to_vec(),
+            vec![],
+        )
+    };
+
+    let (pooled_embeddings, _) = sort_embeddings(backend.embed(input_batch())?);
+
+    // The final `Normalize` module is left to the router: only `Dense` is applied
+    let reference = CandleBackend::new(
+        &without_st_modules(&model_root)?,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Mean),
+    )?;
+    let (reference_embeddings, _) = sort_embeddings(reference.embed(input_batch())?);
+    for (embedding, reference) in pooled_embeddings.iter().zip(&reference_embeddings) {
+        for (v, r) in embedding.iter().zip(reference) {
+            assert!((v - 2.0 * r).abs() < 1e-5);
+        }
+    }
+
+    // The modules keep the direction of the embeddings
+    let embeddings_batch = SnapshotEmbeddings::from(pooled_embeddings);
+    insta::assert_yaml_snapshot!("mini_batch", embeddings_batch, &cosine_matcher());
+
+    Ok(())
+}
+
 #[test]
 #[serial_test::serial]
 fn test_mini_pooled_raw() -> Result<()> {
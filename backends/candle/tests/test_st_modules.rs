@@ -0,0 +1,77 @@
+mod common;
+
+use crate::common::sort_embeddings;
+use anyhow::Result;
+use candle::{DType, Device, Tensor};
+use common::{batch, download_artifacts, download_st_modules, load_tokenizer, without_st_modules};
+use std::collections::HashMap;
+use std::path::Path;
+use text_embeddings_backend_candle::CandleBackend;
+use text_embeddings_backend_core::{Backend, ModelType, Pool};
+
+fn load_dense(path: &Path) -> Result<(Vec<Vec<f32>>, Vec<f32>)> {
+    let mut tensors: HashMap<String, Tensor> = match path.join("model.safetensors") {
+        weights if weights.exists() => candle::safetensors::load(weights, &Device::Cpu)?,
+        _ => candle::pickle::read_all(path.join("pytorch_model.bin"))?
+            .into_iter()
+            .collect(),
+    };
+    let weight = tensors.remove("linear.weight").unwrap();
+    let bias = tensors.remove("linear.bias").unwrap();
+    Ok((
+        weight.to_dtype(DType::F32)?.to_vec2()?,
+        bias.to_dtype(DType::F32)?.to_vec1()?,
+    ))
+}
+
+#[test]
+#[serial_test::serial]
+fn test_distiluse_dense() -> Result<()> {
+    // Mean pooling followed by a 768 -> 512 `Dense` module with a `Tanh` activation
+    let model_id = "sentence-transformers/distiluse-base-multilingual-cased-v2";
+    let model_root = download_artifacts(model_id, None)?;
+    download_st_modules(model_id, None, &["2_Dense"])?;
+    let tokenizer = load_tokenizer(&model_root)?;
+
+    let input_batch = || {
+        batch(
+            vec![
+                tokenizer.encode("What is Deep Learning?", true).unwrap(),
+                tokenizer
+                    .encode("Qu'est-ce que l'apprentissage profond ?", true)
+                    .unwrap(),
+            ],
+            [0, 1].to_vec(),
+            vec![],
+        )
+    };
+
+    let backend = CandleBackend::new(
+        &model_root,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Mean),
+    )?;
+    let (pooled_embeddings, _) = sort_embeddings(backend.embed(input_batch())?);
+
+    // Apply the checkpoint of the module to the embeddings of the transformer
+    let reference = CandleBackend::new(
+        &without_st_modules(&model_root)?,
+        "float32".to_string(),
+        ModelType::Embedding(Pool::Mean),
+    )?;
+    let (reference_embeddings, _) = sort_embeddings(reference.embed(input_batch())?);
+    let (weight, bias) = load_dense(&model_root.join("2_Dense"))?;
+
+    assert_eq!(pooled_embeddings.len(), 2);
+    for (embedding, reference) in pooled_embeddings.iter().zip(&reference_embeddings) {
+        assert_eq!(reference.len(), 768);
+        assert_eq!(embedding.len(), 512);
+
+        for (v, (row, b)) in embedding.iter().zip(weight.iter().zip(&bias)) {
+            let expected = (row.iter().zip(reference).map(|(w, x)| w * x).sum::<f32>() + b).tanh();
+            assert!((v - expected).abs() < 1e-4, "{v} != {expected}");
+        }
+    }
+
+    Ok(())
+}
@@ -1,9 +1,20 @@
+mod st_modules;
+
 #[cfg(feature = "clap")]
 use clap::ValueEnum;
 use nohash_hasher::IntMap;
 use std::fmt;
+use std::path::PathBuf;
 use thiserror::Error;
 
+pub use st_modules::{
+    load_st_modules, st_modules_dimensions, DenseActivation, DenseModule, StModule,
+};
+
+/// A batch of sequences to embed or classify.
+///
+/// Members found in both `pooled_indices` and `multivector_indices` are returned as
+/// [`Embedding::Hybrid`].
 #[derive(Debug)]
 pub struct Batch {
     pub input_ids: Vec<u32>,
@@ -14,6 +25,9 @@ pub struct Batch {
     pub max_length: u32,
     pub pooled_indices: Vec<u32>,
     pub raw_indices: Vec<u32>,
+    pub multivector_indices: Vec<u32>,
+    /// Members of `pooled_indices` that also need lexical weights
+    pub lexical_indices: Vec<u32>,
 }
 
 impl Batch {
@@ -26,9 +40,25 @@ impl Batch {
     }
 }
 
+/// Lexical weight of a single vocabulary entry in a sequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenWeight {
+    pub id: u32,
+    pub token: String,
+    pub weight: f32,
+}
+
 pub enum Embedding {
-    Pooled(Vec<f32>, Vec<(String, f32)>),
+    Pooled(Vec<f32>, Vec<TokenWeight>),
     All(Vec<Vec<f32>>),
+    /// Normalized per-token vectors, without special and padding tokens
+    MultiVector(Vec<Vec<f32>>),
+    /// Pooled, lexical and multi-vector outputs of the same forward pass
+    Hybrid {
+        pooled: Vec<f32>,
+        token_weights: Vec<TokenWeight>,
+        multivector: Vec<Vec<f32>>,
+    },
 }
 
 pub type Embeddings = IntMap<usize, Embedding>;
@@ -42,6 +72,19 @@ pub trait Backend {
 
     fn is_padded(&self) -> bool;
 
+    fn supports_multivector(&self) -> bool {
+        false
+    }
+
+    fn supports_lexical_weights(&self) -> bool {
+        false
+    }
+
+    /// Options of the ONNX Runtime session, for backends that run on ONNX Runtime
+    fn ort_session_config(&self) -> Option<OrtSessionConfig> {
+        None
+    }
+
     fn embed(&self, batch: Batch) -> Result<Embeddings, BackendError>;
 
     fn predict(&self, batch: Batch) -> Result<Predictions, BackendError>;
@@ -66,6 +109,12 @@ pub enum Pool {
     Splade,
     /// Select the last token as embedding
     LastToken,
+    /// Apply Max pooling to the model embeddings
+    Max,
+    /// Apply position-weighted Mean pooling to the model embeddings, as in SGPT
+    WeightedMean,
+    /// Sum the model embeddings and divide by the square root of the number of tokens
+    MeanSqrtLen,
 }
 
 impl fmt::Display for Pool {
@@ -75,6 +124,83 @@ impl fmt::Display for Pool {
             Pool::Mean => write!(f, "mean"),
             Pool::Splade => write!(f, "splade"),
             Pool::LastToken => write!(f, "last_token"),
+            Pool::Max => write!(f, "max"),
+            Pool::WeightedMean => write!(f, "weighted_mean"),
+            Pool::MeanSqrtLen => write!(f, "mean_sqrt_len"),
+        }
+    }
+}
+
+/// Options of the ONNX Runtime session
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrtSessionConfig {
+    /// Threads used to run a single operator. Defaults to the number of CPU cores.
+    pub intra_threads: Option<usize>,
+    /// Threads used to run independent operators with the parallel execution mode.
+    /// Defaults to the ONNX Runtime default.
+    pub inter_threads: Option<usize>,
+    pub execution_mode: OrtExecutionMode,
+    pub optimization_level: OrtOptimizationLevel,
+    /// The optimized model is saved to this path and loaded from it on the next start
+    pub optimized_model_path: Option<PathBuf>,
+    pub memory_arena: bool,
+    pub memory_pattern: bool,
+    pub max_batch_size: usize,
+}
+
+impl Default for OrtSessionConfig {
+    fn default() -> Self {
+        Self {
+            intra_threads: None,
+            inter_threads: None,
+            execution_mode: OrtExecutionMode::Sequential,
+            optimization_level: OrtOptimizationLevel::All,
+            optimized_model_path: None,
+            memory_arena: true,
+            memory_pattern: true,
+            max_batch_size: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum OrtExecutionMode {
+    /// Run the operators one after the other
+    Sequential,
+    /// Run independent operators concurrently on the inter-op threads
+    Parallel,
+}
+
+impl fmt::Display for OrtExecutionMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrtExecutionMode::Sequential => write!(f, "sequential"),
+            OrtExecutionMode::Parallel => write!(f, "parallel"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(ValueEnum))]
+pub enum OrtOptimizationLevel {
+    /// Disable all graph optimizations
+    Disable,
+    /// Redundant node eliminations and constant folding
+    Basic,
+    /// Basic optimizations and complex node fusions
+    Extended,
+    /// Extended optimizations and layout optimizations
+    All,
+}
+
+impl fmt::Display for OrtOptimizationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrtOptimizationLevel::Disable => write!(f, "disable"),
+            OrtOptimizationLevel::Basic => write!(f, "basic"),
+            OrtOptimizationLevel::Extended => write!(f, "extended"),
+            OrtOptimizationLevel::All => write!(f, "all"),
         }
     }
 }
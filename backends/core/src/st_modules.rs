@@ -0,0 +1,246 @@
+/// Sentence Transformers modules applied to the pooled embeddings
+use crate::BackendError;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Entry of `modules.json`
+#[derive(Debug, Deserialize)]
+struct ModuleEntry {
+    path: String,
+    #[serde(rename = "type")]
+    module_type: String,
+}
+
+/// Content of the `config.json` of a `Dense` module
+#[derive(Debug, Deserialize)]
+struct DenseConfig {
+    in_features: usize,
+    out_features: usize,
+    #[serde(default = "default_bias")]
+    bias: bool,
+    #[serde(default = "default_activation_function")]
+    activation_function: String,
+}
+
+fn default_bias() -> bool {
+    true
+}
+
+fn default_activation_function() -> String {
+    "torch.nn.modules.activation.Tanh".to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenseActivation {
+    Identity,
+    Tanh,
+    Sigmoid,
+    Relu,
+    Gelu,
+}
+
+impl TryFrom<&str> for DenseActivation {
+    type Error = BackendError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        // Activations are stored as the path of the torch class
+        match value.rsplit('.').next().unwrap_or(value) {
+            "Identity" => Ok(DenseActivation::Identity),
+            "Tanh" => Ok(DenseActivation::Tanh),
+            "Sigmoid" => Ok(DenseActivation::Sigmoid),
+            "ReLU" => Ok(DenseActivation::Relu),
+            "GELU" => Ok(DenseActivation::Gelu),
+            _ => Err(BackendError::Start(format!(
+                "Dense activation `{value}` is not supported"
+            ))),
+        }
+    }
+}
+
+/// Linear projection of a `Dense` module
+#[derive(Debug, Clone)]
+pub struct DenseModule {
+    pub in_features: usize,
+    pub out_features: usize,
+    pub bias: bool,
+    pub activation: DenseActivation,
+    /// `model.safetensors` or `pytorch_model.bin` with the `linear.weight` and `linear.bias`
+    /// tensors
+    pub weights_path: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub enum StModule {
+    Dense(DenseModule),
+    /// L2 normalization
+    Normalize,
+}
+
+/// Load the modules of `modules.json` that follow the `Pooling` module, in order, except a
+/// final `Normalize` module.
+/// Returns no modules if the model does not have a `modules.json`.
+pub fn load_st_modules(model_path: &Path) -> Result<Vec<StModule>, BackendError> {
+    let Ok(modules) = std::fs::read_to_string(model_path.join("modules.json")) else {
+        return Ok(Vec::new());
+    };
+    let modules: Vec<ModuleEntry> = serde_json::from_str(&modules)
+        .map_err(|err| BackendError::Start(format!("Failed to parse `modules.json`: {err}")))?;
+
+    let mut st_modules = Vec::new();
+    for module in modules {
+        match module.module_type.rsplit('.').next() {
+            Some("Transformer" | "Pooling") => {}
+            Some("Dense") => {
+                let module_path = model_path.join(&module.path);
+                let config =
+                    std::fs::read_to_string(module_path.join("config.json")).map_err(|err| {
+                        BackendError::Start(format!(
+                            "Failed to read `{}/config.json`: {err}",
+                            module.path
+                        ))
+                    })?;
+                let config: DenseConfig = serde_json::from_str(&config).map_err(|err| {
+                    BackendError::Start(format!(
+                        "Failed to parse `{}/config.json`: {err}",
+                        module.path
+                    ))
+                })?;
+
+                let weights_path = match module_path.join("model.safetensors") {
+                    path if path.exists() => path,
+                    _ => module_path.join("pytorch_model.bin"),
+                };
+                st_modules.push(StModule::Dense(DenseModule {
+                    in_features: config.in_features,
+                    out_features: config.out_features,
+                    bias: config.bias,
+                    activation: DenseActivation::try_from(config.activation_function.as_str())?,
+                    weights_path,
+                }));
+            }
+            Some("Normalize") => st_modules.push(StModule::Normalize),
+            _ => tracing::warn!(
+                "Sentence Transformers module `{}` is not supported and will be ignored",
+                module.module_type
+            ),
+        }
+    }
+
+    // The final normalization is left to the router so that requests can disable it with
+    // `normalize: false`
+    while let Some(StModule::Normalize) = st_modules.last() {
+        st_modules.pop();
+        tracing::info!("The final `Normalize` module is applied with the `normalize` parameter");
+    }
+    Ok(st_modules)
+}
+
+/// Size of the embeddings returned by the modules, if they change it
+pub fn st_modules_dimensions(modules: &[StModule]) -> Option<usize> {
+    modules.iter().rev().find_map(|module| match module {
+        StModule::Dense(dense) => Some(dense.out_features),
+        StModule::Normalize => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::st_modules::{load_st_modules, st_modules_dimensions, DenseActivation, StModule};
+    use std::path::Path;
+
+    fn write_model(root: &Path, modules: &[(&str, &str)]) {
+        let entries: Vec<_> = modules
+            .iter()
+            .map(|(path, module_type)| {
+                serde_json::json!({
+                    "path": path,
+                    "type": format!("sentence_transformers.models.{module_type}"),
+                })
+            })
+            .collect();
+        std::fs::write(
+            root.join("modules.json"),
+            serde_json::to_string(&entries).unwrap(),
+        )
+        .unwrap();
+
+        for (path, _) in modules
+            .iter()
+            .filter(|(_, module_type)| *module_type == "Dense")
+        {
+            std::fs::create_dir_all(root.join(path)).unwrap();
+            std::fs::write(
+                root.join(path).join("config.json"),
+                r#"{"in_features": 8, "out_features": 4, "bias": false}"#,
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_final_normalize_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(
+            dir.path(),
+            &[
+                ("", "Transformer"),
+                ("1_Pooling", "Pooling"),
+                ("2_Dense", "Dense"),
+                ("3_Normalize", "Normalize"),
+            ],
+        );
+
+        let modules = load_st_modules(dir.path()).unwrap();
+        assert_eq!(modules.len(), 1);
+        let StModule::Dense(dense) = &modules[0] else {
+            panic!("expected a Dense module, got {modules:?}");
+        };
+        assert_eq!((dense.in_features, dense.out_features), (8, 4));
+        assert!(!dense.bias);
+        // Sentence Transformers defaults to `Tanh`
+        assert_eq!(dense.activation, DenseActivation::Tanh);
+        assert_eq!(
+            dense.weights_path,
+            dir.path().join("2_Dense").join("pytorch_model.bin")
+        );
+        assert_eq!(st_modules_dimensions(&modules), Some(4));
+    }
+
+    #[test]
+    fn test_inner_normalize_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(
+            dir.path(),
+            &[
+                ("", "Transformer"),
+                ("1_Pooling", "Pooling"),
+                ("2_Normalize", "Normalize"),
+                ("3_Dense", "Dense"),
+            ],
+        );
+
+        let modules = load_st_modules(dir.path()).unwrap();
+        assert!(matches!(
+            modules.as_slice(),
+            [StModule::Normalize, StModule::Dense(_)]
+        ));
+    }
+
+    #[test]
+    fn test_no_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_st_modules(dir.path()).unwrap().is_empty());
+
+        write_model(
+            dir.path(),
+            &[
+                ("", "Transformer"),
+                ("1_Pooling", "Pooling"),
+                ("2_Normalize", "Normalize"),
+            ],
+        );
+        let modules = load_st_modules(dir.path()).unwrap();
+        assert!(modules.is_empty());
+        assert_eq!(st_modules_dimensions(&modules), None);
+    }
+}
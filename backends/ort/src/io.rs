@@ -0,0 +1,251 @@
+/// Names of the input and output tensors of the ONNX model
+use ort::session::Session;
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+use text_embeddings_backend_core::BackendError;
+
+/// Optional file, next to the ONNX model, that maps the tensors used by the backend to the
+/// tensors of the model
+pub const IO_CONFIG_NAME: &str = "onnx_io.json";
+
+/// Content of `onnx_io.json`.
+/// A missing key falls back to the default names and `null` ignores an optional tensor.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct IoConfig {
+    input_ids: Option<String>,
+    #[serde(deserialize_with = "explicit")]
+    attention_mask: Option<Option<String>>,
+    #[serde(deserialize_with = "explicit")]
+    token_type_ids: Option<Option<String>>,
+    #[serde(deserialize_with = "explicit")]
+    token_embeddings: Option<Option<String>>,
+    #[serde(deserialize_with = "explicit")]
+    sentence_embedding: Option<Option<String>>,
+    #[serde(deserialize_with = "explicit")]
+    logits: Option<Option<String>>,
+    #[serde(deserialize_with = "explicit")]
+    colbert_vecs: Option<Option<String>>,
+}
+
+/// Tell an explicit `null` apart from a missing key
+fn explicit<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug)]
+pub(crate) struct IoNames {
+    pub input_ids: String,
+    pub attention_mask: Option<String>,
+    pub token_type_ids: Option<String>,
+    /// [batch_size, max_length, hidden_size] token embeddings
+    pub token_embeddings: Option<String>,
+    /// [batch_size, hidden_size] embeddings already pooled by the model
+    pub sentence_embedding: Option<String>,
+    /// Classification scores, masked language modeling logits for SPLADE models or the BGE-M3
+    /// `sparse_linear` head of embedding models
+    pub logits: Option<String>,
+    /// BGE-M3 `colbert_linear` head
+    pub colbert_vecs: Option<String>,
+}
+
+impl IoNames {
+    /// Resolve the tensor names of `session`, using the `onnx_io.json` of the first directory that
+    /// has one
+    pub fn load(directories: &[&Path], session: &Session) -> Result<Self, BackendError> {
+        let config = match directories
+            .iter()
+            .map(|directory| directory.join(IO_CONFIG_NAME))
+            .find(|path| path.exists())
+        {
+            Some(path) => {
+                tracing::info!("Loading tensor names from {}", path.display());
+                let config = std::fs::read_to_string(&path).map_err(|err| {
+                    BackendError::Start(format!("Failed to read `{IO_CONFIG_NAME}`: {err}"))
+                })?;
+                serde_json::from_str(&config).map_err(|err| {
+                    BackendError::Start(format!("Failed to parse `{IO_CONFIG_NAME}`: {err}"))
+                })?
+            }
+            None => IoConfig::default(),
+        };
+
+        let inputs: Vec<&str> = session
+            .inputs
+            .iter()
+            .map(|input| input.name.as_str())
+            .collect();
+        let outputs: Vec<&str> = session
+            .outputs
+            .iter()
+            .map(|output| output.name.as_str())
+            .collect();
+        Self::from_names(config, &inputs, &outputs)
+    }
+
+    fn from_names(
+        config: IoConfig,
+        inputs: &[&str],
+        outputs: &[&str],
+    ) -> Result<Self, BackendError> {
+        let input_ids = resolve(
+            "input_ids",
+            config.input_ids.map(Some),
+            &["input_ids"],
+            inputs,
+        )?
+        .ok_or_else(|| {
+            BackendError::Start(format!(
+                "Model does not have an `input_ids` input. Inputs: {inputs:?}"
+            ))
+        })?;
+
+        let token_embeddings = resolve(
+            "token_embeddings",
+            config.token_embeddings,
+            &["last_hidden_state", "token_embeddings"],
+            outputs,
+        )?;
+        // The BERT pooler is not the pooling of the model: it is only a default when the token
+        // embeddings cannot be pooled
+        let sentence_embedding_defaults: &[&str] = match token_embeddings {
+            Some(_) => &["sentence_embedding"],
+            None => &["sentence_embedding", "pooler_output"],
+        };
+
+        Ok(Self {
+            input_ids,
+            attention_mask: resolve(
+                "attention_mask",
+                config.attention_mask,
+                &["attention_mask"],
+                inputs,
+            )?,
+            token_type_ids: resolve(
+                "token_type_ids",
+                config.token_type_ids,
+                &["token_type_ids", "input_type"],
+                inputs,
+            )?,
+            token_embeddings,
+            sentence_embedding: resolve(
+                "sentence_embedding",
+                config.sentence_embedding,
+                sentence_embedding_defaults,
+                outputs,
+            )?,
+            logits: resolve("logits", config.logits, &["logits"], outputs)?,
+            colbert_vecs: resolve(
+                "colbert_vecs",
+                config.colbert_vecs,
+                &["colbert_vecs"],
+                outputs,
+            )?,
+        })
+    }
+}
+
+fn resolve(
+    key: &str,
+    config: Option<Option<String>>,
+    defaults: &[&str],
+    names: &[&str],
+) -> Result<Option<String>, BackendError> {
+    match config {
+        Some(Some(name)) if names.contains(&name.as_str()) => Ok(Some(name)),
+        Some(Some(name)) => Err(BackendError::Start(format!(
+            "`{key}` is mapped to `{name}` in `{IO_CONFIG_NAME}` but the model does not have this tensor. Available tensors: {names:?}"
+        ))),
+        Some(None) => Ok(None),
+        None => Ok(defaults
+            .iter()
+            .find(|name| names.contains(name))
+            .map(|name| name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::{IoConfig, IoNames};
+
+    fn resolve_names(config: &str, inputs: &[&str], outputs: &[&str]) -> Result<IoNames, String> {
+        let config: IoConfig = serde_json::from_str(config).map_err(|err| err.to_string())?;
+        IoNames::from_names(config, inputs, outputs).map_err(|err| err.to_string())
+    }
+
+    const INPUTS: &[&str] = &["input_ids", "attention_mask", "token_type_ids"];
+
+    #[test]
+    fn test_defaults() {
+        let names = resolve_names("{}", INPUTS, &["last_hidden_state", "pooler_output"]).unwrap();
+        assert_eq!(names.input_ids, "input_ids");
+        assert_eq!(names.attention_mask.as_deref(), Some("attention_mask"));
+        assert_eq!(names.token_type_ids.as_deref(), Some("token_type_ids"));
+        assert_eq!(names.token_embeddings.as_deref(), Some("last_hidden_state"));
+        // The token embeddings are pooled instead of using the BERT pooler
+        assert_eq!(names.sentence_embedding, None);
+        assert_eq!(names.logits, None);
+        assert_eq!(names.colbert_vecs, None);
+    }
+
+    #[test]
+    fn test_sentence_embedding_defaults() {
+        let outputs = ["token_embeddings", "sentence_embedding"];
+        let names = resolve_names("{}", &["input_ids", "input_type"], &outputs).unwrap();
+        assert_eq!(names.token_type_ids.as_deref(), Some("input_type"));
+        assert_eq!(names.token_embeddings.as_deref(), Some("token_embeddings"));
+        assert_eq!(
+            names.sentence_embedding.as_deref(),
+            Some("sentence_embedding")
+        );
+
+        let names = resolve_names("{}", &["input_ids"], &["pooler_output"]).unwrap();
+        assert_eq!(names.attention_mask, None);
+        assert_eq!(names.token_embeddings, None);
+        assert_eq!(names.sentence_embedding.as_deref(), Some("pooler_output"));
+    }
+
+    #[test]
+    fn test_missing_key_and_null() {
+        // A missing key keeps the default name, `null` ignores the tensor
+        let config = r#"{"attention_mask": null, "token_embeddings": "hidden_states"}"#;
+        let names =
+            resolve_names(config, INPUTS, &["hidden_states", "sentence_embedding"]).unwrap();
+        assert_eq!(names.attention_mask, None);
+        assert_eq!(names.token_type_ids.as_deref(), Some("token_type_ids"));
+        assert_eq!(names.token_embeddings.as_deref(), Some("hidden_states"));
+        assert_eq!(
+            names.sentence_embedding.as_deref(),
+            Some("sentence_embedding")
+        );
+
+        let config = r#"{"token_type_ids": null, "sentence_embedding": null}"#;
+        let names =
+            resolve_names(config, INPUTS, &["last_hidden_state", "sentence_embedding"]).unwrap();
+        assert_eq!(names.token_type_ids, None);
+        assert_eq!(names.sentence_embedding, None);
+
+        // `pooler_output` can always be mapped explicitly
+        let config = r#"{"sentence_embedding": "pooler_output"}"#;
+        let names = resolve_names(config, INPUTS, &["last_hidden_state", "pooler_output"]).unwrap();
+        assert_eq!(names.sentence_embedding.as_deref(), Some("pooler_output"));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let err = resolve_names(r#"{"input_ids": "ids"}"#, INPUTS, &[]).unwrap_err();
+        assert!(err.contains("`input_ids` is mapped to `ids`"), "{err}");
+
+        let err = resolve_names("{}", &["ids"], &[]).unwrap_err();
+        assert!(err.contains("does not have an `input_ids` input"), "{err}");
+
+        // `input_ids` cannot be ignored and unknown keys are rejected
+        let names = resolve_names(r#"{"input_ids": null}"#, INPUTS, &[]).unwrap();
+        assert_eq!(names.input_ids, "input_ids");
+        assert!(resolve_names(r#"{"pooled": "pooler_output"}"#, INPUTS, &[]).is_err());
+    }
+}
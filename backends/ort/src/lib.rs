@@ -1,19 +1,74 @@
-use ndarray::{s, Axis};
+mod io;
+mod st_modules;
+
+pub use io::IO_CONFIG_NAME;
+use ndarray::{s, Array1, Array2, ArrayD, ArrayView1, Axis, Ix2, Ix3};
 use nohash_hasher::BuildNoHashHasher;
+use ort::execution_providers::CPUExecutionProvider;
+use ort::session::run_options::{OutputSelector, RunOptions};
 use ort::session::{builder::GraphOptimizationLevel, Session};
+use ort::tensor::TensorElementType;
+use ort::value::{DynValue, Tensor};
 use std::collections::HashMap;
 use std::ops::{Div, Mul};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 use text_embeddings_backend_core::{
-    Backend, BackendError, Batch, Embedding, Embeddings, ModelType, Pool, Predictions,
+    load_st_modules, Backend, BackendError, Batch, Embedding, Embeddings, ModelType,
+    OrtExecutionMode, OrtOptimizationLevel, OrtSessionConfig, Pool, Predictions, TokenWeight,
 };
 
-const UNUSED_TOKENS: &[&str; 5] = &["▁", "<s>", "</s>", "unk", "pad"];
+/// Tokens that never carry a lexical weight
+const UNUSED_TOKENS: &[&str; 5] = &["▁", "<s>", "</s>", "<unk>", "<pad>"];
+
+/// Names of the ONNX exports of a dtype, in order of preference. The files are either at the root
+/// of the model or in its `onnx` directory.
+pub fn onnx_model_names(dtype: &str) -> Result<Vec<&'static str>, BackendError> {
+    match dtype {
+        "float32" => Ok(vec!["model.onnx"]),
+        "float16" => Ok(vec!["model_fp16.onnx"]),
+        "int8" => {
+            // Prefer the exports quantized for the instruction set of this CPU
+            let mut names = Vec::new();
+            #[cfg(target_arch = "x86_64")]
+            {
+                if is_x86_feature_detected!("avx512vnni") {
+                    names.push("model_qint8_avx512_vnni.onnx");
+                }
+                if is_x86_feature_detected!("avx512f") {
+                    names.push("model_qint8_avx512.onnx");
+                }
+                if is_x86_feature_detected!("avx2") {
+                    names.push("model_qint8_avx2.onnx");
+                }
+            }
+            #[cfg(target_arch = "aarch64")]
+            names.push("model_qint8_arm64.onnx");
+            names.extend([
+                "model_quantized.onnx",
+                "model_int8.onnx",
+                "model_uint8.onnx",
+            ]);
+            Ok(names)
+        }
+        _ => Err(BackendError::Start(format!(
+            "DType {dtype} is not supported"
+        ))),
+    }
+}
 
 pub struct OrtBackend {
     session: Session,
     pool: Pool,
-    type_id_name: Option<String>,
+    /// Names of the model tensors
+    io: io::IoNames,
+    /// Element types of the model inputs
+    input_types: HashMap<String, TensorElementType>,
+    has_sparse_head: bool,
+    has_colbert_head: bool,
+    /// Sentence Transformers modules applied after pooling
+    st_modules: Vec<st_modules::Module>,
+    session_config: OrtSessionConfig,
 }
 
 impl OrtBackend {
@@ -21,67 +76,287 @@ impl OrtBackend {
         model_path: &Path,
         dtype: String,
         model_type: ModelType,
+        mut session_config: OrtSessionConfig,
     ) -> Result<Self, BackendError> {
-        // Check dtype
-        if dtype == "float32" {
-        } else {
-            return Err(BackendError::Start(format!(
-                "DType {dtype} is not supported"
-            )));
-        };
-
         // Check model type
+        let classifier = model_type == ModelType::Classifier;
         let pool = match model_type {
             ModelType::Classifier => Pool::Cls,
-            ModelType::Embedding(pool) => match pool {
-                Pool::Splade | Pool::LastToken => {
-                    return Err(BackendError::Start(format!(
-                        "Pooling {pool} is not supported for this backend. Use `candle` backend instead."
-                    )));
-                }
-                pool => pool,
-            },
+            ModelType::Embedding(pool) => pool,
         };
 
         // Get model path
-        let onnx_path = {
-            let default_path = model_path.join("model.onnx");
-            match default_path.exists() {
-                true => default_path,
-                false => model_path.join("onnx/model.onnx"),
-            }
-        };
+        let onnx_names = onnx_model_names(&dtype)?;
+        let export_path = onnx_names
+            .iter()
+            .flat_map(|name| [model_path.join(name), model_path.join("onnx").join(name)])
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                BackendError::Start(format!(
+                    "No ONNX model found for dtype {dtype}. Expected one of {onnx_names:?}"
+                ))
+            })?;
+        tracing::info!("Loading {}", export_path.display());
+
+        if session_config.max_batch_size == 0 {
+            return Err(BackendError::Start(
+                "`max_batch_size` must be greater than 0".to_string(),
+            ));
+        }
+        let intra_threads = *session_config
+            .intra_threads
+            .get_or_insert_with(num_cpus::get);
+
+        // A previously optimized model does not need to be optimized again, unless it was optimized
+        // from another export or with other settings
+        let source = optimized_model_source(&export_path, &dtype, &session_config)?;
+        let (onnx_path, optimization_level, optimized_model_path) =
+            match &session_config.optimized_model_path {
+                Some(path) if path.exists() && read_source(path).as_ref() == Some(&source) => {
+                    tracing::info!("Loading optimized model from {}", path.display());
+                    (path.clone(), GraphOptimizationLevel::Disable, None)
+                }
+                path => {
+                    if let Some(path) = path {
+                        if path.exists() {
+                            tracing::info!(
+                                "{} was optimized from another model: optimizing it again",
+                                path.display()
+                            );
+                        }
+                        // Never leave the source of a previous model next to the new one
+                        let _ = std::fs::remove_file(source_path(path));
+                    }
+                    (
+                        export_path.clone(),
+                        optimization_level(session_config.optimization_level),
+                        path.clone(),
+                    )
+                }
+            };
 
         // Start onnx session
-        let session = Session::builder()
+        let mut builder = Session::builder()
+            .s()?
+            .with_intra_threads(intra_threads)
             .s()?
-            .with_intra_threads(num_cpus::get())
+            .with_parallel_execution(session_config.execution_mode == OrtExecutionMode::Parallel)
             .s()?
-            .with_optimization_level(GraphOptimizationLevel::Level3)
+            .with_optimization_level(optimization_level)
             .s()?
-            .commit_from_file(onnx_path)
+            .with_memory_pattern(session_config.memory_pattern)
             .s()?;
+        if let Some(inter_threads) = session_config.inter_threads {
+            builder = builder.with_inter_threads(inter_threads).s()?;
+        }
+        if !session_config.memory_arena {
+            // The CPU execution provider disables the arena unless asked otherwise
+            builder = builder
+                .with_execution_providers([CPUExecutionProvider::default().build()])
+                .s()?;
+        }
+        if let Some(path) = &optimized_model_path {
+            builder = builder.with_optimized_model_path(path).s()?;
+        }
+        let session = builder.commit_from_file(onnx_path).s()?;
+        if let Some(path) = optimized_model_path {
+            let source_path = source_path(&path);
+            if let Err(err) = std::fs::write(&source_path, source.to_string()) {
+                tracing::warn!("Could not write {}: {err}", source_path.display());
+            }
+        }
+
+        // Map the tensors of the model, optionally using the `onnx_io.json` next to the export.
+        // The optimized model may be cached anywhere else.
+        let mut directories = Vec::with_capacity(2);
+        if let Some(directory) = export_path.parent() {
+            directories.push(directory);
+        }
+        directories.push(model_path);
+        let mut io = io::IoNames::load(&directories, &session)?;
 
-        // Check if the model requires type tokens
-        let mut type_id_name = None;
-        for input in &session.inputs {
-            if &input.name == "token_type_ids" || &input.name == "input_type" {
-                type_id_name = Some(input.name.clone());
-                break;
+        match pool {
+            _ if classifier => {
+                if io.logits.is_none() {
+                    return Err(BackendError::Start(
+                        "Classifier model does not have a `logits` output".to_string(),
+                    ));
+                }
+            }
+            Pool::Splade => {
+                // SPLADE pools the masked language modeling logits
+                io.token_embeddings = Some(io.logits.take().ok_or_else(|| {
+                    BackendError::Start("SPLADE model does not have a `logits` output".to_string())
+                })?);
+                io.sentence_embedding = None;
+            }
+            _ => {
+                if io.token_embeddings.is_none() && io.sentence_embedding.is_none() {
+                    return Err(BackendError::Start(format!(
+                        "Model does not have a token or sentence embedding output. Outputs: {:?}",
+                        session
+                            .outputs
+                            .iter()
+                            .map(|output| output.name.as_str())
+                            .collect::<Vec<_>>()
+                    )));
+                }
             }
         }
 
+        let input_types = session
+            .inputs
+            .iter()
+            .filter_map(|input| Some((input.name.clone(), input.input_type.tensor_type()?)))
+            .collect();
+
+        // Check if the model exports the heads used for lexical weights and multi-vector
+        // embeddings
+        let has_sparse_head = match &io.logits {
+            Some(name) if !classifier => {
+                let dimensions = session
+                    .outputs
+                    .iter()
+                    .find(|output| &output.name == name)
+                    .and_then(|output| output.output_type.tensor_dimensions());
+                match dimensions {
+                    Some(dimensions) if is_sparse_head(dimensions) => true,
+                    _ => {
+                        return Err(BackendError::Start(format!(
+                            "The `{name}` output of shape {dimensions:?} is not a sparse head: expected `[batch_size, sequence_length, 1]`"
+                        )))
+                    }
+                }
+            }
+            _ => false,
+        };
+        let has_colbert_head = io.colbert_vecs.is_some();
+
+        let st_modules = match &io.sentence_embedding {
+            Some(name) => {
+                // The model already pooled and post-processed the embeddings
+                tracing::info!(
+                    "Using the `{name}` output: {pool} pooling and Sentence Transformers modules are skipped"
+                );
+                Vec::new()
+            }
+            None => st_modules::load(load_st_modules(model_path)?)?,
+        };
+
         Ok(Self {
             session,
             pool,
-            type_id_name,
+            io,
+            input_types,
+            has_sparse_head,
+            has_colbert_head,
+            st_modules,
+            session_config,
         })
     }
+
+    /// Convert an integer input to the element type expected by the model
+    fn input(&self, name: &str, array: Array2<i64>) -> ort::Result<DynValue> {
+        let value = match self.input_types.get(name) {
+            Some(TensorElementType::Int32) => {
+                Tensor::from_array(array.mapv(|v| v as i32))?.into_dyn()
+            }
+            Some(TensorElementType::Float32) => {
+                Tensor::from_array(array.mapv(|v| v as f32))?.into_dyn()
+            }
+            Some(TensorElementType::Float16) => {
+                Tensor::from_array(array.mapv(|v| half::f16::from_f32(v as f32)))?.into_dyn()
+            }
+            _ => Tensor::from_array(array)?.into_dyn(),
+        };
+        Ok(value)
+    }
+
+    /// Inputs of the model, skipping the optional inputs it does not have
+    fn inputs(
+        &self,
+        input_ids: Array2<i64>,
+        type_ids: Array2<i64>,
+        attention_mask: Array2<i64>,
+    ) -> Result<Vec<(String, DynValue)>, BackendError> {
+        let mut inputs = vec![(
+            self.io.input_ids.clone(),
+            self.input(&self.io.input_ids, input_ids).e()?,
+        )];
+        if let Some(name) = &self.io.attention_mask {
+            inputs.push((name.clone(), self.input(name, attention_mask).e()?));
+        }
+        if let Some(name) = &self.io.token_type_ids {
+            inputs.push((name.clone(), self.input(name, type_ids).e()?));
+        }
+        Ok(inputs)
+    }
+}
+
+/// File next to an optimized model that describes the model it was optimized from
+fn source_path(optimized_model_path: &Path) -> PathBuf {
+    let mut path = optimized_model_path.to_path_buf().into_os_string();
+    path.push(".source.json");
+    PathBuf::from(path)
+}
+
+/// Export, dtype and optimization level an optimized model is built from
+fn optimized_model_source(
+    export_path: &Path,
+    dtype: &str,
+    session_config: &OrtSessionConfig,
+) -> Result<serde_json::Value, BackendError> {
+    let metadata = std::fs::metadata(export_path).map_err(|err| {
+        BackendError::Start(format!("Could not read {}: {err}", export_path.display()))
+    })?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos().to_string());
+    Ok(serde_json::json!({
+        "path": export_path.to_string_lossy(),
+        "size": metadata.len(),
+        "modified": modified,
+        "dtype": dtype,
+        "optimization_level": format!("{:?}", session_config.optimization_level),
+    }))
+}
+
+fn read_source(optimized_model_path: &Path) -> Option<serde_json::Value> {
+    let source = std::fs::read_to_string(source_path(optimized_model_path)).ok()?;
+    serde_json::from_str(&source).ok()
+}
+
+/// Whether an output of `dimensions` is a BGE-M3 `sparse_linear` head of shape
+/// `[batch_size, sequence_length, 1]`. Dynamic dimensions are `-1`.
+fn is_sparse_head(dimensions: &[i64]) -> bool {
+    matches!(dimensions, [_, _, 1])
+}
+
+/// Extract a float output, converting half precision outputs to `f32`
+fn extract_f32(value: &DynValue) -> Result<ArrayD<f32>, BackendError> {
+    match value.dtype().tensor_type() {
+        Some(TensorElementType::Float16) => Ok(value
+            .try_extract_tensor::<half::f16>()
+            .e()?
+            .mapv(|v| v.to_f32())),
+        _ => Ok(value.try_extract_tensor::<f32>().e()?.into_owned()),
+    }
+}
+
+fn optimization_level(level: OrtOptimizationLevel) -> GraphOptimizationLevel {
+    match level {
+        OrtOptimizationLevel::Disable => GraphOptimizationLevel::Disable,
+        OrtOptimizationLevel::Basic => GraphOptimizationLevel::Level1,
+        OrtOptimizationLevel::Extended => GraphOptimizationLevel::Level2,
+        OrtOptimizationLevel::All => GraphOptimizationLevel::Level3,
+    }
 }
 
 impl Backend for OrtBackend {
     fn max_batch_size(&self) -> Option<usize> {
-        Some(8)
+        Some(self.session_config.max_batch_size)
     }
 
     fn health(&self) -> Result<(), BackendError> {
@@ -92,6 +367,18 @@ impl Backend for OrtBackend {
         true
     }
 
+    fn supports_multivector(&self) -> bool {
+        self.has_colbert_head
+    }
+
+    fn supports_lexical_weights(&self) -> bool {
+        self.has_sparse_head
+    }
+
+    fn ort_session_config(&self) -> Option<OrtSessionConfig> {
+        Some(self.session_config.clone())
+    }
+
     fn embed(&self, batch: Batch) -> Result<Embeddings, BackendError> {
         let batch_size = batch.len();
         let max_length = batch.max_length as usize;
@@ -139,8 +426,8 @@ impl Backend for OrtBackend {
                 let attention_mask = vec![1_i64; elems];
 
                 (
-                    batch.input_ids.into_iter().map(|v| v as i64).collect(),
-                    batch.token_type_ids.into_iter().map(|v| v as i64).collect(),
+                    batch.input_ids.iter().map(|v| *v as i64).collect(),
+                    batch.token_type_ids.iter().map(|v| *v as i64).collect(),
                     vec![batch.max_length as f32],
                     attention_mask,
                 )
@@ -153,54 +440,85 @@ impl Backend for OrtBackend {
             ndarray::Array2::from_shape_vec((batch_size, max_length), attention_mask).e()?;
         let input_lengths = ndarray::Array1::from_vec(input_lengths);
 
+        let type_ids = ndarray::Array2::from_shape_vec((batch_size, max_length), type_ids).e()?;
+
         // Create onnx inputs
-        let inputs = match self.type_id_name.as_ref() {
-            Some(type_id_name) => {
-                // Add type ids to inputs
-                let type_ids =
-                    ndarray::Array2::from_shape_vec((batch_size, max_length), type_ids).e()?;
-                ort::inputs!["input_ids" => input_ids, "attention_mask" => attention_mask.clone(), type_id_name => type_ids].e()?
-            }
-            None => {
-                ort::inputs!["input_ids" => input_ids, "attention_mask" => attention_mask.clone()]
-                    .e()?
-            }
-        };
+        let inputs = self.inputs(input_ids, type_ids, attention_mask.clone())?;
+
+        // Only compute the sparse head when a member of the batch needs lexical weights
+        let sparse_head = self.io.logits.as_ref().filter(|_| self.has_sparse_head);
+        let mut output_selector = OutputSelector::default();
+        if let Some(name) = sparse_head.filter(|_| batch.lexical_indices.is_empty()) {
+            output_selector = output_selector.without(name);
+        }
+        let run_options = RunOptions::new().e()?.with_outputs(output_selector);
 
         // Run model
-        let outputs = self.session.run(inputs).e()?;
+        let outputs = self.session.run_with_options(inputs, &run_options).e()?;
 
-        let logits = outputs
-            .get("logits")
-            .and_then(|logits_value| logits_value.try_extract_tensor::<f32>().ok())
-            .ok_or_else(|| anyhow::anyhow!("Failed to extract logits tensor"))
-            .unwrap();
-
-        let tokens = batch.tokens;
-        tracing::info!("tokens: {:#?}", tokens);
-        let token_weights = logits.mapv(|x| x.max(0.0));
-        let token_weights = token_weights.index_axis(ndarray::Axis(token_weights.ndim() - 1), 0);
-        tracing::info!("token weights: {:#?}", token_weights);
-        let mut token_weight_vec: Vec<(String, f32)> = Vec::new();
-        for (weight, token) in token_weights.iter().zip(tokens.iter()) {
-            if !UNUSED_TOKENS.contains(&token.as_str()) && *weight > 0.0 {
-                token_weight_vec.push((token.clone(), *weight));
+        // Lexical weights from the BGE-M3 `sparse_linear` head: [batch_size, max_length, 1]
+        let token_weights = match sparse_head.filter(|_| !batch.lexical_indices.is_empty()) {
+            Some(name) => {
+                let logits = outputs
+                    .get(name.as_str())
+                    .ok_or(BackendError::Inference(format!(
+                        "Model does not have a `{name}` output"
+                    )))
+                    .and_then(extract_f32)?;
+                let token_weights = logits
+                    .index_axis(Axis(logits.ndim() - 1), 0)
+                    .mapv(|x| x.max(0.0))
+                    .into_dimensionality::<Ix2>()
+                    .e()?;
+                Some(token_weights)
             }
-        }
+            None => None,
+        };
 
-        tracing::info!("sparse token weights: {:#?}", token_weight_vec);
+        // Per-token vectors from the BGE-M3 `colbert_linear` head
+        let colbert_vecs = match self.io.colbert_vecs.as_ref() {
+            Some(name) if !batch.multivector_indices.is_empty() => {
+                let colbert_vecs = outputs
+                    .get(name.as_str())
+                    .ok_or(BackendError::Inference(format!(
+                        "Model does not have a `{name}` output"
+                    )))
+                    .and_then(extract_f32)?
+                    .into_dimensionality::<Ix3>()
+                    .e()?;
+                Some(colbert_vecs)
+            }
+            _ => None,
+        };
 
-        // Get last_hidden_state ndarray
-        let outputs = outputs
-            .get("last_hidden_state")
-            .or(outputs.get("token_embeddings"))
-            .ok_or(BackendError::Inference(format!(
-                "Unknown output keys: {:?}",
-                self.session.outputs
-            )))?
-            .try_extract_tensor::<f32>()
-            .e()?
-            .to_owned();
+        // Embeddings already pooled by the model: [batch_size, hidden_size]
+        let sentence_embeddings = match &self.io.sentence_embedding {
+            Some(name) => {
+                let sentence_embeddings = outputs
+                    .get(name.as_str())
+                    .ok_or(BackendError::Inference(format!(
+                        "Model does not have a `{name}` output"
+                    )))
+                    .and_then(extract_f32)?
+                    .into_dimensionality::<Ix2>()
+                    .e()?;
+                Some(sentence_embeddings)
+            }
+            None => None,
+        };
+
+        // Get last_hidden_state ndarray. SPLADE pools the masked language modeling logits.
+        let outputs = match &self.io.token_embeddings {
+            Some(name) => Some(
+                outputs
+                    .get(name.as_str())
+                    .ok_or(BackendError::Inference(format!(
+                        "Model does not have a `{name}` output"
+                    )))
+                    .and_then(extract_f32)?,
+            ),
+            None => None,
+        };
 
         // Final embeddings struct
         let mut embeddings =
@@ -209,11 +527,28 @@ impl Backend for OrtBackend {
         let has_pooling_requests = !batch.pooled_indices.is_empty();
         let has_raw_requests = !batch.raw_indices.is_empty();
 
-        if has_pooling_requests {
-            let mut outputs = outputs.clone();
+        if let (true, Some(sentence_embeddings)) = (has_pooling_requests, &sentence_embeddings) {
+            for i in batch.pooled_indices.iter() {
+                let i = *i as usize;
+                let lexical_weights = match &token_weights {
+                    Some(token_weights) if batch.lexical_indices.contains(&(i as u32)) => {
+                        lexical_weights(&batch, i, token_weights.row(i), attention_mask.row(i))
+                    }
+                    _ => Vec::new(),
+                };
+                embeddings.insert(
+                    i,
+                    Embedding::Pooled(sentence_embeddings.row(i).to_vec(), lexical_weights),
+                );
+            }
+        } else if has_pooling_requests {
+            let mut outputs = outputs.clone().ok_or(BackendError::Inference(
+                "Model does not have a token embedding output".to_string(),
+            ))?;
 
-            // Only use pooled_indices if at least one member of the batch ask for raw embeddings
-            let indices = if has_raw_requests {
+            // Only use pooled_indices if at least one member of the batch ask for raw or
+            // multi-vector embeddings
+            let indices = if batch.pooled_indices.len() < batch_size {
                 let indices: Vec<usize> =
                     batch.pooled_indices.iter().map(|v| *v as usize).collect();
 
@@ -227,12 +562,10 @@ impl Backend for OrtBackend {
             let pooled_embeddings = match self.pool {
                 // CLS pooling
                 Pool::Cls => outputs.slice(s![.., 0, ..]).into_owned().into_dyn(),
-                // Last token pooling is not supported for this model
-                Pool::LastToken => unreachable!(),
                 // Mean pooling
                 Pool::Mean => {
                     if masking {
-                        let mut attention_mask = attention_mask;
+                        let mut attention_mask = attention_mask.clone();
                         let mut input_lengths = input_lengths;
 
                         if let Some(indices) = indices {
@@ -253,50 +586,75 @@ impl Backend for OrtBackend {
                         outputs.mean_axis(Axis(1)).unwrap()
                     }
                 }
-                Pool::Splade => unreachable!(),
+                // LastToken, Max, WeightedMean, MeanSqrtLen and SPLADE pooling
+                Pool::LastToken
+                | Pool::Max
+                | Pool::WeightedMean
+                | Pool::MeanSqrtLen
+                | Pool::Splade => {
+                    let mut attention_mask = attention_mask.clone();
+                    let mut input_lengths = input_lengths;
+
+                    if let Some(indices) = indices {
+                        // Select values in the batch
+                        attention_mask = attention_mask.select(Axis(0), &indices);
+                        input_lengths = input_lengths.select(Axis(0), &indices);
+                    };
+
+                    pool_tokens(&self.pool, outputs, attention_mask, input_lengths)
+                }
             };
+            let pooled_embeddings = st_modules::forward(
+                &self.st_modules,
+                pooled_embeddings.into_dimensionality::<Ix2>().e()?,
+            );
 
-            for (i, e) in batch
-                .pooled_indices
-                .into_iter()
-                .zip(pooled_embeddings.rows())
-            {
-                embeddings.insert(
-                    i as usize,
-                    Embedding::Pooled(e.to_vec(), token_weight_vec.clone()),
-                );
+            for (i, e) in batch.pooled_indices.iter().zip(pooled_embeddings.rows()) {
+                let i = *i as usize;
+                let lexical_weights = match &token_weights {
+                    Some(token_weights) if batch.lexical_indices.contains(&(i as u32)) => {
+                        lexical_weights(&batch, i, token_weights.row(i), attention_mask.row(i))
+                    }
+                    _ => Vec::new(),
+                };
+                embeddings.insert(i, Embedding::Pooled(e.to_vec(), lexical_weights));
             }
         };
 
         if has_raw_requests {
+            let outputs = outputs.ok_or(BackendError::Inference(
+                "Raw embeddings require a token embedding output".to_string(),
+            ))?;
+
             // Reshape outputs
             let s = outputs.shape().to_vec();
             #[allow(deprecated)]
             let outputs = outputs.into_shape((s[0] * s[1], s[2])).e()?;
 
             // We need to remove the padding tokens only if batch_size > 1 and there are some
-            // member of the batch that require pooling
+            // member of the batch that do not require raw embeddings
             // or if batch_size > 1 and the members of the batch have different lengths
-            let raw_embeddings = if (masking || has_pooling_requests) && batch_size > 1 {
-                let mut final_indices: Vec<usize> = Vec::with_capacity(batch_size * max_length);
-
-                for i in batch.raw_indices.iter() {
-                    let start = i * batch.max_length;
-                    let i = *i as usize;
-                    let length =
-                        batch.cumulative_seq_lengths[i + 1] - batch.cumulative_seq_lengths[i];
-
-                    for j in start..start + length {
-                        // Add indices for the tokens of this specific member of the batch
-                        final_indices.push(j as usize);
+            let raw_embeddings =
+                if (masking || batch.raw_indices.len() < batch_size) && batch_size > 1 {
+                    let mut final_indices: Vec<usize> = Vec::with_capacity(batch_size * max_length);
+
+                    for i in batch.raw_indices.iter() {
+                        let start = i * batch.max_length;
+                        let i = *i as usize;
+                        let length =
+                            batch.cumulative_seq_lengths[i + 1] - batch.cumulative_seq_lengths[i];
+
+                        for j in start..start + length {
+                            // Add indices for the tokens of this specific member of the batch
+                            final_indices.push(j as usize);
+                        }
                     }
-                }
 
-                // Select the tokens with final indices
-                outputs.select(Axis(0), &final_indices)
-            } else {
-                outputs
-            };
+                    // Select the tokens with final indices
+                    outputs.select(Axis(0), &final_indices)
+                } else {
+                    outputs
+                };
 
             // Used for indexing in the raw_embeddings tensor
             let input_lengths: Vec<usize> = (0..batch_size)
@@ -306,7 +664,8 @@ impl Backend for OrtBackend {
                 .collect();
 
             let mut cumulative_length = 0;
-            for i in batch.raw_indices.into_iter() {
+            for i in batch.raw_indices.iter() {
+                let i = *i;
                 let length = input_lengths[i as usize];
                 let e = raw_embeddings.slice(s![cumulative_length..cumulative_length + length, ..]);
                 let e = e.rows().into_iter().map(|v| v.to_vec()).collect();
@@ -316,6 +675,40 @@ impl Backend for OrtBackend {
             }
         }
 
+        if let Some(colbert_vecs) = colbert_vecs {
+            // Some exports already drop the leading CLS token from the `colbert_vecs` output
+            let offset = match max_length.checked_sub(colbert_vecs.len_of(Axis(1))) {
+                Some(offset @ (0 | 1)) => offset,
+                _ => {
+                    return Err(BackendError::Inference(format!(
+                        "Unexpected `colbert_vecs` shape: {:?}",
+                        colbert_vecs.shape()
+                    )))
+                }
+            };
+
+            for i in batch.multivector_indices.iter() {
+                let i = *i as usize;
+                let length = (batch.cumulative_seq_lengths[i + 1] - batch.cumulative_seq_lengths[i])
+                    as usize;
+
+                // Skip the leading and trailing special tokens
+                let e = (1..length.saturating_sub(1))
+                    .map(|j| normalize(colbert_vecs.slice(s![i, j - offset, ..])))
+                    .collect();
+
+                let embedding = match embeddings.remove(&i) {
+                    Some(Embedding::Pooled(pooled, token_weights)) => Embedding::Hybrid {
+                        pooled,
+                        token_weights,
+                        multivector: e,
+                    },
+                    _ => Embedding::MultiVector(e),
+                };
+                embeddings.insert(i, embedding);
+            }
+        }
+
         Ok(embeddings)
     }
 
@@ -371,27 +764,23 @@ impl Backend for OrtBackend {
         let attention_mask =
             ndarray::Array2::from_shape_vec((batch_size, max_length), attention_mask).e()?;
 
+        let type_ids = ndarray::Array2::from_shape_vec((batch_size, max_length), type_ids).e()?;
+
         // Create onnx inputs
-        let inputs = match self.type_id_name.as_ref() {
-            Some(type_id_name) => {
-                // Add type ids to inputs
-                let type_ids =
-                    ndarray::Array2::from_shape_vec((batch_size, max_length), type_ids).e()?;
-                ort::inputs!["input_ids" => input_ids, "attention_mask" => attention_mask.clone(), type_id_name => type_ids].e()?
-            }
-            None => {
-                ort::inputs!["input_ids" => input_ids, "attention_mask" => attention_mask.clone()]
-                    .e()?
-            }
-        };
+        let inputs = self.inputs(input_ids, type_ids, attention_mask.clone())?;
 
         // Run model
         let outputs = self.session.run(inputs).e()?;
-        // Get last_hidden_state ndarray
-        let outputs = outputs["logits"]
-            .try_extract_tensor::<f32>()
-            .e()?
-            .to_owned();
+        // Get logits ndarray
+        let name = self.io.logits.as_ref().ok_or(BackendError::Inference(
+            "Model does not have a `logits` output".to_string(),
+        ))?;
+        let outputs = outputs
+            .get(name.as_str())
+            .ok_or(BackendError::Inference(format!(
+                "Model does not have a `{name}` output"
+            )))
+            .and_then(extract_f32)?;
 
         let mut predictions =
             HashMap::with_capacity_and_hasher(batch_size, BuildNoHashHasher::default());
@@ -403,6 +792,113 @@ impl Backend for OrtBackend {
     }
 }
 
+/// Max, WeightedMean or MeanSqrtLen pooling of padded [batch_size, max_length, hidden_size]
+/// embeddings
+fn pool_tokens(
+    pool: &Pool,
+    outputs: ArrayD<f32>,
+    attention_mask: Array2<i64>,
+    input_lengths: Array1<f32>,
+) -> ArrayD<f32> {
+    if *pool == Pool::LastToken {
+        // Last attended position, whatever the padding side
+        let last_positions: Vec<usize> = attention_mask
+            .rows()
+            .into_iter()
+            .map(|mask| mask.iter().rposition(|m| *m != 0).unwrap_or(0))
+            .collect();
+        let hidden_size = outputs.shape()[2];
+        return Array2::from_shape_fn((last_positions.len(), hidden_size), |(i, j)| {
+            outputs[[i, last_positions[i], j]]
+        })
+        .into_dyn();
+    }
+
+    // [batch_size, max_length, 1]
+    let attention_mask = attention_mask.mapv(|x| x as f32).insert_axis(Axis(2));
+
+    match pool {
+        Pool::Max => {
+            // Padded values can never be the maximum
+            let padding_bias = attention_mask.mapv(|x| (x - 1.0) * 1e9);
+            (outputs + padding_bias).fold_axis(Axis(1), f32::NEG_INFINITY, |a, b| a.max(*b))
+        }
+        Pool::MeanSqrtLen => outputs
+            .mul(attention_mask)
+            .sum_axis(Axis(1))
+            .div(input_lengths.mapv(f32::sqrt).insert_axis(Axis(1))),
+        Pool::WeightedMean => {
+            // Attended token `i` has weight `i + 1`, whatever the padding side
+            let mut weights = attention_mask.clone();
+            weights.accumulate_axis_inplace(Axis(1), |previous, weight| *weight += *previous);
+            let weights = weights * attention_mask;
+            let total = weights.sum_axis(Axis(1));
+
+            outputs.mul(weights).sum_axis(Axis(1)).div(total)
+        }
+        Pool::Splade => {
+            // Max of log(1 + relu(logits)) over the attended positions
+            let relu_log = outputs.mapv(|x| x.max(0.0).ln_1p());
+            relu_log
+                .mul(attention_mask)
+                .fold_axis(Axis(1), 0.0, |a, b| a.max(*b))
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Lexical weights of the `i`-th member of the batch.
+/// Padding is skipped using the attention mask and duplicate tokens keep their maximum weight.
+fn lexical_weights(
+    batch: &Batch,
+    i: usize,
+    token_weights: ArrayView1<f32>,
+    attention_mask: ArrayView1<i64>,
+) -> Vec<TokenWeight> {
+    let start = batch.cumulative_seq_lengths[i] as usize;
+    let end = batch.cumulative_seq_lengths[i + 1] as usize;
+
+    let mut lexical_weights: Vec<TokenWeight> = Vec::new();
+    // Position of each vocab id in `lexical_weights`
+    let mut positions: HashMap<u32, usize, BuildNoHashHasher<u32>> = HashMap::default();
+
+    for j in 0..end - start {
+        let weight = token_weights[j];
+        if attention_mask[j] == 0 || weight <= 0.0 {
+            continue;
+        }
+
+        let token = &batch.tokens[start + j];
+        if UNUSED_TOKENS.contains(&token.as_str()) {
+            continue;
+        }
+
+        let id = batch.input_ids[start + j];
+        match positions.get(&id) {
+            Some(&position) => {
+                let entry = &mut lexical_weights[position];
+                entry.weight = entry.weight.max(weight);
+            }
+            None => {
+                positions.insert(id, lexical_weights.len());
+                lexical_weights.push(TokenWeight {
+                    id,
+                    token: token.clone(),
+                    weight,
+                });
+            }
+        }
+    }
+
+    lexical_weights
+}
+
+/// L2-normalize a single vector
+fn normalize(v: ArrayView1<f32>) -> Vec<f32> {
+    let norm = v.dot(&v).sqrt().max(f32::EPSILON);
+    v.iter().map(|x| x / norm).collect()
+}
+
 pub trait WrapErr<O> {
     fn s(self) -> Result<O, BackendError>;
     fn e(self) -> Result<O, BackendError>;
@@ -425,3 +921,214 @@ impl<O> WrapErr<O> for Result<O, ndarray::ShapeError> {
         self.map_err(|e| BackendError::Inference(e.to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        is_sparse_head, lexical_weights, optimized_model_source, pool_tokens, read_source,
+        source_path,
+    };
+    use ndarray::{array, Array1, Array2, Array3};
+    use std::path::Path;
+    use text_embeddings_backend_core::{
+        Batch, OrtOptimizationLevel, OrtSessionConfig, Pool, TokenWeight,
+    };
+
+    /// Batch of right-padded sequences of `(id, token)`
+    fn batch(sequences: &[&[(u32, &str)]]) -> Batch {
+        let mut cumulative_seq_lengths = vec![0];
+        let mut input_ids = Vec::new();
+        let mut tokens = Vec::new();
+        for sequence in sequences {
+            for (id, token) in sequence.iter() {
+                input_ids.push(*id);
+                tokens.push(token.to_string());
+            }
+            cumulative_seq_lengths.push(input_ids.len() as u32);
+        }
+        let max_length = sequences.iter().map(|s| s.len()).max().unwrap() as u32;
+
+        Batch {
+            token_type_ids: vec![0; input_ids.len()],
+            position_ids: vec![0; input_ids.len()],
+            input_ids,
+            tokens,
+            cumulative_seq_lengths,
+            max_length,
+            pooled_indices: vec![],
+            raw_indices: vec![],
+            multivector_indices: vec![],
+            lexical_indices: vec![],
+        }
+    }
+
+    fn weight(id: u32, token: &str, weight: f32) -> TokenWeight {
+        TokenWeight {
+            id,
+            token: token.to_string(),
+            weight,
+        }
+    }
+
+    #[test]
+    fn test_lexical_weights() {
+        let batch = batch(&[
+            &[(0, "<s>"), (10, "▁hello"), (11, "▁world"), (2, "</s>")],
+            &[(0, "<s>"), (12, "▁hi"), (2, "</s>")],
+        ]);
+        let token_weights = array![[0.5, 0.25, 0.0, 0.5], [0.5, 0.125, 0.5, 0.75]];
+        let attention_mask = array![[1, 1, 1, 1], [1, 1, 1, 0]];
+
+        // Special tokens and null weights are skipped
+        assert_eq!(
+            lexical_weights(&batch, 0, token_weights.row(0), attention_mask.row(0)),
+            vec![weight(10, "▁hello", 0.25)]
+        );
+        // The weight of the padding is ignored
+        assert_eq!(
+            lexical_weights(&batch, 1, token_weights.row(1), attention_mask.row(1)),
+            vec![weight(12, "▁hi", 0.125)]
+        );
+    }
+
+    #[test]
+    fn test_lexical_weights_duplicates() {
+        let batch = batch(&[&[
+            (10, "▁hello"),
+            (11, "▁world"),
+            (10, "▁hello"),
+            (3, "<unk>"),
+            (10, "▁hello"),
+        ]]);
+        let token_weights = array![[0.25, -1.0, 0.75, 1.0, 0.5]];
+        let attention_mask = array![[1, 1, 1, 1, 1]];
+
+        // Duplicates keep their first position and their maximum weight
+        assert_eq!(
+            lexical_weights(&batch, 0, token_weights.row(0), attention_mask.row(0)),
+            vec![weight(10, "▁hello", 0.75)]
+        );
+    }
+
+    #[test]
+    fn test_is_sparse_head() {
+        assert!(is_sparse_head(&[-1, -1, 1]));
+        assert!(is_sparse_head(&[8, 512, 1]));
+        // Classification and masked language modeling logits
+        assert!(!is_sparse_head(&[-1, 2]));
+        assert!(!is_sparse_head(&[-1, -1, 30522]));
+        assert!(!is_sparse_head(&[-1, -1, -1]));
+    }
+
+    #[test]
+    fn test_optimized_model_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let export_path = dir.path().join("model.onnx");
+        let optimized_path = dir.path().join("optimized.onnx");
+        std::fs::write(&export_path, "model").unwrap();
+        assert_eq!(
+            source_path(&optimized_path),
+            Path::new(&dir.path().join("optimized.onnx.source.json"))
+        );
+
+        let config = OrtSessionConfig::default();
+        let source = optimized_model_source(&export_path, "float32", &config).unwrap();
+        assert!(read_source(&optimized_path).is_none());
+        std::fs::write(source_path(&optimized_path), source.to_string()).unwrap();
+        assert_eq!(read_source(&optimized_path), Some(source.clone()));
+
+        // Another dtype, optimization level or export
+        assert_ne!(
+            optimized_model_source(&export_path, "float16", &config).unwrap(),
+            source
+        );
+        let basic = OrtSessionConfig {
+            optimization_level: OrtOptimizationLevel::Basic,
+            ..config.clone()
+        };
+        assert_ne!(
+            optimized_model_source(&export_path, "float32", &basic).unwrap(),
+            source
+        );
+        std::fs::write(&export_path, "another model").unwrap();
+        assert_ne!(
+            optimized_model_source(&export_path, "float32", &config).unwrap(),
+            source
+        );
+        assert!(
+            optimized_model_source(&dir.path().join("missing.onnx"), "float32", &config).is_err()
+        );
+    }
+
+    /// Pool the hidden states of "a" with 3 tokens and "b" with 2 tokens, padded on the right
+    /// or on the left
+    fn pool(pool: Pool, left_padding: bool) -> Vec<Vec<f32>> {
+        let a = [[1.0, 2.0], [3.0, -4.0], [5.0, 0.0]];
+        let b = [[2.0, 2.0], [-2.0, 6.0]];
+        let padding = [100.0, 100.0];
+        let (b, mask_b) = if left_padding {
+            ([padding, b[0], b[1]], [0, 1, 1])
+        } else {
+            ([b[0], b[1], padding], [1, 1, 0])
+        };
+
+        let outputs = Array3::from_shape_vec((2, 3, 2), [a, b].concat().concat()).unwrap();
+        let attention_mask = Array2::from_shape_vec((2, 3), [[1, 1, 1], mask_b].concat()).unwrap();
+        let input_lengths = Array1::from_vec(vec![3.0, 2.0]);
+
+        pool_tokens(&pool, outputs.into_dyn(), attention_mask, input_lengths)
+            .outer_iter()
+            .map(|row| row.iter().copied().collect())
+            .collect()
+    }
+
+    fn assert_pooled(pool_method: Pool, expected: [[f32; 2]; 2]) {
+        for left_padding in [false, true] {
+            let pooled = pool(pool_method.clone(), left_padding);
+            for (row, expected) in pooled.iter().zip(expected) {
+                for (v, e) in row.iter().zip(expected) {
+                    assert!(
+                        (v - e).abs() < 1e-6,
+                        "{pool_method} (left padding: {left_padding}): {pooled:?} != {expected:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pool_last_token() {
+        assert_pooled(Pool::LastToken, [[5.0, 0.0], [-2.0, 6.0]]);
+    }
+
+    #[test]
+    fn test_pool_max() {
+        assert_pooled(Pool::Max, [[5.0, 2.0], [2.0, 6.0]]);
+    }
+
+    #[test]
+    fn test_pool_weighted_mean() {
+        // Weights 1, 2, 3 and 1, 2
+        assert_pooled(
+            Pool::WeightedMean,
+            [[22.0 / 6.0, -6.0 / 6.0], [-2.0 / 3.0, 14.0 / 3.0]],
+        );
+    }
+
+    #[test]
+    fn test_pool_mean_sqrt_len() {
+        let (sqrt_3, sqrt_2) = (3f32.sqrt(), 2f32.sqrt());
+        assert_pooled(
+            Pool::MeanSqrtLen,
+            [[9.0 / sqrt_3, -2.0 / sqrt_3], [0.0, 8.0 / sqrt_2]],
+        );
+    }
+
+    #[test]
+    fn test_pool_splade() {
+        assert_pooled(
+            Pool::Splade,
+            [[6f32.ln(), 3f32.ln()], [3f32.ln(), 7f32.ln()]],
+        );
+    }
+}
@@ -0,0 +1,143 @@
+/// Sentence Transformers modules applied to the pooled embeddings
+use ndarray::{Array1, Array2, Axis};
+use safetensors::{Dtype, SafeTensors};
+use text_embeddings_backend_core::{BackendError, DenseActivation, DenseModule, StModule};
+
+pub(crate) enum Module {
+    Dense {
+        /// [out_features, in_features]
+        weight: Array2<f32>,
+        bias: Option<Array1<f32>>,
+        activation: DenseActivation,
+    },
+    Normalize,
+}
+
+pub(crate) fn load(modules: Vec<StModule>) -> Result<Vec<Module>, BackendError> {
+    modules
+        .into_iter()
+        .map(|module| match module {
+            StModule::Dense(dense) => load_dense(dense),
+            StModule::Normalize => Ok(Module::Normalize),
+        })
+        .collect()
+}
+
+fn load_dense(dense: DenseModule) -> Result<Module, BackendError> {
+    if dense.weights_path.extension().and_then(|e| e.to_str()) != Some("safetensors") {
+        return Err(BackendError::Start(format!(
+            "Dense weights must be stored as safetensors for this backend, found `{}`",
+            dense.weights_path.display()
+        )));
+    }
+    let buffer = std::fs::read(&dense.weights_path).map_err(|err| {
+        BackendError::Start(format!(
+            "Failed to read `{}`: {err}",
+            dense.weights_path.display()
+        ))
+    })?;
+    let tensors = SafeTensors::deserialize(&buffer).map_err(|err| {
+        BackendError::Start(format!(
+            "Failed to parse `{}`: {err}",
+            dense.weights_path.display()
+        ))
+    })?;
+
+    let weight = Array2::from_shape_vec(
+        (dense.out_features, dense.in_features),
+        read_tensor(&tensors, "linear.weight")?,
+    )
+    .map_err(|err| BackendError::Start(format!("Unexpected Dense weight shape: {err}")))?;
+    let bias = if dense.bias {
+        let bias = read_tensor(&tensors, "linear.bias")?;
+        if bias.len() != dense.out_features {
+            return Err(BackendError::Start(format!(
+                "Dense bias has {} values but `out_features` is {}",
+                bias.len(),
+                dense.out_features
+            )));
+        }
+        Some(Array1::from_vec(bias))
+    } else {
+        None
+    };
+
+    Ok(Module::Dense {
+        weight,
+        bias,
+        activation: dense.activation,
+    })
+}
+
+fn read_tensor(tensors: &SafeTensors, name: &str) -> Result<Vec<f32>, BackendError> {
+    let tensor = tensors
+        .tensor(name)
+        .map_err(|err| BackendError::Start(format!("Failed to load `{name}`: {err}")))?;
+    let data = tensor.data();
+    match tensor.dtype() {
+        Dtype::F32 => Ok(data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()),
+        Dtype::F16 => Ok(data
+            .chunks_exact(2)
+            .map(|b| half::f16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect()),
+        Dtype::BF16 => Ok(data
+            .chunks_exact(2)
+            .map(|b| half::bf16::from_le_bytes([b[0], b[1]]).to_f32())
+            .collect()),
+        dtype => Err(BackendError::Start(format!(
+            "`{name}` has unsupported dtype {dtype:?}"
+        ))),
+    }
+}
+
+/// Apply the modules to a [batch_size, hidden_size] array of pooled embeddings
+pub(crate) fn forward(modules: &[Module], mut embeddings: Array2<f32>) -> Array2<f32> {
+    for module in modules {
+        embeddings = match module {
+            Module::Dense {
+                weight,
+                bias,
+                activation,
+            } => {
+                let mut embeddings = embeddings.dot(&weight.t());
+                if let Some(bias) = bias {
+                    embeddings += bias;
+                }
+                embeddings.mapv_into(|x| activate(*activation, x))
+            }
+            Module::Normalize => {
+                for mut row in embeddings.axis_iter_mut(Axis(0)) {
+                    // Same epsilon as `torch.nn.functional.normalize`
+                    let norm = row.dot(&row).sqrt().max(1e-12);
+                    row /= norm;
+                }
+                embeddings
+            }
+        };
+    }
+    embeddings
+}
+
+fn activate(activation: DenseActivation, x: f32) -> f32 {
+    match activation {
+        DenseActivation::Identity => x,
+        DenseActivation::Tanh => x.tanh(),
+        DenseActivation::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+        DenseActivation::Relu => x.max(0.0),
+        DenseActivation::Gelu => 0.5 * x * (1.0 + erf(x / std::f32::consts::SQRT_2)),
+    }
+}
+
+/// Abramowitz and Stegun 7.1.26 approximation, accurate to 1.5e-7
+fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let y = 1.0
+        - (((((1.061_405_4 * t - 1.453_152_1) * t) + 1.421_413_8) * t - 0.284_496_72) * t
+            + 0.254_829_6)
+            * t
+            * (-x * x).exp();
+    y.copysign(x)
+}
@@ -78,6 +78,11 @@ impl Backend for PythonBackend {
                 "raw embeddings are not supported for the Python backend.".to_string(),
             ));
         }
+        if !batch.multivector_indices.is_empty() {
+            return Err(BackendError::Inference(
+                "multi-vector embeddings are not supported for the Python backend.".to_string(),
+            ));
+        }
         let batch_size = batch.len();
 
         let results = self
@@ -95,7 +100,7 @@ impl Backend for PythonBackend {
         let mut embeddings =
             HashMap::with_capacity_and_hasher(batch_size, BuildNoHashHasher::default());
         for (i, e) in pooled_embeddings.into_iter().enumerate() {
-            embeddings.insert(i, Embedding::Pooled(e));
+            embeddings.insert(i, Embedding::Pooled(e, vec![]));
         }
 
         Ok(embeddings)
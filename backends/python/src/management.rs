@@ -36,6 +36,9 @@ impl BackendProcess {
             Pool::Cls => "cls",
             Pool::Mean => "mean",
             Pool::LastToken => "lasttoken",
+            Pool::Max => "max",
+            Pool::WeightedMean => "weightedmean",
+            Pool::MeanSqrtLen => "mean_sqrt_len_tokens",
             Pool::Splade => {
                 return Err(BackendError::Start(format!("{pool:?} is not supported")));
             }
@@ -9,6 +9,7 @@ pub enum DType {
     // Float16 is not available on accelerate
     #[cfg(any(
         feature = "python",
+        feature = "ort",
         all(feature = "candle", not(feature = "accelerate"))
     ))]
     Float16,
@@ -16,6 +17,9 @@ pub enum DType {
     Float32,
     #[cfg(feature = "python")]
     Bfloat16,
+    // Dynamically quantized ONNX models
+    #[cfg(feature = "ort")]
+    Int8,
 }
 
 impl fmt::Display for DType {
@@ -24,6 +28,7 @@ impl fmt::Display for DType {
             // Float16 is not available on accelerate
             #[cfg(any(
                 feature = "python",
+                feature = "ort",
                 all(feature = "candle", not(feature = "accelerate"))
             ))]
             DType::Float16 => write!(f, "float16"),
@@ -31,6 +36,8 @@ impl fmt::Display for DType {
             DType::Float32 => write!(f, "float32"),
             #[cfg(feature = "python")]
             DType::Bfloat16 => write!(f, "bfloat16"),
+            #[cfg(feature = "ort")]
+            DType::Int8 => write!(f, "int8"),
         }
     }
 }
@@ -15,7 +15,8 @@ use tracing::{instrument, Span};
 
 pub use crate::dtype::DType;
 pub use text_embeddings_backend_core::{
-    BackendError, Batch, Embedding, Embeddings, ModelType, Pool,
+    load_st_modules, st_modules_dimensions, BackendError, Batch, Embedding, Embeddings, ModelType,
+    OrtExecutionMode, OrtOptimizationLevel, OrtSessionConfig, Pool, StModule, TokenWeight,
 };
 
 #[cfg(feature = "candle")]
@@ -57,16 +58,22 @@ pub struct Backend {
     health_receiver: watch::Receiver<bool>,
     _backend_thread: Arc<BackendThread>,
     pub padded_model: bool,
+    pub multivector_model: bool,
+    pub lexical_weights_model: bool,
     pub max_batch_size: Option<usize>,
     pub model_type: ModelType,
+    /// Options of the ONNX Runtime session, if the model runs on the ORT backend
+    pub ort_session_config: Option<OrtSessionConfig>,
 }
 
 impl Backend {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         model_path: PathBuf,
         api_repo: Option<ApiRepo>,
         dtype: DType,
         model_type: ModelType,
+        ort_session_config: OrtSessionConfig,
         uds_path: String,
         otlp_endpoint: Option<String>,
         otlp_service_name: String,
@@ -78,13 +85,17 @@ impl Backend {
             api_repo,
             dtype,
             model_type.clone(),
+            ort_session_config,
             uds_path,
             otlp_endpoint,
             otlp_service_name,
         )
         .await?;
         let padded_model = backend.is_padded();
+        let multivector_model = backend.supports_multivector();
+        let lexical_weights_model = backend.supports_lexical_weights();
         let max_batch_size = backend.max_batch_size();
+        let ort_session_config = backend.ort_session_config();
 
         let (health_sender, health_receiver) = watch::channel(false);
         let _backend_thread =
@@ -95,8 +106,11 @@ impl Backend {
             health_receiver,
             _backend_thread,
             padded_model,
+            multivector_model,
+            lexical_weights_model,
             max_batch_size,
             model_type,
+            ort_session_config,
         })
     }
 
@@ -186,12 +200,15 @@ impl Backend {
         }
         Batch {
             input_ids: batched_input_ids,
+            tokens: vec![String::new(); (batch_size * length) as usize],
             token_type_ids: batched_token_type_ids,
             position_ids: batched_position_ids,
             cumulative_seq_lengths,
             max_length: length,
             pooled_indices,
             raw_indices: vec![],
+            multivector_indices: vec![],
+            lexical_indices: vec![],
         }
     }
 
@@ -250,8 +267,14 @@ impl Backend {
             position_ids,
             cumulative_seq_lengths,
             max_length,
+            lexical_indices: if self.lexical_weights_model {
+                pooled_indices.clone()
+            } else {
+                vec![]
+            },
             pooled_indices,
             raw_indices: vec![],
+            multivector_indices: vec![],
         };
 
         match &self.model_type {
@@ -287,6 +310,8 @@ impl Backend {
                 max_length: 1,
                 pooled_indices: vec![0],
                 raw_indices: vec![],
+                multivector_indices: vec![],
+                lexical_indices: vec![],
             };
             match &self.model_type {
                 ModelType::Classifier => self.predict(batch).await.map(|_| ()),
@@ -325,12 +350,13 @@ impl Backend {
     }
 }
 
-#[allow(unused)]
+#[allow(unused, clippy::too_many_arguments)]
 async fn init_backend(
     model_path: PathBuf,
     api_repo: Option<ApiRepo>,
     dtype: DType,
     model_type: ModelType,
+    ort_session_config: OrtSessionConfig,
     uds_path: String,
     otlp_endpoint: Option<String>,
     otlp_service_name: String,
@@ -342,13 +368,18 @@ async fn init_backend(
         {
             if let Some(api_repo) = api_repo.as_ref() {
                 let start = std::time::Instant::now();
-                download_onnx(api_repo)
+                download_onnx(api_repo, &dtype.to_string())
                     .await
                     .map_err(|err| BackendError::WeightsNotFound(err.to_string()));
                 tracing::info!("Model ONNX weights downloaded in {:?}", start.elapsed());
             }
 
-            let backend = OrtBackend::new(&model_path, dtype.to_string(), model_type.clone());
+            let backend = OrtBackend::new(
+                &model_path,
+                dtype.to_string(),
+                model_type.clone(),
+                ort_session_config,
+            );
             match backend {
                 Ok(b) => return Ok(Box::new(b)),
                 Err(err) => {
@@ -371,6 +402,14 @@ async fn init_backend(
                     .map_err(|err| BackendError::WeightsNotFound(err.to_string()))?;
             }
 
+            if cfg!(feature = "candle") {
+                // Optional ColBERT head used for multi-vector embeddings
+                tracing::info!("Downloading `colbert_linear.pt`");
+                if let Err(err) = api_repo.get("colbert_linear.pt").await {
+                    tracing::warn!("Could not download `colbert_linear.pt`: {err}");
+                }
+            }
+
             tracing::info!("Model weights downloaded in {:?}", start.elapsed());
         }
     }
@@ -525,33 +564,53 @@ async fn download_safetensors(api: &ApiRepo) -> Result<Vec<PathBuf>, ApiError> {
 }
 
 #[cfg(feature = "ort")]
-async fn download_onnx(api: &ApiRepo) -> Result<Vec<PathBuf>, ApiError> {
+async fn download_onnx(api: &ApiRepo, dtype: &str) -> Result<Vec<PathBuf>, ApiError> {
     let mut model_files: Vec<PathBuf> = Vec::new();
 
-    tracing::info!("Downloading `model.onnx`");
-    match api.get("model.onnx").await {
-        Ok(p) => model_files.push(p),
-        Err(err) => {
-            tracing::warn!("Could not download `model.onnx`: {err}");
-            tracing::info!("Downloading `onnx/model.onnx`");
-            let p = api.get("onnx/model.onnx").await?;
-            model_files.push(p.parent().unwrap().to_path_buf())
-        }
-    };
+    // The backend reports unsupported dtypes
+    let names = text_embeddings_backend_ort::onnx_model_names(dtype).unwrap_or_default();
 
-    tracing::info!("Downloading `model.onnx_data`");
-    match api.get("model.onnx_data").await {
-        Ok(p) => model_files.push(p),
-        Err(err) => {
-            tracing::warn!("Could not download `model.onnx_data`: {err}");
-            tracing::info!("Downloading `onnx/model.onnx_data`");
+    let mut last_err = None;
+    for path in names
+        .iter()
+        .flat_map(|name| [name.to_string(), format!("onnx/{name}")])
+    {
+        tracing::info!("Downloading `{path}`");
+        match api.get(&path).await {
+            Ok(p) => {
+                model_files.push(p);
+
+                let data_path = format!("{path}_data");
+                tracing::info!("Downloading `{data_path}`");
+                match api.get(&data_path).await {
+                    Ok(p) => model_files.push(p),
+                    Err(err) => tracing::warn!("Could not download `{data_path}`: {err}"),
+                }
 
-            match api.get("onnx/model.onnx_data").await {
-                Ok(p) => model_files.push(p.parent().unwrap().to_path_buf()),
-                Err(err) => tracing::warn!("Could not download `onnx/model.onnx_data`: {err}"),
+                // Optional tensor names, next to the model or at its root
+                let io_config = text_embeddings_backend_ort::IO_CONFIG_NAME;
+                let mut io_paths = vec![io_config.to_string()];
+                if path.starts_with("onnx/") {
+                    io_paths.insert(0, format!("onnx/{io_config}"));
+                }
+                for io_path in io_paths {
+                    if let Ok(p) = api.get(&io_path).await {
+                        tracing::info!("Downloaded `{io_path}`");
+                        model_files.push(p);
+                        break;
+                    }
+                }
+                return Ok(model_files);
+            }
+            Err(err) => {
+                tracing::warn!("Could not download `{path}`: {err}");
+                last_err = Some(err);
             }
         }
     }
 
-    Ok(model_files)
+    match last_err {
+        Some(err) => Err(err),
+        None => Ok(model_files),
+    }
 }
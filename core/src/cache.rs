@@ -0,0 +1,462 @@
+/// Embedding cache logic
+use crate::tokenization::EncodingInput;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use text_embeddings_backend::TokenWeight;
+use tokenizers::TruncationDirection;
+
+/// Format version of the files of the on-disk tier
+static DISK_FORMAT_VERSION: u8 = 2;
+/// Suffix of the temporary files used for atomic writes of the on-disk tier
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Pooled embeddings cache with an in-memory LRU tier and an optional on-disk tier
+#[derive(Debug, Clone)]
+pub struct EmbeddingCache {
+    /// Digest of the model revision and of the server settings that change the embeddings
+    namespace: [u8; 32],
+    memory: Arc<Mutex<MemoryTier>>,
+    disk_path: Option<PathBuf>,
+}
+
+impl EmbeddingCache {
+    pub fn new(
+        namespace: String,
+        capacity: usize,
+        disk_path: Option<PathBuf>,
+    ) -> std::io::Result<Self> {
+        if let Some(disk_path) = disk_path.as_ref() {
+            std::fs::create_dir_all(disk_path)?;
+        }
+
+        tracing::info!(
+            "Starting embedding cache with {capacity} in-memory entries and disk tier: {disk_path:?}"
+        );
+
+        Ok(Self {
+            namespace: Sha256::digest(namespace.as_bytes()).into(),
+            memory: Arc::new(Mutex::new(MemoryTier::new(capacity))),
+            disk_path,
+        })
+    }
+
+    /// Compute the key of an input for a given set of encoding parameters
+    pub fn key(
+        &self,
+        inputs: &EncodingInput,
+        truncate: bool,
+        truncation_direction: TruncationDirection,
+        prompt_name: Option<&str>,
+    ) -> CacheKey {
+        let mut hasher = Sha256::new();
+        hasher.update(self.namespace);
+        hasher.update([
+            truncate as u8,
+            matches!(truncation_direction, TruncationDirection::Left) as u8,
+        ]);
+        match prompt_name {
+            None => hasher.update([0]),
+            Some(prompt_name) => {
+                hasher.update([1]);
+                update_bytes(&mut hasher, prompt_name.as_bytes());
+            }
+        }
+        // Length prefixes keep the encoding of the input unambiguous
+        match inputs {
+            EncodingInput::Single(s) => {
+                hasher.update([0]);
+                update_bytes(&mut hasher, s.as_bytes());
+            }
+            EncodingInput::Dual(s1, s2) => {
+                hasher.update([1]);
+                update_bytes(&mut hasher, s1.as_bytes());
+                update_bytes(&mut hasher, s2.as_bytes());
+            }
+            EncodingInput::Ids(ids) => {
+                hasher.update([2]);
+                hasher.update((ids.len() as u64).to_le_bytes());
+                for id in ids {
+                    hasher.update(id.to_le_bytes());
+                }
+            }
+        }
+        CacheKey(hasher.finalize().into())
+    }
+
+    /// Look up an entry in memory first, then on disk
+    pub async fn get(&self, key: &CacheKey) -> Option<Arc<CachedEmbedding>> {
+        if let Some(value) = self.memory.lock().unwrap().get(key) {
+            return Some(value);
+        }
+
+        let path = self.entry_path(key)?;
+        let value = tokio::task::spawn_blocking(move || read_entry(path))
+            .await
+            .ok()??;
+        let value = Arc::new(value);
+
+        // Promote to the in-memory tier
+        self.memory.lock().unwrap().insert(*key, value.clone());
+        Some(value)
+    }
+
+    /// Insert an entry in memory and, in the background, on disk
+    pub fn insert(&self, key: CacheKey, value: CachedEmbedding) {
+        let value = Arc::new(value);
+        self.memory.lock().unwrap().insert(key, value.clone());
+
+        if let Some(path) = self.entry_path(&key) {
+            tokio::task::spawn_blocking(move || {
+                if let Err(err) = write_entry(path, &value) {
+                    tracing::warn!("Could not write embedding cache entry: {err}");
+                }
+            });
+        }
+    }
+
+    fn entry_path(&self, key: &CacheKey) -> Option<PathBuf> {
+        let disk_path = self.disk_path.as_ref()?;
+        let name = key.to_hex();
+        // Shard entries in sub-directories to keep directory sizes manageable
+        Some(disk_path.join(&name[..2]).join(name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey([u8; 32]);
+
+impl CacheKey {
+    fn to_hex(self) -> String {
+        self.0.iter().fold(String::with_capacity(64), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        })
+    }
+}
+
+/// Un-normalized pooled embedding and lexical weights of an input
+#[derive(Debug, Clone)]
+pub struct CachedEmbedding {
+    pub results: Vec<f32>,
+    pub token_weights: Vec<TokenWeight>,
+    /// Whether the lexical weights were computed with the embedding
+    pub lexical_weights: bool,
+    pub prompt_tokens: usize,
+}
+
+#[derive(Debug)]
+struct MemoryTier {
+    capacity: usize,
+    /// Monotonic counter used to order entries by last use
+    tick: u64,
+    entries: HashMap<CacheKey, (u64, Arc<CachedEmbedding>)>,
+    recency: BTreeMap<u64, CacheKey>,
+}
+
+impl MemoryTier {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<Arc<CachedEmbedding>> {
+        let (last_used, value) = self.entries.get_mut(key)?;
+        self.recency.remove(last_used);
+        self.tick += 1;
+        *last_used = self.tick;
+        self.recency.insert(self.tick, *key);
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: CacheKey, value: Arc<CachedEmbedding>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.tick += 1;
+        if let Some((last_used, _)) = self.entries.insert(key, (self.tick, value)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(self.tick, key);
+
+        // Evict the least recently used entries
+        while self.entries.len() > self.capacity {
+            let (_, oldest) = self
+                .recency
+                .pop_first()
+                .expect("recency is out of sync with entries. This is a bug.");
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+fn update_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+fn write_entry(path: PathBuf, value: &CachedEmbedding) -> std::io::Result<()> {
+    let mut buffer =
+        Vec::with_capacity(14 + 4 * value.results.len() + 12 * value.token_weights.len());
+    buffer.push(DISK_FORMAT_VERSION);
+    buffer.push(value.lexical_weights as u8);
+    buffer.extend((value.prompt_tokens as u32).to_le_bytes());
+    buffer.extend((value.results.len() as u32).to_le_bytes());
+    for v in &value.results {
+        buffer.extend(v.to_le_bytes());
+    }
+    buffer.extend((value.token_weights.len() as u32).to_le_bytes());
+    for token_weight in &value.token_weights {
+        buffer.extend(token_weight.id.to_le_bytes());
+        buffer.extend(token_weight.weight.to_le_bytes());
+        buffer.extend((token_weight.token.len() as u32).to_le_bytes());
+        buffer.extend(token_weight.token.as_bytes());
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Write to a temporary file first so that readers never see a partial entry
+    let tmp_path = path.with_extension(format!(
+        "tmp{}",
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&tmp_path, buffer)?;
+    std::fs::rename(tmp_path, path)
+}
+
+fn read_entry(path: PathBuf) -> Option<CachedEmbedding> {
+    let buffer = std::fs::read(&path).ok()?;
+    let value = decode_entry(&buffer);
+    if value.is_none() {
+        tracing::warn!("Ignoring invalid embedding cache entry {path:?}");
+    }
+    value
+}
+
+fn decode_entry(buffer: &[u8]) -> Option<CachedEmbedding> {
+    let mut reader = Reader(buffer);
+    if reader.take(1)?[0] != DISK_FORMAT_VERSION {
+        return None;
+    }
+
+    let lexical_weights = reader.take(1)?[0] != 0;
+    let prompt_tokens = reader.u32()? as usize;
+    let n_results = reader.u32()? as usize;
+    let results = (0..n_results)
+        .map(|_| reader.u32().map(f32::from_bits))
+        .collect::<Option<Vec<f32>>>()?;
+    let n_token_weights = reader.u32()? as usize;
+    let token_weights = (0..n_token_weights)
+        .map(|_| {
+            let id = reader.u32()?;
+            let weight = f32::from_bits(reader.u32()?);
+            let len = reader.u32()? as usize;
+            let token = String::from_utf8(reader.take(len)?.to_vec()).ok()?;
+            Some(TokenWeight { id, token, weight })
+        })
+        .collect::<Option<Vec<TokenWeight>>>()?;
+
+    Some(CachedEmbedding {
+        results,
+        token_weights,
+        lexical_weights,
+        prompt_tokens,
+    })
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{read_entry, write_entry, CachedEmbedding, EmbeddingCache};
+    use crate::tokenization::EncodingInput;
+    use std::sync::Arc;
+    use text_embeddings_backend::TokenWeight;
+    use tokenizers::TruncationDirection;
+
+    fn embedding(value: f32) -> CachedEmbedding {
+        CachedEmbedding {
+            results: vec![value, -value],
+            token_weights: vec![],
+            lexical_weights: false,
+            prompt_tokens: 2,
+        }
+    }
+
+    fn single(text: &str) -> EncodingInput {
+        EncodingInput::Single(text.to_string())
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = EmbeddingCache::new("model".to_string(), 2, None).unwrap();
+        let key = |text| cache.key(&single(text), false, TruncationDirection::Right, None);
+
+        cache.insert(key("a"), embedding(1.0));
+        cache.insert(key("b"), embedding(2.0));
+        // `a` becomes the most recently used entry: `b` is evicted instead
+        assert!(block_on(cache.get(&key("a"))).is_some());
+        cache.insert(key("c"), embedding(3.0));
+
+        assert_eq!(block_on(cache.get(&key("a"))).unwrap().results[0], 1.0);
+        assert!(block_on(cache.get(&key("b"))).is_none());
+        assert_eq!(block_on(cache.get(&key("c"))).unwrap().results[0], 3.0);
+
+        // Inserting an existing key replaces the value without evicting anything
+        cache.insert(key("c"), embedding(4.0));
+        assert_eq!(block_on(cache.get(&key("c"))).unwrap().results[0], 4.0);
+        assert!(block_on(cache.get(&key("a"))).is_some());
+    }
+
+    #[test]
+    fn test_disabled_memory_tier() {
+        let cache = EmbeddingCache::new("model".to_string(), 0, None).unwrap();
+        let key = cache.key(&single("a"), false, TruncationDirection::Right, None);
+        cache.insert(key, embedding(1.0));
+        assert!(block_on(cache.get(&key)).is_none());
+    }
+
+    #[test]
+    fn test_disk_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let value = CachedEmbedding {
+            results: vec![0.5, -1.25, f32::MIN_POSITIVE],
+            token_weights: vec![
+                TokenWeight {
+                    id: 7,
+                    token: "▁héllo".to_string(),
+                    weight: 0.75,
+                },
+                TokenWeight {
+                    id: 8,
+                    token: String::new(),
+                    weight: 0.0,
+                },
+            ],
+            lexical_weights: true,
+            prompt_tokens: 12,
+        };
+
+        let path = directory.path().join("ab").join("entry");
+        write_entry(path.clone(), &value).unwrap();
+        let read = read_entry(path.clone()).unwrap();
+        assert_eq!(read.results, value.results);
+        assert_eq!(read.token_weights, value.token_weights);
+        assert!(read.lexical_weights);
+        assert_eq!(read.prompt_tokens, value.prompt_tokens);
+
+        // Truncated entries are ignored
+        let buffer = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &buffer[..buffer.len() - 1]).unwrap();
+        assert!(read_entry(path).is_none());
+    }
+
+    #[test]
+    fn test_disk_tier() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = Some(directory.path().to_path_buf());
+        let cache = EmbeddingCache::new("model".to_string(), 1, path.clone()).unwrap();
+        let key = cache.key(&single("a"), false, TruncationDirection::Right, None);
+        write_entry(cache.entry_path(&key).unwrap(), &embedding(1.0)).unwrap();
+
+        // Entries written by a previous process are read back and promoted to memory
+        let restarted = EmbeddingCache::new("model".to_string(), 1, path).unwrap();
+        let value = block_on(restarted.get(&key)).unwrap();
+        assert_eq!(value.results, vec![1.0, -1.0]);
+        let promoted = restarted.memory.lock().unwrap().get(&key).unwrap();
+        assert!(Arc::ptr_eq(&value, &promoted));
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let cache = EmbeddingCache::new("model@sha/float32".to_string(), 1, None).unwrap();
+        let key = cache.key(&single("a"), false, TruncationDirection::Right, None);
+        assert_eq!(
+            key,
+            cache.key(&single("a"), false, TruncationDirection::Right, None)
+        );
+
+        // Other models and dtypes
+        for namespace in [
+            "other@sha/float32",
+            "model@other/float32",
+            "model@sha/float16",
+        ] {
+            let other = EmbeddingCache::new(namespace.to_string(), 1, None).unwrap();
+            assert_ne!(
+                key,
+                other.key(&single("a"), false, TruncationDirection::Right, None)
+            );
+        }
+
+        // Prompts and encoding parameters
+        let keys = [
+            cache.key(
+                &single("a"),
+                false,
+                TruncationDirection::Right,
+                Some("query"),
+            ),
+            cache.key(
+                &single("a"),
+                false,
+                TruncationDirection::Right,
+                Some("document"),
+            ),
+            cache.key(&single("a"), true, TruncationDirection::Right, None),
+            cache.key(&single("a"), true, TruncationDirection::Left, None),
+            cache.key(&single("b"), false, TruncationDirection::Right, None),
+        ];
+        for (i, other) in keys.iter().enumerate() {
+            assert_ne!(key, *other);
+            assert!(keys[i + 1..].iter().all(|k| k != other));
+        }
+
+        // Length prefixes keep the inputs apart
+        assert_ne!(
+            cache.key(
+                &EncodingInput::Dual("ab".to_string(), "c".to_string()),
+                false,
+                TruncationDirection::Right,
+                None
+            ),
+            cache.key(
+                &EncodingInput::Dual("a".to_string(), "bc".to_string()),
+                false,
+                TruncationDirection::Right,
+                None
+            ),
+        );
+    }
+}
@@ -37,6 +37,12 @@ pub async fn download_artifacts(api: &ApiRepo, pool_config: bool) -> Result<Path
         err
     });
 
+    // Download the Sentence Transformers modules applied after pooling
+    let _ = download_st_modules(api).await.map_err(|err| {
+        tracing::warn!("Download failed: {err}");
+        err
+    });
+
     tracing::info!("Downloading `config.json`");
     api.get("config.json").await?;
 
@@ -78,3 +84,33 @@ pub async fn download_new_st_config(api: &ApiRepo) -> Result<PathBuf, ApiError>
     let pool_config_path = api.get("config_sentence_transformers.json").await?;
     Ok(pool_config_path)
 }
+
+#[instrument(skip_all)]
+pub async fn download_st_modules(api: &ApiRepo) -> Result<(), ApiError> {
+    tracing::info!("Downloading `modules.json`");
+    let modules_path = api.get("modules.json").await?;
+    let modules: Vec<serde_json::Value> = std::fs::read_to_string(modules_path)
+        .ok()
+        .and_then(|modules| serde_json::from_str(&modules).ok())
+        .unwrap_or_default();
+
+    for module in modules {
+        let (Some(path), Some(module_type)) = (module["path"].as_str(), module["type"].as_str())
+        else {
+            continue;
+        };
+        if !module_type.ends_with(".Dense") {
+            continue;
+        }
+
+        tracing::info!("Downloading `{path}/config.json`");
+        api.get(&format!("{path}/config.json")).await?;
+        tracing::info!("Downloading `{path}/model.safetensors`");
+        if let Err(err) = api.get(&format!("{path}/model.safetensors")).await {
+            tracing::warn!("Could not download `{path}/model.safetensors`: {err}");
+            tracing::info!("Downloading `{path}/pytorch_model.bin`");
+            api.get(&format!("{path}/pytorch_model.bin")).await?;
+        }
+    }
+    Ok(())
+}
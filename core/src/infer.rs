@@ -1,11 +1,14 @@
-use crate::queue::{Entry, Metadata, NextBatch, Queue};
-use crate::tokenization::{EncodingInput, RawEncoding, Tokenization};
+use crate::cache::{CachedEmbedding, EmbeddingCache};
+use crate::queue::{Entry, Metadata, NextBatch, Priority, Queue, QueueStats};
+use crate::tokenization::{EncodingInput, RawEncoding, Tokenization, ValidEncoding};
 use crate::TextEmbeddingsError;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use text_embeddings_backend::{Backend, BackendError, Embedding, ModelType};
+use text_embeddings_backend::{Backend, BackendError, Embedding, ModelType, TokenWeight};
 use tokenizers::TruncationDirection;
-use tokio::sync::{mpsc, oneshot, watch, Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::sync::{mpsc, oneshot, watch, Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError};
 use tracing::instrument;
 
 /// Inference struct
@@ -16,8 +19,12 @@ pub struct Infer {
     /// Shared notify
     notify_batching_task: Arc<Notify>,
     /// Inference limit
-    limit_concurrent_requests: Arc<Semaphore>,
+    limit_concurrent_requests: ConcurrencyLimit,
+    /// Maximum number of windows of a chunked input
+    max_chunks: usize,
     backend: Backend,
+    /// Pooled embeddings cache
+    embedding_cache: Option<EmbeddingCache>,
 }
 
 impl Infer {
@@ -26,32 +33,48 @@ impl Infer {
         tokenization: Tokenization,
         queue: Queue,
         max_concurrent_requests: usize,
+        reserved_concurrent_requests: HashMap<Priority, usize>,
+        max_chunks: usize,
         backend: Backend,
+        embedding_cache: Option<EmbeddingCache>,
+        batch_wait: BatchWait,
     ) -> Self {
         let notify_batching_task = Arc::new(Notify::new());
 
         // Bound channel to 1 to be able to prefetch one batch
         let (embed_sender, embed_receiver) = mpsc::channel(1);
 
+        // Moving average of the inference duration, in nanoseconds
+        let inference_duration = Arc::new(AtomicU64::new(0));
+
         // Batching task
         tokio::spawn(batching_task(
             queue.clone(),
             notify_batching_task.clone(),
             embed_sender,
+            batch_wait,
+            inference_duration.clone(),
         ));
 
         // Create embed task to communicate with backend
-        tokio::spawn(backend_task(backend.clone(), embed_receiver));
+        tokio::spawn(backend_task(
+            backend.clone(),
+            embed_receiver,
+            inference_duration,
+        ));
 
-        // Inference limit with a semaphore
-        let semaphore = Arc::new(Semaphore::new(max_concurrent_requests));
+        // Inference limit with semaphores
+        let limit_concurrent_requests =
+            ConcurrencyLimit::new(max_concurrent_requests, reserved_concurrent_requests);
 
         Self {
             tokenization,
             queue,
             notify_batching_task,
-            limit_concurrent_requests: semaphore,
+            limit_concurrent_requests,
+            max_chunks,
             backend,
+            embedding_cache,
         }
     }
 
@@ -91,11 +114,13 @@ impl Infer {
     }
 
     #[instrument(skip(self))]
-    pub fn try_acquire_permit(&self) -> Result<OwnedSemaphorePermit, TextEmbeddingsError> {
-        // Limit concurrent requests by acquiring a permit from the semaphore
-        self.clone()
-            .limit_concurrent_requests
-            .try_acquire_owned()
+    pub fn try_acquire_permit(
+        &self,
+        priority: Priority,
+    ) -> Result<OwnedSemaphorePermit, TextEmbeddingsError> {
+        // Limit concurrent requests by acquiring a permit from the semaphores
+        self.limit_concurrent_requests
+            .try_acquire(priority)
             .map_err(|err| {
                 let counter = metrics::counter!("te_request_failure", "err" => "overloaded");
                 counter.increment(1);
@@ -105,15 +130,11 @@ impl Infer {
     }
 
     #[instrument(skip(self))]
-    pub async fn acquire_permit(&self) -> OwnedSemaphorePermit {
-        // Limit concurrent requests by acquiring a permit from the semaphore
-        self.clone()
-            .limit_concurrent_requests
-            .acquire_owned()
-            .await
-            .expect("Semaphore has been closed. This is a bug.")
+    pub async fn acquire_permit(&self, priority: Priority) -> OwnedSemaphorePermit {
+        self.limit_concurrent_requests.acquire(priority).await
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[instrument(skip(self, inputs, permit))]
     pub async fn embed_all<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
@@ -121,6 +142,8 @@ impl Infer {
         truncate: bool,
         truncation_direction: TruncationDirection,
         prompt_name: Option<String>,
+        priority: Priority,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
     ) -> Result<AllEmbeddingsInferResponse, TextEmbeddingsError> {
         let start_time = Instant::now();
@@ -142,7 +165,11 @@ impl Infer {
                 truncation_direction,
                 prompt_name,
                 false,
+                false,
+                false,
                 &start_time,
+                priority,
+                deadline,
                 permit,
             )
             .await?;
@@ -169,21 +196,24 @@ impl Infer {
         Ok(response)
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[instrument(skip(self, inputs, permit))]
-    pub async fn embed_sparse<I: Into<EncodingInput> + std::fmt::Debug>(
+    pub async fn embed_multivector<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
         inputs: I,
         truncate: bool,
         truncation_direction: TruncationDirection,
         prompt_name: Option<String>,
+        priority: Priority,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
-    ) -> Result<PooledEmbeddingsInferResponse, TextEmbeddingsError> {
+    ) -> Result<AllEmbeddingsInferResponse, TextEmbeddingsError> {
         let start_time = Instant::now();
 
-        if !self.is_splade() {
+        if !self.is_multivector() {
             let counter = metrics::counter!("te_request_failure", "err" => "model_type");
             counter.increment(1);
-            let message = "Model is not an embedding model with SPLADE pooling".to_string();
+            let message = "Model does not support multi-vector embeddings".to_string();
             tracing::error!("{message}");
             return Err(TextEmbeddingsError::Backend(BackendError::Inference(
                 message,
@@ -196,13 +226,17 @@ impl Infer {
                 truncate,
                 truncation_direction,
                 prompt_name,
+                false,
                 true,
+                false,
                 &start_time,
+                priority,
+                deadline,
                 permit,
             )
             .await?;
 
-        let InferResult::PooledEmbedding(response) = results else {
+        let InferResult::MultiVectorEmbedding(response) = results else {
             panic!("unexpected enum variant")
         };
 
@@ -224,6 +258,63 @@ impl Infer {
         Ok(response)
     }
 
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(self, inputs, permit))]
+    pub async fn embed_sparse<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        truncate: bool,
+        truncation_direction: TruncationDirection,
+        prompt_name: Option<String>,
+        priority: Priority,
+        deadline: Option<Instant>,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<PooledEmbeddingsInferResponse, TextEmbeddingsError> {
+        let start_time = Instant::now();
+
+        if !self.is_splade() {
+            let counter = metrics::counter!("te_request_failure", "err" => "model_type");
+            counter.increment(1);
+            let message = "Model is not an embedding model with SPLADE pooling".to_string();
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Backend(BackendError::Inference(
+                message,
+            )));
+        }
+
+        let response = self
+            .embed_pooled_cached(
+                inputs,
+                truncate,
+                truncation_direction,
+                prompt_name,
+                false,
+                &start_time,
+                priority,
+                deadline,
+                permit,
+            )
+            .await?;
+
+        // Timings
+        let total_time = start_time.elapsed();
+
+        // Metrics
+        let counter = metrics::counter!("te_embed_success");
+        counter.increment(1);
+        let histogram = metrics::histogram!("te_embed_duration");
+        histogram.record(total_time.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_tokenization_duration");
+        histogram.record(response.metadata.tokenization.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_queue_duration");
+        histogram.record(response.metadata.queue.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_inference_duration");
+        histogram.record(response.metadata.inference.as_secs_f64());
+
+        Ok(response)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     #[instrument(skip(self, inputs, permit))]
     pub async fn embed_pooled<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
@@ -232,51 +323,159 @@ impl Infer {
         truncation_direction: TruncationDirection,
         prompt_name: Option<String>,
         normalize: bool,
+        dimensions: Option<usize>,
+        priority: Priority,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
     ) -> Result<PooledEmbeddingsInferResponse, TextEmbeddingsError> {
         let start_time = Instant::now();
 
-        if self.is_splade() && normalize {
+        self.check_pooled_options(normalize, dimensions)?;
+
+        let mut response = self
+            .embed_pooled_cached(
+                inputs,
+                truncate,
+                truncation_direction,
+                prompt_name,
+                false,
+                &start_time,
+                priority,
+                deadline,
+                permit,
+            )
+            .await?;
+
+        truncate_dimensions(&mut response.results, dimensions)?;
+        if normalize {
+            normalize_embedding(&mut response.results);
+        }
+
+        // Timings
+        let total_time = start_time.elapsed();
+
+        // Metrics
+        let counter = metrics::counter!("te_embed_success");
+        counter.increment(1);
+        let histogram = metrics::histogram!("te_embed_duration");
+        histogram.record(total_time.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_tokenization_duration");
+        histogram.record(response.metadata.tokenization.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_queue_duration");
+        histogram.record(response.metadata.queue.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_inference_duration");
+        histogram.record(response.metadata.inference.as_secs_f64());
+
+        Ok(response)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(self, inputs, permit))]
+    pub async fn embed_hybrid<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        truncate: bool,
+        truncation_direction: TruncationDirection,
+        prompt_name: Option<String>,
+        normalize: bool,
+        dimensions: Option<usize>,
+        outputs: EmbedOutputs,
+        priority: Priority,
+        deadline: Option<Instant>,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<HybridEmbeddingsInferResponse, TextEmbeddingsError> {
+        let start_time = Instant::now();
+
+        if !outputs.dense && !outputs.sparse && !outputs.multivector {
+            let counter = metrics::counter!("te_request_failure", "err" => "validation");
+            counter.increment(1);
+            let message = "at least one output must be selected".to_string();
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Validation(message));
+        }
+
+        if outputs.dense {
+            self.check_pooled_options(normalize, dimensions)?;
+        }
+
+        let message = if outputs.sparse && !self.supports_lexical_weights() {
+            Some("Model does not support sparse lexical weights")
+        } else if outputs.multivector && !self.is_multivector() {
+            Some("Model does not support multi-vector embeddings")
+        } else {
+            None
+        };
+        if let Some(message) = message {
             let counter = metrics::counter!("te_request_failure", "err" => "model_type");
             counter.increment(1);
-            let message = "`normalize` is not available for SPLADE models".to_string();
             tracing::error!("{message}");
             return Err(TextEmbeddingsError::Backend(BackendError::Inference(
-                message,
+                message.to_string(),
             )));
         }
 
-        let results = self
-            .embed(
+        let results = if outputs.multivector {
+            self.embed(
                 inputs,
                 truncate,
                 truncation_direction,
                 prompt_name,
+                outputs.dense || outputs.sparse,
                 true,
+                outputs.sparse,
                 &start_time,
+                priority,
+                deadline,
                 permit,
             )
-            .await?;
+            .await?
+        } else {
+            InferResult::PooledEmbedding(
+                self.embed_pooled_cached(
+                    inputs,
+                    truncate,
+                    truncation_direction,
+                    prompt_name,
+                    outputs.sparse,
+                    &start_time,
+                    priority,
+                    deadline,
+                    permit,
+                )
+                .await?,
+            )
+        };
 
-        let InferResult::PooledEmbedding(mut response) = results else {
-            panic!("unexpected enum variant")
+        let mut response = match results {
+            InferResult::PooledEmbedding(response) => HybridEmbeddingsInferResponse {
+                results: Some(response.results),
+                token_weights: Some(response.token_weights),
+                multivector: None,
+                metadata: response.metadata,
+            },
+            InferResult::MultiVectorEmbedding(response) => HybridEmbeddingsInferResponse {
+                results: None,
+                token_weights: None,
+                multivector: Some(response.results),
+                metadata: response.metadata,
+            },
+            InferResult::HybridEmbedding(response) => response,
+            _ => panic!("unexpected enum variant"),
         };
 
-        if normalize {
-            // Normalize embedding
-            let scale = (1.0
-                / response
-                    .results
-                    .iter()
-                    .map(|v| {
-                        let v = *v as f64;
-                        v * v
-                    })
-                    .sum::<f64>()
-                    .sqrt()) as f32;
-            for v in response.results.iter_mut() {
-                *v *= scale;
+        // Only keep the selected outputs
+        match response.results.as_mut() {
+            Some(_) if !outputs.dense => response.results = None,
+            Some(results) => {
+                truncate_dimensions(results, dimensions)?;
+                if normalize {
+                    normalize_embedding(results);
+                }
             }
+            None => {}
+        }
+        if !outputs.sparse {
+            response.token_weights = None;
         }
 
         // Timings
@@ -297,6 +496,245 @@ impl Infer {
         Ok(response)
     }
 
+    #[allow(clippy::too_many_arguments)]
+    #[instrument(skip(self, inputs, permit))]
+    pub async fn embed_pooled_chunked<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        window_size: usize,
+        stride: usize,
+        aggregation: ChunkAggregation,
+        prompt_name: Option<String>,
+        normalize: bool,
+        dimensions: Option<usize>,
+        priority: Priority,
+        deadline: Option<Instant>,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<ChunkedEmbeddingsInferResponse, TextEmbeddingsError> {
+        let start_time = Instant::now();
+
+        if self.is_classifier() {
+            let counter = metrics::counter!("te_request_failure", "err" => "model_type");
+            counter.increment(1);
+            let message = "Model is not an embedding model".to_string();
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Backend(BackendError::Inference(
+                message,
+            )));
+        }
+
+        self.check_pooled_options(normalize, dimensions)?;
+
+        let counter = metrics::counter!("te_embed_count");
+        counter.increment(1);
+
+        // Tokenization
+        let chunks = self
+            .tokenization
+            .encode_chunks(inputs.into(), window_size, stride, prompt_name)
+            .await
+            .map_err(|err| {
+                let counter = metrics::counter!("te_request_failure", "err" => "tokenization");
+                counter.increment(1);
+                tracing::error!("{err}");
+                err
+            })?;
+        let tokenization = start_time.elapsed();
+
+        if chunks.len() > self.max_chunks {
+            let counter = metrics::counter!("te_request_failure", "err" => "validation");
+            counter.increment(1);
+            let message = format!(
+                "`inputs` is split into {} windows > maximum allowed windows {}. Increase `window_size` or decrease `stride`",
+                chunks.len(),
+                self.max_chunks
+            );
+            tracing::error!("{message}");
+            return Err(TextEmbeddingsError::Validation(message));
+        }
+
+        // Every window is a separate entry of the queue and takes a permit like a request.
+        // The permits are acquired at once to never hold some of them while waiting for the
+        // others.
+        drop(permit);
+        let _permits = self
+            .limit_concurrent_requests
+            .acquire_shared(chunks.len())
+            .await;
+
+        check_deadline(deadline)?;
+        let offsets: Vec<(usize, usize)> = chunks.iter().map(|c| (c.start, c.end)).collect();
+        let receivers: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                self.enqueue(
+                    chunk.encoding,
+                    tokenization,
+                    true,
+                    false,
+                    false,
+                    priority,
+                    deadline,
+                )
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(receivers.len());
+        for response_rx in receivers {
+            results.push(wait_response(response_rx).await?);
+        }
+
+        let mut metadata = InferMetadata {
+            prompt_tokens: 0,
+            tokenization,
+            queue: Duration::ZERO,
+            inference: Duration::ZERO,
+            cached: false,
+        };
+        let mut chunks = Vec::with_capacity(results.len());
+        for (result, (start, end)) in results.into_iter().zip(offsets) {
+            let InferResult::PooledEmbedding(response) = result else {
+                panic!("unexpected enum variant")
+            };
+
+            // Windows are processed concurrently
+            metadata.prompt_tokens += response.metadata.prompt_tokens;
+            metadata.queue = metadata.queue.max(response.metadata.queue);
+            metadata.inference = metadata.inference.max(response.metadata.inference);
+
+            chunks.push(EmbeddingChunk {
+                results: response.results,
+                start,
+                end,
+                prompt_tokens: response.metadata.prompt_tokens,
+            });
+        }
+
+        let mut results = aggregate_chunks(&chunks, aggregation);
+        truncate_dimensions(&mut results, dimensions)?;
+        for chunk in chunks.iter_mut() {
+            truncate_dimensions(&mut chunk.results, dimensions)?;
+        }
+        if normalize {
+            normalize_embedding(&mut results);
+            for chunk in chunks.iter_mut() {
+                normalize_embedding(&mut chunk.results);
+            }
+        }
+
+        // Timings
+        let total_time = start_time.elapsed();
+
+        // Metrics
+        let counter = metrics::counter!("te_embed_success");
+        counter.increment(1);
+        let histogram = metrics::histogram!("te_embed_duration");
+        histogram.record(total_time.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_tokenization_duration");
+        histogram.record(metadata.tokenization.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_queue_duration");
+        histogram.record(metadata.queue.as_secs_f64());
+        let histogram = metrics::histogram!("te_embed_inference_duration");
+        histogram.record(metadata.inference.as_secs_f64());
+
+        Ok(ChunkedEmbeddingsInferResponse {
+            results,
+            chunks,
+            metadata,
+        })
+    }
+
+    /// Pooled embedding of an input, served from the embedding cache when possible.
+    /// Cache hits skip tokenization and the queue.
+    #[allow(clippy::too_many_arguments)]
+    async fn embed_pooled_cached<I: Into<EncodingInput> + std::fmt::Debug>(
+        &self,
+        inputs: I,
+        truncate: bool,
+        truncation_direction: TruncationDirection,
+        prompt_name: Option<String>,
+        lexical_weights: bool,
+        start_time: &Instant,
+        priority: Priority,
+        deadline: Option<Instant>,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<PooledEmbeddingsInferResponse, TextEmbeddingsError> {
+        let inputs = inputs.into();
+
+        let cache_key = match &self.embedding_cache {
+            None => None,
+            Some(cache) => {
+                let key = cache.key(
+                    &inputs,
+                    truncate,
+                    truncation_direction,
+                    prompt_name.as_deref(),
+                );
+                // Entries computed without lexical weights cannot answer sparse requests
+                let cached = cache
+                    .get(&key)
+                    .await
+                    .filter(|cached| cached.lexical_weights || !lexical_weights);
+                if let Some(cached) = cached {
+                    let counter = metrics::counter!("te_embed_count");
+                    counter.increment(1);
+                    let counter = metrics::counter!("te_embed_cache_hit");
+                    counter.increment(1);
+
+                    return Ok(PooledEmbeddingsInferResponse {
+                        results: cached.results.clone(),
+                        token_weights: cached.token_weights.clone(),
+                        metadata: InferMetadata {
+                            prompt_tokens: cached.prompt_tokens,
+                            tokenization: Duration::ZERO,
+                            queue: Duration::ZERO,
+                            inference: Duration::ZERO,
+                            cached: true,
+                        },
+                    });
+                }
+
+                let counter = metrics::counter!("te_embed_cache_miss");
+                counter.increment(1);
+                Some(key)
+            }
+        };
+
+        let results = self
+            .embed(
+                inputs,
+                truncate,
+                truncation_direction,
+                prompt_name,
+                true,
+                false,
+                lexical_weights,
+                start_time,
+                priority,
+                deadline,
+                permit,
+            )
+            .await?;
+
+        let InferResult::PooledEmbedding(response) = results else {
+            panic!("unexpected enum variant")
+        };
+
+        if let (Some(cache), Some(key)) = (&self.embedding_cache, cache_key) {
+            cache.insert(
+                key,
+                CachedEmbedding {
+                    results: response.results.clone(),
+                    token_weights: response.token_weights.clone(),
+                    lexical_weights,
+                    prompt_tokens: response.metadata.prompt_tokens,
+                },
+            );
+        }
+
+        Ok(response)
+    }
+
     #[allow(clippy::too_many_arguments)]
     async fn embed<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
@@ -305,7 +743,11 @@ impl Infer {
         truncation_direction: TruncationDirection,
         prompt_name: Option<String>,
         pooling: bool,
+        multivector: bool,
+        lexical_weights: bool,
         start_time: &Instant,
+        priority: Priority,
+        deadline: Option<Instant>,
         _permit: OwnedSemaphorePermit,
     ) -> Result<InferResult, TextEmbeddingsError> {
         if self.is_classifier() {
@@ -332,9 +774,34 @@ impl Infer {
                 tracing::error!("{err}");
                 err
             })?;
-        
+
         tracing::info!("encoding: {:?}", encoding);
-        
+
+        check_deadline(deadline)?;
+        let response_rx = self.enqueue(
+            encoding,
+            start_time.elapsed(),
+            pooling,
+            multivector,
+            lexical_weights,
+            priority,
+            deadline,
+        );
+        wait_response(response_rx).await
+    }
+
+    /// Append an encoding to the queue
+    #[allow(clippy::too_many_arguments)]
+    fn enqueue(
+        &self,
+        encoding: ValidEncoding,
+        tokenization: Duration,
+        pooling: bool,
+        multivector: bool,
+        lexical_weights: bool,
+        priority: Priority,
+        deadline: Option<Instant>,
+    ) -> oneshot::Receiver<Result<InferResult, TextEmbeddingsError>> {
         // MPSC channel to communicate with the background batching task
         let (response_tx, response_rx) = oneshot::channel();
 
@@ -342,31 +809,24 @@ impl Infer {
         self.queue.append(Entry {
             metadata: Metadata {
                 response_tx,
-                tokenization: start_time.elapsed(),
+                tokenization,
                 queue_time: Instant::now(),
                 prompt_tokens: encoding.input_ids.len(),
                 pooling,
+                multivector,
+                lexical_weights,
+                priority,
+                deadline,
             },
             encoding,
         });
 
         self.notify_batching_task.notify_one();
 
-        let response = response_rx
-            .await
-            .expect(
-                "Infer batching task dropped the sender without sending a response. This is a bug.",
-            )
-            .map_err(|err| {
-                let counter = metrics::counter!("te_request_failure", "err" => "inference");
-                counter.increment(1);
-                tracing::error!("{err}");
-                err
-            })?;
-
-        Ok(response)
+        response_rx
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[instrument(skip(self, inputs, _permit))]
     pub async fn predict<I: Into<EncodingInput> + std::fmt::Debug>(
         &self,
@@ -374,6 +834,8 @@ impl Infer {
         truncate: bool,
         truncation_direction: TruncationDirection,
         raw_scores: bool,
+        priority: Priority,
+        deadline: Option<Instant>,
         _permit: OwnedSemaphorePermit,
     ) -> Result<ClassificationInferResponse, TextEmbeddingsError> {
         if !self.is_classifier() {
@@ -401,34 +863,17 @@ impl Infer {
                 err
             })?;
 
-        // MPSC channel to communicate with the background batching task
-        let (response_tx, response_rx) = oneshot::channel();
-
-        // Append the request to the queue
-        self.queue.append(Entry {
-            metadata: Metadata {
-                response_tx,
-                tokenization: start_time.elapsed(),
-                queue_time: Instant::now(),
-                prompt_tokens: encoding.input_ids.len(),
-                pooling: true,
-            },
+        check_deadline(deadline)?;
+        let response_rx = self.enqueue(
             encoding,
-        });
-
-        self.notify_batching_task.notify_one();
-
-        let response = response_rx
-            .await
-            .expect(
-                "Infer batching task dropped the sender without sending a response. This is a bug.",
-            )
-            .map_err(|err| {
-                let counter = metrics::counter!("te_request_failure", "err" => "inference");
-                counter.increment(1);
-                tracing::error!("{err}");
-                err
-            })?;
+            start_time.elapsed(),
+            true,
+            false,
+            false,
+            priority,
+            deadline,
+        );
+        let response = wait_response(response_rx).await?;
 
         let InferResult::Classification(mut response) = response else {
             panic!("unexpected enum variant")
@@ -476,6 +921,25 @@ impl Infer {
         Ok(response)
     }
 
+    /// Validate the options applied to pooled embeddings
+    fn check_pooled_options(
+        &self,
+        normalize: bool,
+        dimensions: Option<usize>,
+    ) -> Result<(), TextEmbeddingsError> {
+        let message = match (self.is_splade(), normalize, dimensions) {
+            (true, true, _) => "`normalize` is not available for SPLADE models",
+            (true, _, Some(_)) => "`dimensions` is not available for SPLADE models",
+            _ => return Ok(()),
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "model_type");
+        counter.increment(1);
+        tracing::error!("{message}");
+        Err(TextEmbeddingsError::Backend(BackendError::Inference(
+            message.to_string(),
+        )))
+    }
+
     #[instrument(skip(self))]
     pub fn is_classifier(&self) -> bool {
         matches!(self.backend.model_type, ModelType::Classifier)
@@ -489,6 +953,16 @@ impl Infer {
         )
     }
 
+    #[instrument(skip(self))]
+    pub fn is_multivector(&self) -> bool {
+        self.backend.multivector_model
+    }
+
+    #[instrument(skip(self))]
+    pub fn supports_lexical_weights(&self) -> bool {
+        self.backend.lexical_weights_model
+    }
+
     #[instrument(skip(self))]
     pub async fn health(&self) -> bool {
         self.backend.health().await.is_ok()
@@ -500,8 +974,117 @@ impl Infer {
     }
 }
 
+/// Wait for the backend results of a queued entry
+async fn wait_response(
+    response_rx: oneshot::Receiver<Result<InferResult, TextEmbeddingsError>>,
+) -> Result<InferResult, TextEmbeddingsError> {
+    let response = response_rx
+        .await
+        .expect("Infer batching task dropped the sender without sending a response. This is a bug.")
+        .map_err(|err| {
+            // Expired entries are counted by the queue
+            if let TextEmbeddingsError::Backend(_) = err {
+                let counter = metrics::counter!("te_request_failure", "err" => "inference");
+                counter.increment(1);
+            }
+            tracing::error!("{err}");
+            err
+        })?;
+
+    Ok(response)
+}
+
+/// Fail a request whose deadline already passed instead of queuing it
+fn check_deadline(deadline: Option<Instant>) -> Result<(), TextEmbeddingsError> {
+    if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+        let counter = metrics::counter!("te_request_failure", "err" => "deadline");
+        counter.increment(1);
+        let err = TextEmbeddingsError::DeadlineExceeded;
+        tracing::error!("{err}");
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Combine the embeddings of the windows of an input
+fn aggregate_chunks(chunks: &[EmbeddingChunk], aggregation: ChunkAggregation) -> Vec<f32> {
+    let hidden_size = chunks[0].results.len();
+    match aggregation {
+        ChunkAggregation::Mean | ChunkAggregation::WeightedMean => {
+            let weight = |chunk: &EmbeddingChunk| match aggregation {
+                ChunkAggregation::WeightedMean => chunk.prompt_tokens as f64,
+                _ => 1.0,
+            };
+            let total_weight: f64 = chunks.iter().map(weight).sum();
+
+            let mut results = vec![0.0_f64; hidden_size];
+            for chunk in chunks {
+                let weight = weight(chunk) / total_weight;
+                for (r, v) in results.iter_mut().zip(&chunk.results) {
+                    *r += *v as f64 * weight;
+                }
+            }
+            results.into_iter().map(|v| v as f32).collect()
+        }
+        ChunkAggregation::Max => {
+            let mut results = vec![f32::NEG_INFINITY; hidden_size];
+            for chunk in chunks {
+                for (r, v) in results.iter_mut().zip(&chunk.results) {
+                    *r = r.max(*v);
+                }
+            }
+            results
+        }
+    }
+}
+
+/// Keep the first `dimensions` values of a Matryoshka embedding
+fn truncate_dimensions(
+    embedding: &mut Vec<f32>,
+    dimensions: Option<usize>,
+) -> Result<(), TextEmbeddingsError> {
+    let Some(dimensions) = dimensions else {
+        return Ok(());
+    };
+    if dimensions == 0 || dimensions > embedding.len() {
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        let message = format!(
+            "`dimensions` must be between 1 and {}. Given: {dimensions}",
+            embedding.len()
+        );
+        tracing::error!("{message}");
+        return Err(TextEmbeddingsError::Validation(message));
+    }
+    embedding.truncate(dimensions);
+    Ok(())
+}
+
+/// L2-normalize an embedding in place, like the `Normalize` Sentence Transformers module
+fn normalize_embedding(embedding: &mut [f32]) {
+    let norm = embedding
+        .iter()
+        .map(|v| {
+            let v = *v as f64;
+            v * v
+        })
+        .sum::<f64>()
+        .sqrt();
+    // Same epsilon as `torch.nn.functional.normalize`
+    let scale = (1.0 / norm.max(1e-12)) as f32;
+    for v in embedding.iter_mut() {
+        *v *= scale;
+    }
+}
+
 #[instrument(skip_all)]
-async fn batching_task(queue: Queue, notify: Arc<Notify>, embed_sender: mpsc::Sender<NextBatch>) {
+async fn batching_task(
+    queue: Queue,
+    notify: Arc<Notify>,
+    embed_sender: mpsc::Sender<NextBatch>,
+    batch_wait: BatchWait,
+    inference_duration: Arc<AtomicU64>,
+) {
     loop {
         notify.notified().await;
 
@@ -511,7 +1094,14 @@ async fn batching_task(queue: Queue, notify: Arc<Notify>, embed_sender: mpsc::Se
                 .await
                 .expect("embed receiver was dropped. This is a bug.");
 
-            while let Some(next_batch) = queue.next_batch().await {
+            loop {
+                if !batch_wait.max_wait.is_zero() {
+                    wait_batch_fill(&queue, &notify, &batch_wait, &inference_duration).await;
+                }
+
+                let Some(next_batch) = queue.next_batch().await else {
+                    break;
+                };
                 permit.send(next_batch);
                 permit = embed_sender
                     .reserve()
@@ -522,12 +1112,71 @@ async fn batching_task(queue: Queue, notify: Arc<Notify>, embed_sender: mpsc::Se
     }
 }
 
+/// Hold the next batch until the queue reaches the fill target or its oldest entry waited for the
+/// maximum wait
+async fn wait_batch_fill(
+    queue: &Queue,
+    notify: &Notify,
+    batch_wait: &BatchWait,
+    inference_duration: &AtomicU64,
+) {
+    let start_time = Instant::now();
+    let max_wait = batch_wait.wait(Duration::from_nanos(
+        inference_duration.load(Ordering::Relaxed),
+    ));
+
+    loop {
+        let stats = queue.stats().await;
+        let Some(oldest) = stats.oldest else {
+            // Nothing to wait for
+            return;
+        };
+        if batch_wait.is_filled(&stats) {
+            break;
+        }
+
+        let waited = oldest.elapsed();
+        if waited >= max_wait {
+            break;
+        }
+        // Re-check the fill target every time an entry is appended
+        if tokio::time::timeout(max_wait - waited, notify.notified())
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    let histogram = metrics::histogram!("te_batch_wait_duration");
+    histogram.record(start_time.elapsed().as_secs_f64());
+}
+
+/// Update the moving average of the inference duration
+fn record_inference_duration(average: &AtomicU64, inference_duration: Duration) {
+    let duration = inference_duration.as_nanos() as u64;
+    let previous = average.load(Ordering::Relaxed);
+    let average_duration = if previous == 0 {
+        duration
+    } else {
+        (previous * 4 + duration) / 5
+    };
+    average.store(average_duration, Ordering::Relaxed);
+}
+
 #[instrument(skip_all)]
-async fn backend_task(backend: Backend, mut embed_receiver: mpsc::Receiver<NextBatch>) {
+async fn backend_task(
+    backend: Backend,
+    mut embed_receiver: mpsc::Receiver<NextBatch>,
+    inference_duration: Arc<AtomicU64>,
+) {
     while let Some(batch) = embed_receiver.recv().await {
         match &backend.model_type {
             ModelType::Classifier => {
                 let results = backend.predict(batch.1).await;
+                if let Ok((_, duration)) = &results {
+                    record_inference_duration(&inference_duration, *duration);
+                }
 
                 // Handle sending responses in another thread to avoid starving the backend
                 std::thread::spawn(move || match results {
@@ -538,6 +1187,7 @@ async fn backend_task(backend: Backend, mut embed_receiver: mpsc::Receiver<NextB
                                 tokenization: m.tokenization,
                                 queue: m.queue_time.elapsed() - inference_duration,
                                 inference: inference_duration,
+                                cached: false,
                             };
 
                             let _ = m.response_tx.send(Ok(InferResult::Classification(
@@ -552,13 +1202,16 @@ async fn backend_task(backend: Backend, mut embed_receiver: mpsc::Receiver<NextB
                     }
                     Err(err) => {
                         batch.0.into_iter().for_each(|m| {
-                            let _ = m.response_tx.send(Err(err.clone()));
+                            let _ = m.response_tx.send(Err(err.clone().into()));
                         });
                     }
                 });
             }
             ModelType::Embedding(_) => {
                 let results = backend.embed(batch.1).await;
+                if let Ok((_, duration)) = &results {
+                    record_inference_duration(&inference_duration, *duration);
+                }
 
                 // Handle sending responses in another thread to avoid starving the backend
                 std::thread::spawn(move || match results {
@@ -569,6 +1222,7 @@ async fn backend_task(backend: Backend, mut embed_receiver: mpsc::Receiver<NextB
                                 tokenization: m.tokenization,
                                 queue: m.queue_time.elapsed() - inference_duration,
                                 inference: inference_duration,
+                                cached: false,
                             };
 
                             let results = match embeddings
@@ -588,6 +1242,22 @@ async fn backend_task(backend: Backend, mut embed_receiver: mpsc::Receiver<NextB
                                         metadata,
                                     })
                                 }
+                                Embedding::MultiVector(e) => {
+                                    InferResult::MultiVectorEmbedding(AllEmbeddingsInferResponse {
+                                        results: e,
+                                        metadata,
+                                    })
+                                }
+                                Embedding::Hybrid {
+                                    pooled,
+                                    token_weights,
+                                    multivector,
+                                } => InferResult::HybridEmbedding(HybridEmbeddingsInferResponse {
+                                    results: Some(pooled),
+                                    token_weights: Some(token_weights),
+                                    multivector: Some(multivector),
+                                    metadata,
+                                }),
                             };
 
                             let _ = m.response_tx.send(Ok(results));
@@ -595,7 +1265,7 @@ async fn backend_task(backend: Backend, mut embed_receiver: mpsc::Receiver<NextB
                     }
                     Err(err) => {
                         batch.0.into_iter().for_each(|m| {
-                            let _ = m.response_tx.send(Err(err.clone()));
+                            let _ = m.response_tx.send(Err(err.clone().into()));
                         });
                     }
                 });
@@ -604,12 +1274,118 @@ async fn backend_task(backend: Backend, mut embed_receiver: mpsc::Receiver<NextB
     }
 }
 
+/// Concurrent requests limit. Part of the capacity can be reserved to some priority classes, the
+/// rest is shared by all the classes.
+#[derive(Debug, Clone)]
+struct ConcurrencyLimit {
+    shared: Arc<Semaphore>,
+    reserved: Arc<HashMap<Priority, Arc<Semaphore>>>,
+}
+
+impl ConcurrencyLimit {
+    fn new(max_concurrent_requests: usize, reserved: HashMap<Priority, usize>) -> Self {
+        // Reserved permits are taken out of the shared semaphore
+        let total_reserved: usize = reserved.values().sum();
+        let shared = Arc::new(Semaphore::new(
+            max_concurrent_requests.saturating_sub(total_reserved),
+        ));
+        let reserved = reserved
+            .into_iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(priority, n)| (priority, Arc::new(Semaphore::new(n))))
+            .collect();
+
+        Self {
+            shared,
+            reserved: Arc::new(reserved),
+        }
+    }
+
+    fn try_acquire(&self, priority: Priority) -> Result<OwnedSemaphorePermit, TryAcquireError> {
+        // Use the permits reserved to this priority class first
+        if let Some(permit) = self
+            .reserved
+            .get(&priority)
+            .and_then(|semaphore| semaphore.clone().try_acquire_owned().ok())
+        {
+            return Ok(permit);
+        }
+        self.shared.clone().try_acquire_owned()
+    }
+
+    async fn acquire(&self, priority: Priority) -> OwnedSemaphorePermit {
+        if let Some(reserved) = self.reserved.get(&priority) {
+            if let Ok(permit) = self.try_acquire(priority) {
+                return permit;
+            }
+            // Wait for the capacity reserved to this priority class
+            return reserved
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("Semaphore has been closed. This is a bug.");
+        }
+
+        self.shared
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Semaphore has been closed. This is a bug.")
+    }
+
+    /// Acquire `n` permits of the shared capacity at once
+    async fn acquire_shared(&self, n: usize) -> OwnedSemaphorePermit {
+        self.shared
+            .clone()
+            .acquire_many_owned(n as u32)
+            .await
+            .expect("Semaphore has been closed. This is a bug.")
+    }
+}
+
+/// How long the batching task holds a batch to let it fill up
+#[derive(Debug, Clone, Copy)]
+pub struct BatchWait {
+    /// Maximum time an entry is held. Zero disables waiting.
+    pub max_wait: Duration,
+    /// A batch is sent as soon as the queue holds this many tokens
+    pub min_batch_tokens: usize,
+    /// A batch is sent as soon as the queue holds this many requests
+    pub min_batch_requests: Option<usize>,
+    /// Derive the wait from the observed inference durations, up to `max_wait`
+    pub adaptive: bool,
+}
+
+impl BatchWait {
+    /// Fraction of the average inference duration used as wait in adaptive mode
+    const ADAPTIVE_RATIO: f64 = 0.5;
+
+    fn wait(&self, average_inference_duration: Duration) -> Duration {
+        if !self.adaptive || average_inference_duration.is_zero() {
+            return self.max_wait;
+        }
+        // Waiting longer than a fraction of a forward pass costs more latency than it saves
+        average_inference_duration
+            .mul_f64(Self::ADAPTIVE_RATIO)
+            .min(self.max_wait)
+    }
+
+    fn is_filled(&self, stats: &QueueStats) -> bool {
+        stats.tokens >= self.min_batch_tokens
+            || self
+                .min_batch_requests
+                .is_some_and(|min_batch_requests| stats.requests >= min_batch_requests)
+    }
+}
+
 #[derive(Debug)]
 pub struct InferMetadata {
     pub prompt_tokens: usize,
     pub tokenization: Duration,
     pub queue: Duration,
     pub inference: Duration,
+    /// The response was served from the embedding cache
+    pub cached: bool,
 }
 
 #[derive(Debug)]
@@ -617,6 +1393,29 @@ pub(crate) enum InferResult {
     Classification(ClassificationInferResponse),
     PooledEmbedding(PooledEmbeddingsInferResponse),
     AllEmbedding(AllEmbeddingsInferResponse),
+    MultiVectorEmbedding(AllEmbeddingsInferResponse),
+    HybridEmbedding(HybridEmbeddingsInferResponse),
+}
+
+/// How the embeddings of the windows of a chunked input are combined
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkAggregation {
+    #[default]
+    Mean,
+    Max,
+    /// Mean weighted by the number of tokens of each window
+    WeightedMean,
+}
+
+/// Outputs to compute for an embedding request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmbedOutputs {
+    /// Pooled embedding
+    pub dense: bool,
+    /// Lexical weights
+    pub sparse: bool,
+    /// Multi-vector embedding
+    pub multivector: bool,
 }
 
 #[derive(Debug)]
@@ -628,7 +1427,7 @@ pub struct ClassificationInferResponse {
 #[derive(Debug)]
 pub struct PooledEmbeddingsInferResponse {
     pub results: Vec<f32>,
-    pub token_weights: Vec<(String, f32)>,
+    pub token_weights: Vec<TokenWeight>,
     pub metadata: InferMetadata,
 }
 
@@ -637,3 +1436,276 @@ pub struct AllEmbeddingsInferResponse {
     pub results: Vec<Vec<f32>>,
     pub metadata: InferMetadata,
 }
+
+#[derive(Debug)]
+pub struct HybridEmbeddingsInferResponse {
+    pub results: Option<Vec<f32>>,
+    pub token_weights: Option<Vec<TokenWeight>>,
+    pub multivector: Option<Vec<Vec<f32>>>,
+    pub metadata: InferMetadata,
+}
+
+#[derive(Debug)]
+pub struct EmbeddingChunk {
+    pub results: Vec<f32>,
+    /// Character offsets of the window in the input
+    pub start: usize,
+    pub end: usize,
+    pub prompt_tokens: usize,
+}
+
+#[derive(Debug)]
+pub struct ChunkedEmbeddingsInferResponse {
+    pub results: Vec<f32>,
+    pub chunks: Vec<EmbeddingChunk>,
+    pub metadata: InferMetadata,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::infer::{
+        aggregate_chunks, normalize_embedding, wait_batch_fill, BatchWait, ChunkAggregation,
+        ConcurrencyLimit, EmbeddingChunk,
+    };
+    use crate::queue::tests::{block_on, entry};
+    use crate::queue::{Priority, Queue, QueueStats};
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicU64;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::sync::Notify;
+
+    fn chunk(results: Vec<f32>, prompt_tokens: usize) -> EmbeddingChunk {
+        EmbeddingChunk {
+            results,
+            start: 0,
+            end: 0,
+            prompt_tokens,
+        }
+    }
+
+    #[test]
+    fn test_aggregate_chunks() {
+        let chunks = vec![chunk(vec![1.0, -2.0], 6), chunk(vec![4.0, 1.0], 2)];
+
+        assert_eq!(
+            aggregate_chunks(&chunks, ChunkAggregation::Mean),
+            vec![2.5, -0.5]
+        );
+        assert_eq!(
+            aggregate_chunks(&chunks, ChunkAggregation::Max),
+            vec![4.0, 1.0]
+        );
+        // Weighted by the 6 and 2 tokens of the windows
+        assert_eq!(
+            aggregate_chunks(&chunks, ChunkAggregation::WeightedMean),
+            vec![1.75, -1.25]
+        );
+
+        // A single window is returned as is
+        let chunks = vec![chunk(vec![0.5, -0.25], 3)];
+        for aggregation in [
+            ChunkAggregation::Mean,
+            ChunkAggregation::Max,
+            ChunkAggregation::WeightedMean,
+        ] {
+            assert_eq!(aggregate_chunks(&chunks, aggregation), vec![0.5, -0.25]);
+        }
+    }
+
+    #[test]
+    fn test_reserved_permits() {
+        let limit = ConcurrencyLimit::new(4, HashMap::from([(Priority::High, 2)]));
+
+        // The normal class only gets the 2 shared permits
+        let normal = [
+            limit.try_acquire(Priority::Normal).unwrap(),
+            limit.try_acquire(Priority::Normal).unwrap(),
+        ];
+        assert!(limit.try_acquire(Priority::Normal).is_err());
+        assert!(limit.try_acquire(Priority::Low).is_err());
+
+        // The high class still has its reserved permits
+        let high = [
+            limit.try_acquire(Priority::High).unwrap(),
+            limit.try_acquire(Priority::High).unwrap(),
+        ];
+        assert!(limit.try_acquire(Priority::High).is_err());
+
+        // Released shared permits can be used by the high class too
+        drop(normal);
+        let shared = limit.try_acquire(Priority::High).unwrap();
+        assert_eq!(limit.shared.available_permits(), 1);
+        assert!(limit.try_acquire(Priority::Low).is_ok());
+
+        // The reserved permits are used first
+        drop(high);
+        drop(shared);
+        let _high = limit.try_acquire(Priority::High).unwrap();
+        assert_eq!(limit.shared.available_permits(), 2);
+    }
+
+    #[test]
+    fn test_reserved_permits_wait() {
+        let limit = ConcurrencyLimit::new(3, HashMap::from([(Priority::High, 1)]));
+        block_on(async {
+            let _normal = [
+                limit.acquire(Priority::Normal).await,
+                limit.acquire(Priority::Normal).await,
+            ];
+            // Waiting normal requests cannot take the reserved permit
+            let waiting =
+                tokio::time::timeout(Duration::from_millis(10), limit.acquire(Priority::Normal));
+            assert!(waiting.await.is_err());
+
+            let high = limit.acquire(Priority::High).await;
+            let waiting =
+                tokio::time::timeout(Duration::from_millis(10), limit.acquire(Priority::High));
+            assert!(waiting.await.is_err());
+
+            // A waiting high request gets the reserved permit once released
+            let limit_clone = limit.clone();
+            let waiting = tokio::spawn(async move { limit_clone.acquire(Priority::High).await });
+            drop(high);
+            assert!(tokio::time::timeout(Duration::from_secs(1), waiting)
+                .await
+                .is_ok());
+        });
+    }
+
+    #[test]
+    fn test_no_reserved_permits() {
+        let limit = ConcurrencyLimit::new(2, HashMap::from([(Priority::High, 0)]));
+        let _permits = [
+            limit.try_acquire(Priority::Low).unwrap(),
+            limit.try_acquire(Priority::Normal).unwrap(),
+        ];
+        assert!(limit.try_acquire(Priority::High).is_err());
+    }
+
+    fn batch_wait(max_wait: u64, adaptive: bool) -> BatchWait {
+        BatchWait {
+            max_wait: Duration::from_millis(max_wait),
+            min_batch_tokens: 16,
+            min_batch_requests: Some(3),
+            adaptive,
+        }
+    }
+
+    #[test]
+    fn test_batch_wait() {
+        let ms = Duration::from_millis;
+
+        assert_eq!(batch_wait(100, false).wait(ms(40)), ms(100));
+        // Half of the average inference duration, up to the maximum wait
+        assert_eq!(batch_wait(100, true).wait(ms(40)), ms(20));
+        assert_eq!(batch_wait(100, true).wait(ms(1000)), ms(100));
+        // No inference yet
+        assert_eq!(batch_wait(100, true).wait(Duration::ZERO), ms(100));
+    }
+
+    #[test]
+    fn test_batch_filled() {
+        let stats = |requests, tokens| QueueStats {
+            requests,
+            tokens,
+            oldest: Some(Instant::now()),
+        };
+        let wait = batch_wait(100, false);
+
+        assert!(!wait.is_filled(&stats(2, 15)));
+        assert!(wait.is_filled(&stats(1, 16)));
+        assert!(wait.is_filled(&stats(3, 3)));
+
+        let wait = BatchWait {
+            min_batch_requests: None,
+            ..wait
+        };
+        assert!(!wait.is_filled(&stats(100, 15)));
+    }
+
+    /// Run `wait_batch_fill` on a queue holding entries of the given ages, in milliseconds, and
+    /// return how long it waited
+    fn wait_fill(
+        batch_wait: BatchWait,
+        inference_duration: Duration,
+        queued: &[u64],
+        appended: Option<Duration>,
+    ) -> Duration {
+        let queue = Queue::new(false, 1024, None, 16, Duration::ZERO, 0);
+        let notify = Arc::new(Notify::new());
+        let inference_duration = AtomicU64::new(inference_duration.as_nanos() as u64);
+
+        let mut responses = Vec::new();
+        for (id, queued) in queued.iter().enumerate() {
+            let (entry, response) = entry(
+                id as u32,
+                4,
+                Priority::Normal,
+                Duration::from_millis(*queued),
+            );
+            queue.append(entry);
+            responses.push(response);
+        }
+
+        block_on(async {
+            // Fill the batch after `appended`
+            if let Some(appended) = appended {
+                let queue = queue.clone();
+                let notify = notify.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(appended).await;
+                    let mut responses = Vec::new();
+                    for id in 0..2 {
+                        let (entry, response) = entry(id + 10, 4, Priority::Normal, Duration::ZERO);
+                        queue.append(entry);
+                        responses.push(response);
+                        notify.notify_one();
+                    }
+                    // Keep the entries alive until the end of the test
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                });
+            }
+
+            let start = Instant::now();
+            wait_batch_fill(&queue, &notify, &batch_wait, &inference_duration).await;
+            start.elapsed()
+        })
+    }
+
+    #[test]
+    fn test_wait_batch_fill() {
+        let ms = Duration::from_millis;
+
+        // Nothing to wait for
+        assert!(wait_fill(batch_wait(10_000, false), ms(0), &[], None) < ms(1000));
+        // Already filled
+        assert!(wait_fill(batch_wait(10_000, false), ms(0), &[0, 0, 0], None) < ms(1000));
+        // The oldest entry already waited for the maximum wait
+        assert!(wait_fill(batch_wait(100, false), ms(0), &[150, 0], None) < ms(50));
+
+        // Waits until the oldest entry waited for the maximum wait
+        let waited = wait_fill(batch_wait(100, false), ms(0), &[40, 0], None);
+        assert!(waited >= ms(50) && waited < ms(1000), "{waited:?}");
+
+        // Stops waiting as soon as the batch is filled
+        let waited = wait_fill(batch_wait(10_000, false), ms(0), &[0], Some(ms(20)));
+        assert!(waited >= ms(20) && waited < ms(5000), "{waited:?}");
+
+        // Adaptive wait: half of the 100ms inference duration
+        let waited = wait_fill(batch_wait(10_000, true), ms(100), &[0], None);
+        assert!(waited >= ms(40) && waited < ms(5000), "{waited:?}");
+    }
+
+    #[test]
+    fn test_normalize_embedding() {
+        let mut embedding = vec![3.0, 0.0, -4.0];
+        normalize_embedding(&mut embedding);
+        assert_eq!(embedding, vec![0.6, 0.0, -0.8]);
+
+        // A null embedding stays null instead of becoming `NaN`
+        let mut embedding = vec![0.0; 3];
+        normalize_embedding(&mut embedding);
+        assert_eq!(embedding, vec![0.0; 3]);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod cache;
 pub mod download;
 pub mod infer;
 pub mod queue;
@@ -15,6 +16,8 @@ pub enum TextEmbeddingsError {
     Validation(String),
     #[error("Model is overloaded")]
     Overloaded(#[from] TryAcquireError),
+    #[error("Request deadline exceeded")]
+    DeadlineExceeded,
     #[error("Backend error: {0}")]
     Backend(#[from] BackendError),
 }
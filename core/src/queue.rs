@@ -1,9 +1,11 @@
 use crate::infer::InferResult;
 use crate::tokenization::ValidEncoding;
+use crate::TextEmbeddingsError;
 use std::cmp::max;
 use std::collections::VecDeque;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
-use text_embeddings_backend::{BackendError, Batch};
+use text_embeddings_backend::Batch;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{instrument, Span};
 
@@ -20,7 +22,7 @@ pub struct Entry {
 #[derive(Debug)]
 pub struct Metadata {
     /// InferResponse sender to communicate between the Infer struct and the batching_task
-    pub(crate) response_tx: oneshot::Sender<Result<InferResult, BackendError>>,
+    pub(crate) response_tx: oneshot::Sender<Result<InferResult, TextEmbeddingsError>>,
     /// Tokenization duration
     pub(crate) tokenization: Duration,
     /// Instant when this entry was queued
@@ -29,6 +31,46 @@ pub struct Metadata {
     pub(crate) prompt_tokens: usize,
     /// Pooled embedding
     pub(crate) pooling: bool,
+    /// Multi-vector embedding
+    pub(crate) multivector: bool,
+    /// Lexical weights of the pooled embedding
+    pub(crate) lexical_weights: bool,
+    /// Scheduling class
+    pub(crate) priority: Priority,
+    /// Instant after which the request is failed instead of being computed
+    pub(crate) deadline: Option<Instant>,
+}
+
+/// Scheduling class of a request. Batches are filled from the higher classes first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Priority::ALL
+            .into_iter()
+            .find(|p| p.as_str() == s)
+            .ok_or_else(|| format!("unknown priority `{s}`. Expected one of: high, normal, low"))
+    }
 }
 
 /// Request Queue
@@ -44,6 +86,8 @@ impl Queue {
         max_batch_tokens: usize,
         max_batch_requests: Option<usize>,
         max_concurrent_requests: usize,
+        priority_aging: Duration,
+        length_bucketing_window: usize,
     ) -> Self {
         // Create channels
         let (queue_sender, queue_receiver) = mpsc::channel(max_concurrent_requests);
@@ -55,6 +99,8 @@ impl Queue {
                 max_batch_tokens,
                 max_batch_requests,
                 max_concurrent_requests,
+                priority_aging,
+                length_bucketing_window,
                 queue_receiver,
             )
         });
@@ -72,6 +118,23 @@ impl Queue {
             .expect("Queue background task dropped the receiver or the receiver is too behind. This is a bug.");
     }
 
+    /// Get the number of queued requests and tokens
+    #[instrument(skip(self))]
+    pub async fn stats(&self) -> QueueStats {
+        let (response_sender, response_receiver) = oneshot::channel();
+
+        // Send stats command to the background task managing the state
+        // Unwrap is safe here
+        self.queue_sender
+            .try_send(QueueCommand::Stats { response_sender })
+            .expect("Queue background task dropped the receiver or the receiver is too behind. This is a bug.");
+        // Await on response channel
+        // Unwrap is safe here
+        response_receiver.await.expect(
+            "Queue background task dropped the sender without sending the stats. This is a bug.",
+        )
+    }
+
     /// Get the next batch from the queue
     #[instrument(skip(self))]
     pub async fn next_batch(&self) -> Option<NextBatch> {
@@ -99,19 +162,44 @@ fn queue_blocking_task(
     max_batch_tokens: usize,
     max_batch_requests: Option<usize>,
     max_concurrent_requests: usize,
+    priority_aging: Duration,
+    length_bucketing_window: usize,
     mut queue_receiver: mpsc::Receiver<QueueCommand>,
 ) {
     let capacity = max_batch_requests.unwrap_or(max_concurrent_requests);
+    // Reordering only reduces padding
+    let length_bucketing_window = if padded_model {
+        length_bucketing_window
+    } else {
+        0
+    };
 
-    let mut entries: VecDeque<Entry> = VecDeque::with_capacity(max_concurrent_requests);
+    // One FIFO per priority class, indexed by `Priority as usize`
+    let mut entries: [VecDeque<Entry>; 3] = Default::default();
 
     while let Some(cmd) = queue_receiver.blocking_recv() {
         match cmd {
             QueueCommand::Append(entry, span) => {
                 let _span = span.entered();
-                entries.push_back(*entry);
+                let priority = entry.metadata.priority;
+                entries[priority as usize].push_back(*entry);
                 let gauge = metrics::gauge!("te_queue_size");
                 gauge.increment(1.0);
+                let gauge =
+                    metrics::gauge!("te_queue_priority_size", "priority" => priority.as_str());
+                gauge.increment(1.0);
+            }
+            QueueCommand::Stats { response_sender } => {
+                let queued = entries.iter().flatten();
+                let stats = QueueStats {
+                    requests: queued.clone().count(),
+                    tokens: queued
+                        .clone()
+                        .map(|entry| entry.encoding.input_ids.len())
+                        .sum(),
+                    oldest: queued.map(|entry| entry.metadata.queue_time).min(),
+                };
+                let _ = response_sender.send(stats);
             }
             QueueCommand::NextBatch {
                 response_sender,
@@ -126,6 +214,8 @@ fn queue_blocking_task(
 
                 let mut pooled_indices = Vec::with_capacity(capacity);
                 let mut raw_indices = Vec::with_capacity(capacity);
+                let mut multivector_indices = Vec::with_capacity(capacity);
+                let mut lexical_indices = Vec::new();
                 let mut metadata = Vec::with_capacity(capacity);
                 let mut cu_seq_lengths = Vec::with_capacity(capacity);
                 cu_seq_lengths.push(0);
@@ -135,13 +225,19 @@ fn queue_blocking_task(
 
                 let mut entry_index = 0;
 
-                while let Some(entry) = entries.pop_front() {
-                    // Filter entries where the response receiver was dropped (== entries where the request
-                    // was dropped by the client)
-                    if entry.metadata.response_tx.is_closed() {
-                        let counter = metrics::counter!("te_request_failure", "err" => "dropped");
-                        counter.increment(1);
-                        continue;
+                // Entries taken out of the queue, in the order they must be considered, and the
+                // entries of other length buckets that were taken out with them
+                let (mut candidates, mut held_back, bucket) =
+                    bucketed_candidates(&mut entries, priority_aging, length_bucketing_window);
+
+                while let Some(entry) = candidates
+                    .pop_front()
+                    .or_else(|| next_valid_entry(&mut entries, priority_aging))
+                {
+                    // Batches only hold entries of the same length bucket
+                    if bucket.is_some_and(|bucket| length_bucket(&entry) != bucket) {
+                        held_back.push(entry);
+                        break;
                     }
 
                     let entry_tokens = entry.encoding.input_ids.len();
@@ -154,13 +250,24 @@ fn queue_blocking_task(
                     };
 
                     if total_tokens > max_batch_tokens {
-                        entries.push_front(entry);
+                        candidates.push_front(entry);
                         break;
                     }
 
-                    match entry.metadata.pooling {
-                        true => pooled_indices.push(entry_index),
-                        false => raw_indices.push(entry_index),
+                    let histogram = metrics::histogram!("te_queue_priority_duration", "priority" => entry.metadata.priority.as_str());
+                    histogram.record(entry.metadata.queue_time.elapsed().as_secs_f64());
+
+                    match (entry.metadata.pooling, entry.metadata.multivector) {
+                        (true, true) => {
+                            pooled_indices.push(entry_index);
+                            multivector_indices.push(entry_index);
+                        }
+                        (true, false) => pooled_indices.push(entry_index),
+                        (false, true) => multivector_indices.push(entry_index),
+                        (false, false) => raw_indices.push(entry_index),
+                    }
+                    if entry.metadata.pooling && entry.metadata.lexical_weights {
+                        lexical_indices.push(entry_index);
                     }
 
                     max_length = max(max_length, entry_tokens as u32);
@@ -181,6 +288,9 @@ fn queue_blocking_task(
                     }
                 }
 
+                held_back.extend(candidates);
+                requeue(&mut entries, held_back);
+
                 let batch_size = metadata.len();
                 let next_batch = if metadata.is_empty() {
                     None
@@ -196,6 +306,8 @@ fn queue_blocking_task(
                             max_length,
                             pooled_indices,
                             raw_indices,
+                            multivector_indices,
+                            lexical_indices,
                         },
                     ))
                 };
@@ -206,15 +318,137 @@ fn queue_blocking_task(
                 histogram.record(batch_size as f64);
                 let histogram = metrics::histogram!("te_batch_next_tokens");
                 histogram.record(current_tokens as f64);
+                if padded_model {
+                    let histogram = metrics::histogram!("te_batch_next_padding_tokens");
+                    histogram.record((max_length as usize * batch_size - current_tokens) as f64);
+                }
                 let gauge = metrics::gauge!("te_queue_size");
-                gauge.set(entries.len() as f64)
+                gauge.set(entries.iter().map(|e| e.len()).sum::<usize>() as f64);
+                for priority in Priority::ALL {
+                    let gauge =
+                        metrics::gauge!("te_queue_priority_size", "priority" => priority.as_str());
+                    gauge.set(entries[priority as usize].len() as f64);
+                }
             }
         }
     }
 }
 
+/// Pop the next entry to schedule.
+///
+/// Entries are promoted by one class for every `priority_aging` spent in the queue so that lower
+/// classes still make progress. Ties are broken by queue time.
+fn next_entry(entries: &mut [VecDeque<Entry>; 3], priority_aging: Duration) -> Option<Entry> {
+    let class = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(class, e)| e.front().map(|entry| (class, entry.metadata.queue_time)))
+        .min_by_key(|(class, queue_time)| {
+            let promotions = if priority_aging.is_zero() {
+                0
+            } else {
+                (queue_time.elapsed().as_nanos() / priority_aging.as_nanos()) as usize
+            };
+            (class.saturating_sub(promotions), *queue_time)
+        })?
+        .0;
+    entries[class].pop_front()
+}
+
+/// Pop the next entry to schedule, failing the entries that do not need to be computed anymore
+fn next_valid_entry(entries: &mut [VecDeque<Entry>; 3], priority_aging: Duration) -> Option<Entry> {
+    while let Some(entry) = next_entry(entries, priority_aging) {
+        // Filter entries where the response receiver was dropped (== entries where the request
+        // was dropped by the client)
+        if entry.metadata.response_tx.is_closed() {
+            let counter = metrics::counter!("te_request_failure", "err" => "dropped");
+            counter.increment(1);
+            continue;
+        }
+
+        // Fail entries that cannot be answered in time
+        if entry
+            .metadata
+            .deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            let counter = metrics::counter!("te_request_failure", "err" => "deadline");
+            counter.increment(1);
+            let _ = entry
+                .metadata
+                .response_tx
+                .send(Err(TextEmbeddingsError::DeadlineExceeded));
+            continue;
+        }
+
+        return Some(entry);
+    }
+    None
+}
+
+/// Take the next entry to schedule and up to `window` following entries out of the queue, and
+/// split them between the entries of the length bucket of the first one, in scheduling order, and
+/// the others.
+///
+/// The first entry is always scheduled first so that reordering never starves an entry. Also
+/// returns the bucket of the first entry, or `None` if bucketing is disabled.
+fn bucketed_candidates(
+    entries: &mut [VecDeque<Entry>; 3],
+    priority_aging: Duration,
+    window: usize,
+) -> (VecDeque<Entry>, Vec<Entry>, Option<u32>) {
+    if window == 0 {
+        return (VecDeque::new(), Vec::new(), None);
+    }
+
+    let Some(first) = next_valid_entry(entries, priority_aging) else {
+        return (VecDeque::new(), Vec::new(), None);
+    };
+    let first_bucket = length_bucket(&first);
+
+    let mut candidates = VecDeque::with_capacity(window + 1);
+    candidates.push_back(first);
+    let mut others = Vec::new();
+    for _ in 0..window {
+        match next_valid_entry(entries, priority_aging) {
+            Some(entry) if length_bucket(&entry) == first_bucket => candidates.push_back(entry),
+            Some(entry) => others.push(entry),
+            None => break,
+        }
+    }
+    (candidates, others, Some(first_bucket))
+}
+
+/// Power of two length bucket of an entry
+fn length_bucket(entry: &Entry) -> u32 {
+    entry
+        .encoding
+        .input_ids
+        .len()
+        .next_power_of_two()
+        .trailing_zeros()
+}
+
+/// Put back the entries that did not make it into the batch at the front of their class
+fn requeue(entries: &mut [VecDeque<Entry>; 3], mut candidates: Vec<Entry>) {
+    // Restore the arrival order of each class
+    candidates.sort_by_key(|entry| entry.metadata.queue_time);
+    for entry in candidates.into_iter().rev() {
+        entries[entry.metadata.priority as usize].push_front(entry);
+    }
+}
+
 pub type NextBatch = (Vec<Metadata>, Batch);
 
+/// Snapshot of the queue content
+#[derive(Debug, Clone, Copy)]
+pub struct QueueStats {
+    pub requests: usize,
+    pub tokens: usize,
+    /// Queue time of the oldest entry
+    pub oldest: Option<Instant>,
+}
+
 #[derive(Debug)]
 enum QueueCommand {
     Append(Box<Entry>, Span),
@@ -222,4 +456,202 @@ enum QueueCommand {
         response_sender: oneshot::Sender<Option<NextBatch>>,
         span: Span,
     },
+    Stats {
+        response_sender: oneshot::Sender<QueueStats>,
+    },
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::infer::InferResult;
+    use crate::queue::{Entry, Metadata, Priority, Queue};
+    use crate::tokenization::ValidEncoding;
+    use crate::TextEmbeddingsError;
+    use std::time::{Duration, Instant};
+    use tokio::sync::oneshot;
+
+    pub(crate) type Response = oneshot::Receiver<Result<InferResult, TextEmbeddingsError>>;
+
+    /// Entry of `tokens` tokens queued `queued` ago. Token ids are set to `id`.
+    pub(crate) fn entry(
+        id: u32,
+        tokens: usize,
+        priority: Priority,
+        queued: Duration,
+    ) -> (Entry, Response) {
+        let (response_tx, response_rx) = oneshot::channel();
+        let entry = Entry {
+            encoding: ValidEncoding {
+                input_ids: vec![id; tokens],
+                tokens: vec![String::new(); tokens],
+                token_type_ids: vec![0; tokens],
+                position_ids: (0..tokens as u32).collect(),
+            },
+            metadata: Metadata {
+                response_tx,
+                tokenization: Duration::ZERO,
+                queue_time: Instant::now() - queued,
+                prompt_tokens: tokens,
+                pooling: true,
+                multivector: false,
+                lexical_weights: false,
+                priority,
+                deadline: None,
+            },
+        };
+        (entry, response_rx)
+    }
+
+    pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    /// Token ids of the first token of every member of the next batches
+    fn batches(queue: &Queue) -> Vec<Vec<u32>> {
+        let mut batches = Vec::new();
+        while let Some((_, batch)) = block_on(queue.next_batch()) {
+            let starts = &batch.cumulative_seq_lengths[..batch.len()];
+            batches.push(
+                starts
+                    .iter()
+                    .map(|start| batch.input_ids[*start as usize])
+                    .collect(),
+            );
+        }
+        batches
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let queue = Queue::new(false, 1024, None, 16, Duration::ZERO, 0);
+        let mut responses = Vec::new();
+        for (id, priority) in [
+            (0, Priority::Low),
+            (1, Priority::Normal),
+            (2, Priority::High),
+            (3, Priority::Low),
+            (4, Priority::High),
+        ] {
+            let (entry, response) = entry(id, 4, priority, Duration::ZERO);
+            queue.append(entry);
+            responses.push(response);
+        }
+
+        // Batches are filled from the higher classes first, in arrival order within a class
+        assert_eq!(batches(&queue), vec![vec![2, 4, 1, 0, 3]]);
+    }
+
+    #[test]
+    fn test_priority_order_token_budget() {
+        // Only two entries fit in a batch
+        let queue = Queue::new(false, 8, None, 16, Duration::ZERO, 0);
+        let mut responses = Vec::new();
+        for (id, priority) in [
+            (0, Priority::Low),
+            (1, Priority::Normal),
+            (2, Priority::High),
+            (3, Priority::Normal),
+        ] {
+            let (entry, response) = entry(id, 4, priority, Duration::ZERO);
+            queue.append(entry);
+            responses.push(response);
+        }
+
+        assert_eq!(batches(&queue), vec![vec![2, 1], vec![3, 0]]);
+    }
+
+    #[test]
+    fn test_priority_aging() {
+        let queue = Queue::new(false, 1024, Some(1), 16, Duration::from_secs(1), 0);
+        let mut responses = Vec::new();
+        for (id, priority, queued) in [
+            // Promoted to the high class and older than the high entry
+            (0, Priority::Low, 2500),
+            // Promoted to the normal class
+            (1, Priority::Low, 1500),
+            (2, Priority::High, 0),
+            (3, Priority::Normal, 0),
+        ] {
+            let (entry, response) = entry(id, 4, priority, Duration::from_millis(queued));
+            queue.append(entry);
+            responses.push(response);
+        }
+
+        // Ties between classes are broken by queue time
+        assert_eq!(batches(&queue), vec![vec![0], vec![2], vec![1], vec![3]]);
+    }
+
+    #[test]
+    fn test_no_priority_aging() {
+        let queue = Queue::new(false, 1024, Some(1), 16, Duration::ZERO, 0);
+        let mut responses = Vec::new();
+        for (id, priority, queued) in [(0, Priority::Low, 60_000), (1, Priority::High, 0)] {
+            let (entry, response) = entry(id, 4, priority, Duration::from_millis(queued));
+            queue.append(entry);
+            responses.push(response);
+        }
+
+        assert_eq!(batches(&queue), vec![vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn test_dropped_entries() {
+        let queue = Queue::new(false, 1024, None, 16, Duration::ZERO, 0);
+        let (first, _response) = entry(0, 4, Priority::Normal, Duration::ZERO);
+        let (dropped, response) = entry(1, 4, Priority::High, Duration::ZERO);
+        drop(response);
+        queue.append(first);
+        queue.append(dropped);
+
+        assert_eq!(batches(&queue), vec![vec![0]]);
+    }
+
+    /// Append entries of the given lengths, in order
+    fn append(queue: &Queue, lengths: &[usize]) -> Vec<Response> {
+        lengths
+            .iter()
+            .enumerate()
+            .map(|(id, tokens)| {
+                let (entry, response) = entry(id as u32, *tokens, Priority::Normal, Duration::ZERO);
+                queue.append(entry);
+                response
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_length_bucketing() {
+        let queue = Queue::new(true, 1024, None, 16, Duration::ZERO, 8);
+        // Buckets: 8, 128, 8, 128, 8, 16, 128
+        let _responses = append(&queue, &[8, 100, 7, 120, 5, 16, 128]);
+
+        // Every batch holds a single bucket, starting with the oldest entry
+        assert_eq!(batches(&queue), vec![vec![0, 2, 4], vec![1, 3, 6], vec![5]]);
+    }
+
+    #[test]
+    fn test_length_bucketing_window() {
+        // Only the next 2 entries are reordered
+        let queue = Queue::new(true, 1024, None, 16, Duration::ZERO, 2);
+        let _responses = append(&queue, &[8, 100, 7, 6, 120]);
+
+        // Entries after the window are only taken while they are in the bucket
+        assert_eq!(batches(&queue), vec![vec![0, 2, 3], vec![1, 4]]);
+    }
+
+    #[test]
+    fn test_length_bucketing_disabled() {
+        // Unpadded backends never reorder entries
+        let queue = Queue::new(false, 1024, None, 16, Duration::ZERO, 8);
+        let _responses = append(&queue, &[8, 100, 7, 120]);
+        assert_eq!(batches(&queue), vec![vec![0, 1, 2, 3]]);
+
+        let queue = Queue::new(true, 1024, None, 16, Duration::ZERO, 0);
+        let _responses = append(&queue, &[8, 100, 7, 120]);
+        assert_eq!(batches(&queue), vec![vec![0, 1, 2, 3]]);
+    }
 }
@@ -88,6 +88,43 @@ impl Tokenization {
         response_receiver.await.expect("Tokenization background task dropped the sender without sending a response. This is a bug.")
     }
 
+    /// Split an input into overlapping windows of at most `window_size` tokens and encode them
+    #[instrument(skip_all)]
+    pub async fn encode_chunks(
+        &self,
+        inputs: EncodingInput,
+        window_size: usize,
+        stride: usize,
+        prompt_name: Option<String>,
+    ) -> Result<Vec<ValidChunk>, TextEmbeddingsError> {
+        // Check if inputs is empty
+        if inputs.is_empty() {
+            return Err(TextEmbeddingsError::Validation(
+                "`inputs` cannot be empty".to_string(),
+            ));
+        }
+
+        // Create response channel
+        let (response_sender, response_receiver) = oneshot::channel();
+        // Send request to the background validation task
+        // Unwrap is safe here
+        self.sender
+            .send(TokenizerRequest::EncodeChunks(
+                inputs,
+                window_size,
+                stride,
+                prompt_name,
+                response_sender,
+                Span::current(),
+            ))
+            .await
+            .expect("Tokenization background task dropped the receiver. This is a bug.");
+
+        // Await on response channel
+        // Unwrap is safe here
+        response_receiver.await.expect("Tokenization background task dropped the sender without sending a response. This is a bug.")
+    }
+
     #[instrument(skip_all)]
     pub async fn tokenize(
         &self,
@@ -198,6 +235,37 @@ fn tokenizer_worker(
                     }
                 })
             }
+            TokenizerRequest::EncodeChunks(
+                inputs,
+                window_size,
+                stride,
+                prompt_name,
+                response_tx,
+                parent_span,
+            ) => {
+                parent_span.in_scope(|| {
+                    if !response_tx.is_closed() {
+                        let default_prompt_clone = match prompt_name {
+                            None => default_prompt.clone(),
+                            Some(_) => None,
+                        };
+
+                        // It's possible that the user dropped its request resulting in a send error.
+                        // We just discard the error
+                        let _ = response_tx.send(encode_chunks(
+                            inputs,
+                            window_size,
+                            stride,
+                            max_input_length,
+                            position_offset,
+                            default_prompt_clone,
+                            prompt_name,
+                            prompts.as_ref(),
+                            &mut tokenizer,
+                        ));
+                    }
+                })
+            }
             TokenizerRequest::Tokenize(
                 inputs,
                 add_special_tokens,
@@ -400,6 +468,112 @@ fn encode_input(
     })
 }
 
+/// Split a text input into overlapping windows and encode each of them with the prompt and
+/// special tokens
+#[allow(clippy::too_many_arguments)]
+fn encode_chunks(
+    inputs: EncodingInput,
+    window_size: usize,
+    stride: usize,
+    max_input_length: usize,
+    position_offset: usize,
+    default_prompt: Option<String>,
+    prompt_name: Option<String>,
+    prompts: Option<&HashMap<String, String>>,
+    tokenizer: &mut Tokenizer,
+) -> Result<Vec<ValidChunk>, TextEmbeddingsError> {
+    let EncodingInput::Single(text) = inputs else {
+        return Err(TextEmbeddingsError::Validation(
+            "chunking is only supported for text inputs".to_string(),
+        ));
+    };
+
+    let input_chars = text.chars().count();
+    let limit = max_input_length * MAX_CHAR_MULTIPLIER;
+    if input_chars > limit {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`inputs` must have less than {limit} characters. Given: {input_chars}"
+        )));
+    }
+    if window_size > max_input_length {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`window_size` must be lower or equal to {max_input_length}. Given: {window_size}"
+        )));
+    }
+
+    tokenizer.with_truncation(None)?;
+
+    // Each window also holds the prompt and the special tokens
+    let pre_prompt = prepare_pre_prompt(default_prompt, prompt_name, prompts)?;
+    let prompt = tokenizer.encode::<&str>(pre_prompt.as_deref().unwrap_or_default(), false)?;
+    let added_tokens = tokenizer.post_process(prompt.clone(), None, true)?.len();
+    let content_length = window_size.saturating_sub(added_tokens);
+    if stride >= content_length {
+        return Err(TextEmbeddingsError::Validation(format!(
+            "`stride` must be lower than the {content_length} tokens available in each window. Given: {stride}"
+        )));
+    }
+
+    let encoding = tokenizer.encode_char_offsets::<&str>(&text, false)?;
+    let offsets = encoding.get_offsets();
+
+    // The windows are built from the tokens of the whole input: encoding a slice of the input
+    // again can give other tokens
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = offsets.len().min(start + content_length);
+        let (char_start, char_end) = match offsets.is_empty() {
+            true => (0, input_chars),
+            false => (offsets[start].0, offsets[end - 1].1),
+        };
+
+        let window = RawEncoding::new(
+            encoding.get_ids()[start..end].to_vec(),
+            encoding.get_type_ids()[start..end].to_vec(),
+            encoding.get_tokens()[start..end].to_vec(),
+            encoding.get_word_ids()[start..end].to_vec(),
+            offsets[start..end].to_vec(),
+            encoding.get_special_tokens_mask()[start..end].to_vec(),
+            encoding.get_attention_mask()[start..end].to_vec(),
+            Vec::new(),
+            HashMap::new(),
+        );
+        let window = tokenizer.post_process(
+            RawEncoding::merge([prompt.clone(), window], false),
+            None,
+            true,
+        )?;
+        if window.len() > window_size {
+            return Err(TextEmbeddingsError::Validation(format!(
+                "a window has {} tokens but `window_size` is {window_size}",
+                window.len()
+            )));
+        }
+
+        let histogram = metrics::histogram!("te_request_input_length");
+        histogram.record(window.len() as f64);
+        chunks.push(ValidChunk {
+            encoding: ValidEncoding {
+                input_ids: window.get_ids().to_vec(),
+                tokens: window.get_tokens().to_vec(),
+                token_type_ids: window.get_type_ids().to_vec(),
+                position_ids: (position_offset as u32..(window.len() + position_offset) as u32)
+                    .collect(),
+            },
+            start: char_start,
+            end: char_end,
+        });
+
+        if end >= offsets.len() {
+            break;
+        }
+        start = end - stride;
+    }
+
+    Ok(chunks)
+}
+
 #[derive(Debug)]
 pub struct ValidEncoding {
     pub input_ids: Vec<u32>,
@@ -408,6 +582,14 @@ pub struct ValidEncoding {
     pub position_ids: Vec<u32>,
 }
 
+#[derive(Debug)]
+pub struct ValidChunk {
+    pub encoding: ValidEncoding,
+    /// Character offsets of the chunk in the input
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Debug)]
 pub enum EncodingInput {
     Single(String),
@@ -473,6 +655,14 @@ enum TokenizerRequest {
         oneshot::Sender<Result<ValidEncoding, TextEmbeddingsError>>,
         Span,
     ),
+    EncodeChunks(
+        EncodingInput,
+        usize,
+        usize,
+        Option<String>,
+        oneshot::Sender<Result<Vec<ValidChunk>, TextEmbeddingsError>>,
+        Span,
+    ),
     Tokenize(
         EncodingInput,
         bool,
@@ -487,3 +677,182 @@ enum TokenizerRequest {
         Span,
     ),
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::tokenization::{encode_chunks, EncodingInput, ValidChunk};
+    use crate::TextEmbeddingsError;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use tokenizers::Tokenizer;
+
+    /// Word level tokenizer adding `[CLS]` and `[SEP]` around the inputs
+    fn tokenizer() -> Tokenizer {
+        build_tokenizer(
+            r#"{
+                "type": "WordLevel",
+                "vocab": {
+                    "[PAD]": 0, "[CLS]": 1, "[SEP]": 2, "[UNK]": 3, "query": 4, ":": 5,
+                    "a": 6, "b": 7, "c": 8, "d": 9, "e": 10, "f": 11, "g": 12
+                },
+                "unk_token": "[UNK]"
+            }"#,
+        )
+    }
+
+    /// Tokenizer with the `model` adding `[CLS]` and `[SEP]` around the inputs
+    fn build_tokenizer(model: &str) -> Tokenizer {
+        let tokenizer = r#"{
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": {"type": "Whitespace"},
+            "post_processor": {
+                "type": "TemplateProcessing",
+                "single": [
+                    {"SpecialToken": {"id": "[CLS]", "type_id": 0}},
+                    {"Sequence": {"id": "A", "type_id": 0}},
+                    {"SpecialToken": {"id": "[SEP]", "type_id": 0}}
+                ],
+                "pair": [
+                    {"SpecialToken": {"id": "[CLS]", "type_id": 0}},
+                    {"Sequence": {"id": "A", "type_id": 0}},
+                    {"SpecialToken": {"id": "[SEP]", "type_id": 0}},
+                    {"Sequence": {"id": "B", "type_id": 1}},
+                    {"SpecialToken": {"id": "[SEP]", "type_id": 1}}
+                ],
+                "special_tokens": {
+                    "[CLS]": {"id": "[CLS]", "ids": [1], "tokens": ["[CLS]"]},
+                    "[SEP]": {"id": "[SEP]", "ids": [2], "tokens": ["[SEP]"]}
+                }
+            },
+            "decoder": null,
+            "model": MODEL
+        }"#;
+        Tokenizer::from_str(&tokenizer.replace("MODEL", model)).unwrap()
+    }
+
+    fn split(
+        text: &str,
+        window_size: usize,
+        stride: usize,
+        prompt_name: Option<&str>,
+    ) -> Result<Vec<ValidChunk>, TextEmbeddingsError> {
+        let prompts = HashMap::from([("query".to_string(), "query: ".to_string())]);
+        encode_chunks(
+            EncodingInput::Single(text.to_string()),
+            window_size,
+            stride,
+            8,
+            0,
+            None,
+            prompt_name.map(str::to_string),
+            Some(&prompts),
+            &mut tokenizer(),
+        )
+    }
+
+    fn offsets(chunks: &[ValidChunk]) -> Vec<(usize, usize)> {
+        chunks.iter().map(|c| (c.start, c.end)).collect()
+    }
+
+    #[test]
+    fn test_encode_chunks() {
+        // 3 tokens of content per window, 1 shared with the previous window
+        let chunks = split("a b c d e f g", 5, 1, None).unwrap();
+        assert_eq!(offsets(&chunks), vec![(0, 5), (4, 9), (8, 13)]);
+        assert_eq!(chunks[0].encoding.input_ids, vec![1, 6, 7, 8, 2]);
+        assert_eq!(chunks[1].encoding.input_ids, vec![1, 8, 9, 10, 2]);
+        assert_eq!(chunks[2].encoding.input_ids, vec![1, 10, 11, 12, 2]);
+        assert_eq!(chunks[2].encoding.position_ids, vec![0, 1, 2, 3, 4]);
+
+        // The last window is shorter
+        let chunks = split("a b c d", 5, 0, None).unwrap();
+        assert_eq!(offsets(&chunks), vec![(0, 5), (6, 7)]);
+        assert_eq!(chunks[1].encoding.input_ids, vec![1, 9, 2]);
+
+        // Inputs that fit in a window are not split
+        let chunks = split("a b", 5, 0, None).unwrap();
+        assert_eq!(offsets(&chunks), vec![(0, 3)]);
+    }
+
+    #[test]
+    fn test_encode_chunks_subwords() {
+        // `abc` is `a ##bc` but the `bc` slice alone would be `b ##c`
+        let mut tokenizer = build_tokenizer(
+            r###"{
+                "type": "WordPiece",
+                "vocab": {
+                    "[PAD]": 0, "[CLS]": 1, "[SEP]": 2, "[UNK]": 3,
+                    "a": 4, "##bc": 5, "b": 6, "##c": 7, "d": 8
+                },
+                "unk_token": "[UNK]",
+                "continuing_subword_prefix": "##",
+                "max_input_chars_per_word": 100
+            }"###,
+        );
+        let chunks = encode_chunks(
+            EncodingInput::Single("abc d".to_string()),
+            4,
+            1,
+            8,
+            0,
+            None,
+            None,
+            None,
+            &mut tokenizer,
+        )
+        .unwrap();
+        assert_eq!(offsets(&chunks), vec![(0, 3), (1, 5)]);
+        assert_eq!(chunks[0].encoding.input_ids, vec![1, 4, 5, 2]);
+        assert_eq!(chunks[1].encoding.input_ids, vec![1, 5, 8, 2]);
+        assert_eq!(
+            chunks[1].encoding.tokens,
+            vec!["[CLS]", "##bc", "d", "[SEP]"]
+        );
+        assert_eq!(chunks[1].encoding.position_ids, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_encode_chunks_prompt() {
+        // `[CLS] query : [SEP]` leaves 2 tokens of content per window
+        let chunks = split("a b c", 6, 0, Some("query")).unwrap();
+        assert_eq!(offsets(&chunks), vec![(0, 3), (4, 5)]);
+        assert_eq!(chunks[0].encoding.input_ids, vec![1, 4, 5, 6, 7, 2]);
+        assert_eq!(chunks[1].encoding.input_ids, vec![1, 4, 5, 8, 2]);
+    }
+
+    #[test]
+    fn test_encode_chunks_validation() {
+        // The stride must leave room for new tokens in every window
+        assert!(matches!(
+            split("a b c d", 5, 3, None),
+            Err(TextEmbeddingsError::Validation(_))
+        ));
+        assert!(matches!(
+            split("a b c d", 6, 2, Some("query")),
+            Err(TextEmbeddingsError::Validation(_))
+        ));
+        // Windows are limited to the maximum input length
+        assert!(matches!(
+            split("a b c d", 9, 0, None),
+            Err(TextEmbeddingsError::Validation(_))
+        ));
+        assert!(matches!(
+            encode_chunks(
+                EncodingInput::Dual("a".to_string(), "b".to_string()),
+                5,
+                0,
+                8,
+                0,
+                None,
+                None,
+                None,
+                &mut tokenizer(),
+            ),
+            Err(TextEmbeddingsError::Validation(_))
+        ));
+    }
+}
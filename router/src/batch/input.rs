@@ -0,0 +1,345 @@
+/// Texts of JSONL, CSV and Parquet files
+use anyhow::{Context, Result};
+use arrow_array::cast::AsArray;
+use arrow_array::{Array, StringArray};
+use arrow_schema::DataType;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ProjectionMask;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InputFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// Comma separated values with a header row
+    Csv,
+    /// Apache Parquet file with a string column
+    Parquet,
+}
+
+impl InputFormat {
+    /// Guess the format from the extension of `path`
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match extension(path).as_deref() {
+            Some("jsonl" | "ndjson") => Ok(Self::Jsonl),
+            Some("csv") => Ok(Self::Csv),
+            Some("parquet") => Ok(Self::Parquet),
+            _ => anyhow::bail!(
+                "Unknown format for `{}`: set `--input-format`",
+                path.display()
+            ),
+        }
+    }
+}
+
+pub(crate) fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+/// Texts of the rows of a file. Blank lines are skipped. A row without text is an `Err` with
+/// the reason.
+pub(crate) enum Texts {
+    Jsonl {
+        lines: Lines<BufReader<File>>,
+        field: String,
+    },
+    Csv {
+        records: csv::StringRecordsIntoIter<File>,
+        position: usize,
+    },
+    Parquet {
+        batches: ParquetRecordBatchReader,
+        column: String,
+        /// Texts of the current record batch and position of the next one
+        texts: StringArray,
+        next: usize,
+    },
+}
+
+impl Texts {
+    pub fn open(path: &Path, format: InputFormat, column: &str) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open `{}`", path.display()))?;
+
+        match format {
+            InputFormat::Jsonl => Ok(Self::Jsonl {
+                lines: BufReader::new(file).lines(),
+                field: column.to_string(),
+            }),
+            InputFormat::Csv => {
+                // Records may have fewer fields than the header
+                let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(file);
+                let header = reader
+                    .headers()
+                    .with_context(|| format!("Failed to read `{}`", path.display()))?;
+                if header.is_empty() {
+                    anyhow::bail!("`{}` is empty", path.display());
+                }
+                let position = header
+                    .iter()
+                    .position(|name| name.trim() == column)
+                    .with_context(|| {
+                        format!("`{}` has no `{column}` column: {header:?}", path.display())
+                    })?;
+                Ok(Self::Csv {
+                    records: reader.into_records(),
+                    position,
+                })
+            }
+            InputFormat::Parquet => {
+                let builder =
+                    ParquetRecordBatchReaderBuilder::try_new(file).with_context(|| {
+                        format!("Failed to read the Parquet file `{}`", path.display())
+                    })?;
+                let schema = builder.schema().clone();
+                let (index, field) = schema.column_with_name(column).with_context(|| {
+                    let names: Vec<_> = schema.fields().iter().map(|field| field.name()).collect();
+                    format!("`{}` has no `{column}` column: {names:?}", path.display())
+                })?;
+                if !is_string(field.data_type()) {
+                    anyhow::bail!(
+                        "the `{column}` column of `{}` holds `{}` values instead of strings",
+                        path.display(),
+                        field.data_type()
+                    );
+                }
+
+                // Only decode the column of the texts
+                let mask = ProjectionMask::roots(builder.parquet_schema(), [index]);
+                Ok(Self::Parquet {
+                    batches: builder.with_projection(mask).build()?,
+                    column: column.to_string(),
+                    texts: StringArray::new_null(0),
+                    next: 0,
+                })
+            }
+        }
+    }
+}
+
+impl Iterator for Texts {
+    type Item = Result<Result<String, String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Jsonl { lines, field } => {
+                let line = loop {
+                    match lines.next()? {
+                        Ok(line) if line.trim().is_empty() => continue,
+                        Ok(line) => break line,
+                        Err(err) => return Some(Err(err.into())),
+                    }
+                };
+                let text = match serde_json::from_str::<serde_json::Value>(&line) {
+                    Ok(value) => match value.get(&*field).and_then(|text| text.as_str()) {
+                        Some(text) => Ok(text.to_string()),
+                        None => Err(format!("no `{field}` string field")),
+                    },
+                    Err(err) => Err(format!("invalid JSON: {err}")),
+                };
+                Some(Ok(text))
+            }
+            Self::Csv { records, position } => {
+                let record = loop {
+                    match records.next()? {
+                        Ok(record) if record.len() == 1 && record[0].trim().is_empty() => continue,
+                        Ok(record) => break record,
+                        Err(err) => return Some(Err(err.into())),
+                    }
+                };
+                let text = record
+                    .get(*position)
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("no field at position {position}"));
+                Some(Ok(text))
+            }
+            Self::Parquet {
+                batches,
+                column,
+                texts,
+                next,
+            } => {
+                while *next == texts.len() {
+                    let batch = match batches.next()? {
+                        Ok(batch) => batch,
+                        Err(err) => return Some(Err(err.into())),
+                    };
+                    // Large, view and dictionary encoded strings are read as `Utf8` arrays
+                    match arrow_cast::cast(batch.column(0), &DataType::Utf8) {
+                        Ok(array) => *texts = array.as_string::<i32>().clone(),
+                        Err(err) => return Some(Err(err.into())),
+                    }
+                    *next = 0;
+                }
+
+                let i = *next;
+                *next += 1;
+                let text = match texts.is_null(i) {
+                    true => Err(format!("null `{column}` value")),
+                    false => Ok(texts.value(i).to_string()),
+                };
+                Some(Ok(text))
+            }
+        }
+    }
+}
+
+fn is_string(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => true,
+        DataType::Dictionary(_, values) => is_string(values),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::input::{InputFormat, Texts};
+    use arrow_array::types::Int32Type;
+    use arrow_array::StringArray;
+    use arrow_array::{ArrayRef, DictionaryArray, Int64Array, LargeStringArray, RecordBatch};
+    use parquet::arrow::ArrowWriter;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn read_texts(path: &Path, format: InputFormat, column: &str) -> Vec<Result<String, String>> {
+        Texts::open(path, format, column)
+            .unwrap()
+            .collect::<anyhow::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_from_path() {
+        for (path, format) in [
+            ("corpus.jsonl", InputFormat::Jsonl),
+            ("corpus.NDJSON", InputFormat::Jsonl),
+            ("corpus.csv", InputFormat::Csv),
+            ("corpus.parquet", InputFormat::Parquet),
+        ] {
+            assert_eq!(InputFormat::from_path(Path::new(path)).unwrap(), format);
+        }
+        assert!(InputFormat::from_path(Path::new("corpus.txt")).is_err());
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corpus.jsonl");
+        std::fs::write(
+            &path,
+            "{\"text\": \"a \\\"quoted\\\"\\nline\"}\n\n  \n{\"id\": 1}\n{\"text\": 2}\nnot json\n{\"text\": \"b\"}",
+        )
+        .unwrap();
+
+        let texts = read_texts(&path, InputFormat::Jsonl, "text");
+        assert_eq!(texts.len(), 5);
+        assert_eq!(texts[0], Ok("a \"quoted\"\nline".to_string()));
+        assert_eq!(texts[1], Err("no `text` string field".to_string()));
+        assert_eq!(texts[2], Err("no `text` string field".to_string()));
+        assert!(texts[3].as_ref().unwrap_err().starts_with("invalid JSON"));
+        assert_eq!(texts[4], Ok("b".to_string()));
+    }
+
+    #[test]
+    fn test_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corpus.csv");
+        std::fs::write(
+            &path,
+            concat!(
+                "id, text ,lang\r\n",
+                "1,plain,en\r\n",
+                "2,\"with, a comma\",en\n",
+                "3,\"on\r\ntwo lines\",en\n",
+                "\n",
+                "4,\"\"\"escaped\"\" quotes\",en\n",
+                "5,\"\",en\n",
+                "6\n",
+                "7,unquoted \"quotes\",en\n",
+                "8,last,en",
+            ),
+        )
+        .unwrap();
+
+        let texts = read_texts(&path, InputFormat::Csv, "text");
+        assert_eq!(
+            texts,
+            [
+                Ok("plain".to_string()),
+                Ok("with, a comma".to_string()),
+                Ok("on\r\ntwo lines".to_string()),
+                Ok("\"escaped\" quotes".to_string()),
+                Ok(String::new()),
+                Err("no field at position 1".to_string()),
+                Ok("unquoted \"quotes\"".to_string()),
+                Ok("last".to_string()),
+            ]
+        );
+
+        let err = Texts::open(&path, InputFormat::Csv, "content")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("has no `content` column"));
+        std::fs::write(&path, "").unwrap();
+        let err = Texts::open(&path, InputFormat::Csv, "text").err().unwrap();
+        assert!(err.to_string().ends_with("is empty"));
+    }
+
+    #[test]
+    fn test_parquet() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corpus.parquet");
+
+        // More rows than a record batch
+        let rows = 2500;
+        let text: StringArray = (0..rows)
+            .map(|i| (i != 3).then(|| format!("text {i}")))
+            .collect();
+        let large: LargeStringArray = (0..rows).map(|i| Some(format!("large {i}"))).collect();
+        let dictionary: DictionaryArray<Int32Type> = (0..rows).map(|i| ["a", "b"][i % 2]).collect();
+        let batch = RecordBatch::try_from_iter([
+            (
+                "id",
+                Arc::new(Int64Array::from_iter_values(0..rows as i64)) as ArrayRef,
+            ),
+            ("text", Arc::new(text)),
+            ("large", Arc::new(large)),
+            ("dictionary", Arc::new(dictionary)),
+        ])
+        .unwrap();
+        let mut writer =
+            ArrowWriter::try_new(std::fs::File::create(&path).unwrap(), batch.schema(), None)
+                .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let texts = read_texts(&path, InputFormat::Parquet, "text");
+        assert_eq!(texts.len(), rows);
+        assert_eq!(texts[0], Ok("text 0".to_string()));
+        assert_eq!(texts[3], Err("null `text` value".to_string()));
+        assert_eq!(texts[rows - 1], Ok(format!("text {}", rows - 1)));
+
+        let large = read_texts(&path, InputFormat::Parquet, "large");
+        assert_eq!(large[2000], Ok("large 2000".to_string()));
+        let dictionary = read_texts(&path, InputFormat::Parquet, "dictionary");
+        assert_eq!(
+            dictionary[..3],
+            [Ok("a".into()), Ok("b".into()), Ok("a".into())]
+        );
+
+        let err = Texts::open(&path, InputFormat::Parquet, "id")
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("holds `Int64` values instead of strings"));
+        let err = Texts::open(&path, InputFormat::Parquet, "content")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("has no `content` column"));
+    }
+}
@@ -0,0 +1,470 @@
+/// Offline embedding of JSONL, CSV and Parquet files
+mod input;
+mod output;
+
+pub use input::InputFormat;
+pub use output::OutputFormat;
+
+use crate::{npy, Info};
+use anyhow::{anyhow, Context, Result};
+use futures::future::join_all;
+use indicatif::{ProgressBar, ProgressStyle};
+use input::Texts;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::future::Future;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use text_embeddings_core::infer::Infer;
+use text_embeddings_core::queue::Priority;
+use tokenizers::TruncationDirection;
+use tokio::sync::mpsc;
+
+#[derive(Debug)]
+pub struct BatchOptions {
+    pub input: PathBuf,
+    /// Guessed from the extension of `input` if not set
+    pub input_format: Option<InputFormat>,
+    /// JSON field, CSV column or Parquet column holding the texts
+    pub text_column: String,
+    pub output: PathBuf,
+    /// Guessed from the extension of `output` if not set
+    pub output_format: Option<OutputFormat>,
+    pub prompt_name: Option<String>,
+    pub normalize: bool,
+    pub dimensions: Option<usize>,
+    /// Number of rows embedded between two checkpoints
+    pub checkpoint_rows: usize,
+    /// Continue from the checkpoint of an interrupted run
+    pub resume: bool,
+    pub progress: bool,
+}
+
+/// Progress of a run, saved next to the output file
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    /// Number of rows of the input
+    total: usize,
+    columns: usize,
+    /// Number of rows already written
+    rows: usize,
+    failed: usize,
+    /// Size of the output at the checkpoint
+    output_bytes: u64,
+}
+
+impl Checkpoint {
+    fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read(path)
+            .with_context(|| format!("Failed to read the checkpoint `{}`", path.display()))?;
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse the checkpoint `{}`", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        // Write to a temporary file first to never leave a truncated checkpoint behind
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .with_context(|| format!("Failed to write `{}`", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to write the checkpoint `{}`", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Embed the texts of `options.input` into `options.output`. Rows that cannot be embedded are
+/// written as `NaN`.
+pub(crate) async fn run(infer: Infer, info: Info, options: BatchOptions) -> Result<()> {
+    let dimensions = info
+        .dimensions(options.dimensions)
+        .map_err(|err| anyhow!(err.error))?;
+    let columns = dimensions
+        .or(info.hidden_size)
+        .context("The embedding size of the model is unknown: set `--dimensions`")?;
+
+    let truncate = info.auto_truncate;
+    let embed = |text: String| {
+        let infer = &infer;
+        let prompt_name = options.prompt_name.clone();
+        async move {
+            let permit = infer.acquire_permit(Priority::Normal).await;
+            infer
+                .embed_pooled(
+                    text,
+                    truncate,
+                    TruncationDirection::Right,
+                    prompt_name,
+                    options.normalize,
+                    dimensions,
+                    Priority::Normal,
+                    None,
+                    permit,
+                )
+                .await
+                .map(|response| response.results)
+                .map_err(|err| err.to_string())
+        }
+    };
+    embed_file(&options, columns, embed).await
+}
+
+/// Embed the texts of `options.input` with `embed`, saving a checkpoint every
+/// `options.checkpoint_rows` rows
+async fn embed_file<F, R>(options: &BatchOptions, columns: usize, embed: F) -> Result<()>
+where
+    F: Fn(String) -> R,
+    R: Future<Output = Result<Vec<f32>, String>>,
+{
+    let input_format = match options.input_format {
+        Some(format) => format,
+        None => InputFormat::from_path(&options.input)?,
+    };
+    let output_format = match options.output_format {
+        Some(format) => format,
+        None => OutputFormat::from_path(&options.output)?,
+    };
+    let rows_path = output_format.rows_path(&options.output);
+
+    // The `.npy` and `.safetensors` formats store the number of rows in their header
+    tracing::info!("Counting the rows of {}", options.input.display());
+    let total = {
+        let path = options.input.clone();
+        let column = options.text_column.clone();
+        tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut total = 0;
+            for text in Texts::open(&path, input_format, &column)? {
+                // Rows without text are counted too: they are written as `NaN`
+                let _ = text?;
+                total += 1;
+            }
+            Ok(total)
+        })
+        .await??
+    };
+    if total == 0 {
+        anyhow::bail!("`{}` has no rows", options.input.display());
+    }
+
+    let mut checkpoint_path = options.output.clone().into_os_string();
+    checkpoint_path.push(".checkpoint");
+    let checkpoint_path = PathBuf::from(checkpoint_path);
+
+    let mut checkpoint = if options.resume {
+        let checkpoint = Checkpoint::load(&checkpoint_path)?;
+        if checkpoint.total != total || checkpoint.columns != columns {
+            anyhow::bail!(
+                "the checkpoint is for {} rows of {} dimensions but the run has {total} rows of {columns} dimensions",
+                checkpoint.total,
+                checkpoint.columns
+            );
+        }
+        tracing::info!("Resuming after {} rows", checkpoint.rows);
+        checkpoint
+    } else {
+        if options.output.exists() {
+            anyhow::bail!(
+                "`{}` already exists: use `--resume` to continue an interrupted run or delete it",
+                options.output.display()
+            );
+        }
+        let mut file = BufWriter::new(
+            File::create(&rows_path)
+                .with_context(|| format!("Failed to create `{}`", rows_path.display()))?,
+        );
+        output_format.write_header(&mut file, total, columns)?;
+        let output_bytes = file.stream_position()?;
+        file.into_inner()?.sync_all()?;
+
+        let checkpoint = Checkpoint {
+            total,
+            columns,
+            rows: 0,
+            failed: 0,
+            output_bytes,
+        };
+        checkpoint.save(&checkpoint_path)?;
+        checkpoint
+    };
+
+    // Drop the rows written after the checkpoint
+    let mut output = OpenOptions::new()
+        .write(true)
+        .open(&rows_path)
+        .with_context(|| format!("Failed to open `{}`", rows_path.display()))?;
+    output.set_len(checkpoint.output_bytes)?;
+    output.seek(SeekFrom::End(0))?;
+    let mut output = BufWriter::new(output);
+
+    // Read the texts in a blocking task. The bound pauses the reader while the chunks are
+    // embedded.
+    let (sender, mut receiver) = mpsc::channel(2);
+    let reader = {
+        let path = options.input.clone();
+        let column = options.text_column.clone();
+        let skip = checkpoint.rows;
+        let checkpoint_rows = options.checkpoint_rows.max(1);
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut texts = Texts::open(&path, input_format, &column)?.skip(skip);
+            loop {
+                let chunk = texts
+                    .by_ref()
+                    .take(checkpoint_rows)
+                    .collect::<Result<Vec<_>, _>>()?;
+                if chunk.is_empty() || sender.blocking_send(chunk).is_err() {
+                    return Ok(());
+                }
+            }
+        })
+    };
+
+    let progress = if options.progress {
+        ProgressBar::new(total as u64)
+    } else {
+        ProgressBar::hidden()
+    };
+    progress.set_style(
+        ProgressStyle::with_template(
+            "{elapsed_precise} [{bar:40}] {pos}/{len} rows ({per_sec}, ETA {eta})",
+        )?
+        .progress_chars("=> "),
+    );
+    progress.set_position(checkpoint.rows as u64);
+
+    let missing = vec![f32::NAN; columns];
+    while let Some(chunk) = receiver.recv().await {
+        let futures = chunk.into_iter().map(|text| {
+            let embed = &embed;
+            async move { embed(text?).await }
+        });
+
+        let results = join_all(futures).await;
+        let size = results.len();
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(embedding) if embedding.len() == columns => {
+                    npy::write_row(&mut output, &embedding)?;
+                }
+                Ok(embedding) => anyhow::bail!(
+                    "the model returned {} dimensions instead of {columns}",
+                    embedding.len()
+                ),
+                Err(err) => {
+                    progress.suspend(|| {
+                        tracing::warn!("Row {} was not embedded: {err}", checkpoint.rows + i)
+                    });
+                    checkpoint.failed += 1;
+                    npy::write_row(&mut output, &missing)?;
+                }
+            }
+        }
+        output.flush()?;
+        output.get_ref().sync_data()?;
+
+        checkpoint.rows += size;
+        checkpoint.output_bytes = output.stream_position()?;
+        checkpoint.save(&checkpoint_path)?;
+        progress.inc(size as u64);
+    }
+    reader.await??;
+    progress.finish();
+
+    if checkpoint.rows != total {
+        anyhow::bail!(
+            "`{}` changed: {} rows were read instead of {total}",
+            options.input.display(),
+            checkpoint.rows
+        );
+    }
+    output_format.finish(&rows_path, columns, &options.output)?;
+    std::fs::remove_file(&checkpoint_path)?;
+    if rows_path != options.output {
+        std::fs::remove_file(&rows_path)?;
+    }
+
+    tracing::info!(
+        "Embedded {total} rows into {} ({} failed)",
+        options.output.display(),
+        checkpoint.failed
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::{embed_file, BatchOptions, Checkpoint, OutputFormat};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Float32Type;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+
+    fn options(input: &Path, output: PathBuf) -> BatchOptions {
+        BatchOptions {
+            input: input.to_path_buf(),
+            input_format: None,
+            text_column: "text".to_string(),
+            output,
+            output_format: None,
+            prompt_name: None,
+            normalize: true,
+            dimensions: None,
+            checkpoint_rows: 2,
+            resume: false,
+            progress: false,
+        }
+    }
+
+    fn write_input(path: &Path, texts: &[&str]) {
+        let lines: Vec<_> = texts
+            .iter()
+            .map(|text| serde_json::json!({ "text": text }).to_string())
+            .collect();
+        std::fs::write(path, lines.join("\n")).unwrap();
+    }
+
+    /// Embedding of `row <i>`: the length of the text and `i`
+    async fn embed(text: String) -> Result<Vec<f32>, String> {
+        match text.strip_prefix("row ") {
+            Some(i) => Ok(vec![text.len() as f32, i.parse().unwrap()]),
+            None => Err(format!("cannot embed `{text}`")),
+        }
+    }
+
+    /// Rows of an output file with 2 columns
+    fn read_output(path: &Path, format: OutputFormat, rows: usize) -> Vec<Vec<f32>> {
+        let values: Vec<f32> = match format {
+            OutputFormat::Npy | OutputFormat::Safetensors => {
+                let mut header = Vec::new();
+                format.write_header(&mut header, rows, 2).unwrap();
+                let content = std::fs::read(path).unwrap();
+                assert_eq!(content[..header.len()], header);
+                content[header.len()..]
+                    .chunks(4)
+                    .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect()
+            }
+            OutputFormat::Parquet => {
+                ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+                    .unwrap()
+                    .build()
+                    .unwrap()
+                    .flat_map(|batch| {
+                        let batch = batch.unwrap();
+                        let embeddings = batch.column(0).as_fixed_size_list();
+                        assert_eq!(embeddings.value_length(), 2);
+                        let values = embeddings.values().as_primitive::<Float32Type>();
+                        values.values().to_vec()
+                    })
+                    .collect()
+            }
+        };
+        assert_eq!(values.len(), rows * 2);
+        values.chunks(2).map(<[f32]>::to_vec).collect()
+    }
+
+    #[tokio::test]
+    async fn test_embed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("corpus.jsonl");
+        write_input(&input, &["row 0", "row 1", "skipped", "row 3"]);
+
+        for (name, format) in [
+            ("embeddings.npy", OutputFormat::Npy),
+            ("embeddings.safetensors", OutputFormat::Safetensors),
+            ("embeddings.parquet", OutputFormat::Parquet),
+        ] {
+            let output = dir.path().join(name);
+            embed_file(&options(&input, output.clone()), 2, embed)
+                .await
+                .unwrap();
+
+            // Rows that cannot be embedded are `NaN`
+            let rows = read_output(&output, format, 4);
+            assert_eq!(rows[0], [5.0, 0.0]);
+            assert_eq!(rows[1], [5.0, 1.0]);
+            assert!(rows[2].iter().all(|value| value.is_nan()));
+            assert_eq!(rows[3], [5.0, 3.0]);
+
+            // Only the output is left
+            let files = std::fs::read_dir(dir.path()).unwrap().count();
+            assert_eq!(files, 2);
+            std::fs::remove_file(&output).unwrap();
+        }
+
+        let output = dir.path().join("embeddings.npy");
+        std::fs::write(&output, "").unwrap();
+        let err = embed_file(&options(&input, output), 2, embed)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("corpus.jsonl");
+        let texts: Vec<_> = (0..7).map(|i| format!("row {i}")).collect();
+        write_input(
+            &input,
+            &texts.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+
+        for (name, format) in [
+            ("embeddings.npy", OutputFormat::Npy),
+            ("embeddings.parquet", OutputFormat::Parquet),
+        ] {
+            let output = dir.path().join(name);
+            let rows_path = format.rows_path(&output);
+            let checkpoint_path = dir.path().join(format!("{name}.checkpoint"));
+
+            // The run stops on the second row of the third chunk
+            let err = embed_file(&options(&input, output.clone()), 2, |text| async move {
+                match text.as_str() {
+                    "row 5" => Ok(vec![0.0; 3]),
+                    _ => embed(text).await,
+                }
+            })
+            .await
+            .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "the model returned 3 dimensions instead of 2"
+            );
+            let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+            assert_eq!((checkpoint.total, checkpoint.rows), (7, 4));
+            // The first row of the chunk was written after the checkpoint
+            let length = std::fs::metadata(&rows_path).unwrap().len();
+            assert_eq!(length, checkpoint.output_bytes + 8);
+
+            // The checkpoint is for another input
+            let other = dir.path().join("other.jsonl");
+            write_input(&other, &["row 0"]);
+            let mut options = options(&other, output.clone());
+            options.resume = true;
+            let err = embed_file(&options, 2, embed).await.unwrap_err();
+            assert!(err
+                .to_string()
+                .starts_with("the checkpoint is for 7 rows of 2 dimensions"));
+
+            // The rows written after the checkpoint are embedded again, the others are skipped
+            let embedded = Mutex::new(Vec::new());
+            options.input = input.clone();
+            options.checkpoint_rows = 3;
+            embed_file(&options, 2, |text| {
+                embedded.lock().unwrap().push(text.clone());
+                embed(text)
+            })
+            .await
+            .unwrap();
+            assert_eq!(embedded.into_inner().unwrap(), &texts[4..]);
+
+            let rows = read_output(&output, format, 7);
+            for (i, row) in rows.iter().enumerate() {
+                assert_eq!(row, &[5.0, i as f32]);
+            }
+            assert!(!checkpoint_path.exists());
+            assert_eq!(rows_path.exists(), format == OutputFormat::Npy);
+        }
+    }
+}
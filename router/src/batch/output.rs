@@ -0,0 +1,185 @@
+/// Embedding files
+use crate::batch::input::extension;
+use crate::npy;
+use anyhow::{Context, Result};
+use arrow_array::{FixedSizeListArray, Float32Array, RecordBatch};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Number of rows of the row groups of Parquet files
+const ROW_GROUP_ROWS: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// NumPy `float32` array of shape `[rows, dimensions]`
+    Npy,
+    /// `embeddings` `F32` tensor of shape `[rows, dimensions]`
+    Safetensors,
+    /// Apache Parquet file with an `embedding` column of `float32` fixed size lists
+    Parquet,
+}
+
+impl OutputFormat {
+    /// Guess the format from the extension of `path`
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match extension(path).as_deref() {
+            Some("npy") => Ok(Self::Npy),
+            Some("safetensors") => Ok(Self::Safetensors),
+            Some("parquet") => Ok(Self::Parquet),
+            _ => anyhow::bail!(
+                "Unknown format for `{}`: set `--output-format`",
+                path.display()
+            ),
+        }
+    }
+
+    /// File the rows are written to while they are embedded. Parquet files are written once
+    /// all the rows are embedded: their rows are kept in `<path>.partial` until then.
+    pub(crate) fn rows_path(&self, path: &Path) -> PathBuf {
+        match self {
+            Self::Npy | Self::Safetensors => path.to_path_buf(),
+            Self::Parquet => {
+                let mut rows_path = path.to_path_buf().into_os_string();
+                rows_path.push(".partial");
+                PathBuf::from(rows_path)
+            }
+        }
+    }
+
+    /// Write the header of a `rows x columns` array. The values follow as little-endian
+    /// `float32` in row-major order. The rows of Parquet files have no header.
+    pub fn write_header(
+        &self,
+        writer: &mut impl Write,
+        rows: usize,
+        columns: usize,
+    ) -> io::Result<()> {
+        match self {
+            Self::Npy => npy::write_header(writer, rows, columns),
+            Self::Safetensors => {
+                let size = rows * columns * std::mem::size_of::<f32>();
+                let mut header = serde_json::json!({
+                    "embeddings": {
+                        "dtype": "F32",
+                        "shape": [rows, columns],
+                        "data_offsets": [0, size],
+                    }
+                })
+                .to_string();
+                // The data starts on an 8 bytes boundary
+                while header.len() % 8 != 0 {
+                    header.push(' ');
+                }
+                writer.write_all(&(header.len() as u64).to_le_bytes())?;
+                writer.write_all(header.as_bytes())
+            }
+            Self::Parquet => Ok(()),
+        }
+    }
+
+    /// Write `path` from the rows of `rows_path` once all the rows are embedded
+    pub(crate) fn finish(&self, rows_path: &Path, columns: usize, path: &Path) -> Result<()> {
+        match self {
+            Self::Npy | Self::Safetensors => Ok(()),
+            Self::Parquet => write_parquet(rows_path, columns, path)
+                .with_context(|| format!("Failed to write `{}`", path.display())),
+        }
+    }
+}
+
+fn write_parquet(rows_path: &Path, columns: usize, path: &Path) -> Result<()> {
+    let item = Arc::new(Field::new("item", DataType::Float32, false));
+    let size = i32::try_from(columns)?;
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "embedding",
+        DataType::FixedSizeList(item.clone(), size),
+        false,
+    )]));
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(ROW_GROUP_ROWS)
+        .build();
+
+    // Write to a temporary file first to never leave a truncated file behind
+    let tmp_path = path.with_extension("tmp");
+    let mut writer =
+        ArrowWriter::try_new(File::create(&tmp_path)?, schema.clone(), Some(properties))?;
+    let mut rows = BufReader::new(File::open(rows_path)?);
+    let mut chunk = Vec::new();
+    loop {
+        chunk.clear();
+        let length = ROW_GROUP_ROWS * columns * std::mem::size_of::<f32>();
+        (&mut rows).take(length as u64).read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        // Unwrap is safe here: the chunks have the size of a `f32`
+        let values: Float32Array = chunk
+            .chunks_exact(std::mem::size_of::<f32>())
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        let embeddings = FixedSizeListArray::try_new(item.clone(), size, Arc::new(values), None)?;
+        writer.write(&RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(embeddings)],
+        )?)?;
+    }
+    writer.into_inner()?.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::batch::output::{OutputFormat, ROW_GROUP_ROWS};
+    use arrow_schema::DataType;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::path::Path;
+
+    #[test]
+    fn test_from_path() {
+        for (path, format) in [
+            ("embeddings.npy", OutputFormat::Npy),
+            ("embeddings.safetensors", OutputFormat::Safetensors),
+            ("embeddings.Parquet", OutputFormat::Parquet),
+        ] {
+            assert_eq!(OutputFormat::from_path(Path::new(path)).unwrap(), format);
+        }
+        assert!(OutputFormat::from_path(Path::new("embeddings.bin")).is_err());
+        assert_eq!(
+            OutputFormat::Parquet.rows_path(Path::new("out/embeddings.parquet")),
+            Path::new("out/embeddings.parquet.partial")
+        );
+    }
+
+    #[test]
+    fn test_parquet_row_groups() {
+        let dir = tempfile::tempdir().unwrap();
+        let rows_path = dir.path().join("embeddings.parquet.partial");
+        let path = dir.path().join("embeddings.parquet");
+
+        let rows = ROW_GROUP_ROWS + 1;
+        let values: Vec<u8> = (0..rows * 3)
+            .flat_map(|i| (i as f32).to_le_bytes())
+            .collect();
+        std::fs::write(&rows_path, values).unwrap();
+        OutputFormat::Parquet.finish(&rows_path, 3, &path).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 2);
+        assert_eq!(builder.metadata().file_metadata().num_rows(), rows as i64);
+        let field = builder.schema().field(0).clone();
+        assert_eq!(field.name(), "embedding");
+        assert!(!field.is_nullable());
+        assert!(
+            matches!(field.data_type(), DataType::FixedSizeList(item, 3) if *item.data_type() == DataType::Float32)
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+use text_embeddings_backend::{DType, OrtExecutionMode, OrtOptimizationLevel, OrtSessionConfig};
+use text_embeddings_router::batch::{BatchOptions, InputFormat, OutputFormat};
+use veil::Redact;
+
+#[cfg(not(target_os = "linux"))]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+/// Embed the rows of a JSONL, CSV or Parquet file without starting a server
+#[derive(Parser, Redact)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// The name of the model to load.
+    /// Can be a MODEL_ID as listed on <https://hf.co/models> like
+    /// `BAAI/bge-large-en-v1.5`.
+    /// Or it can be a local directory containing the necessary files
+    /// as saved by `save_pretrained(...)` methods of transformers
+    #[clap(default_value = "/data/gte-multilingual-base-onnx", long, env)]
+    #[redact(partial)]
+    model_id: String,
+
+    /// The actual revision of the model if you're referring to a model
+    /// on the hub. You can use a specific commit id or a branch like `refs/pr/2`.
+    #[clap(long, env)]
+    revision: Option<String>,
+
+    /// File to embed. The format is guessed from the `.jsonl`, `.ndjson`, `.csv` and `.parquet`
+    /// extensions.
+    #[clap(long, short)]
+    input: PathBuf,
+
+    #[clap(long, value_enum)]
+    input_format: Option<InputFormat>,
+
+    /// JSON field, CSV column or Parquet column holding the texts
+    #[clap(default_value = "text", long)]
+    text_column: String,
+
+    /// Embeddings file. The format is guessed from the `.npy`, `.safetensors` and `.parquet`
+    /// extensions.
+    ///
+    /// Rows that cannot be embedded are written as `NaN`.
+    #[clap(long, short)]
+    output: PathBuf,
+
+    #[clap(long, value_enum)]
+    output_format: Option<OutputFormat>,
+
+    /// The name of the Sentence Transformers prompt applied to every text
+    #[clap(long, env)]
+    prompt_name: Option<String>,
+
+    /// Do not normalize the embeddings
+    #[clap(long)]
+    no_normalize: bool,
+
+    /// Truncate the embeddings of Matryoshka models to this many dimensions
+    #[clap(long)]
+    dimensions: Option<usize>,
+
+    /// Automatically truncate inputs that are longer than the maximum supported size.
+    /// Otherwise, these rows are not embedded.
+    #[clap(long, env)]
+    auto_truncate: bool,
+
+    /// Number of rows embedded between two checkpoints. The checkpoint is saved to
+    /// `<output>.checkpoint` and removed once all the rows are embedded.
+    #[clap(default_value = "1024", long)]
+    checkpoint_rows: usize,
+
+    /// Continue an interrupted run from its checkpoint
+    #[clap(long)]
+    resume: bool,
+
+    /// Do not show the progress bar
+    #[clap(long)]
+    no_progress: bool,
+
+    /// Optionally control the number of tokenizer workers used for payload tokenization, validation
+    /// and truncation.
+    /// Default to the number of CPU cores on the machine.
+    #[clap(long, env)]
+    tokenization_workers: Option<usize>,
+
+    /// The dtype to be forced upon the model.
+    #[clap(long, env, value_enum)]
+    dtype: Option<DType>,
+
+    /// Optionally control the pooling method for embedding models.
+    ///
+    /// If `pooling` is not set, the pooling configuration will be parsed from the
+    /// model `1_Pooling/config.json` configuration.
+    #[clap(long, env, value_enum)]
+    pooling: Option<text_embeddings_backend::Pool>,
+
+    /// The number of threads ONNX Runtime uses to run a single operator.
+    /// Default to the number of CPU cores on the machine.
+    #[clap(long, env)]
+    ort_intra_threads: Option<usize>,
+
+    /// The number of threads ONNX Runtime uses to run independent operators with the `parallel`
+    /// execution mode. Default to the ONNX Runtime default.
+    #[clap(long, env)]
+    ort_inter_threads: Option<usize>,
+
+    /// The ONNX Runtime execution mode
+    #[clap(default_value = "sequential", long, env, value_enum)]
+    ort_execution_mode: OrtExecutionMode,
+
+    /// The ONNX Runtime graph optimization level
+    #[clap(default_value = "all", long, env, value_enum)]
+    ort_optimization_level: OrtOptimizationLevel,
+
+    /// Save the model optimized by ONNX Runtime to this path and load it from there on the next
+    /// start instead of optimizing it again.
+    #[clap(long, env)]
+    ort_optimized_model_path: Option<PathBuf>,
+
+    /// Disable the ONNX Runtime CPU memory arena
+    #[clap(long, env)]
+    ort_disable_memory_arena: bool,
+
+    /// Disable the ONNX Runtime memory pattern optimization
+    #[clap(long, env)]
+    ort_disable_memory_pattern: bool,
+
+    /// The maximum number of requests in a batch for the ONNX Runtime backend
+    #[clap(default_value = "8", long, env)]
+    ort_max_batch_size: usize,
+
+    /// The maximum number of rows embedded concurrently
+    #[clap(default_value = "512", long, env)]
+    max_concurrent_requests: usize,
+
+    /// The total amount of potential tokens within a batch
+    #[clap(default_value = "16384", long, env)]
+    max_batch_tokens: usize,
+
+    /// Optionally control the maximum number of individual requests in a batch
+    #[clap(long, env)]
+    max_batch_requests: Option<usize>,
+
+    /// The name of the prompt that should be used by default for encoding
+    #[clap(long, env, conflicts_with = "default_prompt")]
+    default_prompt_name: Option<String>,
+
+    /// The prompt that should be used by default for encoding
+    #[clap(long, env, conflicts_with = "default_prompt_name")]
+    default_prompt: Option<String>,
+
+    /// Your HuggingFace hub token
+    #[clap(long, env)]
+    #[redact(partial)]
+    hf_api_token: Option<String>,
+
+    /// The name of the unix socket some text-embeddings-inference backends will use as they
+    /// communicate internally with gRPC.
+    #[clap(default_value = "/tmp/text-embeddings-inference-batch", long, env)]
+    uds_path: String,
+
+    /// The location of the huggingface hub cache.
+    /// Used to override the location if you want to provide a mounted disk for instance
+    #[clap(long, env)]
+    huggingface_hub_cache: Option<String>,
+
+    /// Outputs the logs in JSON format (useful for telemetry)
+    #[clap(long, env)]
+    json_output: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args: Args = Args::parse();
+
+    text_embeddings_router::init_logging(
+        None,
+        "text-embeddings-inference.batch".to_string(),
+        args.json_output,
+    );
+
+    tracing::info!("{args:?}");
+
+    let ort_session_config = OrtSessionConfig {
+        intra_threads: args.ort_intra_threads,
+        inter_threads: args.ort_inter_threads,
+        execution_mode: args.ort_execution_mode,
+        optimization_level: args.ort_optimization_level,
+        optimized_model_path: args.ort_optimized_model_path,
+        memory_arena: !args.ort_disable_memory_arena,
+        memory_pattern: !args.ort_disable_memory_pattern,
+        max_batch_size: args.ort_max_batch_size,
+    };
+
+    let options = BatchOptions {
+        input: args.input,
+        input_format: args.input_format,
+        text_column: args.text_column,
+        output: args.output,
+        output_format: args.output_format,
+        prompt_name: args.prompt_name,
+        normalize: !args.no_normalize,
+        dimensions: args.dimensions,
+        checkpoint_rows: args.checkpoint_rows,
+        resume: args.resume,
+        progress: !args.no_progress,
+    };
+
+    text_embeddings_router::run_batch(
+        args.model_id,
+        args.revision,
+        args.tokenization_workers,
+        args.dtype,
+        args.pooling,
+        ort_session_config,
+        args.max_concurrent_requests,
+        args.max_batch_tokens,
+        args.max_batch_requests,
+        args.auto_truncate,
+        args.default_prompt,
+        args.default_prompt_name,
+        args.hf_api_token,
+        Some(args.uds_path),
+        args.huggingface_hub_cache,
+        options,
+    )
+    .await
+}
@@ -1,16 +1,21 @@
-use crate::grpc::pb::tei::v1::{EmbedAllRequest, EmbedAllResponse, EmbedSparseRequest, EmbedSparseResponse, EncodeRequest, EncodeResponse, KeyValue, PredictPairRequest, RerankStreamRequest, SimpleToken, SparseValue, TokenEmbedding, TruncationDirection};
+use crate::grpc::pb::tei::v1::{EmbedAllRequest, EmbedAllResponse, EmbedMultiVectorRequest, EmbedMultiVectorResponse, EmbedOutput, EmbedSparseRequest, EmbedSparseResponse, EncodeRequest, EncodeResponse, EncodingFormat, KeyValue, PredictPairRequest, Priority, RerankStreamRequest, ServedModel, SimpleToken, SparseValue, TokenEmbedding, TruncationDirection};
 use crate::grpc::{
     DecodeRequest, DecodeResponse, EmbedRequest, EmbedResponse, InfoRequest, InfoResponse,
     PredictRequest, PredictResponse, Prediction, Rank, RerankRequest, RerankResponse,
 };
+use crate::quantization::{quantize, Precision};
 use crate::ResponseMetadata;
-use crate::{grpc, shutdown, ErrorResponse, ErrorType, Info, ModelType};
+use crate::{grpc, ErrorResponse, ErrorType, Info, ModelType};
+use anyhow::Context;
 use futures::future::join_all;
-use metrics_exporter_prometheus::PrometheusBuilder;
+use simsimd::SpatialSimilarity;
+use std::collections::HashMap;
 use std::future::Future;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use text_embeddings_core::infer::Infer;
+use text_embeddings_core::infer::{EmbedOutputs, Infer, PooledEmbeddingsInferResponse};
+use text_embeddings_core::queue;
 use text_embeddings_core::tokenization::EncodingInput;
 use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit};
 use tokio_stream::wrappers::UnboundedReceiverStream;
@@ -32,15 +37,62 @@ impl From<&ResponseMetadata> for grpc::Metadata {
             tokenization_time_ns: value.tokenization_time.as_nanos() as u64,
             queue_time_ns: value.queue_time.as_nanos() as u64,
             inference_time_ns: value.inference_time.as_nanos() as u64,
+            cache_hits: value.cache_hits as u32,
         }
     }
 }
 
+/// Streamed requests that carry a priority class
+trait Prioritized {
+    fn queue_priority(&self) -> Result<queue::Priority, Status>;
+}
+
+impl Prioritized for EmbedRequest {
+    fn queue_priority(&self) -> Result<queue::Priority, Status> {
+        convert_priority(self.priority)
+    }
+}
+
+impl Prioritized for EmbedSparseRequest {
+    fn queue_priority(&self) -> Result<queue::Priority, Status> {
+        convert_priority(self.priority)
+    }
+}
+
+impl Prioritized for EmbedAllRequest {
+    fn queue_priority(&self) -> Result<queue::Priority, Status> {
+        convert_priority(self.priority)
+    }
+}
+
+impl Prioritized for EmbedMultiVectorRequest {
+    fn queue_priority(&self) -> Result<queue::Priority, Status> {
+        convert_priority(self.priority)
+    }
+}
+
+impl Prioritized for PredictRequest {
+    fn queue_priority(&self) -> Result<queue::Priority, Status> {
+        convert_priority(self.priority)
+    }
+}
+
+impl Prioritized for PredictPairRequest {
+    fn queue_priority(&self) -> Result<queue::Priority, Status> {
+        convert_priority(self.priority)
+    }
+}
+
+/// Metadata key used to select the model serving a request
+const MODEL_METADATA_KEY: &str = "model";
+
 #[derive(Debug, Clone)]
 struct TextEmbeddingsService {
     infer: Infer,
     info: Info,
     max_parallel_stream_requests: usize,
+    /// Served models, by name and by model id
+    models: Arc<HashMap<String, TextEmbeddingsService>>,
 }
 
 impl TextEmbeddingsService {
@@ -53,9 +105,50 @@ impl TextEmbeddingsService {
             infer,
             info,
             max_parallel_stream_requests,
+            models: Arc::new(HashMap::new()),
         }
     }
 
+    /// Embed the query of a re-ranking request served by an embedding model
+    async fn embed_rerank_query(
+        &self,
+        query: String,
+        truncate: bool,
+        truncation_direction: tokenizers::TruncationDirection,
+        dimensions: Option<usize>,
+        priority: queue::Priority,
+        deadline: Option<Instant>,
+    ) -> Result<PooledEmbeddingsInferResponse, ErrorResponse> {
+        let permit = self.infer.acquire_permit(priority).await;
+        self.infer
+            .embed_pooled(
+                query,
+                truncate,
+                truncation_direction,
+                self.info.query_prompt_name.clone(),
+                false,
+                dimensions,
+                priority,
+                deadline,
+                permit,
+            )
+            .await
+            .map_err(ErrorResponse::from)
+    }
+
+    /// Get the service of the model selected by the request metadata
+    fn select_model(&self, metadata: &MetadataMap) -> Result<&Self, Status> {
+        let Some(name) = metadata.get(MODEL_METADATA_KEY) else {
+            return Ok(self);
+        };
+        let name = name
+            .to_str()
+            .map_err(|_| Status::invalid_argument("invalid `model` metadata"))?;
+        self.models
+            .get(name)
+            .ok_or_else(|| Status::not_found(format!("model `{name}` is not served")))
+    }
+
     #[instrument(
         skip_all,
         fields(
@@ -70,6 +163,7 @@ impl TextEmbeddingsService {
     async fn embed_pooled_inner(
         &self,
         request: EmbedRequest,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
     ) -> Result<(EmbedResponse, ResponseMetadata), Status> {
         let span = Span::current();
@@ -77,19 +171,51 @@ impl TextEmbeddingsService {
 
         let compute_chars = request.inputs.chars().count();
         let truncation_direction = convert_truncation_direction(request.truncation_direction);
+        let outputs =
+            convert_embed_outputs(&request.outputs, self.infer.supports_lexical_weights())?;
+        let precision = convert_encoding_format(request.encoding_format)?;
+        let dimensions = self
+            .info
+            .dimensions(request.dimensions.map(|d| d as usize))?;
         let response = self
             .infer
-            .embed_pooled(
+            .embed_hybrid(
                 request.inputs,
                 request.truncate,
                 truncation_direction,
                 request.prompt_name,
                 request.normalize,
+                dimensions,
+                outputs,
+                convert_priority(request.priority)?,
+                deadline,
                 permit,
             )
             .await
             .map_err(ErrorResponse::from)?;
 
+        let (embeddings, encoded_embeddings) = match (response.results, precision) {
+            (Some(embeddings), Precision::Float32) => (embeddings, vec![]),
+            (Some(embeddings), precision) => {
+                let encoded = quantize(
+                    embeddings,
+                    precision,
+                    self.info.quantization_calibration.as_deref(),
+                )
+                .map_err(|message| {
+                    tracing::error!("{message}");
+                    let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                    counter.increment(1);
+                    ErrorResponse {
+                        error: message,
+                        error_type: ErrorType::Validation,
+                    }
+                })?;
+                (vec![], encoded.to_le_bytes())
+            }
+            (None, _) => (vec![], vec![]),
+        };
+
         let response_metadata = ResponseMetadata::new(
             compute_chars,
             response.metadata.prompt_tokens,
@@ -97,25 +223,37 @@ impl TextEmbeddingsService {
             response.metadata.tokenization,
             response.metadata.queue,
             response.metadata.inference,
-        );
+        )
+        .with_cache_hits(response.metadata.cached as usize);
         response_metadata.record_span(&span);
         response_metadata.record_metrics();
 
         tracing::info!("Success");
-        
-        let mut token_weights: Vec<KeyValue> = Vec::new(); 
-        for (key, value) in response.token_weights.iter() {
-            token_weights.push(KeyValue {
-                key: key.clone(),
-                value: *value,
-            });
-        }
+
+        let token_weights = response
+            .token_weights
+            .unwrap_or_default()
+            .into_iter()
+            .map(|w| KeyValue {
+                key: w.token,
+                value: w.weight,
+                id: w.id,
+            })
+            .collect();
+        let multivector_embeddings = response
+            .multivector
+            .unwrap_or_default()
+            .into_iter()
+            .map(|v| TokenEmbedding { embeddings: v })
+            .collect();
 
         Ok((
             EmbedResponse {
-                embeddings: response.results,
-                token_weights: token_weights,
+                embeddings,
+                token_weights,
                 metadata: Some(grpc::Metadata::from(&response_metadata)),
+                multivector_embeddings,
+                encoded_embeddings,
             },
             response_metadata,
         ))
@@ -135,6 +273,7 @@ impl TextEmbeddingsService {
     async fn embed_sparse_inner(
         &self,
         request: EmbedSparseRequest,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
     ) -> Result<(EmbedSparseResponse, ResponseMetadata), Status> {
         let span = Span::current();
@@ -149,6 +288,8 @@ impl TextEmbeddingsService {
                 request.truncate,
                 truncation_direction,
                 request.prompt_name,
+                convert_priority(request.priority)?,
+                deadline,
                 permit,
             )
             .await
@@ -161,7 +302,8 @@ impl TextEmbeddingsService {
             response.metadata.tokenization,
             response.metadata.queue,
             response.metadata.inference,
-        );
+        )
+        .with_cache_hits(response.metadata.cached as usize);
 
         let mut sparse_values = Vec::with_capacity(response.results.len());
         for (index, value) in response.results.into_iter().enumerate() {
@@ -201,6 +343,7 @@ impl TextEmbeddingsService {
     async fn embed_all_inner(
         &self,
         request: EmbedAllRequest,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
     ) -> Result<(EmbedAllResponse, ResponseMetadata), Status> {
         let span = Span::current();
@@ -215,6 +358,8 @@ impl TextEmbeddingsService {
                 request.truncate,
                 truncation_direction,
                 request.prompt_name,
+                convert_priority(request.priority)?,
+                deadline,
                 permit,
             )
             .await
@@ -248,6 +393,71 @@ impl TextEmbeddingsService {
         ))
     }
 
+    #[instrument(
+        skip_all,
+        fields(
+            compute_chars,
+            compute_tokens,
+            total_time,
+            tokenization_time,
+            queue_time,
+            inference_time,
+        )
+    )]
+    async fn embed_multivector_inner(
+        &self,
+        request: EmbedMultiVectorRequest,
+        deadline: Option<Instant>,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<(EmbedMultiVectorResponse, ResponseMetadata), Status> {
+        let span = Span::current();
+        let start_time = Instant::now();
+
+        let compute_chars = request.inputs.chars().count();
+        let truncation_direction = convert_truncation_direction(request.truncation_direction);
+        let response = self
+            .infer
+            .embed_multivector(
+                request.inputs,
+                request.truncate,
+                truncation_direction,
+                request.prompt_name,
+                convert_priority(request.priority)?,
+                deadline,
+                permit,
+            )
+            .await
+            .map_err(ErrorResponse::from)?;
+
+        let response_metadata = ResponseMetadata::new(
+            compute_chars,
+            response.metadata.prompt_tokens,
+            start_time,
+            response.metadata.tokenization,
+            response.metadata.queue,
+            response.metadata.inference,
+        );
+        response_metadata.record_span(&span);
+        response_metadata.record_metrics();
+
+        tracing::info!("Success");
+
+        let token_embeddings = response
+            .results
+            .into_iter()
+            .map(|v| TokenEmbedding { embeddings: v })
+            .collect();
+
+        Ok((
+            EmbedMultiVectorResponse {
+                token_embeddings,
+                metadata: Some(grpc::Metadata::from(&response_metadata)),
+            },
+            response_metadata,
+        ))
+    }
+
+    #[allow(clippy::too_many_arguments)]
     #[instrument(
         skip_all,
         fields(
@@ -265,6 +475,8 @@ impl TextEmbeddingsService {
         truncate: bool,
         truncation_direction: tokenizers::TruncationDirection,
         raw_scores: bool,
+        priority: queue::Priority,
+        deadline: Option<Instant>,
         permit: OwnedSemaphorePermit,
     ) -> Result<(PredictResponse, ResponseMetadata), Status> {
         let span = Span::current();
@@ -279,7 +491,15 @@ impl TextEmbeddingsService {
 
         let response = self
             .infer
-            .predict(inputs, truncate, truncation_direction, raw_scores, permit)
+            .predict(
+                inputs,
+                truncate,
+                truncation_direction,
+                raw_scores,
+                priority,
+                deadline,
+                permit,
+            )
             .await
             .map_err(ErrorResponse::from)?;
 
@@ -395,7 +615,7 @@ impl TextEmbeddingsService {
         function: F,
     ) -> Result<Response<UnboundedReceiverStream<Result<Res, Status>>>, Status>
     where
-        Req: Send + 'static,
+        Req: Prioritized + Send + 'static,
         Res: Send + 'static,
         F: FnOnce(Req, OwnedSemaphorePermit) -> Fut + Send + Clone + 'static,
         Fut: Future<Output = Result<(Res, ResponseMetadata), Status>> + Send,
@@ -416,7 +636,14 @@ impl TextEmbeddingsService {
         tokio::spawn(async move {
             while let Some((request, mut sender)) = internal_receiver.recv().await {
                 // Wait on permit before spawning the task to avoid creating more tasks than needed
-                let permit = local.infer.acquire_permit().await;
+                let priority = match request.queue_priority() {
+                    Ok(priority) => priority,
+                    Err(status) => {
+                        let _ = sender.send(Err(status));
+                        continue;
+                    }
+                };
+                let permit = local.infer.acquire_permit(priority).await;
 
                 // Required for the async move below
                 let function_local = function.clone();
@@ -571,27 +798,49 @@ impl TextEmbeddingsService {
 
 #[tonic::async_trait]
 impl grpc::info_server::Info for TextEmbeddingsService {
-    async fn info(&self, _request: Request<InfoRequest>) -> Result<Response<InfoResponse>, Status> {
-        let model_type = match self.info.model_type {
-            ModelType::Classifier(_) => grpc::ModelType::Classifier,
-            ModelType::Embedding(_) => grpc::ModelType::Embedding,
-            ModelType::Reranker(_) => grpc::ModelType::Reranker,
-        };
+    async fn info(&self, request: Request<InfoRequest>) -> Result<Response<InfoResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
+        let model_type = convert_model_type(&service.info.model_type);
+        let models = service
+            .info
+            .models
+            .iter()
+            .map(|model| ServedModel {
+                name: model.name.clone(),
+                model_id: model.model_id.clone(),
+                model_type: convert_model_type(&model.model_type).into(),
+            })
+            .collect();
 
         Ok(Response::new(InfoResponse {
-            version: self.info.version.to_string(),
-            sha: self.info.sha.map(|s| s.to_string()),
-            docker_label: self.info.docker_label.map(|s| s.to_string()),
-            model_id: self.info.model_id.clone(),
-            model_sha: self.info.model_sha.clone(),
-            model_dtype: self.info.model_dtype.clone(),
+            version: service.info.version.to_string(),
+            sha: service.info.sha.map(|s| s.to_string()),
+            docker_label: service.info.docker_label.map(|s| s.to_string()),
+            model_id: service.info.model_id.clone(),
+            model_sha: service.info.model_sha.clone(),
+            model_dtype: service.info.model_dtype.clone(),
             model_type: model_type.into(),
-            max_concurrent_requests: self.info.max_concurrent_requests as u32,
-            max_input_length: self.info.max_input_length as u32,
-            max_batch_tokens: self.info.max_batch_tokens as u32,
-            max_batch_requests: self.info.max_batch_requests.map(|v| v as u32),
-            max_client_batch_size: self.info.max_client_batch_size as u32,
-            tokenization_workers: self.info.tokenization_workers as u32,
+            max_concurrent_requests: service.info.max_concurrent_requests as u32,
+            max_input_length: service.info.max_input_length as u32,
+            max_batch_tokens: service.info.max_batch_tokens as u32,
+            max_batch_requests: service.info.max_batch_requests.map(|v| v as u32),
+            max_client_batch_size: service.info.max_client_batch_size as u32,
+            tokenization_workers: service.info.tokenization_workers as u32,
+            hidden_size: service.info.hidden_size.map(|v| v as u32),
+            default_dimensions: service.info.default_dimensions.map(|v| v as u32),
+            onnx_runtime: service.info.onnx_runtime.as_ref().map(|onnx_runtime| {
+                grpc::OnnxRuntimeInfo {
+                    intra_threads: onnx_runtime.intra_threads as u32,
+                    inter_threads: onnx_runtime.inter_threads.map(|v| v as u32),
+                    execution_mode: onnx_runtime.execution_mode.clone(),
+                    optimization_level: onnx_runtime.optimization_level.clone(),
+                    optimized_model_path: onnx_runtime.optimized_model_path.clone(),
+                    memory_arena: onnx_runtime.memory_arena,
+                    memory_pattern: onnx_runtime.memory_pattern,
+                    max_batch_size: onnx_runtime.max_batch_size as u32,
+                }
+            }),
+            models,
         }))
     }
 }
@@ -603,16 +852,21 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         &self,
         request: Request<EmbedRequest>,
     ) -> Result<Response<EmbedResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let counter = metrics::counter!("te_request_count", "method" => "single");
         counter.increment(1);
 
-        let permit = self
+        let deadline = grpc_deadline(request.metadata())?;
+
+        let request = request.into_inner();
+        let permit = service
             .infer
-            .try_acquire_permit()
+            .try_acquire_permit(convert_priority(request.priority)?)
             .map_err(ErrorResponse::from)?;
 
-        let request = request.into_inner();
-        let (response, metadata) = self.embed_pooled_inner(request, permit).await?;
+        let (response, metadata) = service
+            .embed_pooled_inner(request, deadline, permit)
+            .await?;
         let headers = HeaderMap::from(metadata);
 
         let counter = metrics::counter!("te_request_count", "method" => "single");
@@ -632,29 +886,36 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         &self,
         request: Request<Streaming<EmbedRequest>>,
     ) -> Result<Response<Self::EmbedStreamStream>, Status> {
+        let service = self.select_model(request.metadata())?;
+        let deadline = grpc_deadline(request.metadata())?;
         // Clone for move below
-        let clone = self.clone();
-        let function = |req: EmbedRequest, permit: OwnedSemaphorePermit| async move {
-            clone.embed_pooled_inner(req, permit).await
+        let clone = service.clone();
+        let function = move |req: EmbedRequest, permit: OwnedSemaphorePermit| async move {
+            clone.embed_pooled_inner(req, deadline, permit).await
         };
 
-        self.stream(request, function).await
+        service.stream(request, function).await
     }
 
     async fn embed_sparse(
         &self,
         request: Request<EmbedSparseRequest>,
     ) -> Result<Response<EmbedSparseResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let counter = metrics::counter!("te_request_count", "method" => "single");
         counter.increment(1);
 
-        let permit = self
+        let deadline = grpc_deadline(request.metadata())?;
+
+        let request = request.into_inner();
+        let permit = service
             .infer
-            .try_acquire_permit()
+            .try_acquire_permit(convert_priority(request.priority)?)
             .map_err(ErrorResponse::from)?;
 
-        let request = request.into_inner();
-        let (response, metadata) = self.embed_sparse_inner(request, permit).await?;
+        let (response, metadata) = service
+            .embed_sparse_inner(request, deadline, permit)
+            .await?;
         let headers = HeaderMap::from(metadata);
 
         let counter = metrics::counter!("te_request_count", "method" => "single");
@@ -673,13 +934,15 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         &self,
         request: Request<Streaming<EmbedSparseRequest>>,
     ) -> Result<Response<Self::EmbedSparseStreamStream>, Status> {
+        let service = self.select_model(request.metadata())?;
+        let deadline = grpc_deadline(request.metadata())?;
         // Clone for move below
-        let clone = self.clone();
-        let function = |req: EmbedSparseRequest, permit: OwnedSemaphorePermit| async move {
-            clone.embed_sparse_inner(req, permit).await
+        let clone = service.clone();
+        let function = move |req: EmbedSparseRequest, permit: OwnedSemaphorePermit| async move {
+            clone.embed_sparse_inner(req, deadline, permit).await
         };
 
-        self.stream(request, function).await
+        service.stream(request, function).await
     }
 
     #[instrument(skip_all)]
@@ -687,16 +950,19 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         &self,
         request: Request<EmbedAllRequest>,
     ) -> Result<Response<EmbedAllResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let counter = metrics::counter!("te_request_count", "method" => "single");
         counter.increment(1);
 
-        let permit = self
+        let deadline = grpc_deadline(request.metadata())?;
+
+        let request = request.into_inner();
+        let permit = service
             .infer
-            .try_acquire_permit()
+            .try_acquire_permit(convert_priority(request.priority)?)
             .map_err(ErrorResponse::from)?;
 
-        let request = request.into_inner();
-        let (response, metadata) = self.embed_all_inner(request, permit).await?;
+        let (response, metadata) = service.embed_all_inner(request, deadline, permit).await?;
         let headers = HeaderMap::from(metadata);
 
         let counter = metrics::counter!("te_request_count", "method" => "single");
@@ -716,13 +982,66 @@ impl grpc::embed_server::Embed for TextEmbeddingsService {
         &self,
         request: Request<Streaming<EmbedAllRequest>>,
     ) -> Result<Response<Self::EmbedAllStreamStream>, Status> {
+        let service = self.select_model(request.metadata())?;
+        let deadline = grpc_deadline(request.metadata())?;
         // Clone for move below
-        let clone = self.clone();
-        let function = |req: EmbedAllRequest, permit: OwnedSemaphorePermit| async move {
-            clone.embed_all_inner(req, permit).await
+        let clone = service.clone();
+        let function = move |req: EmbedAllRequest, permit: OwnedSemaphorePermit| async move {
+            clone.embed_all_inner(req, deadline, permit).await
         };
 
-        self.stream(request, function).await
+        service.stream(request, function).await
+    }
+
+    #[instrument(skip_all)]
+    async fn embed_multi_vector(
+        &self,
+        request: Request<EmbedMultiVectorRequest>,
+    ) -> Result<Response<EmbedMultiVectorResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
+        let counter = metrics::counter!("te_request_count", "method" => "single");
+        counter.increment(1);
+
+        let deadline = grpc_deadline(request.metadata())?;
+
+        let request = request.into_inner();
+        let permit = service
+            .infer
+            .try_acquire_permit(convert_priority(request.priority)?)
+            .map_err(ErrorResponse::from)?;
+
+        let (response, metadata) = service
+            .embed_multivector_inner(request, deadline, permit)
+            .await?;
+        let headers = HeaderMap::from(metadata);
+
+        let counter = metrics::counter!("te_request_success", "method" => "single");
+        counter.increment(1);
+
+        Ok(Response::from_parts(
+            MetadataMap::from_headers(headers),
+            response,
+            Extensions::default(),
+        ))
+    }
+
+    type EmbedMultiVectorStreamStream =
+        UnboundedReceiverStream<Result<EmbedMultiVectorResponse, Status>>;
+
+    #[instrument(skip_all)]
+    async fn embed_multi_vector_stream(
+        &self,
+        request: Request<Streaming<EmbedMultiVectorRequest>>,
+    ) -> Result<Response<Self::EmbedMultiVectorStreamStream>, Status> {
+        let service = self.select_model(request.metadata())?;
+        let deadline = grpc_deadline(request.metadata())?;
+        // Clone for move below
+        let clone = service.clone();
+        let function = move |req: EmbedMultiVectorRequest, permit: OwnedSemaphorePermit| async move {
+            clone.embed_multivector_inner(req, deadline, permit).await
+        };
+
+        service.stream(request, function).await
     }
 }
 
@@ -733,22 +1052,27 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
         &self,
         request: Request<PredictRequest>,
     ) -> Result<Response<PredictResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let counter = metrics::counter!("te_request_count", "method" => "single");
         counter.increment(1);
 
-        let permit = self
+        let deadline = grpc_deadline(request.metadata())?;
+
+        let request = request.into_inner();
+        let permit = service
             .infer
-            .try_acquire_permit()
+            .try_acquire_permit(convert_priority(request.priority)?)
             .map_err(ErrorResponse::from)?;
 
-        let request = request.into_inner();
         let truncation_direction = convert_truncation_direction(request.truncation_direction);
-        let (response, metadata) = self
+        let (response, metadata) = service
             .predict_inner(
                 request.inputs,
                 request.truncate,
                 truncation_direction,
                 request.raw_scores,
+                convert_priority(request.priority)?,
+                deadline,
                 permit,
             )
             .await?;
@@ -768,8 +1092,10 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
         &self,
         request: Request<PredictPairRequest>,
     ) -> Result<Response<PredictResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let counter = metrics::counter!("te_request_count", "method" => "single");
         counter.increment(1);
+        let deadline = grpc_deadline(request.metadata())?;
         let request = request.into_inner();
 
         let mut inputs = request.inputs;
@@ -788,18 +1114,20 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
             }
         };
 
-        let permit = self
+        let permit = service
             .infer
-            .try_acquire_permit()
+            .try_acquire_permit(convert_priority(request.priority)?)
             .map_err(ErrorResponse::from)?;
 
         let truncation_direction = convert_truncation_direction(request.truncation_direction);
-        let (response, metadata) = self
+        let (response, metadata) = service
             .predict_inner(
                 inputs,
                 request.truncate,
                 truncation_direction,
                 request.raw_scores,
+                convert_priority(request.priority)?,
+                deadline,
                 permit,
             )
             .await?;
@@ -822,9 +1150,11 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
         &self,
         request: Request<Streaming<PredictRequest>>,
     ) -> Result<Response<Self::PredictStreamStream>, Status> {
+        let service = self.select_model(request.metadata())?;
+        let deadline = grpc_deadline(request.metadata())?;
         // Clone for move below
-        let clone = self.clone();
-        let function = |req: PredictRequest, permit: OwnedSemaphorePermit| async move {
+        let clone = service.clone();
+        let function = move |req: PredictRequest, permit: OwnedSemaphorePermit| async move {
             let truncation_direction = convert_truncation_direction(req.truncation_direction);
             clone
                 .predict_inner(
@@ -832,12 +1162,14 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
                     req.truncate,
                     truncation_direction,
                     req.raw_scores,
+                    convert_priority(req.priority)?,
+                    deadline,
                     permit,
                 )
                 .await
         };
 
-        self.stream(request, function).await
+        service.stream(request, function).await
     }
 
     type PredictPairStreamStream = UnboundedReceiverStream<Result<PredictResponse, Status>>;
@@ -846,9 +1178,11 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
         &self,
         request: Request<Streaming<PredictPairRequest>>,
     ) -> Result<Response<Self::PredictPairStreamStream>, Status> {
+        let service = self.select_model(request.metadata())?;
+        let deadline = grpc_deadline(request.metadata())?;
         // Clone for move below
-        let clone = self.clone();
-        let function = |req: PredictPairRequest, permit: OwnedSemaphorePermit| async move {
+        let clone = service.clone();
+        let function = move |req: PredictPairRequest, permit: OwnedSemaphorePermit| async move {
             let mut inputs = req.inputs;
 
             let inputs = match inputs.len() {
@@ -872,12 +1206,14 @@ impl grpc::predict_server::Predict for TextEmbeddingsService {
                     req.truncate,
                     truncation_direction,
                     req.raw_scores,
+                    convert_priority(req.priority)?,
+                    deadline,
                     permit,
                 )
                 .await
         };
 
-        self.stream(request, function).await
+        service.stream(request, function).await
     }
 }
 
@@ -898,9 +1234,12 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         &self,
         request: Request<RerankRequest>,
     ) -> Result<Response<RerankResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let span = Span::current();
         let start_time = Instant::now();
 
+        let deadline = grpc_deadline(request.metadata())?;
+
         let request = request.into_inner();
 
         if request.texts.is_empty() {
@@ -915,88 +1254,133 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
             Err(err)?;
         }
 
-        match &self.info.model_type {
+        let counter = metrics::counter!("te_request_count", "method" => "batch");
+        counter.increment(1);
+
+        let batch_size = request.texts.len();
+        if batch_size > service.info.max_client_batch_size {
+            let message = format!(
+                "batch size {batch_size} > maximum allowed batch size {}",
+                service.info.max_client_batch_size
+            );
+            tracing::error!("{message}");
+            let err = ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            };
+            let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+            counter.increment(1);
+            Err(err)?;
+        }
+
+        let bi_encoder = match &service.info.model_type {
             ModelType::Classifier(_) => {
                 let counter = metrics::counter!("te_request_failure", "err" => "model_type");
                 counter.increment(1);
-                let message = "model is not a re-ranker model".to_string();
-                tracing::error!("{message}");
-                Err(Status::new(Code::FailedPrecondition, message))
-            }
-            ModelType::Reranker(_) => Ok(()),
-            ModelType::Embedding(_) => {
-                let counter = metrics::counter!("te_request_failure", "err" => "model_type");
-                counter.increment(1);
-                let message = "model is not a classifier model".to_string();
+                let message = "model is not a re-ranker or embedding model".to_string();
                 tracing::error!("{message}");
                 Err(Status::new(Code::FailedPrecondition, message))
             }
+            ModelType::Reranker(_) => Ok(false),
+            ModelType::Embedding(_) => Ok(true),
         }?;
 
+        let truncation_direction = convert_truncation_direction(request.truncation_direction);
+        let dimensions = service.info.dimensions(None)?;
+        let document_prompt_name = service.info.document_prompt_name.as_deref();
+
+        // Embedding models embed the query once
+        let query_embedding = if bi_encoder {
+            Some(
+                service
+                    .embed_rerank_query(
+                        request.query.clone(),
+                        request.truncate,
+                        truncation_direction,
+                        dimensions,
+                        convert_priority(request.priority)?,
+                        deadline,
+                    )
+                    .await?,
+            )
+        } else {
+            None
+        };
+        let query_vector = query_embedding.as_ref().map(|r| r.results.as_slice());
+
         // Closure for rerank
         let rerank_inner = move |query: String,
                                  text: String,
                                  truncate: bool,
                                  truncation_direction: tokenizers::TruncationDirection,
                                  raw_scores: bool,
+                                 priority: queue::Priority,
                                  infer: Infer| async move {
-            let permit = infer.acquire_permit().await;
-
-            let response = infer
-                .predict(
-                    (query, text),
-                    truncate,
-                    truncation_direction,
-                    raw_scores,
-                    permit,
-                )
-                .await
-                .map_err(ErrorResponse::from)?;
-
-            let score = response.results[0];
+            let permit = infer.acquire_permit(priority).await;
+
+            let (metadata, score) = match query_vector {
+                None => {
+                    let response = infer
+                        .predict(
+                            (query, text),
+                            truncate,
+                            truncation_direction,
+                            raw_scores,
+                            priority,
+                            deadline,
+                            permit,
+                        )
+                        .await
+                        .map_err(ErrorResponse::from)?;
+                    (response.metadata, response.results[0])
+                }
+                Some(query_vector) => {
+                    let response = infer
+                        .embed_pooled(
+                            text,
+                            truncate,
+                            truncation_direction,
+                            document_prompt_name.map(str::to_string),
+                            false,
+                            dimensions,
+                            priority,
+                            deadline,
+                            permit,
+                        )
+                        .await
+                        .map_err(ErrorResponse::from)?;
+                    let score = cosine_similarity(query_vector, &response.results);
+                    (response.metadata, score)
+                }
+            };
 
             Ok::<(usize, Duration, Duration, Duration, f32), ErrorResponse>((
-                response.metadata.prompt_tokens,
-                response.metadata.tokenization,
-                response.metadata.queue,
-                response.metadata.inference,
+                metadata.prompt_tokens,
+                metadata.tokenization,
+                metadata.queue,
+                metadata.inference,
                 score,
             ))
         };
 
-        let counter = metrics::counter!("te_request_count", "method" => "batch");
-        counter.increment(1);
-
-        let batch_size = request.texts.len();
-        if batch_size > self.info.max_client_batch_size {
-            let message = format!(
-                "batch size {batch_size} > maximum allowed batch size {}",
-                self.info.max_client_batch_size
-            );
-            tracing::error!("{message}");
-            let err = ErrorResponse {
-                error: message,
-                error_type: ErrorType::Validation,
-            };
-            let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
-            counter.increment(1);
-            Err(err)?;
-        }
-
         let mut futures = Vec::with_capacity(batch_size);
         let query_chars = request.query.chars().count();
-        let mut total_compute_chars = query_chars * batch_size;
-        let truncation_direction = convert_truncation_direction(request.truncation_direction);
+        // Embedding models only encode the query once
+        let mut total_compute_chars = match &query_embedding {
+            Some(_) => query_chars,
+            None => query_chars * batch_size,
+        };
 
         for text in &request.texts {
             total_compute_chars += text.chars().count();
-            let local_infer = self.infer.clone();
+            let local_infer = service.infer.clone();
             futures.push(rerank_inner(
                 request.query.clone(),
                 text.clone(),
                 request.truncate,
                 truncation_direction,
                 request.raw_scores,
+                convert_priority(request.priority)?,
                 local_infer,
             ))
         }
@@ -1009,7 +1393,10 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let mut total_tokenization_time = 0;
         let mut total_queue_time = 0;
         let mut total_inference_time = 0;
-        let mut total_compute_tokens = 0;
+        let mut total_compute_tokens = query_embedding
+            .as_ref()
+            .map(|r| r.metadata.prompt_tokens)
+            .unwrap_or_default();
 
         for (index, r) in results.into_iter().enumerate() {
             total_compute_tokens += r.0;
@@ -1089,28 +1476,26 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         &self,
         request: Request<Streaming<RerankStreamRequest>>,
     ) -> Result<Response<RerankResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let span = Span::current();
         let start_time = Instant::now();
+        let deadline = grpc_deadline(request.metadata())?;
 
         // Check model type
-        match &self.info.model_type {
+        let bi_encoder = match &service.info.model_type {
             ModelType::Classifier(_) => {
                 let counter = metrics::counter!("te_request_failure", "err" => "model_type");
                 counter.increment(1);
-                let message = "model is not a re-ranker model".to_string();
-                tracing::error!("{message}");
-                Err(Status::new(Code::FailedPrecondition, message))
-            }
-            ModelType::Reranker(_) => Ok(()),
-            ModelType::Embedding(_) => {
-                let counter = metrics::counter!("te_request_failure", "err" => "model_type");
-                counter.increment(1);
-                let message = "model is not a classifier model".to_string();
+                let message = "model is not a re-ranker or embedding model".to_string();
                 tracing::error!("{message}");
                 Err(Status::new(Code::FailedPrecondition, message))
             }
+            ModelType::Reranker(_) => Ok(false),
+            ModelType::Embedding(_) => Ok(true),
         }?;
 
+        let dimensions = service.info.dimensions(None)?;
+
         // Closure for rerank
         let rerank_inner = move |index: usize,
                                  query: String,
@@ -1118,27 +1503,53 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                                  truncate: bool,
                                  truncation_direction: tokenizers::TruncationDirection,
                                  raw_scores: bool,
+                                 priority: queue::Priority,
+                                 query_vector: Option<Arc<Vec<f32>>>,
+                                 document_prompt_name: Option<String>,
                                  infer: Infer,
                                  permit: OwnedSemaphorePermit| async move {
-            let response = infer
-                .predict(
-                    (query, text.clone()),
-                    truncate,
-                    truncation_direction,
-                    raw_scores,
-                    permit,
-                )
-                .await
-                .map_err(ErrorResponse::from)?;
-
-            let score = response.results[0];
+            let (metadata, score) = match query_vector {
+                None => {
+                    let response = infer
+                        .predict(
+                            (query, text.clone()),
+                            truncate,
+                            truncation_direction,
+                            raw_scores,
+                            priority,
+                            deadline,
+                            permit,
+                        )
+                        .await
+                        .map_err(ErrorResponse::from)?;
+                    (response.metadata, response.results[0])
+                }
+                Some(query_vector) => {
+                    let response = infer
+                        .embed_pooled(
+                            text.clone(),
+                            truncate,
+                            truncation_direction,
+                            document_prompt_name,
+                            false,
+                            dimensions,
+                            priority,
+                            deadline,
+                            permit,
+                        )
+                        .await
+                        .map_err(ErrorResponse::from)?;
+                    let score = cosine_similarity(&query_vector, &response.results);
+                    (response.metadata, score)
+                }
+            };
 
             Ok::<(usize, usize, Duration, Duration, Duration, f32, String), ErrorResponse>((
                 index,
-                response.metadata.prompt_tokens,
-                response.metadata.tokenization,
-                response.metadata.queue,
-                response.metadata.inference,
+                metadata.prompt_tokens,
+                metadata.tokenization,
+                metadata.queue,
+                metadata.inference,
                 score,
                 text,
             ))
@@ -1159,24 +1570,37 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                 bool,
                 tokenizers::TruncationDirection,
                 bool,
+                queue::Priority,
+                Option<Arc<Vec<f32>>>,
+                Option<String>,
             ),
             oneshot::Sender<
                 Result<(usize, usize, Duration, Duration, Duration, f32, String), ErrorResponse>,
             >,
-        )>(self.max_parallel_stream_requests);
+        )>(service.max_parallel_stream_requests);
 
         // Required for the async move below
-        let local_infer = self.infer.clone();
+        let local_infer = service.infer.clone();
 
         // Background task that uses the bounded channel
         tokio::spawn(async move {
             while let Some((
-                (index, query, text, truncate, truncation_direction, raw_scores),
+                (
+                    index,
+                    query,
+                    text,
+                    truncate,
+                    truncation_direction,
+                    raw_scores,
+                    priority,
+                    query_vector,
+                    document_prompt_name,
+                ),
                 mut sender,
             )) = rerank_receiver.recv().await
             {
                 // Wait on permit before spawning the task to avoid creating more tasks than needed
-                let permit = local_infer.acquire_permit().await;
+                let permit = local_infer.acquire_permit(priority).await;
 
                 // Required for the async move below
                 let task_infer = local_infer.clone();
@@ -1185,7 +1609,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                 tokio::spawn(async move {
                     // Select on closed to cancel work if the stream was closed
                     tokio::select! {
-                    result = rerank_inner(index, query, text, truncate, truncation_direction, raw_scores, task_infer, permit) => {
+                    result = rerank_inner(index, query, text, truncate, truncation_direction, raw_scores, priority, query_vector, document_prompt_name, task_infer, permit) => {
                         let _ = sender.send(result);
                     }
                     _ = sender.closed() => {}
@@ -1196,10 +1620,14 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
 
         let mut index = 0;
         let mut total_compute_chars = 0;
+        let mut query_compute_tokens = 0;
+        // Embedding models embed every distinct query once
+        let mut last_query: Option<(String, Arc<Vec<f32>>)> = None;
 
         // Set by first request
         let mut raw_scores = None;
         let mut return_text = None;
+        let mut priority = None;
 
         // Intermediate channels
         // Required to keep the order of the requests
@@ -1215,16 +1643,43 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                 .send(result_receiver)
                 .expect("`intermediate_receiver` was dropped. This is a bug.");
 
-            // Set `raw_scores` and `return_text` using the values in the first request
+            // Set `raw_scores`, `return_text` and `priority` using the values in the first request
             if raw_scores.is_none() && return_text.is_none() {
                 raw_scores = Some(request.raw_scores);
                 return_text = Some(request.return_text);
+                priority = Some(convert_priority(request.priority)?);
             }
 
-            total_compute_chars += request.query.chars().count();
+            let truncation_direction = convert_truncation_direction(request.truncation_direction);
+            let query_vector = match &last_query {
+                _ if !bi_encoder => {
+                    total_compute_chars += request.query.chars().count();
+                    None
+                }
+                Some((query, query_vector)) if query == &request.query => {
+                    Some(query_vector.clone())
+                }
+                _ => {
+                    let response = service
+                        .embed_rerank_query(
+                            request.query.clone(),
+                            request.truncate,
+                            truncation_direction,
+                            dimensions,
+                            priority.unwrap(),
+                            deadline,
+                        )
+                        .await?;
+                    total_compute_chars += request.query.chars().count();
+                    query_compute_tokens += response.metadata.prompt_tokens;
+
+                    let query_vector = Arc::new(response.results);
+                    last_query = Some((request.query.clone(), query_vector.clone()));
+                    Some(query_vector)
+                }
+            };
             total_compute_chars += request.text.chars().count();
 
-            let truncation_direction = convert_truncation_direction(request.truncation_direction);
             rerank_sender
                 .send((
                     (
@@ -1234,6 +1689,9 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
                         request.truncate,
                         truncation_direction,
                         raw_scores.unwrap(),
+                        priority.unwrap(),
+                        query_vector,
+                        service.info.document_prompt_name.clone(),
                     ),
                     result_sender,
                 ))
@@ -1252,7 +1710,7 @@ impl grpc::rerank_server::Rerank for TextEmbeddingsService {
         let mut total_tokenization_time = 0;
         let mut total_queue_time = 0;
         let mut total_inference_time = 0;
-        let mut total_compute_tokens = 0;
+        let mut total_compute_tokens = query_compute_tokens;
 
         // Iterate on result stream
         while let Some(result_receiver) = intermediate_receiver.recv().await {
@@ -1342,8 +1800,9 @@ impl grpc::tokenize_server::Tokenize for TextEmbeddingsService {
         &self,
         request: Request<EncodeRequest>,
     ) -> Result<Response<EncodeResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let request = request.into_inner();
-        let tokens = self.tokenize_inner(request).await?;
+        let tokens = service.tokenize_inner(request).await?;
         Ok(Response::new(tokens))
     }
 
@@ -1353,19 +1812,21 @@ impl grpc::tokenize_server::Tokenize for TextEmbeddingsService {
         &self,
         request: Request<Streaming<EncodeRequest>>,
     ) -> Result<Response<Self::TokenizeStreamStream>, Status> {
+        let service = self.select_model(request.metadata())?;
         // Clone for move below
-        let clone = self.clone();
+        let clone = service.clone();
         let function = |req: EncodeRequest| async move { clone.tokenize_inner(req).await };
 
-        self.stream_no_permit(request, function).await
+        service.stream_no_permit(request, function).await
     }
 
     async fn decode(
         &self,
         request: Request<DecodeRequest>,
     ) -> Result<Response<DecodeResponse>, Status> {
+        let service = self.select_model(request.metadata())?;
         let request = request.into_inner();
-        let tokens = self.decode_inner(request).await?;
+        let tokens = service.decode_inner(request).await?;
         Ok(Response::new(tokens))
     }
 
@@ -1375,24 +1836,59 @@ impl grpc::tokenize_server::Tokenize for TextEmbeddingsService {
         &self,
         request: Request<Streaming<DecodeRequest>>,
     ) -> Result<Response<Self::DecodeStreamStream>, Status> {
+        let service = self.select_model(request.metadata())?;
         // Clone for move below
-        let clone = self.clone();
+        let clone = service.clone();
         let function = |req: DecodeRequest| async move { clone.decode_inner(req).await };
 
-        self.stream_no_permit(request, function).await
+        service.stream_no_permit(request, function).await
     }
 }
 
+/// Status of the services of the models and their health.
+///
+/// A model serves the services of its model type: re-rankers serve both the `Rerank` and
+/// `Predict` services. This hints back to the user that the other services always return an
+/// error: with only an `Embedding` model, `Rerank` requests always return an `UNIMPLEMENTED`
+/// Status and both the `Rerank` and `Predict` services are `NOT_SERVING`.
+fn services_health(models: &[(ModelType, bool)]) -> [(&'static str, ServingStatus); 3] {
+    let status = |serves: fn(&ModelType) -> bool| {
+        let mut served = models
+            .iter()
+            .filter(|(model_type, _)| serves(model_type))
+            .peekable();
+        match served.peek().is_some() && served.all(|(_, health)| *health) {
+            true => ServingStatus::Serving,
+            false => ServingStatus::NotServing,
+        }
+    };
+    [
+        (
+            <grpc::EmbedServer<TextEmbeddingsService>>::NAME,
+            status(|model_type| matches!(model_type, ModelType::Embedding(_))),
+        ),
+        (
+            <grpc::RerankServer<TextEmbeddingsService>>::NAME,
+            status(|model_type| matches!(model_type, ModelType::Reranker(_))),
+        ),
+        (
+            <grpc::PredictServer<TextEmbeddingsService>>::NAME,
+            status(|model_type| {
+                matches!(
+                    model_type,
+                    ModelType::Classifier(_) | ModelType::Reranker(_)
+                )
+            }),
+        ),
+    ]
+}
+
 pub async fn run(
-    infer: Infer,
-    info: Info,
+    models: Vec<(String, Infer, Info)>,
     addr: SocketAddr,
-    prom_builder: PrometheusBuilder,
     api_key: Option<String>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
 ) -> Result<(), anyhow::Error> {
-    prom_builder.install()?;
-    tracing::info!("Serving Prometheus metrics: 0.0.0.0:9000");
-
     // Liveness service
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
     // Info is always serving
@@ -1415,64 +1911,34 @@ pub async fn run(
         .set_not_serving::<grpc::PredictServer<TextEmbeddingsService>>()
         .await;
 
-    // Backend health watcher
-    let mut health_watcher = infer.health_watcher();
-
-    // Clone model_type and move it to the task
-    let health_watcher_model_type = info.model_type.clone();
+    // Backend health watchers
+    // A service shared by several models is only serving when all of them are healthy
+    let mut health_watchers: Vec<_> = models
+        .iter()
+        .map(|(_, infer, info)| (infer.health_watcher(), info.model_type.clone()))
+        .collect();
 
     // Update services health
     tokio::spawn(async move {
-        while health_watcher.changed().await.is_ok() {
-            let health = *health_watcher.borrow_and_update();
-            let status = match health {
-                true => ServingStatus::Serving,
-                false => ServingStatus::NotServing,
-            };
+        while !health_watchers.is_empty() {
+            // Wait for the health of any model to change
+            let changes = health_watchers
+                .iter_mut()
+                .map(|(health_watcher, _)| Box::pin(health_watcher.changed()));
+            let (changed, _, _) = futures::future::select_all(changes).await;
+            if changed.is_err() {
+                break;
+            }
 
-            // Match on model type and set the health of the correct service(s)
-            //
-            // If Reranker, we have both a predict and rerank service
-            //
-            // This logic hints back to the user that if they try using the wrong service
-            // given the model type, it will always return an error.
-            //
-            // For example if the model type is `Embedding`, sending requests to `Rerank` will
-            // always return an `UNIMPLEMENTED` Status and both the `Rerank` and `Predict` services
-            // will have a `NOT_SERVING` ServingStatus.
-            match health_watcher_model_type {
-                ModelType::Classifier(_) => {
-                    health_reporter
-                        .set_service_status(
-                            <grpc::PredictServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await
-                }
-                ModelType::Embedding(_) => {
-                    health_reporter
-                        .set_service_status(
-                            <grpc::EmbedServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await
-                }
-                ModelType::Reranker(_) => {
-                    // Reranker has both a predict and rerank service
-                    health_reporter
-                        .set_service_status(
-                            <grpc::PredictServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await;
-                    health_reporter
-                        .set_service_status(
-                            <grpc::RerankServer<TextEmbeddingsService>>::NAME,
-                            status,
-                        )
-                        .await;
-                }
-            };
+            let models: Vec<_> = health_watchers
+                .iter_mut()
+                .map(|(health_watcher, model_type)| {
+                    (model_type.clone(), *health_watcher.borrow_and_update())
+                })
+                .collect();
+            for (name, status) in services_health(&models) {
+                health_reporter.set_service_status(name, status).await;
+            }
         }
     });
 
@@ -1483,7 +1949,25 @@ pub async fn run(
         .build()?;
 
     // Main service
-    let service = TextEmbeddingsService::new(infer, info);
+    // The first model serves the requests that do not select a model
+    let services: Vec<(String, TextEmbeddingsService)> = models
+        .into_iter()
+        .map(|(name, infer, info)| (name, TextEmbeddingsService::new(infer, info)))
+        .collect();
+    let mut by_name = HashMap::with_capacity(2 * services.len());
+    // Names take precedence over model ids
+    for (_, service) in services.iter().rev() {
+        by_name.insert(service.info.model_id.clone(), service.clone());
+    }
+    for (name, service) in &services {
+        by_name.insert(name.clone(), service.clone());
+    }
+    let mut service = services
+        .into_iter()
+        .next()
+        .map(|(_, service)| service)
+        .context("at least one model must be served")?;
+    service.models = Arc::new(by_name);
 
     // Create gRPC server
     let server = if let Some(api_key) = api_key {
@@ -1511,7 +1995,7 @@ pub async fn run(
             .add_service(grpc::EmbedServer::with_interceptor(service.clone(), auth))
             .add_service(grpc::PredictServer::with_interceptor(service.clone(), auth))
             .add_service(grpc::RerankServer::with_interceptor(service, auth))
-            .serve_with_shutdown(addr, shutdown::shutdown_signal())
+            .serve_with_shutdown(addr, shutdown)
     } else {
         Server::builder()
             .add_service(health_service)
@@ -1521,7 +2005,7 @@ pub async fn run(
             .add_service(grpc::EmbedServer::new(service.clone()))
             .add_service(grpc::PredictServer::new(service.clone()))
             .add_service(grpc::RerankServer::new(service))
-            .serve_with_shutdown(addr, shutdown::shutdown_signal())
+            .serve_with_shutdown(addr, shutdown)
     };
 
     tracing::info!("Starting gRPC server: {}", &addr);
@@ -1540,15 +2024,216 @@ impl From<ErrorResponse> for Status {
             ErrorType::Validation => Code::InvalidArgument,
             ErrorType::Tokenizer => Code::FailedPrecondition,
             ErrorType::Empty => Code::InvalidArgument,
+            ErrorType::Deadline => Code::DeadlineExceeded,
         };
 
         Status::new(code, value.error)
     }
 }
 
+/// Get the deadline of a request from its `grpc-timeout` metadata
+fn grpc_deadline(metadata: &MetadataMap) -> Result<Option<Instant>, Status> {
+    let Some(value) = metadata.get("grpc-timeout") else {
+        return Ok(None);
+    };
+
+    // The timeout is encoded as at most 8 digits followed by a unit
+    let timeout = value.to_str().ok().and_then(|value| {
+        if value.len() < 2 || value.len() > 9 {
+            return None;
+        }
+        let (amount, unit) = value.split_at(value.len() - 1);
+        let amount = amount.parse::<u64>().ok()?;
+        match unit {
+            "H" => Some(Duration::from_secs(amount * 3600)),
+            "M" => Some(Duration::from_secs(amount * 60)),
+            "S" => Some(Duration::from_secs(amount)),
+            "m" => Some(Duration::from_millis(amount)),
+            "u" => Some(Duration::from_micros(amount)),
+            "n" => Some(Duration::from_nanos(amount)),
+            _ => None,
+        }
+    });
+
+    match timeout {
+        Some(timeout) => Ok(Some(Instant::now() + timeout)),
+        None => {
+            let counter = metrics::counter!("te_request_failure", "err" => "validation");
+            counter.increment(1);
+            let message = "invalid `grpc-timeout` metadata".to_string();
+            tracing::error!("{message}");
+            Err(Status::new(Code::InvalidArgument, message))
+        }
+    }
+}
+
+/// Error of a request with an unknown value in an enum field
+fn invalid_enum_value(field: &str, value: i32) -> Status {
+    let message = format!("invalid `{field}` value: {value}");
+    tracing::error!("{message}");
+    let counter = metrics::counter!("te_request_failure", "err" => "validation");
+    counter.increment(1);
+    Status::invalid_argument(message)
+}
+
+fn convert_model_type(model_type: &ModelType) -> grpc::ModelType {
+    match model_type {
+        ModelType::Classifier(_) => grpc::ModelType::Classifier,
+        ModelType::Embedding(_) => grpc::ModelType::Embedding,
+        ModelType::Reranker(_) => grpc::ModelType::Reranker,
+    }
+}
+
+fn convert_encoding_format(value: i32) -> Result<Precision, Status> {
+    match EncodingFormat::try_from(value) {
+        Ok(EncodingFormat::Float) => Ok(Precision::Float32),
+        Ok(EncodingFormat::Float16) => Ok(Precision::Float16),
+        Ok(EncodingFormat::Int8) => Ok(Precision::Int8),
+        Ok(EncodingFormat::Uint8) => Ok(Precision::Uint8),
+        Ok(EncodingFormat::Binary) => Ok(Precision::Binary),
+        Ok(EncodingFormat::Ubinary) => Ok(Precision::Ubinary),
+        Err(_) => Err(invalid_enum_value("encoding_format", value)),
+    }
+}
+
+/// Cosine similarity of the embeddings of a query and a text
+fn cosine_similarity(query: &[f32], text: &[f32]) -> f32 {
+    1.0 - f32::cosine(query, text).unwrap() as f32
+}
+
+fn convert_priority(value: i32) -> Result<queue::Priority, Status> {
+    match Priority::try_from(value) {
+        Ok(Priority::Normal) => Ok(queue::Priority::Normal),
+        Ok(Priority::High) => Ok(queue::Priority::High),
+        Ok(Priority::Low) => Ok(queue::Priority::Low),
+        Err(_) => Err(invalid_enum_value("priority", value)),
+    }
+}
+
 fn convert_truncation_direction(value: i32) -> tokenizers::TruncationDirection {
     match TruncationDirection::try_from(value).expect("Unexpected enum value") {
         TruncationDirection::Right => tokenizers::TruncationDirection::Right,
         TruncationDirection::Left => tokenizers::TruncationDirection::Left,
     }
 }
+
+/// Defaults to the embeddings and, if the model provides them, the token weights
+fn convert_embed_outputs(values: &[i32], lexical_weights: bool) -> Result<EmbedOutputs, Status> {
+    if values.is_empty() {
+        return Ok(EmbedOutputs {
+            dense: true,
+            sparse: lexical_weights,
+            multivector: false,
+        });
+    }
+
+    let mut outputs = EmbedOutputs::default();
+    for value in values {
+        match EmbedOutput::try_from(*value) {
+            Ok(EmbedOutput::Dense) => outputs.dense = true,
+            Ok(EmbedOutput::Sparse) => outputs.sparse = true,
+            Ok(EmbedOutput::Multivector) => outputs.multivector = true,
+            Err(_) => return Err(invalid_enum_value("outputs", *value)),
+        }
+    }
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grpc::pb::tei::v1::{EmbedOutput, EncodingFormat, Priority};
+    use crate::grpc::server::{
+        convert_embed_outputs, convert_encoding_format, convert_priority, services_health,
+    };
+    use crate::quantization::Precision;
+    use crate::{ClassifierModel, EmbeddingModel, ModelType};
+    use text_embeddings_core::queue;
+    use tonic::Code;
+    use tonic_health::ServingStatus;
+
+    fn embedding() -> ModelType {
+        ModelType::Embedding(EmbeddingModel {
+            pooling: "cls".to_string(),
+        })
+    }
+
+    fn reranker() -> ModelType {
+        ModelType::Reranker(ClassifierModel {
+            id2label: Default::default(),
+            label2id: Default::default(),
+        })
+    }
+
+    fn statuses(models: &[(ModelType, bool)]) -> Vec<ServingStatus> {
+        services_health(models)
+            .into_iter()
+            .map(|(_, status)| status)
+            .collect()
+    }
+
+    #[test]
+    fn test_services_health() {
+        use ServingStatus::{NotServing, Serving};
+
+        // Embed, Rerank and Predict
+        assert_eq!(
+            statuses(&[(embedding(), true)]),
+            [Serving, NotServing, NotServing]
+        );
+        assert_eq!(
+            statuses(&[(embedding(), true), (reranker(), true)]),
+            [Serving, Serving, Serving]
+        );
+        // Any unhealthy model of a service makes it not serving, whatever the order
+        assert_eq!(
+            statuses(&[
+                (embedding(), false),
+                (embedding(), true),
+                (reranker(), true)
+            ]),
+            [NotServing, Serving, Serving]
+        );
+        assert_eq!(
+            statuses(&[
+                (embedding(), true),
+                (embedding(), false),
+                (reranker(), false)
+            ]),
+            [NotServing, NotServing, NotServing]
+        );
+    }
+
+    #[test]
+    fn test_convert_priority() {
+        assert_eq!(
+            convert_priority(Priority::High as i32).unwrap(),
+            queue::Priority::High
+        );
+        let status = convert_priority(42).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "invalid `priority` value: 42");
+    }
+
+    #[test]
+    fn test_convert_encoding_format() {
+        assert_eq!(
+            convert_encoding_format(EncodingFormat::Ubinary as i32).unwrap(),
+            Precision::Ubinary
+        );
+        let status = convert_encoding_format(-1).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "invalid `encoding_format` value: -1");
+    }
+
+    #[test]
+    fn test_convert_embed_outputs() {
+        let outputs = convert_embed_outputs(&[], true).unwrap();
+        assert!(outputs.dense && outputs.sparse && !outputs.multivector);
+        let outputs = convert_embed_outputs(&[EmbedOutput::Multivector as i32], true).unwrap();
+        assert!(!outputs.dense && !outputs.sparse && outputs.multivector);
+
+        let status = convert_embed_outputs(&[EmbedOutput::Dense as i32, 7], false).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "invalid `outputs` value: 7");
+    }
+}
@@ -0,0 +1,927 @@
+/// Asynchronous embedding jobs persisted to a local directory
+use crate::http::types::{
+    EmbedStreamResponse, Embedding, InputType, Job, JobParameters, JobResultsFormat, JobStatus,
+};
+use crate::{npy, ErrorResponse, ErrorType, Info};
+use anyhow::{Context, Result};
+use axum::body::Body;
+use futures::future::join_all;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufWriter, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use text_embeddings_core::infer::Infer;
+use text_embeddings_core::queue::Priority;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+/// Number of lines embedded between two checkpoints
+const CHECKPOINT_LINES: usize = 1024;
+
+const STATE_FILE: &str = "job.json";
+const INPUTS_FILE: &str = "inputs.jsonl";
+const RESULTS_FILE: &str = "results.jsonl";
+
+/// Content of `job.json`
+#[derive(Clone, Serialize, Deserialize)]
+struct JobRecord {
+    #[serde(flatten)]
+    job: Job,
+    /// File read by the job
+    input: PathBuf,
+    /// Bytes of the input already embedded
+    input_offset: u64,
+    /// Bytes of results written at the last checkpoint
+    results_offset: u64,
+}
+
+/// A line of a job input
+#[derive(Deserialize)]
+struct JobLine {
+    inputs: InputType,
+}
+
+/// The embedding of a line of `results.jsonl`
+#[derive(Deserialize)]
+struct JobResult {
+    embedding: Option<Vec<f32>>,
+}
+
+/// Jobs of the default model, run one after the other at low priority.
+/// Every job has a directory holding its state, its uploaded inputs and its results, so that
+/// unfinished jobs resume from their last checkpoint after a restart.
+#[derive(Clone)]
+pub(crate) struct JobStore {
+    directory: Arc<PathBuf>,
+    /// Canonical directory of the files that jobs can read with `path`
+    input_directory: Option<Arc<PathBuf>>,
+    jobs: Arc<Mutex<BTreeMap<String, JobRecord>>>,
+    /// Held while a job state is saved, so that the saves land in the order of the updates
+    saving: Arc<tokio::sync::Mutex<()>>,
+    queue: mpsc::UnboundedSender<String>,
+}
+
+impl JobStore {
+    /// Load the jobs of `directory` and start running the unfinished ones
+    pub fn load(
+        directory: &Path,
+        input_directory: Option<&Path>,
+        infer: Infer,
+        info: Info,
+    ) -> Result<Self> {
+        let (store, receiver) = Self::open(directory, input_directory)?;
+        tokio::spawn(store.clone().run(infer, info, receiver));
+        Ok(store)
+    }
+
+    /// Load the jobs of `directory` and queue the unfinished ones, oldest first
+    fn open(
+        directory: &Path,
+        input_directory: Option<&Path>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<String>)> {
+        let input_directory = input_directory
+            .map(|input_directory| {
+                input_directory.canonicalize().with_context(|| {
+                    format!(
+                        "Failed to open the jobs input directory `{}`",
+                        input_directory.display()
+                    )
+                })
+            })
+            .transpose()?
+            .map(Arc::new);
+        std::fs::create_dir_all(directory).with_context(|| {
+            format!(
+                "Failed to create the jobs directory `{}`",
+                directory.display()
+            )
+        })?;
+
+        let mut jobs = BTreeMap::new();
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path().join(STATE_FILE);
+            if !path.exists() {
+                continue;
+            }
+            let content = std::fs::read(&path)
+                .with_context(|| format!("Failed to read `{}`", path.display()))?;
+            let record: JobRecord = serde_json::from_slice(&content)
+                .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+            jobs.insert(record.job.id.clone(), record);
+        }
+
+        let mut unfinished: Vec<_> = jobs
+            .values()
+            .filter(|record| !record.job.status.is_finished())
+            .map(|record| (record.job.created_at, record.job.id.clone()))
+            .collect();
+        unfinished.sort();
+        if !unfinished.is_empty() {
+            tracing::info!("Resuming {} jobs", unfinished.len());
+        }
+
+        let (queue, receiver) = mpsc::unbounded_channel();
+        for (_, id) in unfinished {
+            // Unwrap is safe here: the receiver is alive
+            queue.send(id).unwrap();
+        }
+
+        let store = Self {
+            directory: Arc::new(directory.to_path_buf()),
+            input_directory,
+            jobs: Arc::new(Mutex::new(jobs)),
+            saving: Arc::new(tokio::sync::Mutex::new(())),
+            queue,
+        };
+        Ok((store, receiver))
+    }
+
+    /// Queue a job embedding the file at `parameters.path` or the lines of `body`
+    pub async fn submit(
+        &self,
+        parameters: JobParameters,
+        body: Body,
+    ) -> Result<Job, ErrorResponse> {
+        let id = format!("{:032x}", rand::random::<u128>());
+        let directory = self.directory.join(&id);
+        tokio::fs::create_dir_all(&directory)
+            .await
+            .map_err(|err| job_error(format!("Failed to create the job directory: {err}")))?;
+
+        let result = match &parameters.path {
+            Some(path) => {
+                if has_content(body).await {
+                    Err(validation_error(
+                        "`path` cannot be used with a request body".to_string(),
+                    ))
+                } else {
+                    self.input_path(path)
+                }
+            }
+            None => {
+                let input = directory.join(INPUTS_FILE);
+                match upload(body, &input).await {
+                    Ok(0) => Err(validation_error(
+                        "`path` or a JSONL request body must be given".to_string(),
+                    )),
+                    Ok(_) => Ok(input),
+                    Err(err) => Err(validation_error(format!(
+                        "Failed to read the request body: {err}"
+                    ))),
+                }
+            }
+        };
+        let input = match result {
+            Ok(input) => input,
+            Err(err) => {
+                let _ = tokio::fs::remove_dir_all(&directory).await;
+                return Err(err);
+            }
+        };
+
+        let record = JobRecord {
+            job: Job {
+                id: id.clone(),
+                status: JobStatus::Queued,
+                created_at: now(),
+                finished_at: None,
+                parameters,
+                total: None,
+                completed: 0,
+                failed: 0,
+                error: None,
+            },
+            input,
+            input_offset: 0,
+            results_offset: 0,
+        };
+        self.persist(record.clone())
+            .await
+            .map_err(|err| job_error(format!("Failed to save the job: {err}")))?;
+
+        let job = record.job.clone();
+        self.jobs.lock().unwrap().insert(id.clone(), record);
+        // Unwrap is safe here: the worker lives as long as the store
+        self.queue.send(id).unwrap();
+        Ok(job)
+    }
+
+    /// Resolve `path` in the input directory. Symbolic links and `..` components are resolved
+    /// before checking that the file is in the directory.
+    fn input_path(&self, path: &str) -> Result<PathBuf, ErrorResponse> {
+        let Some(input_directory) = self.input_directory.as_deref() else {
+            return Err(validation_error(
+                "`path` is disabled: set `--jobs-input-dir` to embed the files of a directory"
+                    .to_string(),
+            ));
+        };
+        match input_directory.join(path).canonicalize() {
+            Ok(input) if input.starts_with(input_directory) && input.is_file() => Ok(input),
+            _ => Err(validation_error(format!(
+                "`{path}` is not a file of the jobs input directory"
+            ))),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|record| record.job.clone())
+    }
+
+    /// Jobs, from the oldest to the most recent
+    pub fn list(&self) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|record| record.job.clone())
+            .collect();
+        jobs.sort_by_key(|job| job.created_at);
+        jobs
+    }
+
+    /// Cancel a queued or running job. The results embedded so far are kept.
+    pub async fn cancel(&self, id: &str) -> Option<Result<Job>> {
+        self.update(id, |record| {
+            if !record.job.status.is_finished() {
+                record.job.status = JobStatus::Cancelled;
+                record.job.finished_at = Some(now());
+            }
+        })
+        .await
+        .map(|result| result.map(|record| record.job))
+    }
+
+    /// Path and length of the results of a completed or cancelled job
+    pub async fn results(
+        &self,
+        id: &str,
+        format: JobResultsFormat,
+    ) -> Option<Result<(PathBuf, u64), ErrorResponse>> {
+        let record = self.jobs.lock().unwrap().get(id).cloned()?;
+        if !matches!(
+            record.job.status,
+            JobStatus::Completed | JobStatus::Cancelled
+        ) {
+            return Some(Err(validation_error(format!(
+                "job `{id}` is {:?}: results are available once it is completed or cancelled",
+                record.job.status
+            ))));
+        }
+
+        let directory = self.directory.join(id);
+        let results = directory.join(RESULTS_FILE);
+        Some(match format {
+            JobResultsFormat::Jsonl => Ok((results, record.results_offset)),
+            JobResultsFormat::Npy => {
+                // The results of a cancelled job still grow with the chunk it was embedding
+                let length = record.results_offset;
+                let path = directory.join(format!("results-{length}.npy"));
+                let rows = record.job.completed + record.job.failed;
+                tokio::task::spawn_blocking(move || -> Result<(PathBuf, u64)> {
+                    if !path.exists() {
+                        write_npy(&results, length, rows, &path)?;
+                    }
+                    Ok((path.clone(), std::fs::metadata(&path)?.len()))
+                })
+                .await
+                .map_err(|err| job_error(err.to_string()))
+                .and_then(|result| result.map_err(|err| job_error(err.to_string())))
+            }
+        })
+    }
+
+    /// Run the queued jobs one after the other
+    async fn run(self, infer: Infer, info: Info, mut receiver: mpsc::UnboundedReceiver<String>) {
+        while let Some(id) = receiver.recv().await {
+            match self.run_job(&id, &infer, &info).await {
+                Ok(status) => tracing::info!("Job {id} is {status:?}"),
+                Err(err) => {
+                    tracing::error!("Job {id} failed: {err:#}");
+                    let counter = metrics::counter!("te_request_failure", "err" => "job");
+                    counter.increment(1);
+                    let _ = self
+                        .update(&id, |record| {
+                            record.job.status = JobStatus::Failed;
+                            record.job.error = Some(format!("{err:#}"));
+                            record.job.finished_at = Some(now());
+                        })
+                        .await;
+                }
+            }
+        }
+    }
+
+    async fn run_job(&self, id: &str, infer: &Infer, info: &Info) -> Result<JobStatus> {
+        let Some(Ok(mut record)) = self
+            .update(id, |record| {
+                if record.job.status == JobStatus::Queued {
+                    record.job.status = JobStatus::Running;
+                }
+            })
+            .await
+        else {
+            anyhow::bail!("job state could not be saved");
+        };
+        if record.job.status != JobStatus::Running {
+            return Ok(record.job.status);
+        }
+
+        if record.job.total.is_none() {
+            let input = record.input.clone();
+            let total = tokio::task::spawn_blocking(move || count_lines(&input)).await??;
+            record = self
+                .update(id, |record| record.job.total = Some(total))
+                .await
+                .context("job was removed")??;
+        }
+
+        let parameters = record.job.parameters.clone();
+        let dimensions = info
+            .dimensions(parameters.dimensions)
+            .map_err(|err| anyhow::anyhow!(err.error))?;
+        let truncate = parameters.truncate.unwrap_or(info.auto_truncate);
+
+        let (mut input, mut results) = self.restore_checkpoint(&record).await?;
+
+        let mut line = String::new();
+        loop {
+            let mut lines = Vec::with_capacity(CHECKPOINT_LINES);
+            let mut read = 0;
+            while lines.len() < CHECKPOINT_LINES {
+                line.clear();
+                let size = input.read_line(&mut line).await?;
+                if size == 0 {
+                    break;
+                }
+                read += size as u64;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                lines.push(
+                    serde_json::from_str::<JobLine>(&line)
+                        .map(|line| line.inputs)
+                        .map_err(|err| validation_error(format!("invalid line: {err}"))),
+                );
+            }
+            if read == 0 {
+                break;
+            }
+
+            let futures = lines.into_iter().map(|line| {
+                let prompt_name = parameters.prompt_name.clone();
+                async move {
+                    let permit = infer.acquire_permit(Priority::Low).await;
+                    let response = infer
+                        .embed_pooled(
+                            line?,
+                            truncate,
+                            parameters.truncation_direction.into(),
+                            prompt_name,
+                            parameters.normalize,
+                            dimensions,
+                            Priority::Low,
+                            None,
+                            permit,
+                        )
+                        .await?;
+                    Ok::<_, ErrorResponse>(response.results)
+                }
+            });
+
+            let mut index = record.job.completed + record.job.failed;
+            let mut buffer = Vec::new();
+            let (mut completed, mut failed) = (0, 0);
+            for result in join_all(futures).await {
+                let response = match result {
+                    Ok(embedding) => {
+                        completed += 1;
+                        EmbedStreamResponse {
+                            index,
+                            embedding: Some(Embedding::Float(embedding)),
+                            error: None,
+                            error_type: None,
+                        }
+                    }
+                    Err(err) => {
+                        failed += 1;
+                        EmbedStreamResponse {
+                            index,
+                            embedding: None,
+                            error: Some(err.error),
+                            error_type: Some(err.error_type),
+                        }
+                    }
+                };
+                serde_json::to_writer(&mut buffer, &response)?;
+                buffer.push(b'\n');
+                index += 1;
+            }
+            results.write_all(&buffer).await?;
+            results.sync_data().await?;
+
+            record = self
+                .update(id, |record| {
+                    record.job.completed += completed;
+                    record.job.failed += failed;
+                    record.input_offset += read;
+                    record.results_offset += buffer.len() as u64;
+                })
+                .await
+                .context("job was removed")??;
+            if record.job.status != JobStatus::Running {
+                return Ok(record.job.status);
+            }
+        }
+
+        let record = self
+            .update(id, |record| {
+                record.job.status = JobStatus::Completed;
+                record.job.finished_at = Some(now());
+            })
+            .await
+            .context("job was removed")??;
+        let counter = metrics::counter!("te_request_success", "method" => "job");
+        counter.increment(1);
+        Ok(record.job.status)
+    }
+
+    /// Open the input and the results of a job at its last checkpoint. The results written
+    /// after the checkpoint are dropped.
+    async fn restore_checkpoint(&self, record: &JobRecord) -> Result<(BufReader<File>, File)> {
+        let mut input = File::open(&record.input)
+            .await
+            .with_context(|| format!("Failed to open `{}`", record.input.display()))?;
+        input.seek(SeekFrom::Start(record.input_offset)).await?;
+
+        let mut results = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(self.directory.join(&record.job.id).join(RESULTS_FILE))
+            .await?;
+        results.set_len(record.results_offset).await?;
+        results.seek(SeekFrom::Start(record.results_offset)).await?;
+        Ok((BufReader::new(input), results))
+    }
+
+    /// Update the state of a job and save it
+    async fn update(&self, id: &str, f: impl FnOnce(&mut JobRecord)) -> Option<Result<JobRecord>> {
+        let _saving = self.saving.lock().await;
+        let record = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let record = jobs.get_mut(id)?;
+            f(record);
+            record.clone()
+        };
+        Some(self.persist(record.clone()).await.map(|_| record))
+    }
+
+    /// Save the state of a job outside of the runtime threads
+    async fn persist(&self, record: JobRecord) -> Result<()> {
+        let path = self.directory.join(&record.job.id).join(STATE_FILE);
+        tokio::task::spawn_blocking(move || {
+            // Write to a temporary file first to never leave a truncated state behind
+            let tmp_path = path.with_extension("tmp");
+            std::fs::write(&tmp_path, serde_json::to_vec(&record)?)
+                .with_context(|| format!("Failed to write `{}`", tmp_path.display()))?;
+            std::fs::rename(&tmp_path, &path)
+                .with_context(|| format!("Failed to write `{}`", path.display()))
+        })
+        .await?
+    }
+}
+
+/// Write `body` to `path` and return its size
+async fn upload(body: Body, path: &Path) -> Result<u64> {
+    let mut file = File::create(path).await?;
+    let mut chunks = body.into_data_stream();
+    let mut size = 0;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+    }
+    file.sync_all().await?;
+    Ok(size)
+}
+
+async fn has_content(body: Body) -> bool {
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        match chunk {
+            Ok(chunk) if chunk.is_empty() => continue,
+            _ => return true,
+        }
+    }
+    false
+}
+
+/// Number of non-empty lines of a file
+fn count_lines(path: &Path) -> Result<usize> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open `{}`", path.display()))?;
+    let mut total = 0;
+    for line in std::io::BufReader::new(file).split(b'\n') {
+        if !line?.iter().all(u8::is_ascii_whitespace) {
+            total += 1;
+        }
+    }
+    Ok(total)
+}
+
+/// Convert the first `length` bytes of `results.jsonl` to a `.npy` file
+fn write_npy(results: &Path, length: u64, rows: usize, path: &Path) -> Result<()> {
+    let lines = || -> Result<_> {
+        let file = std::fs::File::open(results)?;
+        Ok(std::io::Read::take(std::io::BufReader::new(file), length)
+            .lines()
+            .map(|line| -> Result<Option<Vec<f32>>> {
+                Ok(serde_json::from_str::<JobResult>(&line?)?.embedding)
+            }))
+    };
+
+    let mut columns = None;
+    for embedding in lines()? {
+        if let Some(embedding) = embedding? {
+            columns = Some(embedding.len());
+            break;
+        }
+    }
+    let columns = columns.context("the job has no embeddings")?;
+
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(std::fs::File::create(&tmp_path)?);
+    npy::write_header(&mut writer, rows, columns)?;
+    let missing = vec![f32::NAN; columns];
+    for embedding in lines()? {
+        npy::write_row(&mut writer, embedding?.as_deref().unwrap_or(&missing))?;
+    }
+    writer.into_inner()?.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+fn validation_error(message: String) -> ErrorResponse {
+    tracing::error!("{message}");
+    let counter = metrics::counter!("te_request_failure", "err" => "validation");
+    counter.increment(1);
+    ErrorResponse {
+        error: message,
+        error_type: ErrorType::Validation,
+    }
+}
+
+fn job_error(message: String) -> ErrorResponse {
+    tracing::error!("{message}");
+    ErrorResponse {
+        error: message,
+        error_type: ErrorType::Backend,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::jobs::{write_npy, JobStore, RESULTS_FILE};
+    use crate::http::types::{JobParameters, JobResultsFormat, JobStatus};
+    use crate::npy;
+    use axum::body::Body;
+    use std::path::Path;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+    use tokio::sync::mpsc;
+
+    fn open(
+        directory: &Path,
+        input_directory: Option<&Path>,
+    ) -> (JobStore, mpsc::UnboundedReceiver<String>) {
+        JobStore::open(directory, input_directory).unwrap()
+    }
+
+    fn parameters(path: Option<&str>) -> JobParameters {
+        serde_json::from_value(serde_json::json!({ "path": path })).unwrap()
+    }
+
+    fn queued(receiver: &mut mpsc::UnboundedReceiver<String>) -> Vec<String> {
+        let mut ids = Vec::new();
+        while let Ok(id) = receiver.try_recv() {
+            ids.push(id);
+        }
+        ids
+    }
+
+    #[test]
+    fn test_input_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = dir.path().join("inputs");
+        std::fs::create_dir_all(inputs.join("sub")).unwrap();
+        std::fs::write(inputs.join("corpus.jsonl"), "").unwrap();
+        std::fs::write(inputs.join("sub/nested.jsonl"), "").unwrap();
+        std::fs::write(dir.path().join("secret.jsonl"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path().join("secret.jsonl"), inputs.join("link.jsonl"))
+            .unwrap();
+
+        let (store, _receiver) = open(&dir.path().join("jobs"), Some(&inputs));
+        let inputs = inputs.canonicalize().unwrap();
+        assert_eq!(
+            store.input_path("corpus.jsonl").unwrap(),
+            inputs.join("corpus.jsonl")
+        );
+        assert_eq!(
+            store.input_path("sub/../sub/nested.jsonl").unwrap(),
+            inputs.join("sub/nested.jsonl")
+        );
+        let absolute = inputs.join("corpus.jsonl");
+        assert_eq!(
+            store.input_path(absolute.to_str().unwrap()).unwrap(),
+            absolute
+        );
+
+        let secret = dir.path().join("secret.jsonl");
+        for path in [
+            "../secret.jsonl",
+            secret.to_str().unwrap(),
+            "link.jsonl",
+            "sub",
+            "missing.jsonl",
+        ] {
+            let err = store.input_path(path).unwrap_err();
+            assert_eq!(
+                err.error,
+                format!("`{path}` is not a file of the jobs input directory")
+            );
+        }
+
+        // `path` is disabled without an input directory
+        let (store, _receiver) = open(&dir.path().join("jobs"), None);
+        let err = store.input_path(absolute.to_str().unwrap()).unwrap_err();
+        assert!(err.error.starts_with("`path` is disabled"));
+
+        assert!(JobStore::open(dir.path(), Some(&dir.path().join("missing"))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_submit() {
+        let dir = tempfile::tempdir().unwrap();
+        let jobs = dir.path().join("jobs");
+        std::fs::write(dir.path().join("corpus.jsonl"), "{\"inputs\": \"a\"}\n").unwrap();
+        let (store, mut receiver) = open(&jobs, Some(dir.path()));
+
+        let job = store
+            .submit(parameters(Some("corpus.jsonl")), Body::empty())
+            .await
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Queued);
+        let record = store.jobs.lock().unwrap()[&job.id].clone();
+        assert_eq!(
+            record.input,
+            dir.path().canonicalize().unwrap().join("corpus.jsonl")
+        );
+
+        let lines = "{\"inputs\": \"b\"}\n{\"inputs\": \"c\"}\n";
+        let uploaded = store
+            .submit(parameters(None), Body::from(lines))
+            .await
+            .unwrap();
+        let record = store.jobs.lock().unwrap()[&uploaded.id].clone();
+        assert_eq!(std::fs::read_to_string(record.input).unwrap(), lines);
+        assert_eq!(queued(&mut receiver), [job.id, uploaded.id]);
+
+        // Rejected jobs leave nothing behind
+        for (path, body) in [
+            (Some("corpus.jsonl"), Body::from(lines)),
+            (Some("../jobs"), Body::empty()),
+            (None, Body::empty()),
+        ] {
+            assert!(store.submit(parameters(path), body).await.is_err());
+        }
+        assert_eq!(std::fs::read_dir(&jobs).unwrap().count(), 2);
+        assert_eq!(store.list().len(), 2);
+        assert!(queued(&mut receiver).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let (store, _receiver) = open(dir.path(), None);
+
+        let mut ids = Vec::new();
+        for (created_at, status) in [
+            (3, JobStatus::Queued),
+            (1, JobStatus::Running),
+            (2, JobStatus::Completed),
+            (4, JobStatus::Failed),
+        ] {
+            let job = store
+                .submit(parameters(None), Body::from("{\"inputs\": \"a\"}\n"))
+                .await
+                .unwrap();
+            store
+                .update(&job.id, |record| {
+                    record.job.created_at = created_at;
+                    record.job.status = status;
+                    record.input_offset = 16;
+                    record.results_offset = 64;
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            ids.push(job.id);
+        }
+
+        // Unfinished jobs are queued again, oldest first, with their checkpoint
+        let (restarted, mut receiver) = open(dir.path(), None);
+        assert_eq!(queued(&mut receiver), [1, 0].map(|i| ids[i].clone()));
+        let listed: Vec<_> = restarted.list().into_iter().map(|job| job.id).collect();
+        assert_eq!(listed, [1, 2, 0, 3].map(|i| ids[i].clone()));
+        let record = restarted.jobs.lock().unwrap()[&ids[1]].clone();
+        assert_eq!(record.job.status, JobStatus::Running);
+        assert_eq!((record.input_offset, record.results_offset), (16, 64));
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let dir = tempfile::tempdir().unwrap();
+        let (store, _receiver) = open(dir.path(), None);
+        let job = store
+            .submit(parameters(None), Body::from("{\"inputs\": \"a\"}\n"))
+            .await
+            .unwrap();
+
+        let err = store
+            .results(&job.id, JobResultsFormat::Jsonl)
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(err.error.contains("is Queued"));
+
+        let cancelled = store.cancel(&job.id).await.unwrap().unwrap();
+        assert_eq!(cancelled.status, JobStatus::Cancelled);
+        let finished_at = cancelled.finished_at.unwrap();
+        assert!(store.cancel("unknown").await.is_none());
+        assert!(store
+            .results("unknown", JobResultsFormat::Jsonl)
+            .await
+            .is_none());
+
+        // Cancelled jobs keep the results embedded so far
+        let (path, length) = store
+            .results(&job.id, JobResultsFormat::Jsonl)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(path, dir.path().join(&job.id).join(RESULTS_FILE));
+        assert_eq!(length, 0);
+
+        // Finished jobs are neither cancelled again nor resumed
+        store
+            .update(&job.id, |record| record.job.status = JobStatus::Completed)
+            .await
+            .unwrap()
+            .unwrap();
+        let job = store.cancel(&job.id).await.unwrap().unwrap();
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.finished_at, Some(finished_at));
+        let (restarted, mut receiver) = open(dir.path(), None);
+        assert_eq!(restarted.get(&job.id).unwrap().status, JobStatus::Completed);
+        assert!(queued(&mut receiver).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_npy_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let (store, _receiver) = open(dir.path(), None);
+        let job = store
+            .submit(parameters(None), Body::from("{\"inputs\": \"a\"}\n"))
+            .await
+            .unwrap();
+        let lines = [
+            "{\"index\":0,\"embedding\":[1.0]}\n",
+            "{\"index\":1,\"embedding\":[2.0]}\n",
+        ];
+        std::fs::write(dir.path().join(&job.id).join(RESULTS_FILE), lines.concat()).unwrap();
+        store
+            .update(&job.id, |record| {
+                record.job.status = JobStatus::Cancelled;
+                record.job.completed = 1;
+                record.results_offset = lines[0].len() as u64;
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let (path, length) = store
+            .results(&job.id, JobResultsFormat::Npy)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // The chunk embedded when the job was cancelled is saved afterwards
+        store
+            .update(&job.id, |record| {
+                record.job.completed = 2;
+                record.results_offset = lines.concat().len() as u64;
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let (updated_path, updated_length) = store
+            .results(&job.id, JobResultsFormat::Npy)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_ne!(updated_path, path);
+        assert_eq!(updated_length, length + 4);
+        let content = std::fs::read(&updated_path).unwrap();
+        assert_eq!(
+            content[content.len() - 8..],
+            [1f32, 2f32].map(f32::to_le_bytes).concat()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restore_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let (store, _receiver) = open(dir.path(), None);
+        let lines = "{\"inputs\": \"a\"}\n{\"inputs\": \"b\"}\n";
+        let job = store
+            .submit(parameters(None), Body::from(lines))
+            .await
+            .unwrap();
+
+        // The job stopped after writing part of the results of the second line
+        let results = dir.path().join(&job.id).join(RESULTS_FILE);
+        std::fs::write(&results, "{\"index\": 0}\n{\"ind").unwrap();
+        let record = store
+            .update(&job.id, |record| {
+                record.input_offset = 16;
+                record.results_offset = 13;
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let (mut input, mut output) = store.restore_checkpoint(&record).await.unwrap();
+        let mut line = String::new();
+        input.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "{\"inputs\": \"b\"}\n");
+        assert_eq!(
+            std::fs::read_to_string(&results).unwrap(),
+            "{\"index\": 0}\n"
+        );
+
+        output.write_all(b"{\"index\": 1}\n").await.unwrap();
+        output.sync_all().await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&results).unwrap(),
+            "{\"index\": 0}\n{\"index\": 1}\n"
+        );
+    }
+
+    #[test]
+    fn test_write_npy() {
+        let dir = tempfile::tempdir().unwrap();
+        let results = dir.path().join(RESULTS_FILE);
+        let path = dir.path().join("results.npy");
+
+        // The line after the last checkpoint is ignored
+        let lines = [
+            "{\"index\":0,\"embedding\":null,\"error\":\"invalid line\"}\n",
+            "{\"index\":1,\"embedding\":[1.0,2.0]}\n",
+            "{\"index\":2,\"embedding\":[3.0,-4.0]}\n",
+            "{\"index\":3,\"embe",
+        ];
+        std::fs::write(&results, lines.concat()).unwrap();
+        let length = lines[..3].iter().map(|line| line.len() as u64).sum();
+        write_npy(&results, length, 3, &path).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let mut header = Vec::new();
+        npy::write_header(&mut header, 3, 2).unwrap();
+        let content = std::fs::read(&path).unwrap();
+        assert_eq!(content[..header.len()], header);
+        let values: Vec<f32> = content[header.len()..]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        assert_eq!(values.len(), 6);
+        assert!(values[..2].iter().all(|value| value.is_nan()));
+        assert_eq!(values[2..], [1.0, 2.0, 3.0, -4.0]);
+
+        let err = write_npy(&results, lines[0].len() as u64, 1, &path).unwrap_err();
+        assert_eq!(err.to_string(), "the job has no embeddings");
+    }
+}
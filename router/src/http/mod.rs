@@ -1,2 +1,3 @@
+mod jobs;
 pub mod server;
 mod types;
@@ -1,46 +1,156 @@
 /// HTTP Server logic
+use crate::http::jobs::JobStore;
 use crate::http::types::{
-    DecodeRequest, DecodeResponse, EmbedAllRequest, EmbedAllResponse, EmbedRequest, EmbedResponse,
-    EmbedSparseRequest, EmbedSparseResponse, Embedding, EncodingFormat, Input, InputIds, InputType,
-    OpenAICompatEmbedding, OpenAICompatErrorResponse, OpenAICompatRequest, OpenAICompatResponse,
-    OpenAICompatUsage, PredictInput, PredictRequest, PredictResponse, Prediction, Rank,
-    RerankRequest, RerankResponse, Sequence, SimilarityInput, SimilarityParameters,
-    SimilarityRequest, SimilarityResponse, SimpleToken, SparseValue, TokenizeInput,
-    TokenizeRequest, TokenizeResponse, TruncationDirection, VertexPrediction, VertexRequest,
-    VertexResponse,
+    ChunkAggregation, ChunkedEmbedding, ChunkingParameters, CohereApiVersion, CohereBilledUnits,
+    CohereEmbedRequest, CohereEmbedResponse, CohereEmbeddingType, CohereEmbeddings,
+    CohereEmbeddingsByType, CohereInputType, CohereMeta, CohereTruncate, CompatRerankDocument,
+    CompatRerankRequest, CompatRerankResponse, CompatRerankResult, CompatRerankText,
+    CompatRerankUsage, DecodeRequest, DecodeResponse, EmbedAllRequest, EmbedAllResponse,
+    EmbedMultiVectorRequest, EmbedMultiVectorResponse, EmbedOutput, EmbedRequest, EmbedResponse,
+    EmbedSparseRequest, EmbedSparseResponse, EmbedStreamRequest, EmbedStreamResponse, Embedding,
+    EmbeddingChunk, EncodingFormat, HybridEmbedding, Input, InputIds, InputType, Job,
+    JobParameters, JobResultsFormat, JobResultsQuery, JobStatus, JobsResponse, OllamaEmbedRequest,
+    OllamaEmbedResponse, OpenAICompatEmbedding, OpenAICompatErrorResponse, OpenAICompatRequest,
+    OpenAICompatResponse, OpenAICompatUsage, PredictInput, PredictRequest, PredictResponse,
+    Prediction, Priority, Rank, RerankRequest, RerankResponse, Sequence, SimilarityInput,
+    SimilarityParameters, SimilarityRequest, SimilarityResponse, SimpleToken, SparseValue,
+    TokenizeInput, TokenizeRequest, TokenizeResponse, TruncationDirection, VertexPrediction,
+    VertexRequest, VertexResponse,
 };
+#[cfg(feature = "index")]
+use crate::http::types::{
+    IndexDeleteRequest, IndexDeleteResponse, IndexDocument, IndexUpsertRequest,
+    IndexUpsertResponse, SearchHit, SearchMode, SearchRequest, SearchResponse,
+};
+#[cfg(feature = "index")]
+use crate::index::VectorIndex;
+use crate::quantization::{quantize, Precision, QuantizedEmbedding};
 use crate::{
-    shutdown, ClassifierModel, EmbeddingModel, ErrorResponse, ErrorType, Info, ModelType,
-    ResponseMetadata,
+    ClassifierModel, EmbeddingModel, ErrorResponse, ErrorType, IndexMetric, Info, ModelType,
+    OnnxRuntimeInfo, ResponseMetadata, ServedModel,
 };
 use ::http::HeaderMap;
 use anyhow::Context;
-use axum::extract::{DefaultBodyLimit, Extension};
+use axum::body::{Body, Bytes};
+use axum::extract::{DefaultBodyLimit, Extension, Query};
+use axum::http::header::CONTENT_TYPE;
 use axum::http::HeaderValue;
 use axum::http::{Method, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, post};
 use axum::{http, Json, Router};
 use axum_tracing_opentelemetry::middleware::OtelAxumLayer;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use futures::future::join_all;
-use futures::FutureExt;
+use futures::{FutureExt, StreamExt};
 use http::header::AUTHORIZATION;
-use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use metrics_exporter_prometheus::PrometheusHandle;
 use simsimd::SpatialSimilarity;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use text_embeddings_backend::BackendError;
 use text_embeddings_core::infer::{
-    AllEmbeddingsInferResponse, Infer, InferMetadata, PooledEmbeddingsInferResponse,
+    AllEmbeddingsInferResponse, ChunkedEmbeddingsInferResponse, EmbedOutputs,
+    HybridEmbeddingsInferResponse, Infer, InferMetadata, PooledEmbeddingsInferResponse,
 };
 use text_embeddings_core::TextEmbeddingsError;
-use tokio::sync::OwnedSemaphorePermit;
+use tokio::io::AsyncReadExt;
+#[cfg(feature = "index")]
+use tokio::sync::RwLock;
+use tokio::sync::{mpsc, oneshot, OwnedSemaphorePermit};
 use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::instrument;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+/// Header carrying the time budget of a request, in milliseconds
+const REQUEST_TIMEOUT_HEADER: &str = "x-request-timeout-ms";
+
+/// Served models, by name and by model id
+#[derive(Clone)]
+struct ServedModels {
+    by_name: Arc<HashMap<String, (Infer, Info)>>,
+    single: bool,
+}
+
+impl ServedModels {
+    fn new(models: &[(String, Infer, Info)]) -> Self {
+        let mut by_name = HashMap::with_capacity(2 * models.len());
+        // Names take precedence over model ids
+        for (_, infer, info) in models.iter().rev() {
+            by_name.insert(info.model_id.clone(), (infer.clone(), info.clone()));
+        }
+        for (name, infer, info) in models {
+            by_name.insert(name.clone(), (infer.clone(), info.clone()));
+        }
+        Self {
+            by_name: Arc::new(by_name),
+            single: models.len() == 1,
+        }
+    }
+
+    /// Model named by the `model` field of a request, or the default model if it is not set.
+    /// With a single model, `model` is ignored: clients of the compatible APIs always set it.
+    fn select(
+        &self,
+        name: Option<&str>,
+        infer: Extension<Infer>,
+        info: Extension<Info>,
+    ) -> Result<(Extension<Infer>, Extension<Info>), ModelNotFound> {
+        match name {
+            Some(name) if !self.single => match self.by_name.get(name) {
+                Some((infer, info)) => Ok((Extension(infer.clone()), Extension(info.clone()))),
+                None => {
+                    let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                    counter.increment(1);
+                    let message = format!("model `{name}` is not served");
+                    tracing::error!("{message}");
+                    Err(ModelNotFound(message))
+                }
+            },
+            _ => Ok((infer, info)),
+        }
+    }
+}
+
+/// A request named a model that is not served
+struct ModelNotFound(String);
+
+impl From<ModelNotFound> for (StatusCode, Json<ErrorResponse>) {
+    fn from(err: ModelNotFound) -> Self {
+        (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: err.0,
+                error_type: ErrorType::Validation,
+            }),
+        )
+    }
+}
+
+impl From<ModelNotFound> for (StatusCode, Json<OpenAICompatErrorResponse>) {
+    fn from(err: ModelNotFound) -> Self {
+        (
+            StatusCode::NOT_FOUND,
+            Json(OpenAICompatErrorResponse {
+                message: err.0,
+                code: StatusCode::NOT_FOUND.as_u16(),
+                error_type: ErrorType::Validation,
+            }),
+        )
+    }
+}
+
+/// Maximum size of a request body, also applied to every line of a streamed body
+#[derive(Clone, Copy)]
+struct PayloadLimit(usize);
+
 ///Text Embeddings Inference endpoint info
 #[utoipa::path(
 get,
@@ -102,10 +212,13 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
 async fn predict(
     infer: Extension<Infer>,
     info: Extension<Info>,
+    headers: HeaderMap,
     Json(req): Json<PredictRequest>,
 ) -> Result<(HeaderMap, Json<PredictResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     let start_time = Instant::now();
+    let priority = req.priority.into();
+    let deadline = request_deadline(&headers)?;
 
     // Closure for predict
     let predict_inner = move |inputs: Sequence,
@@ -114,7 +227,7 @@ async fn predict(
                               info: Info,
                               permit: Option<OwnedSemaphorePermit>| async move {
         let permit = match permit {
-            None => infer.acquire_permit().await,
+            None => infer.acquire_permit(priority).await,
             Some(permit) => permit,
         };
 
@@ -124,6 +237,8 @@ async fn predict(
                 truncate,
                 req.truncation_direction.into(),
                 req.raw_scores,
+                priority,
+                deadline,
                 permit,
             )
             .await
@@ -171,7 +286,9 @@ async fn predict(
             counter.increment(1);
 
             let compute_chars = inputs.count_chars();
-            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let permit = infer
+                .try_acquire_permit(priority)
+                .map_err(ErrorResponse::from)?;
             let (prompt_tokens, tokenization, queue, inference, predictions) =
                 predict_inner(inputs, truncate, infer.0, info.0, Some(permit)).await?;
 
@@ -273,7 +390,8 @@ async fn predict(
 }
 
 /// Get Ranks. Returns a 424 status code if the model is not a Sequence Classification model with
-/// a single class.
+/// a single class or an embedding model. Embedding models rank the texts by the cosine similarity
+/// of their embeddings with the embedding of the query.
 #[utoipa::path(
 post,
 tag = "Text Embeddings Inference",
@@ -300,12 +418,63 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
 async fn rerank(
     infer: Extension<Infer>,
     info: Extension<Info>,
+    headers: HeaderMap,
     Json(req): Json<RerankRequest>,
 ) -> Result<(HeaderMap, Json<RerankResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     let start_time = Instant::now();
+    let deadline = request_deadline(&headers)?;
+
+    let (scores, metadata) = rank_texts(
+        &infer,
+        &info,
+        req.query,
+        &req.texts,
+        req.truncate.unwrap_or(info.auto_truncate),
+        req.truncation_direction,
+        req.raw_scores,
+        req.priority.into(),
+        deadline,
+        start_time,
+    )
+    .await?;
+
+    let ranks = scores
+        .into_iter()
+        .map(|(index, score)| Rank {
+            index,
+            text: req.return_text.then(|| req.texts[index].clone()),
+            score,
+        })
+        .collect();
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
 
-    if req.texts.is_empty() {
+    tracing::info!("Success");
+
+    Ok((headers, Json(RerankResponse(ranks))))
+}
+
+/// Score `texts` against `query` with a re-ranker, or with the cosine similarity of the
+/// embeddings for embedding models. Returns the `(index, score)` pairs from the most to the least
+/// relevant text.
+#[allow(clippy::too_many_arguments)]
+async fn rank_texts(
+    infer: &Infer,
+    info: &Info,
+    query: String,
+    texts: &[String],
+    truncate: bool,
+    truncation_direction: TruncationDirection,
+    raw_scores: bool,
+    priority: text_embeddings_core::queue::Priority,
+    deadline: Option<Instant>,
+    start_time: Instant,
+) -> Result<(Vec<(usize, f32)>, ResponseMetadata), ErrorResponse> {
+    if texts.is_empty() {
         let message = "`texts` cannot be empty".to_string();
         tracing::error!("{message}");
         let err = ErrorResponse {
@@ -317,12 +486,32 @@ async fn rerank(
         Err(err)?;
     }
 
-    match &info.model_type {
-        ModelType::Reranker(_) => Ok(()),
-        ModelType::Classifier(_) | ModelType::Embedding(_) => {
+    let counter = metrics::counter!("te_request_count", "method" => "batch");
+    counter.increment(1);
+
+    let batch_size = texts.len();
+    if batch_size > info.max_client_batch_size {
+        let message = format!(
+            "batch size {batch_size} > maximum allowed batch size {}",
+            info.max_client_batch_size
+        );
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+        counter.increment(1);
+        Err(err)?;
+    }
+
+    let bi_encoder = match &info.model_type {
+        ModelType::Reranker(_) => Ok(false),
+        ModelType::Embedding(_) => Ok(true),
+        ModelType::Classifier(_) => {
             let counter = metrics::counter!("te_request_failure", "err" => "model_type");
             counter.increment(1);
-            let message = "model is not a re-ranker model".to_string();
+            let message = "model is not a re-ranker or embedding model".to_string();
             Err(TextEmbeddingsError::Backend(BackendError::Inference(
                 message,
             )))
@@ -333,132 +522,150 @@ async fn rerank(
         ErrorResponse::from(err)
     })?;
 
-    // Closure for rerank
-    let rerank_inner = move |query: String, text: String, truncate: bool, infer: Infer| async move {
-        let permit = infer.acquire_permit().await;
+    let dimensions = info.dimensions(None)?;
+    let document_prompt_name = info.document_prompt_name.as_deref();
 
+    // Embedding models embed the query once
+    let query_embedding = if bi_encoder {
+        let permit = infer.acquire_permit(priority).await;
         let response = infer
-            .predict(
-                (query, text),
+            .embed_pooled(
+                query.clone(),
                 truncate,
-                req.truncation_direction.into(),
-                req.raw_scores,
+                truncation_direction.into(),
+                info.query_prompt_name.clone(),
+                false,
+                dimensions,
+                priority,
+                deadline,
                 permit,
             )
             .await
             .map_err(ErrorResponse::from)?;
+        Some(response)
+    } else {
+        None
+    };
+    let query_vector = query_embedding.as_ref().map(|r| r.results.as_slice());
 
-        let score = response.results[0];
+    // Closure for rerank
+    let rerank_inner = move |query: String, text: String, truncate: bool, infer: Infer| async move {
+        let permit = infer.acquire_permit(priority).await;
+
+        let (metadata, score) = match query_vector {
+            None => {
+                let response = infer
+                    .predict(
+                        (query, text),
+                        truncate,
+                        truncation_direction.into(),
+                        raw_scores,
+                        priority,
+                        deadline,
+                        permit,
+                    )
+                    .await
+                    .map_err(ErrorResponse::from)?;
+                (response.metadata, response.results[0])
+            }
+            Some(query_vector) => {
+                let response = infer
+                    .embed_pooled(
+                        text,
+                        truncate,
+                        truncation_direction.into(),
+                        document_prompt_name.map(str::to_string),
+                        false,
+                        dimensions,
+                        priority,
+                        deadline,
+                        permit,
+                    )
+                    .await
+                    .map_err(ErrorResponse::from)?;
+                let score = 1.0 - f32::cosine(query_vector, &response.results).unwrap() as f32;
+                (response.metadata, score)
+            }
+        };
 
         Ok::<(usize, Duration, Duration, Duration, f32), ErrorResponse>((
-            response.metadata.prompt_tokens,
-            response.metadata.tokenization,
-            response.metadata.queue,
-            response.metadata.inference,
+            metadata.prompt_tokens,
+            metadata.tokenization,
+            metadata.queue,
+            metadata.inference,
             score,
         ))
     };
 
-    let truncate = req.truncate.unwrap_or(info.auto_truncate);
-
-    let (response, metadata) = {
-        let counter = metrics::counter!("te_request_count", "method" => "batch");
-        counter.increment(1);
-
-        let batch_size = req.texts.len();
-        if batch_size > info.max_client_batch_size {
-            let message = format!(
-                "batch size {batch_size} > maximum allowed batch size {}",
-                info.max_client_batch_size
-            );
-            tracing::error!("{message}");
-            let err = ErrorResponse {
-                error: message,
-                error_type: ErrorType::Validation,
-            };
-            let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
-            counter.increment(1);
-            Err(err)?;
-        }
-
-        let mut futures = Vec::with_capacity(batch_size);
-        let query_chars = req.query.chars().count();
-        let mut compute_chars = query_chars * batch_size;
-
-        for text in &req.texts {
-            compute_chars += text.chars().count();
-            let local_infer = infer.clone();
-            futures.push(rerank_inner(
-                req.query.clone(),
-                text.clone(),
-                truncate,
-                local_infer.0,
-            ))
-        }
-        let results = join_all(futures)
-            .await
-            .into_iter()
-            .collect::<Result<Vec<(usize, Duration, Duration, Duration, f32)>, ErrorResponse>>()?;
-
-        let mut ranks = Vec::with_capacity(batch_size);
-        let mut total_tokenization_time = 0;
-        let mut total_queue_time = 0;
-        let mut total_inference_time = 0;
-        let mut total_compute_tokens = 0;
-
-        for (index, r) in results.into_iter().enumerate() {
-            total_compute_tokens += r.0;
-            total_tokenization_time += r.1.as_nanos() as u64;
-            total_queue_time += r.2.as_nanos() as u64;
-            total_inference_time += r.3.as_nanos() as u64;
-            let text = if req.return_text {
-                Some(req.texts[index].clone())
-            } else {
-                None
-            };
-
-            let score = r.4;
-            // Check that s is not NaN or the partial_cmp below will panic
-            if score.is_nan() {
-                Err(ErrorResponse {
-                    error: "score is NaN".to_string(),
-                    error_type: ErrorType::Backend,
-                })?;
-            }
-
-            ranks.push(Rank { index, text, score })
-        }
-
-        // Reverse sort
-        ranks.sort_by(|x, y| x.score.partial_cmp(&y.score).unwrap());
-        ranks.reverse();
-
-        let batch_size = batch_size as u64;
-
-        let counter = metrics::counter!("te_request_success", "method" => "batch");
-        counter.increment(1);
-
-        (
-            RerankResponse(ranks),
-            ResponseMetadata::new(
-                compute_chars,
-                total_compute_tokens,
-                start_time,
-                Duration::from_nanos(total_tokenization_time / batch_size),
-                Duration::from_nanos(total_queue_time / batch_size),
-                Duration::from_nanos(total_inference_time / batch_size),
-            ),
-        )
+    let mut futures = Vec::with_capacity(batch_size);
+    let query_chars = query.chars().count();
+    // Embedding models only encode the query once
+    let mut compute_chars = match &query_embedding {
+        Some(_) => query_chars,
+        None => query_chars * batch_size,
     };
 
-    metadata.record_span(&span);
-    metadata.record_metrics();
-
-    let headers = HeaderMap::from(metadata);
+    for text in texts {
+        compute_chars += text.chars().count();
+        futures.push(rerank_inner(
+            query.clone(),
+            text.clone(),
+            truncate,
+            infer.clone(),
+        ))
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<(usize, Duration, Duration, Duration, f32)>, ErrorResponse>>()?;
+
+    let mut scores = Vec::with_capacity(batch_size);
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = query_embedding
+        .as_ref()
+        .map(|r| r.metadata.prompt_tokens)
+        .unwrap_or_default();
+
+    for (index, r) in results.into_iter().enumerate() {
+        total_compute_tokens += r.0;
+        total_tokenization_time += r.1.as_nanos() as u64;
+        total_queue_time += r.2.as_nanos() as u64;
+        total_inference_time += r.3.as_nanos() as u64;
+
+        let score = r.4;
+        // Check that s is not NaN or the partial_cmp below will panic
+        if score.is_nan() {
+            Err(ErrorResponse {
+                error: "score is NaN".to_string(),
+                error_type: ErrorType::Backend,
+            })?;
+        }
 
-    tracing::info!("Success");
+        scores.push((index, score))
+    }
 
-    Ok((headers, Json(response)))
+    // Reverse sort
+    scores.sort_by(|x, y| x.1.partial_cmp(&y.1).unwrap());
+    scores.reverse();
+
+    let batch_size = batch_size as u64;
+
+    let counter = metrics::counter!("te_request_success", "method" => "batch");
+    counter.increment(1);
+
+    Ok((
+        scores,
+        ResponseMetadata::new(
+            compute_chars,
+            total_compute_tokens,
+            start_time,
+            Duration::from_nanos(total_tokenization_time / batch_size),
+            Duration::from_nanos(total_queue_time / batch_size),
+            Duration::from_nanos(total_inference_time / batch_size),
+        ),
+    ))
 }
 
 /// Get Sentence Similarity. Returns a 424 status code if the model is not an embedding model.
@@ -488,6 +695,7 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
 async fn similarity(
     infer: Extension<Infer>,
     info: Extension<Info>,
+    headers: HeaderMap,
     Json(req): Json<SimilarityRequest>,
 ) -> Result<(HeaderMap, Json<SimilarityResponse>), (StatusCode, Json<ErrorResponse>)> {
     if req.inputs.sentences.is_empty() {
@@ -531,11 +739,25 @@ async fn similarity(
         truncation_direction: parameters.truncation_direction,
         prompt_name: parameters.prompt_name,
         normalize: false,
+        dimensions: None,
+        outputs: None,
+        chunking: None,
+        encoding_format: EncodingFormat::Float,
+        priority: Priority::default(),
     };
 
     // Get embeddings
-    let (header_map, embed_response) = embed(infer, info, Json(embed_req)).await?;
-    let embeddings = embed_response.0 .0;
+    let (header_map, embed_response) = embed(infer, info, headers, Json(embed_req)).await?;
+    let EmbedResponse::Dense(embeddings) = embed_response.0 else {
+        panic!("unexpected enum variant")
+    };
+    let embeddings: Vec<Vec<f32>> = embeddings
+        .into_iter()
+        .map(|e| match e {
+            Embedding::Float(e) => e,
+            _ => panic!("unexpected enum variant"),
+        })
+        .collect();
 
     // Compute cosine
     let distances = (1..batch_size)
@@ -572,12 +794,22 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
 async fn embed(
     infer: Extension<Infer>,
     info: Extension<Info>,
+    headers: HeaderMap,
     Json(req): Json<EmbedRequest>,
 ) -> Result<(HeaderMap, Json<EmbedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    if req.chunking.is_some() {
+        return embed_chunked(infer, info, headers, req).await;
+    }
+
     let span = tracing::Span::current();
     let start_time = Instant::now();
+    let priority = req.priority.into();
+    let deadline = request_deadline(&headers)?;
+    let dimensions = info.dimensions(req.dimensions)?;
 
     let truncate = req.truncate.unwrap_or(info.auto_truncate);
+    let hybrid = req.outputs.is_some();
+    let outputs = embed_outputs(req.outputs.as_deref());
 
     let (response, metadata) = match req.inputs {
         Input::Single(input) => {
@@ -586,14 +818,20 @@ async fn embed(
 
             let compute_chars = input.count_chars();
 
-            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let permit = infer
+                .try_acquire_permit(priority)
+                .map_err(ErrorResponse::from)?;
             let response = infer
-                .embed_pooled(
+                .embed_hybrid(
                     input,
                     truncate,
                     req.truncation_direction.into(),
                     req.prompt_name,
                     req.normalize,
+                    dimensions,
+                    outputs,
+                    priority,
+                    deadline,
                     permit,
                 )
                 .await
@@ -602,16 +840,18 @@ async fn embed(
             let counter = metrics::counter!("te_request_count", "method" => "single");
             counter.increment(1);
 
+            let metadata = ResponseMetadata::new(
+                compute_chars,
+                response.metadata.prompt_tokens,
+                start_time,
+                response.metadata.tokenization,
+                response.metadata.queue,
+                response.metadata.inference,
+            )
+            .with_cache_hits(response.metadata.cached as usize);
             (
-                EmbedResponse(vec![response.results]),
-                ResponseMetadata::new(
-                    compute_chars,
-                    response.metadata.prompt_tokens,
-                    start_time,
-                    response.metadata.tokenization,
-                    response.metadata.queue,
-                    response.metadata.inference,
-                ),
+                embed_response(hybrid, vec![response], req.encoding_format, &info)?,
+                metadata,
             )
         }
         Input::Batch(inputs) => {
@@ -655,14 +895,18 @@ async fn embed(
                 let local_infer = infer.clone();
                 let prompt_name = req.prompt_name.clone();
                 futures.push(async move {
-                    let permit = local_infer.acquire_permit().await;
+                    let permit = local_infer.acquire_permit(priority).await;
                     local_infer
-                        .embed_pooled(
+                        .embed_hybrid(
                             input,
                             truncate,
                             req.truncation_direction.into(),
                             prompt_name,
                             req.normalize,
+                            dimensions,
+                            outputs,
+                            priority,
+                            deadline,
                             permit,
                         )
                         .await
@@ -671,7 +915,7 @@ async fn embed(
             let results = join_all(futures)
                 .await
                 .into_iter()
-                .collect::<Result<Vec<PooledEmbeddingsInferResponse>, TextEmbeddingsError>>()
+                .collect::<Result<Vec<HybridEmbeddingsInferResponse>, TextEmbeddingsError>>()
                 .map_err(ErrorResponse::from)?;
 
             let mut embeddings = Vec::with_capacity(batch_size);
@@ -679,13 +923,15 @@ async fn embed(
             let mut total_queue_time = 0;
             let mut total_inference_time = 0;
             let mut total_compute_tokens = 0;
+            let mut total_cache_hits = 0;
 
             for r in results {
                 total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
                 total_queue_time += r.metadata.queue.as_nanos() as u64;
                 total_inference_time += r.metadata.inference.as_nanos() as u64;
                 total_compute_tokens += r.metadata.prompt_tokens;
-                embeddings.push(r.results);
+                total_cache_hits += r.metadata.cached as usize;
+                embeddings.push(r);
             }
             let batch_size = batch_size as u64;
 
@@ -693,7 +939,7 @@ async fn embed(
             counter.increment(1);
 
             (
-                EmbedResponse(embeddings),
+                embed_response(hybrid, embeddings, req.encoding_format, &info)?,
                 ResponseMetadata::new(
                     compute_chars,
                     total_compute_tokens,
@@ -701,7 +947,8 @@ async fn embed(
                     Duration::from_nanos(total_tokenization_time / batch_size),
                     Duration::from_nanos(total_queue_time / batch_size),
                     Duration::from_nanos(total_inference_time / batch_size),
-                ),
+                )
+                .with_cache_hits(total_cache_hits),
             )
         }
     };
@@ -716,82 +963,66 @@ async fn embed(
     Ok((headers, Json(response)))
 }
 
-/// Get Sparse Embeddings. Returns a 424 status code if the model is not an embedding model with SPLADE pooling.
-#[utoipa::path(
-post,
-tag = "Text Embeddings Inference",
-path = "/embed_sparse",
-request_body = EmbedSparseRequest,
-responses(
-(status = 200, description = "Embeddings", body = EmbedSparseResponse),
-(status = 424, description = "Embedding Error", body = ErrorResponse,
-example = json ! ({"error": "Inference failed", "error_type": "backend"})),
-(status = 429, description = "Model is overloaded", body = ErrorResponse,
-example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
-(status = 422, description = "Tokenization error", body = ErrorResponse,
-example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
-(status = 400, description = "Batch is empty", body = ErrorResponse,
-example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
-(status = 413, description = "Batch size error", body = ErrorResponse,
-example = json ! ({"error": "Batch size error", "error_type": "validation"})),
-)
-)]
-#[instrument(
-    skip_all,
-    fields(total_time, tokenization_time, queue_time, inference_time,)
-)]
-async fn embed_sparse(
+/// `/embed` with `chunking`: every input is split into windows that are embedded separately
+async fn embed_chunked(
     infer: Extension<Infer>,
     info: Extension<Info>,
-    Json(req): Json<EmbedSparseRequest>,
-) -> Result<(HeaderMap, Json<EmbedSparseResponse>), (StatusCode, Json<ErrorResponse>)> {
+    headers: HeaderMap,
+    req: EmbedRequest,
+) -> Result<(HeaderMap, Json<EmbedResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     let start_time = Instant::now();
+    let priority = req.priority.into();
+    let deadline = request_deadline(&headers)?;
+    let dimensions = info.dimensions(req.dimensions)?;
+
+    // Unwrap is safe here
+    let chunking = req.chunking.unwrap();
+    if req.outputs.is_some() {
+        let message = "`chunking` cannot be used with `outputs`".to_string();
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        Err(err)?;
+    }
 
-    let sparsify = |values: Vec<f32>| {
-        let mut sparse_values = Vec::with_capacity(values.len());
-        for (index, value) in values.into_iter().enumerate() {
-            if value != 0.0 {
-                sparse_values.push(SparseValue { index, value });
-            }
-        }
-        sparse_values
-    };
-    let truncate = req.truncate.unwrap_or(info.auto_truncate);
+    let window_size = chunking.window_size.unwrap_or(info.max_input_length);
+    let aggregation = chunking.aggregation.into();
 
-    let (response, metadata) = match req.inputs {
+    let (results, compute_chars) = match req.inputs {
         Input::Single(input) => {
             let counter = metrics::counter!("te_request_count", "method" => "single");
             counter.increment(1);
 
             let compute_chars = input.count_chars();
 
-            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let permit = infer
+                .try_acquire_permit(priority)
+                .map_err(ErrorResponse::from)?;
             let response = infer
-                .embed_sparse(
+                .embed_pooled_chunked(
                     input,
-                    truncate,
-                    req.truncation_direction.into(),
+                    window_size,
+                    chunking.stride,
+                    aggregation,
                     req.prompt_name,
+                    req.normalize,
+                    dimensions,
+                    priority,
+                    deadline,
                     permit,
                 )
                 .await
                 .map_err(ErrorResponse::from)?;
 
-            let counter = metrics::counter!("te_request_count", "method" => "single");
+            let counter = metrics::counter!("te_request_success", "method" => "single");
             counter.increment(1);
 
-            (
-                EmbedSparseResponse(vec![sparsify(response.results)]),
-                ResponseMetadata::new(
-                    compute_chars,
-                    response.metadata.prompt_tokens,
-                    start_time,
-                    response.metadata.tokenization,
-                    response.metadata.queue,
-                    response.metadata.inference,
-                ),
-            )
+            (vec![response], compute_chars)
         }
         Input::Batch(inputs) => {
             let counter = metrics::counter!("te_request_count", "method" => "batch");
@@ -834,57 +1065,77 @@ async fn embed_sparse(
                 let local_infer = infer.clone();
                 let prompt_name = req.prompt_name.clone();
                 futures.push(async move {
-                    let permit = local_infer.acquire_permit().await;
-                    let response = local_infer
-                        .embed_sparse(
+                    let permit = local_infer.acquire_permit(priority).await;
+                    local_infer
+                        .embed_pooled_chunked(
                             input,
-                            truncate,
-                            req.truncation_direction.into(),
+                            window_size,
+                            chunking.stride,
+                            aggregation,
                             prompt_name,
+                            req.normalize,
+                            dimensions,
+                            priority,
+                            deadline,
                             permit,
                         )
-                        .await?;
-                    Ok((sparsify(response.results), response.metadata))
+                        .await
                 })
             }
             let results = join_all(futures)
                 .await
                 .into_iter()
-                .collect::<Result<Vec<(Vec<SparseValue>, InferMetadata)>, TextEmbeddingsError>>()
+                .collect::<Result<Vec<ChunkedEmbeddingsInferResponse>, TextEmbeddingsError>>()
                 .map_err(ErrorResponse::from)?;
 
-            let mut embeddings = Vec::with_capacity(batch_size);
-            let mut total_tokenization_time = 0;
-            let mut total_queue_time = 0;
-            let mut total_inference_time = 0;
-            let mut total_compute_tokens = 0;
-
-            for r in results {
-                total_tokenization_time += r.1.tokenization.as_nanos() as u64;
-                total_queue_time += r.1.queue.as_nanos() as u64;
-                total_inference_time += r.1.inference.as_nanos() as u64;
-                total_compute_tokens += r.1.prompt_tokens;
-                embeddings.push(r.0);
-            }
-            let batch_size = batch_size as u64;
-
             let counter = metrics::counter!("te_request_success", "method" => "batch");
             counter.increment(1);
 
-            (
-                EmbedSparseResponse(embeddings),
-                ResponseMetadata::new(
-                    compute_chars,
-                    total_compute_tokens,
-                    start_time,
-                    Duration::from_nanos(total_tokenization_time / batch_size),
-                    Duration::from_nanos(total_queue_time / batch_size),
-                    Duration::from_nanos(total_inference_time / batch_size),
-                ),
-            )
+            (results, compute_chars)
         }
     };
 
+    let batch_size = results.len() as u64;
+    let mut embeddings = Vec::with_capacity(results.len());
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+
+    for r in results {
+        total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+        total_queue_time += r.metadata.queue.as_nanos() as u64;
+        total_inference_time += r.metadata.inference.as_nanos() as u64;
+        total_compute_tokens += r.metadata.prompt_tokens;
+        embeddings.push(ChunkedEmbedding {
+            embedding: encode_embedding(r.results, req.encoding_format, &info)?,
+            chunks: r
+                .chunks
+                .into_iter()
+                .map(|c| {
+                    Ok(EmbeddingChunk {
+                        embedding: encode_embedding(c.results, req.encoding_format, &info)?,
+                        start: c.start,
+                        end: c.end,
+                    })
+                })
+                .collect::<Result<_, ErrorResponse>>()?,
+        });
+    }
+
+    let response = match chunking.return_chunks {
+        true => EmbedResponse::Chunked(embeddings),
+        false => EmbedResponse::Dense(embeddings.into_iter().map(|e| e.embedding).collect()),
+    };
+
+    let metadata = ResponseMetadata::new(
+        compute_chars,
+        total_compute_tokens,
+        start_time,
+        Duration::from_nanos(total_tokenization_time / batch_size),
+        Duration::from_nanos(total_queue_time / batch_size),
+        Duration::from_nanos(total_inference_time / batch_size),
+    );
     metadata.record_span(&span);
     metadata.record_metrics();
 
@@ -895,15 +1146,116 @@ async fn embed_sparse(
     Ok((headers, Json(response)))
 }
 
-/// Get all Embeddings without Pooling.
-/// Returns a 424 status code if the model is not an embedding model.
+/// Select the outputs of an `/embed` request. Defaults to the dense embeddings only.
+fn embed_outputs(outputs: Option<&[EmbedOutput]>) -> EmbedOutputs {
+    match outputs {
+        None => EmbedOutputs {
+            dense: true,
+            ..Default::default()
+        },
+        Some(outputs) => EmbedOutputs {
+            dense: outputs.contains(&EmbedOutput::Dense),
+            sparse: outputs.contains(&EmbedOutput::Sparse),
+            multivector: outputs.contains(&EmbedOutput::Multivector),
+        },
+    }
+}
+
+/// Build an `/embed` response. The plain list of vectors is kept when no outputs were selected.
+fn embed_response(
+    hybrid: bool,
+    results: Vec<HybridEmbeddingsInferResponse>,
+    encoding_format: EncodingFormat,
+    info: &Info,
+) -> Result<EmbedResponse, ErrorResponse> {
+    if !hybrid {
+        // Unwrap is safe here: the dense output is selected by default
+        return Ok(EmbedResponse::Dense(
+            results
+                .into_iter()
+                .map(|r| encode_embedding(r.results.unwrap(), encoding_format, info))
+                .collect::<Result<_, _>>()?,
+        ));
+    }
+
+    Ok(EmbedResponse::Hybrid(
+        results
+            .into_iter()
+            .map(|r| {
+                Ok(HybridEmbedding {
+                    dense: r
+                        .results
+                        .map(|dense| encode_embedding(dense, encoding_format, info))
+                        .transpose()?,
+                    sparse: r.token_weights.map(|token_weights| {
+                        token_weights
+                            .into_iter()
+                            .map(|w| SparseValue {
+                                index: w.id as usize,
+                                value: w.weight,
+                            })
+                            .collect()
+                    }),
+                    multivector: r.multivector,
+                })
+            })
+            .collect::<Result<_, ErrorResponse>>()?,
+    ))
+}
+
+/// Encode an embedding in the requested format
+fn encode_embedding(
+    embedding: Vec<f32>,
+    encoding_format: EncodingFormat,
+    info: &Info,
+) -> Result<Embedding, ErrorResponse> {
+    let precision = match encoding_format {
+        EncodingFormat::Float => return Ok(Embedding::Float(embedding)),
+        EncodingFormat::Base64 => Precision::Float32,
+        EncodingFormat::Float16 => Precision::Float16,
+        EncodingFormat::Int8 => Precision::Int8,
+        EncodingFormat::Uint8 => Precision::Uint8,
+        EncodingFormat::Binary => Precision::Binary,
+        EncodingFormat::Ubinary => Precision::Ubinary,
+    };
+
+    Ok(match quantize_embedding(embedding, precision, info)? {
+        QuantizedEmbedding::Int8(values) => Embedding::Int8(values),
+        QuantizedEmbedding::Uint8(values) => Embedding::Uint8(values),
+        floats => Embedding::Base64(BASE64_STANDARD.encode(floats.to_le_bytes())),
+    })
+}
+
+/// Quantize an embedding with the calibration of the model
+fn quantize_embedding(
+    embedding: Vec<f32>,
+    precision: Precision,
+    info: &Info,
+) -> Result<QuantizedEmbedding, ErrorResponse> {
+    quantize(
+        embedding,
+        precision,
+        info.quantization_calibration.as_deref(),
+    )
+    .map_err(|message| {
+        tracing::error!("{message}");
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        }
+    })
+}
+
+/// Get Sparse Embeddings. Returns a 424 status code if the model is not an embedding model with SPLADE pooling.
 #[utoipa::path(
 post,
 tag = "Text Embeddings Inference",
-path = "/embed_all",
-request_body = EmbedAllRequest,
+path = "/embed_sparse",
+request_body = EmbedSparseRequest,
 responses(
-(status = 200, description = "Embeddings", body = EmbedAllResponse),
+(status = 200, description = "Embeddings", body = EmbedSparseResponse),
 (status = 424, description = "Embedding Error", body = ErrorResponse,
 example = json ! ({"error": "Inference failed", "error_type": "backend"})),
 (status = 429, description = "Model is overloaded", body = ErrorResponse,
@@ -920,14 +1272,26 @@ example = json ! ({"error": "Batch size error", "error_type": "validation"})),
     skip_all,
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
-async fn embed_all(
+async fn embed_sparse(
     infer: Extension<Infer>,
     info: Extension<Info>,
-    Json(req): Json<EmbedAllRequest>,
-) -> Result<(HeaderMap, Json<EmbedAllResponse>), (StatusCode, Json<ErrorResponse>)> {
+    headers: HeaderMap,
+    Json(req): Json<EmbedSparseRequest>,
+) -> Result<(HeaderMap, Json<EmbedSparseResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     let start_time = Instant::now();
+    let priority = req.priority.into();
+    let deadline = request_deadline(&headers)?;
 
+    let sparsify = |values: Vec<f32>| {
+        let mut sparse_values = Vec::with_capacity(values.len());
+        for (index, value) in values.into_iter().enumerate() {
+            if value != 0.0 {
+                sparse_values.push(SparseValue { index, value });
+            }
+        }
+        sparse_values
+    };
     let truncate = req.truncate.unwrap_or(info.auto_truncate);
 
     let (response, metadata) = match req.inputs {
@@ -937,13 +1301,17 @@ async fn embed_all(
 
             let compute_chars = input.count_chars();
 
-            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let permit = infer
+                .try_acquire_permit(priority)
+                .map_err(ErrorResponse::from)?;
             let response = infer
-                .embed_all(
+                .embed_sparse(
                     input,
                     truncate,
                     req.truncation_direction.into(),
                     req.prompt_name,
+                    priority,
+                    deadline,
                     permit,
                 )
                 .await
@@ -953,7 +1321,7 @@ async fn embed_all(
             counter.increment(1);
 
             (
-                EmbedAllResponse(vec![response.results]),
+                EmbedSparseResponse(vec![sparsify(response.results)]),
                 ResponseMetadata::new(
                     compute_chars,
                     response.metadata.prompt_tokens,
@@ -961,7 +1329,8 @@ async fn embed_all(
                     response.metadata.tokenization,
                     response.metadata.queue,
                     response.metadata.inference,
-                ),
+                )
+                .with_cache_hits(response.metadata.cached as usize),
             )
         }
         Input::Batch(inputs) => {
@@ -1005,22 +1374,25 @@ async fn embed_all(
                 let local_infer = infer.clone();
                 let prompt_name = req.prompt_name.clone();
                 futures.push(async move {
-                    let permit = local_infer.acquire_permit().await;
-                    local_infer
-                        .embed_all(
+                    let permit = local_infer.acquire_permit(priority).await;
+                    let response = local_infer
+                        .embed_sparse(
                             input,
                             truncate,
                             req.truncation_direction.into(),
                             prompt_name,
+                            priority,
+                            deadline,
                             permit,
                         )
-                        .await
+                        .await?;
+                    Ok((sparsify(response.results), response.metadata))
                 })
             }
             let results = join_all(futures)
                 .await
                 .into_iter()
-                .collect::<Result<Vec<AllEmbeddingsInferResponse>, TextEmbeddingsError>>()
+                .collect::<Result<Vec<(Vec<SparseValue>, InferMetadata)>, TextEmbeddingsError>>()
                 .map_err(ErrorResponse::from)?;
 
             let mut embeddings = Vec::with_capacity(batch_size);
@@ -1028,13 +1400,15 @@ async fn embed_all(
             let mut total_queue_time = 0;
             let mut total_inference_time = 0;
             let mut total_compute_tokens = 0;
+            let mut total_cache_hits = 0;
 
             for r in results {
-                total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
-                total_queue_time += r.metadata.queue.as_nanos() as u64;
-                total_inference_time += r.metadata.inference.as_nanos() as u64;
-                total_compute_tokens += r.metadata.prompt_tokens;
-                embeddings.push(r.results);
+                total_tokenization_time += r.1.tokenization.as_nanos() as u64;
+                total_queue_time += r.1.queue.as_nanos() as u64;
+                total_inference_time += r.1.inference.as_nanos() as u64;
+                total_compute_tokens += r.1.prompt_tokens;
+                total_cache_hits += r.1.cached as usize;
+                embeddings.push(r.0);
             }
             let batch_size = batch_size as u64;
 
@@ -1042,7 +1416,7 @@ async fn embed_all(
             counter.increment(1);
 
             (
-                EmbedAllResponse(embeddings),
+                EmbedSparseResponse(embeddings),
                 ResponseMetadata::new(
                     compute_chars,
                     total_compute_tokens,
@@ -1050,7 +1424,8 @@ async fn embed_all(
                     Duration::from_nanos(total_tokenization_time / batch_size),
                     Duration::from_nanos(total_queue_time / batch_size),
                     Duration::from_nanos(total_inference_time / batch_size),
-                ),
+                )
+                .with_cache_hits(total_cache_hits),
             )
         }
     };
@@ -1065,71 +1440,62 @@ async fn embed_all(
     Ok((headers, Json(response)))
 }
 
-/// OpenAI compatible route. Returns a 424 status code if the model is not an embedding model.
+/// Get all Embeddings without Pooling.
+/// Returns a 424 status code if the model is not an embedding model.
 #[utoipa::path(
 post,
 tag = "Text Embeddings Inference",
-path = "/v1/embeddings",
-request_body = OpenAICompatRequest,
+path = "/embed_all",
+request_body = EmbedAllRequest,
 responses(
-(status = 200, description = "Embeddings", body = OpenAICompatResponse),
-(status = 424, description = "Embedding Error", body = OpenAICompatErrorResponse,
-example = json ! ({"message": "Inference failed", "type": "backend"})),
-(status = 429, description = "Model is overloaded", body = OpenAICompatErrorResponse,
-example = json ! ({"message": "Model is overloaded", "type": "overloaded"})),
-(status = 422, description = "Tokenization error", body = OpenAICompatErrorResponse,
-example = json ! ({"message": "Tokenization error", "type": "tokenizer"})),
-(status = 400, description = "Batch is empty", body = OpenAICompatErrorResponse,
-example = json ! ({"message": "Batch is empty", "type": "empty"})),
-(status = 413, description = "Batch size error", body = OpenAICompatErrorResponse,
-example = json ! ({"message": "Batch size error", "type": "validation"})),
+(status = 200, description = "Embeddings", body = EmbedAllResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = ErrorResponse,
+example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
 )
 )]
 #[instrument(
     skip_all,
     fields(total_time, tokenization_time, queue_time, inference_time,)
 )]
-async fn openai_embed(
+async fn embed_all(
     infer: Extension<Infer>,
     info: Extension<Info>,
-    Json(req): Json<OpenAICompatRequest>,
-) -> Result<(HeaderMap, Json<OpenAICompatResponse>), (StatusCode, Json<OpenAICompatErrorResponse>)>
-{
-    let encode_embedding = |array: Vec<f32>| {
-        match req.encoding_format {
-            EncodingFormat::Float => Embedding::Float(array),
-            EncodingFormat::Base64 => {
-                // Unsafe is fine here since we do not violate memory ownership: bytes
-                // is only used in this scope and we return an owned string
-                let bytes = unsafe {
-                    std::slice::from_raw_parts(array.as_ptr() as *const u8, array.len() * 4)
-                };
-
-                Embedding::Base64(BASE64_STANDARD.encode(bytes))
-            }
-        }
-    };
-
+    headers: HeaderMap,
+    Json(req): Json<EmbedAllRequest>,
+) -> Result<(HeaderMap, Json<EmbedAllResponse>), (StatusCode, Json<ErrorResponse>)> {
     let span = tracing::Span::current();
     let start_time = Instant::now();
+    let priority = req.priority.into();
+    let deadline = request_deadline(&headers)?;
 
-    let truncate = info.auto_truncate;
+    let truncate = req.truncate.unwrap_or(info.auto_truncate);
 
-    let (embeddings, metadata) = match req.input {
+    let (response, metadata) = match req.inputs {
         Input::Single(input) => {
             let counter = metrics::counter!("te_request_count", "method" => "single");
             counter.increment(1);
 
             let compute_chars = input.count_chars();
 
-            let permit = infer.try_acquire_permit().map_err(ErrorResponse::from)?;
+            let permit = infer
+                .try_acquire_permit(priority)
+                .map_err(ErrorResponse::from)?;
             let response = infer
-                .embed_pooled(
+                .embed_all(
                     input,
                     truncate,
-                    tokenizers::TruncationDirection::Right,
-                    None,
-                    true,
+                    req.truncation_direction.into(),
+                    req.prompt_name,
+                    priority,
+                    deadline,
                     permit,
                 )
                 .await
@@ -1138,13 +1504,8 @@ async fn openai_embed(
             let counter = metrics::counter!("te_request_count", "method" => "single");
             counter.increment(1);
 
-            let embedding = encode_embedding(response.results);
             (
-                vec![OpenAICompatEmbedding {
-                    object: "embedding",
-                    embedding,
-                    index: 0,
-                }],
+                EmbedAllResponse(vec![response.results]),
                 ResponseMetadata::new(
                     compute_chars,
                     response.metadata.prompt_tokens,
@@ -1194,15 +1555,17 @@ async fn openai_embed(
                 compute_chars += input.count_chars();
 
                 let local_infer = infer.clone();
+                let prompt_name = req.prompt_name.clone();
                 futures.push(async move {
-                    let permit = local_infer.acquire_permit().await;
+                    let permit = local_infer.acquire_permit(priority).await;
                     local_infer
-                        .embed_pooled(
+                        .embed_all(
                             input,
                             truncate,
-                            tokenizers::TruncationDirection::Right,
-                            None,
-                            true,
+                            req.truncation_direction.into(),
+                            prompt_name,
+                            priority,
+                            deadline,
                             permit,
                         )
                         .await
@@ -1211,7 +1574,7 @@ async fn openai_embed(
             let results = join_all(futures)
                 .await
                 .into_iter()
-                .collect::<Result<Vec<PooledEmbeddingsInferResponse>, TextEmbeddingsError>>()
+                .collect::<Result<Vec<AllEmbeddingsInferResponse>, TextEmbeddingsError>>()
                 .map_err(ErrorResponse::from)?;
 
             let mut embeddings = Vec::with_capacity(batch_size);
@@ -1220,17 +1583,12 @@ async fn openai_embed(
             let mut total_inference_time = 0;
             let mut total_compute_tokens = 0;
 
-            for (i, r) in results.into_iter().enumerate() {
+            for r in results {
                 total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
                 total_queue_time += r.metadata.queue.as_nanos() as u64;
                 total_inference_time += r.metadata.inference.as_nanos() as u64;
                 total_compute_tokens += r.metadata.prompt_tokens;
-                let embedding = encode_embedding(r.results);
-                embeddings.push(OpenAICompatEmbedding {
-                    object: "embedding",
-                    embedding,
-                    index: i,
-                });
+                embeddings.push(r.results);
             }
             let batch_size = batch_size as u64;
 
@@ -1238,7 +1596,7 @@ async fn openai_embed(
             counter.increment(1);
 
             (
-                embeddings,
+                EmbedAllResponse(embeddings),
                 ResponseMetadata::new(
                     compute_chars,
                     total_compute_tokens,
@@ -1254,106 +1612,1208 @@ async fn openai_embed(
     metadata.record_span(&span);
     metadata.record_metrics();
 
-    let compute_tokens = metadata.compute_tokens;
     let headers = HeaderMap::from(metadata);
 
     tracing::info!("Success");
 
-    let response = OpenAICompatResponse {
-        object: "list",
-        data: embeddings,
-        model: info.model_id.clone(),
-        usage: OpenAICompatUsage {
-            prompt_tokens: compute_tokens,
-            total_tokens: compute_tokens,
-        },
-    };
     Ok((headers, Json(response)))
 }
 
-/// Tokenize inputs
+/// Get multi-vector (ColBERT) Embeddings.
+/// Returns a 424 status code if the model does not support multi-vector embeddings.
 #[utoipa::path(
 post,
 tag = "Text Embeddings Inference",
-path = "/tokenize",
-request_body = TokenizeRequest,
+path = "/embed_multivector",
+request_body = EmbedMultiVectorRequest,
 responses(
-(status = 200, description = "Tokenized ids", body = TokenizeResponse),
+(status = 200, description = "Embeddings", body = EmbedMultiVectorResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 (status = 400, description = "Batch is empty", body = ErrorResponse,
 example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
 (status = 413, description = "Batch size error", body = ErrorResponse,
 example = json ! ({"error": "Batch size error", "error_type": "validation"})),
-(status = 422, description = "Tokenization error", body = ErrorResponse,
-example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
 )
 )]
-#[instrument(skip_all)]
-async fn tokenize(
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn embed_multivector(
     infer: Extension<Infer>,
     info: Extension<Info>,
-    Json(req): Json<TokenizeRequest>,
-) -> Result<Json<TokenizeResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let tokenize_inner = move |input: String,
-                               add_special_tokens: bool,
-                               prompt_name: Option<String>,
-                               infer: Infer| async move {
-        let (encoded_input, encoding) = infer
-            .tokenize(input.clone(), add_special_tokens, prompt_name)
-            .await
-            .map_err(ErrorResponse::from)?;
-        let input = encoded_input.unwrap_or(input);
+    headers: HeaderMap,
+    Json(req): Json<EmbedMultiVectorRequest>,
+) -> Result<(HeaderMap, Json<EmbedMultiVectorResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+    let priority = req.priority.into();
+    let deadline = request_deadline(&headers)?;
 
-        let tokens: Vec<SimpleToken> = encoding
-            .get_ids()
-            .iter()
-            .zip(encoding.get_offsets())
-            .zip(encoding.get_special_tokens_mask())
-            .zip(encoding.get_tokens())
-            .map(|(((&id, &(start, stop)), special), token)| {
-                let special = *special == 1;
-                match special {
-                    true => SimpleToken {
-                        id,
-                        text: token.clone(),
-                        special,
-                        start: None,
-                        stop: None,
-                    },
-                    false => {
-                        let text: String = input.chars().skip(start).take(stop - start).collect();
-                        SimpleToken {
-                            id,
-                            text,
-                            special,
-                            start: Some(start),
-                            stop: Some(stop),
-                        }
-                    }
-                }
-            })
-            .collect();
-        Ok::<Vec<SimpleToken>, ErrorResponse>(tokens)
-    };
+    let truncate = req.truncate.unwrap_or(info.auto_truncate);
 
-    let tokens = match req.inputs {
-        TokenizeInput::Single(input) => {
-            vec![tokenize_inner(input, req.add_special_tokens, req.prompt_name, infer.0).await?]
-        }
-        TokenizeInput::Batch(inputs) => {
-            if inputs.is_empty() {
-                let message = "`inputs` cannot be empty".to_string();
-                tracing::error!("{message}");
-                let err = ErrorResponse {
-                    error: message,
-                    error_type: ErrorType::Empty,
-                };
-                let counter = metrics::counter!("te_request_failure", "err" => "validation");
-                counter.increment(1);
-                Err(err)?;
-            }
+    let (response, metadata) = match req.inputs {
+        Input::Single(input) => {
+            let counter = metrics::counter!("te_request_count", "method" => "single");
+            counter.increment(1);
 
-            let batch_size = inputs.len();
-            if batch_size > info.max_client_batch_size {
+            let compute_chars = input.count_chars();
+
+            let permit = infer
+                .try_acquire_permit(priority)
+                .map_err(ErrorResponse::from)?;
+            let response = infer
+                .embed_multivector(
+                    input,
+                    truncate,
+                    req.truncation_direction.into(),
+                    req.prompt_name,
+                    priority,
+                    deadline,
+                    permit,
+                )
+                .await
+                .map_err(ErrorResponse::from)?;
+
+            let counter = metrics::counter!("te_request_success", "method" => "single");
+            counter.increment(1);
+
+            (
+                EmbedMultiVectorResponse(vec![response.results]),
+                ResponseMetadata::new(
+                    compute_chars,
+                    response.metadata.prompt_tokens,
+                    start_time,
+                    response.metadata.tokenization,
+                    response.metadata.queue,
+                    response.metadata.inference,
+                ),
+            )
+        }
+        Input::Batch(inputs) => {
+            let counter = metrics::counter!("te_request_count", "method" => "batch");
+            counter.increment(1);
+
+            if inputs.is_empty() {
+                let message = "`inputs` cannot be empty".to_string();
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Empty,
+                };
+                let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                counter.increment(1);
+                Err(err)?;
+            }
+
+            let batch_size = inputs.len();
+            if batch_size > info.max_client_batch_size {
+                let message = format!(
+                    "batch size {batch_size} > maximum allowed batch size {}",
+                    info.max_client_batch_size
+                );
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                };
+                let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+                counter.increment(1);
+                Err(err)?;
+            }
+
+            let mut futures = Vec::with_capacity(batch_size);
+            let mut compute_chars = 0;
+
+            for input in inputs {
+                compute_chars += input.count_chars();
+
+                let local_infer = infer.clone();
+                let prompt_name = req.prompt_name.clone();
+                futures.push(async move {
+                    let permit = local_infer.acquire_permit(priority).await;
+                    local_infer
+                        .embed_multivector(
+                            input,
+                            truncate,
+                            req.truncation_direction.into(),
+                            prompt_name,
+                            priority,
+                            deadline,
+                            permit,
+                        )
+                        .await
+                })
+            }
+            let results = join_all(futures)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<AllEmbeddingsInferResponse>, TextEmbeddingsError>>()
+                .map_err(ErrorResponse::from)?;
+
+            let mut embeddings = Vec::with_capacity(batch_size);
+            let mut total_tokenization_time = 0;
+            let mut total_queue_time = 0;
+            let mut total_inference_time = 0;
+            let mut total_compute_tokens = 0;
+
+            for r in results {
+                total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+                total_queue_time += r.metadata.queue.as_nanos() as u64;
+                total_inference_time += r.metadata.inference.as_nanos() as u64;
+                total_compute_tokens += r.metadata.prompt_tokens;
+                embeddings.push(r.results);
+            }
+            let batch_size = batch_size as u64;
+
+            let counter = metrics::counter!("te_request_success", "method" => "batch");
+            counter.increment(1);
+
+            (
+                EmbedMultiVectorResponse(embeddings),
+                ResponseMetadata::new(
+                    compute_chars,
+                    total_compute_tokens,
+                    start_time,
+                    Duration::from_nanos(total_tokenization_time / batch_size),
+                    Duration::from_nanos(total_queue_time / batch_size),
+                    Duration::from_nanos(total_inference_time / batch_size),
+                ),
+            )
+        }
+    };
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    Ok((headers, Json(response)))
+}
+
+/// OpenAI compatible route. Returns a 424 status code if the model is not an embedding model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/v1/embeddings",
+request_body = OpenAICompatRequest,
+responses(
+(status = 200, description = "Embeddings", body = OpenAICompatResponse),
+(status = 404, description = "Model is not served", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "model `my-model` is not served", "type": "validation"})),
+(status = 424, description = "Embedding Error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Inference failed", "type": "backend"})),
+(status = 429, description = "Model is overloaded", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Model is overloaded", "type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Tokenization error", "type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Batch is empty", "type": "empty"})),
+(status = 413, description = "Batch size error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Batch size error", "type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn openai_embed(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    models: Extension<ServedModels>,
+    headers: HeaderMap,
+    Json(req): Json<OpenAICompatRequest>,
+) -> Result<(HeaderMap, Json<OpenAICompatResponse>), (StatusCode, Json<OpenAICompatErrorResponse>)>
+{
+    let (infer, info) = models.select(req.model.as_deref(), infer, info)?;
+    let dimensions = info.dimensions(req.dimensions)?;
+
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+    // OpenAI requests do not carry a priority
+    let priority = Priority::default().into();
+    let deadline = request_deadline(&headers)?;
+
+    let truncate = info.auto_truncate;
+
+    let (embeddings, metadata) = match req.input {
+        Input::Single(input) => {
+            let counter = metrics::counter!("te_request_count", "method" => "single");
+            counter.increment(1);
+
+            let compute_chars = input.count_chars();
+
+            let permit = infer
+                .try_acquire_permit(priority)
+                .map_err(ErrorResponse::from)?;
+            let response = infer
+                .embed_pooled(
+                    input,
+                    truncate,
+                    tokenizers::TruncationDirection::Right,
+                    None,
+                    true,
+                    dimensions,
+                    priority,
+                    deadline,
+                    permit,
+                )
+                .await
+                .map_err(ErrorResponse::from)?;
+
+            let counter = metrics::counter!("te_request_count", "method" => "single");
+            counter.increment(1);
+
+            let embedding = encode_embedding(response.results, req.encoding_format, &info)?;
+            (
+                vec![OpenAICompatEmbedding {
+                    object: "embedding",
+                    embedding,
+                    index: 0,
+                }],
+                ResponseMetadata::new(
+                    compute_chars,
+                    response.metadata.prompt_tokens,
+                    start_time,
+                    response.metadata.tokenization,
+                    response.metadata.queue,
+                    response.metadata.inference,
+                )
+                .with_cache_hits(response.metadata.cached as usize),
+            )
+        }
+        Input::Batch(inputs) => {
+            let counter = metrics::counter!("te_request_count", "method" => "batch");
+            counter.increment(1);
+
+            if inputs.is_empty() {
+                let message = "`inputs` cannot be empty".to_string();
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Empty,
+                };
+                let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                counter.increment(1);
+                Err(err)?;
+            }
+
+            let batch_size = inputs.len();
+            if batch_size > info.max_client_batch_size {
+                let message = format!(
+                    "batch size {batch_size} > maximum allowed batch size {}",
+                    info.max_client_batch_size
+                );
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Validation,
+                };
+                let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+                counter.increment(1);
+                Err(err)?;
+            }
+
+            let mut futures = Vec::with_capacity(batch_size);
+            let mut compute_chars = 0;
+
+            for input in inputs {
+                compute_chars += input.count_chars();
+
+                let local_infer = infer.clone();
+                futures.push(async move {
+                    let permit = local_infer.acquire_permit(priority).await;
+                    local_infer
+                        .embed_pooled(
+                            input,
+                            truncate,
+                            tokenizers::TruncationDirection::Right,
+                            None,
+                            true,
+                            dimensions,
+                            priority,
+                            deadline,
+                            permit,
+                        )
+                        .await
+                })
+            }
+            let results = join_all(futures)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<PooledEmbeddingsInferResponse>, TextEmbeddingsError>>()
+                .map_err(ErrorResponse::from)?;
+
+            let mut embeddings = Vec::with_capacity(batch_size);
+            let mut total_tokenization_time = 0;
+            let mut total_queue_time = 0;
+            let mut total_inference_time = 0;
+            let mut total_compute_tokens = 0;
+            let mut total_cache_hits = 0;
+
+            for (i, r) in results.into_iter().enumerate() {
+                total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+                total_queue_time += r.metadata.queue.as_nanos() as u64;
+                total_inference_time += r.metadata.inference.as_nanos() as u64;
+                total_compute_tokens += r.metadata.prompt_tokens;
+                total_cache_hits += r.metadata.cached as usize;
+                let embedding = encode_embedding(r.results, req.encoding_format, &info)?;
+                embeddings.push(OpenAICompatEmbedding {
+                    object: "embedding",
+                    embedding,
+                    index: i,
+                });
+            }
+            let batch_size = batch_size as u64;
+
+            let counter = metrics::counter!("te_request_success", "method" => "batch");
+            counter.increment(1);
+
+            (
+                embeddings,
+                ResponseMetadata::new(
+                    compute_chars,
+                    total_compute_tokens,
+                    start_time,
+                    Duration::from_nanos(total_tokenization_time / batch_size),
+                    Duration::from_nanos(total_queue_time / batch_size),
+                    Duration::from_nanos(total_inference_time / batch_size),
+                )
+                .with_cache_hits(total_cache_hits),
+            )
+        }
+    };
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let compute_tokens = metadata.compute_tokens;
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    let response = OpenAICompatResponse {
+        object: "list",
+        data: embeddings,
+        model: info.model_id.clone(),
+        usage: OpenAICompatUsage {
+            prompt_tokens: compute_tokens,
+            total_tokens: compute_tokens,
+        },
+    };
+    Ok((headers, Json(response)))
+}
+
+/// Pooled and normalized embeddings of a batch of inputs, for the compatibility routes
+#[allow(clippy::too_many_arguments)]
+async fn embed_pooled_batch(
+    infer: &Infer,
+    info: &Info,
+    inputs: Vec<InputType>,
+    truncate: bool,
+    truncation_direction: tokenizers::TruncationDirection,
+    prompt_name: Option<String>,
+    dimensions: Option<usize>,
+    deadline: Option<Instant>,
+    start_time: Instant,
+) -> Result<(Vec<Vec<f32>>, ResponseMetadata), ErrorResponse> {
+    let counter = metrics::counter!("te_request_count", "method" => "batch");
+    counter.increment(1);
+
+    if inputs.is_empty() {
+        let message = "batch cannot be empty".to_string();
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Empty,
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        Err(err)?;
+    }
+
+    let batch_size = inputs.len();
+    if batch_size > info.max_client_batch_size {
+        let message = format!(
+            "batch size {batch_size} > maximum allowed batch size {}",
+            info.max_client_batch_size
+        );
+        tracing::error!("{message}");
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+        counter.increment(1);
+        Err(err)?;
+    }
+
+    // Compatibility requests do not carry a priority
+    let priority = Priority::default().into();
+    let mut futures = Vec::with_capacity(batch_size);
+    let mut compute_chars = 0;
+
+    for input in inputs {
+        compute_chars += input.count_chars();
+
+        let local_infer = infer.clone();
+        let prompt_name = prompt_name.clone();
+        futures.push(async move {
+            let permit = local_infer.acquire_permit(priority).await;
+            local_infer
+                .embed_pooled(
+                    input,
+                    truncate,
+                    truncation_direction,
+                    prompt_name,
+                    true,
+                    dimensions,
+                    priority,
+                    deadline,
+                    permit,
+                )
+                .await
+        })
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<PooledEmbeddingsInferResponse>, TextEmbeddingsError>>()
+        .map_err(ErrorResponse::from)?;
+
+    let mut embeddings = Vec::with_capacity(batch_size);
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+    let mut total_cache_hits = 0;
+
+    for r in results {
+        total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+        total_queue_time += r.metadata.queue.as_nanos() as u64;
+        total_inference_time += r.metadata.inference.as_nanos() as u64;
+        total_compute_tokens += r.metadata.prompt_tokens;
+        total_cache_hits += r.metadata.cached as usize;
+        embeddings.push(r.results);
+    }
+    let batch_size = batch_size as u64;
+
+    let counter = metrics::counter!("te_request_success", "method" => "batch");
+    counter.increment(1);
+
+    Ok((
+        embeddings,
+        ResponseMetadata::new(
+            compute_chars,
+            total_compute_tokens,
+            start_time,
+            Duration::from_nanos(total_tokenization_time / batch_size),
+            Duration::from_nanos(total_queue_time / batch_size),
+            Duration::from_nanos(total_inference_time / batch_size),
+        )
+        .with_cache_hits(total_cache_hits),
+    ))
+}
+
+/// Random identifier of a compatibility response
+fn response_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Quantize embeddings to signed bytes
+fn quantize_i8(
+    embeddings: &[Vec<f32>],
+    precision: Precision,
+    info: &Info,
+) -> Result<Vec<Vec<i8>>, ErrorResponse> {
+    embeddings
+        .iter()
+        .map(
+            |embedding| match quantize_embedding(embedding.clone(), precision, info)? {
+                QuantizedEmbedding::Int8(values) => Ok(values),
+                _ => unreachable!("{precision:?} does not quantize to signed bytes"),
+            },
+        )
+        .collect()
+}
+
+/// Quantize embeddings to unsigned bytes
+fn quantize_u8(
+    embeddings: &[Vec<f32>],
+    precision: Precision,
+    info: &Info,
+) -> Result<Vec<Vec<u8>>, ErrorResponse> {
+    embeddings
+        .iter()
+        .map(
+            |embedding| match quantize_embedding(embedding.clone(), precision, info)? {
+                QuantizedEmbedding::Uint8(values) => Ok(values),
+                _ => unreachable!("{precision:?} does not quantize to unsigned bytes"),
+            },
+        )
+        .collect()
+}
+
+/// Cohere embeddings of the requested types, floats only if no type is requested
+fn cohere_embeddings(
+    embeddings: Vec<Vec<f32>>,
+    embedding_types: Option<Vec<CohereEmbeddingType>>,
+    info: &Info,
+) -> Result<(&'static str, CohereEmbeddings), ErrorResponse> {
+    let Some(embedding_types) = embedding_types else {
+        return Ok(("embeddings_floats", CohereEmbeddings::Floats(embeddings)));
+    };
+
+    let mut by_type = CohereEmbeddingsByType::default();
+    for embedding_type in embedding_types {
+        match embedding_type {
+            CohereEmbeddingType::Float => by_type.float = Some(embeddings.clone()),
+            CohereEmbeddingType::Int8 => {
+                by_type.int8 = Some(quantize_i8(&embeddings, Precision::Int8, info)?)
+            }
+            CohereEmbeddingType::Uint8 => {
+                by_type.uint8 = Some(quantize_u8(&embeddings, Precision::Uint8, info)?)
+            }
+            CohereEmbeddingType::Binary => {
+                by_type.binary = Some(quantize_i8(&embeddings, Precision::Binary, info)?)
+            }
+            CohereEmbeddingType::Ubinary => {
+                by_type.ubinary = Some(quantize_u8(&embeddings, Precision::Ubinary, info)?)
+            }
+        }
+    }
+    Ok(("embeddings_by_type", CohereEmbeddings::ByType(by_type)))
+}
+
+/// Cohere compatible route. Search queries and documents use the Sentence Transformers prompts of
+/// the model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/v1/embed",
+request_body = CohereEmbedRequest,
+responses(
+(status = 200, description = "Embeddings", body = CohereEmbedResponse),
+(status = 404, description = "Model is not served", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "model `my-model` is not served", "type": "validation"})),
+(status = 424, description = "Embedding Error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Inference failed", "type": "backend"})),
+(status = 429, description = "Model is overloaded", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Model is overloaded", "type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Tokenization error", "type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Batch is empty", "type": "empty"})),
+(status = 413, description = "Batch size error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Batch size error", "type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn cohere_embed(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    models: Extension<ServedModels>,
+    headers: HeaderMap,
+    Json(req): Json<CohereEmbedRequest>,
+) -> Result<(HeaderMap, Json<CohereEmbedResponse>), (StatusCode, Json<OpenAICompatErrorResponse>)> {
+    let (infer, info) = models.select(req.model.as_deref(), infer, info)?;
+    let dimensions = info.dimensions(req.output_dimension)?;
+
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+    let deadline = request_deadline(&headers)?;
+
+    let prompt_name = match req.input_type {
+        Some(CohereInputType::SearchQuery) => info.query_prompt_name.clone(),
+        Some(CohereInputType::SearchDocument) => info.document_prompt_name.clone(),
+        _ => None,
+    };
+    let (truncate, truncation_direction) = match req.truncate {
+        CohereTruncate::None => (false, tokenizers::TruncationDirection::Right),
+        CohereTruncate::Start => (true, tokenizers::TruncationDirection::Left),
+        CohereTruncate::End => (true, tokenizers::TruncationDirection::Right),
+    };
+
+    let inputs = req.texts.iter().cloned().map(InputType::String).collect();
+    let (embeddings, metadata) = embed_pooled_batch(
+        &infer,
+        &info,
+        inputs,
+        truncate,
+        truncation_direction,
+        prompt_name,
+        dimensions,
+        deadline,
+        start_time,
+    )
+    .await?;
+
+    let (response_type, embeddings) = cohere_embeddings(embeddings, req.embedding_types, &info)?;
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let compute_tokens = metadata.compute_tokens;
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    let response = CohereEmbedResponse {
+        id: response_id(),
+        response_type,
+        embeddings,
+        texts: req.texts,
+        meta: CohereMeta {
+            api_version: CohereApiVersion { version: "1" },
+            billed_units: CohereBilledUnits {
+                input_tokens: Some(compute_tokens),
+                search_units: None,
+            },
+        },
+    };
+    Ok((headers, Json(response)))
+}
+
+/// Ranks of the `top_n` most relevant texts, with the texts if `return_documents` is set.
+/// `scores` are sorted from the most relevant.
+fn compat_rerank_results(
+    scores: Vec<(usize, f32)>,
+    texts: &[String],
+    top_n: Option<usize>,
+    return_documents: bool,
+) -> Vec<CompatRerankResult> {
+    scores
+        .into_iter()
+        .take(top_n.unwrap_or(usize::MAX))
+        .map(|(index, relevance_score)| CompatRerankResult {
+            index,
+            relevance_score,
+            document: return_documents.then(|| CompatRerankText {
+                text: texts[index].clone(),
+            }),
+        })
+        .collect()
+}
+
+/// Jina and Cohere compatible re-ranking route. Returns a 424 status code if the model is not a
+/// re-ranker or embedding model.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/v1/rerank",
+request_body = CompatRerankRequest,
+responses(
+(status = 200, description = "Ranks", body = CompatRerankResponse),
+(status = 404, description = "Model is not served", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "model `my-model` is not served", "type": "validation"})),
+(status = 424, description = "Rerank Error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Inference failed", "type": "backend"})),
+(status = 429, description = "Model is overloaded", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Model is overloaded", "type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Tokenization error", "type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Batch is empty", "type": "empty"})),
+(status = 413, description = "Batch size error", body = OpenAICompatErrorResponse,
+example = json ! ({"message": "Batch size error", "type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn compat_rerank(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    models: Extension<ServedModels>,
+    headers: HeaderMap,
+    Json(req): Json<CompatRerankRequest>,
+) -> Result<(HeaderMap, Json<CompatRerankResponse>), (StatusCode, Json<OpenAICompatErrorResponse>)>
+{
+    let (infer, info) = models.select(req.model.as_deref(), infer, info)?;
+
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+    let deadline = request_deadline(&headers)?;
+
+    let texts: Vec<String> = req
+        .documents
+        .into_iter()
+        .map(CompatRerankDocument::into_text)
+        .collect();
+    let (scores, metadata) = rank_texts(
+        &infer,
+        &info,
+        req.query,
+        &texts,
+        info.auto_truncate,
+        TruncationDirection::Right,
+        false,
+        Priority::default().into(),
+        deadline,
+        start_time,
+    )
+    .await?;
+
+    let results = compat_rerank_results(scores, &texts, req.top_n, req.return_documents);
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let compute_tokens = metadata.compute_tokens;
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    let response = CompatRerankResponse {
+        id: response_id(),
+        model: info.model_id.clone(),
+        results,
+        usage: CompatRerankUsage {
+            total_tokens: compute_tokens,
+        },
+        meta: CohereMeta {
+            api_version: CohereApiVersion { version: "1" },
+            billed_units: CohereBilledUnits {
+                input_tokens: None,
+                search_units: Some(1),
+            },
+        },
+    };
+    Ok((headers, Json(response)))
+}
+
+/// Ollama compatible route
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/api/embed",
+request_body = OllamaEmbedRequest,
+responses(
+(status = 200, description = "Embeddings", body = OllamaEmbedResponse),
+(status = 404, description = "Model is not served", body = ErrorResponse,
+example = json ! ({"error": "model `my-model` is not served", "error_type": "validation"})),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = ErrorResponse,
+example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn ollama_embed(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    models: Extension<ServedModels>,
+    headers: HeaderMap,
+    Json(req): Json<OllamaEmbedRequest>,
+) -> Result<(HeaderMap, Json<OllamaEmbedResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let (infer, info) = models.select(req.model.as_deref(), infer, info)?;
+    let dimensions = info.dimensions(None)?;
+
+    let span = tracing::Span::current();
+    let start_time = Instant::now();
+    let deadline = request_deadline(&headers)?;
+
+    let inputs = match req.input {
+        Input::Single(input) => vec![input],
+        Input::Batch(inputs) => inputs,
+    };
+    let (embeddings, metadata) = embed_pooled_batch(
+        &infer,
+        &info,
+        inputs,
+        req.truncate,
+        tokenizers::TruncationDirection::Right,
+        None,
+        dimensions,
+        deadline,
+        start_time,
+    )
+    .await?;
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    let compute_tokens = metadata.compute_tokens;
+    let headers = HeaderMap::from(metadata);
+
+    tracing::info!("Success");
+
+    let response = OllamaEmbedResponse {
+        model: info.model_id.clone(),
+        embeddings,
+        total_duration: start_time.elapsed().as_nanos() as u64,
+        load_duration: 0,
+        prompt_eval_count: compute_tokens,
+    };
+    Ok((headers, Json(response)))
+}
+
+/// Stream embeddings. Every line of the body is a JSON request and gets one JSON line in the
+/// response, in the same order. Lines are only read when the router has capacity for them.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/embed/stream",
+request_body(content = EmbedStreamRequest, content_type = "application/x-ndjson"),
+responses(
+(status = 200, description = "Embeddings", body = EmbedStreamResponse, content_type = "application/x-ndjson",
+example = json ! ({"index": 0, "embedding": [0.0, 1.0, 2.0]})),
+(status = 413, description = "Invalid request timeout", body = ErrorResponse,
+example = json ! ({"error": "`x-request-timeout-ms` must be a number of milliseconds", "error_type": "validation"})),
+)
+)]
+#[instrument(skip_all)]
+async fn embed_stream(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    Extension(PayloadLimit(payload_limit)): Extension<PayloadLimit>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let deadline = request_deadline(&headers)?;
+
+    let counter = metrics::counter!("te_request_count", "method" => "stream");
+    counter.increment(1);
+
+    // Responses are queued in input order. The bound pauses the reader when the client does not
+    // read the responses fast enough.
+    let (sender, receiver) = mpsc::channel(info.max_concurrent_requests);
+    tokio::spawn(read_stream_requests(
+        infer.0,
+        Arc::new(info.0),
+        body,
+        payload_limit,
+        deadline,
+        sender,
+    ));
+
+    let lines = futures::stream::unfold(receiver, |mut receiver| async move {
+        let line = receiver.recv().await?.await.ok()?;
+        Some((Ok::<_, Infallible>(line), receiver))
+    });
+
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+/// Read the lines of an `/embed/stream` body
+async fn read_stream_requests(
+    infer: Infer,
+    info: Arc<Info>,
+    body: Body,
+    payload_limit: usize,
+    deadline: Option<Instant>,
+    sender: mpsc::Sender<oneshot::Receiver<Bytes>>,
+) {
+    let mut chunks = body.into_data_stream();
+    let mut buffer = Vec::new();
+    let mut index = 0;
+
+    while let Some(chunk) = chunks.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                tracing::error!("Failed to read the request body: {err}");
+                return;
+            }
+        };
+
+        // Only look for line breaks in the new bytes
+        let mut scanned = buffer.len();
+        buffer.extend_from_slice(&chunk);
+        while let Some(position) = buffer[scanned..].iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..scanned + position + 1).collect();
+            scanned = 0;
+            if !queue_stream_line(
+                &infer,
+                &info,
+                &line,
+                &mut index,
+                payload_limit,
+                deadline,
+                &sender,
+            )
+            .await
+            {
+                return;
+            }
+        }
+
+        // The line is already too long: report it without waiting for its end
+        if buffer.len() > payload_limit {
+            queue_stream_line(
+                &infer,
+                &info,
+                &buffer,
+                &mut index,
+                payload_limit,
+                deadline,
+                &sender,
+            )
+            .await;
+            return;
+        }
+    }
+
+    // The last line does not need a line break
+    if queue_stream_line(
+        &infer,
+        &info,
+        &buffer,
+        &mut index,
+        payload_limit,
+        deadline,
+        &sender,
+    )
+    .await
+    {
+        let counter = metrics::counter!("te_request_success", "method" => "stream");
+        counter.increment(1);
+        tracing::info!("Success");
+    }
+}
+
+/// Queue the response of a line of an `/embed/stream` body.
+/// Returns `false` when the stream must stop.
+async fn queue_stream_line(
+    infer: &Infer,
+    info: &Arc<Info>,
+    line: &[u8],
+    index: &mut usize,
+    payload_limit: usize,
+    deadline: Option<Instant>,
+    sender: &mpsc::Sender<oneshot::Receiver<Bytes>>,
+) -> bool {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return true;
+    }
+
+    let (mut result_sender, result_receiver) = oneshot::channel();
+    // Wait for the client to read the previous responses
+    if sender.send(result_receiver).await.is_err() {
+        // The client disconnected
+        return false;
+    }
+    let line_index = *index;
+    *index += 1;
+
+    if line.len() > payload_limit {
+        let message =
+            format!("line {line_index} is longer than the payload limit ({payload_limit} bytes)");
+        tracing::error!("{message}");
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        let err = ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        };
+        let _ = result_sender.send(stream_line(line_index, Err(err)));
+        return false;
+    }
+
+    let req: EmbedStreamRequest = match serde_json::from_slice(line) {
+        Ok(req) => req,
+        Err(err) => {
+            let message = format!("line {line_index} is not a valid request: {err}");
+            tracing::error!("{message}");
+            let counter = metrics::counter!("te_request_failure", "err" => "validation");
+            counter.increment(1);
+            let err = ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            };
+            let _ = result_sender.send(stream_line(line_index, Err(err)));
+            return true;
+        }
+    };
+
+    let permit = tokio::select! {
+        permit = infer.acquire_permit(req.priority.into()) => permit,
+        _ = result_sender.closed() => return false,
+    };
+
+    let infer = infer.clone();
+    let info = info.clone();
+    tokio::spawn(async move {
+        let result = tokio::select! {
+            result = embed_stream_request(&infer, &info, req, deadline, permit) => result,
+            // The client disconnected
+            _ = result_sender.closed() => return,
+        };
+        let _ = result_sender.send(stream_line(line_index, result));
+    });
+    true
+}
+
+/// Embed a line of an `/embed/stream` body
+async fn embed_stream_request(
+    infer: &Infer,
+    info: &Info,
+    req: EmbedStreamRequest,
+    deadline: Option<Instant>,
+    permit: OwnedSemaphorePermit,
+) -> Result<Embedding, ErrorResponse> {
+    let start_time = Instant::now();
+    let dimensions = info.dimensions(req.dimensions)?;
+    let compute_chars = req.inputs.count_chars();
+
+    let response = infer
+        .embed_pooled(
+            req.inputs,
+            req.truncate.unwrap_or(info.auto_truncate),
+            req.truncation_direction.into(),
+            req.prompt_name,
+            req.normalize,
+            dimensions,
+            req.priority.into(),
+            deadline,
+            permit,
+        )
+        .await
+        .map_err(ErrorResponse::from)?;
+
+    ResponseMetadata::new(
+        compute_chars,
+        response.metadata.prompt_tokens,
+        start_time,
+        response.metadata.tokenization,
+        response.metadata.queue,
+        response.metadata.inference,
+    )
+    .with_cache_hits(response.metadata.cached as usize)
+    .record_metrics();
+
+    encode_embedding(response.results, req.encoding_format, info)
+}
+
+/// Serialize a line of an `/embed/stream` response
+fn stream_line(index: usize, result: Result<Embedding, ErrorResponse>) -> Bytes {
+    let response = match result {
+        Ok(embedding) => EmbedStreamResponse {
+            index,
+            embedding: Some(embedding),
+            error: None,
+            error_type: None,
+        },
+        Err(err) => EmbedStreamResponse {
+            index,
+            embedding: None,
+            error: Some(err.error),
+            error_type: Some(err.error_type),
+        },
+    };
+    // Unwrap is safe here: the response only contains strings and numbers
+    let mut line = serde_json::to_vec(&response).unwrap();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// Tokenize inputs
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/tokenize",
+request_body = TokenizeRequest,
+responses(
+(status = 200, description = "Tokenized ids", body = TokenizeResponse),
+(status = 400, description = "Batch is empty", body = ErrorResponse,
+example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+)
+)]
+#[instrument(skip_all)]
+async fn tokenize(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    Json(req): Json<TokenizeRequest>,
+) -> Result<Json<TokenizeResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let tokenize_inner = move |input: String,
+                               add_special_tokens: bool,
+                               prompt_name: Option<String>,
+                               infer: Infer| async move {
+        let (encoded_input, encoding) = infer
+            .tokenize(input.clone(), add_special_tokens, prompt_name)
+            .await
+            .map_err(ErrorResponse::from)?;
+        let input = encoded_input.unwrap_or(input);
+
+        let tokens: Vec<SimpleToken> = encoding
+            .get_ids()
+            .iter()
+            .zip(encoding.get_offsets())
+            .zip(encoding.get_special_tokens_mask())
+            .zip(encoding.get_tokens())
+            .map(|(((&id, &(start, stop)), special), token)| {
+                let special = *special == 1;
+                match special {
+                    true => SimpleToken {
+                        id,
+                        text: token.clone(),
+                        special,
+                        start: None,
+                        stop: None,
+                    },
+                    false => {
+                        let text: String = input.chars().skip(start).take(stop - start).collect();
+                        SimpleToken {
+                            id,
+                            text,
+                            special,
+                            start: Some(start),
+                            stop: Some(stop),
+                        }
+                    }
+                }
+            })
+            .collect();
+        Ok::<Vec<SimpleToken>, ErrorResponse>(tokens)
+    };
+
+    let tokens = match req.inputs {
+        TokenizeInput::Single(input) => {
+            vec![tokenize_inner(input, req.add_special_tokens, req.prompt_name, infer.0).await?]
+        }
+        TokenizeInput::Batch(inputs) => {
+            if inputs.is_empty() {
+                let message = "`inputs` cannot be empty".to_string();
+                tracing::error!("{message}");
+                let err = ErrorResponse {
+                    error: message,
+                    error_type: ErrorType::Empty,
+                };
+                let counter = metrics::counter!("te_request_failure", "err" => "validation");
+                counter.increment(1);
+                Err(err)?;
+            }
+
+            let batch_size = inputs.len();
+            if batch_size > info.max_client_batch_size {
                 let message = format!(
                     "batch size {batch_size} > maximum allowed batch size {}",
                     info.max_client_batch_size
@@ -1453,98 +2913,574 @@ async fn decode(
                 futures.push(decode_inner(ids, req.skip_special_tokens, infer.0.clone()));
             }
 
-            join_all(futures)
-                .await
-                .into_iter()
-                .collect::<Result<Vec<String>, ErrorResponse>>()?
-        }
-    };
-    Ok(Json(DecodeResponse(texts)))
+            join_all(futures)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<String>, ErrorResponse>>()?
+        }
+    };
+    Ok(Json(DecodeResponse(texts)))
+}
+
+/// Generate embeddings from a Vertex request
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/vertex",
+request_body = VertexRequest,
+responses(
+(status = 200, description = "Results"),
+(status = 424, description = "Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = ErrorResponse,
+example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+)
+)]
+#[instrument(skip_all)]
+async fn vertex_compatibility(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    headers: HeaderMap,
+    Json(req): Json<VertexRequest>,
+) -> Result<Json<VertexResponse>, (StatusCode, Json<ErrorResponse>)> {
+    // All instances share the headers, and therefore the deadline, of the request
+    let headers = &headers;
+    let embed_future = move |infer: Extension<Infer>, info: Extension<Info>, req: EmbedRequest| async move {
+        let result = embed(infer, info, headers.clone(), Json(req)).await?;
+        Ok(VertexPrediction::Embed(result.1 .0))
+    };
+    let embed_sparse_future =
+        move |infer: Extension<Infer>, info: Extension<Info>, req: EmbedSparseRequest| async move {
+            let result = embed_sparse(infer, info, headers.clone(), Json(req)).await?;
+            Ok(VertexPrediction::EmbedSparse(result.1 .0))
+        };
+    let predict_future =
+        move |infer: Extension<Infer>, info: Extension<Info>, req: PredictRequest| async move {
+            let result = predict(infer, info, headers.clone(), Json(req)).await?;
+            Ok(VertexPrediction::Predict(result.1 .0))
+        };
+    let rerank_future =
+        move |infer: Extension<Infer>, info: Extension<Info>, req: RerankRequest| async move {
+            let result = rerank(infer, info, headers.clone(), Json(req)).await?;
+            Ok(VertexPrediction::Rerank(result.1 .0))
+        };
+
+    let mut futures = Vec::with_capacity(req.instances.len());
+    for instance in req.instances {
+        let local_infer = infer.clone();
+        let local_info = info.clone();
+
+        // Rerank is the only payload that can me matched safely
+        if let Ok(instance) = serde_json::from_value::<RerankRequest>(instance.clone()) {
+            futures.push(rerank_future(local_infer, local_info, instance).boxed());
+            continue;
+        }
+
+        match info.model_type {
+            ModelType::Classifier(_) | ModelType::Reranker(_) => {
+                let instance = serde_json::from_value::<PredictRequest>(instance)
+                    .map_err(ErrorResponse::from)?;
+                futures.push(predict_future(local_infer, local_info, instance).boxed());
+            }
+            ModelType::Embedding(_) => {
+                if infer.is_splade() {
+                    let instance = serde_json::from_value::<EmbedSparseRequest>(instance)
+                        .map_err(ErrorResponse::from)?;
+                    futures.push(embed_sparse_future(local_infer, local_info, instance).boxed());
+                } else {
+                    let instance = serde_json::from_value::<EmbedRequest>(instance)
+                        .map_err(ErrorResponse::from)?;
+                    futures.push(embed_future(local_infer, local_info, instance).boxed());
+                }
+            }
+        }
+    }
+
+    let predictions = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<VertexPrediction>, (StatusCode, Json<ErrorResponse>)>>()?;
+
+    Ok(Json(VertexResponse { predictions }))
+}
+
+/// Embed and index documents. Documents with an indexed id are replaced.
+#[cfg(feature = "index")]
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/index/upsert",
+request_body = IndexUpsertRequest,
+responses(
+(status = 200, description = "Indexed documents", body = IndexUpsertResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 400, description = "Batch is empty", body = ErrorResponse,
+example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
+(status = 413, description = "Batch size error", body = ErrorResponse,
+example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn index_upsert(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    index: Extension<Arc<RwLock<VectorIndex>>>,
+    headers: HeaderMap,
+    Json(req): Json<IndexUpsertRequest>,
+) -> Result<(HeaderMap, Json<IndexUpsertResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    let deadline = request_deadline(&headers)?;
+
+    let upserted = req.documents.len();
+    let (documents, texts): (Vec<_>, Vec<_>) = req
+        .documents
+        .into_iter()
+        .map(|d| ((d.id, d.metadata), d.text))
+        .unzip();
+    // Cosine indexes normalize the vectors themselves
+    let normalize = index.read().await.metric() == IndexMetric::Cosine;
+    let (embeddings, metadata) = embed_index_texts(
+        &infer,
+        &info,
+        texts,
+        req.truncate,
+        req.truncation_direction,
+        req.prompt_name,
+        normalize,
+        req.priority,
+        deadline,
+    )
+    .await?;
+
+    let size = {
+        let mut index = index.write().await;
+        for ((id, document_metadata), embedding) in documents.into_iter().zip(embeddings) {
+            index
+                .upsert(id, embedding, document_metadata)
+                .map_err(index_error)?;
+        }
+        index.len()
+    };
+    let gauge = metrics::gauge!("te_index_size");
+    gauge.set(size as f64);
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    tracing::info!("Success");
+
+    Ok((
+        HeaderMap::from(metadata),
+        Json(IndexUpsertResponse { upserted, size }),
+    ))
+}
+
+/// Remove documents from the index
+#[cfg(feature = "index")]
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/index/delete",
+request_body = IndexDeleteRequest,
+responses(
+(status = 200, description = "Deleted documents", body = IndexDeleteResponse),
+)
+)]
+#[instrument(skip_all)]
+async fn index_delete(
+    index: Extension<Arc<RwLock<VectorIndex>>>,
+    Json(req): Json<IndexDeleteRequest>,
+) -> Json<IndexDeleteResponse> {
+    let mut index = index.write().await;
+    let deleted = req.ids.iter().filter(|id| index.delete(id)).count();
+    let size = index.len();
+    drop(index);
+
+    let gauge = metrics::gauge!("te_index_size");
+    gauge.set(size as f64);
+
+    Json(IndexDeleteResponse { deleted, size })
+}
+
+/// Search the index for the documents most similar to a query
+#[cfg(feature = "index")]
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/search",
+request_body = SearchRequest,
+responses(
+(status = 200, description = "Most similar documents", body = SearchResponse),
+(status = 424, description = "Embedding Error", body = ErrorResponse,
+example = json ! ({"error": "Inference failed", "error_type": "backend"})),
+(status = 429, description = "Model is overloaded", body = ErrorResponse,
+example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
+(status = 422, description = "Tokenization error", body = ErrorResponse,
+example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
+(status = 413, description = "Validation error", body = ErrorResponse,
+example = json ! ({"error": "Validation error", "error_type": "validation"})),
+)
+)]
+#[instrument(
+    skip_all,
+    fields(total_time, tokenization_time, queue_time, inference_time,)
+)]
+async fn search(
+    infer: Extension<Infer>,
+    info: Extension<Info>,
+    index: Extension<Arc<RwLock<VectorIndex>>>,
+    headers: HeaderMap,
+    Json(req): Json<SearchRequest>,
+) -> Result<(HeaderMap, Json<SearchResponse>), (StatusCode, Json<ErrorResponse>)> {
+    let span = tracing::Span::current();
+    let deadline = request_deadline(&headers)?;
+
+    let normalize = index.read().await.metric() == IndexMetric::Cosine;
+    let (mut embeddings, metadata) = embed_index_texts(
+        &infer,
+        &info,
+        vec![req.query],
+        req.truncate,
+        req.truncation_direction,
+        req.prompt_name,
+        normalize,
+        req.priority,
+        deadline,
+    )
+    .await?;
+    // Unwrap is safe here: one embedding per text
+    let query = embeddings.pop().unwrap();
+
+    let hits = index
+        .read()
+        .await
+        .search(query, req.top_k, req.mode.into(), req.ef_search)
+        .map_err(index_error)?
+        .into_iter()
+        .map(|r| SearchHit {
+            id: r.id,
+            score: r.score,
+            metadata: r.metadata,
+        })
+        .collect();
+
+    metadata.record_span(&span);
+    metadata.record_metrics();
+
+    tracing::info!("Success");
+
+    Ok((HeaderMap::from(metadata), Json(SearchResponse(hits))))
+}
+
+/// Embed the documents and queries of the index with the pooled embeddings of the model
+#[cfg(feature = "index")]
+#[allow(clippy::too_many_arguments)]
+async fn embed_index_texts(
+    infer: &Infer,
+    info: &Info,
+    texts: Vec<String>,
+    truncate: Option<bool>,
+    truncation_direction: TruncationDirection,
+    prompt_name: Option<String>,
+    normalize: bool,
+    priority: Priority,
+    deadline: Option<Instant>,
+) -> Result<(Vec<Vec<f32>>, ResponseMetadata), ErrorResponse> {
+    let start_time = Instant::now();
+    let priority = priority.into();
+    let dimensions = info.dimensions(None)?;
+    let truncate = truncate.unwrap_or(info.auto_truncate);
+
+    let counter = metrics::counter!("te_request_count", "method" => "batch");
+    counter.increment(1);
+
+    if texts.is_empty() {
+        let message = "`documents` cannot be empty".to_string();
+        tracing::error!("{message}");
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        return Err(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Empty,
+        });
+    }
+
+    let batch_size = texts.len();
+    if batch_size > info.max_client_batch_size {
+        let message = format!(
+            "batch size {batch_size} > maximum allowed batch size {}",
+            info.max_client_batch_size
+        );
+        tracing::error!("{message}");
+        let counter = metrics::counter!("te_request_failure", "err" => "batch_size");
+        counter.increment(1);
+        return Err(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        });
+    }
+
+    let mut futures = Vec::with_capacity(batch_size);
+    let mut compute_chars = 0;
+
+    for text in texts {
+        compute_chars += text.chars().count();
+
+        let local_infer = infer.clone();
+        let prompt_name = prompt_name.clone();
+        futures.push(async move {
+            let permit = local_infer.acquire_permit(priority).await;
+            local_infer
+                .embed_pooled(
+                    text,
+                    truncate,
+                    truncation_direction.into(),
+                    prompt_name,
+                    normalize,
+                    dimensions,
+                    priority,
+                    deadline,
+                    permit,
+                )
+                .await
+        })
+    }
+    let results = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<PooledEmbeddingsInferResponse>, TextEmbeddingsError>>()?;
+
+    let mut embeddings = Vec::with_capacity(batch_size);
+    let mut total_tokenization_time = 0;
+    let mut total_queue_time = 0;
+    let mut total_inference_time = 0;
+    let mut total_compute_tokens = 0;
+    let mut total_cache_hits = 0;
+
+    for r in results {
+        total_tokenization_time += r.metadata.tokenization.as_nanos() as u64;
+        total_queue_time += r.metadata.queue.as_nanos() as u64;
+        total_inference_time += r.metadata.inference.as_nanos() as u64;
+        total_compute_tokens += r.metadata.prompt_tokens;
+        total_cache_hits += r.metadata.cached as usize;
+        embeddings.push(r.results);
+    }
+    let batch_size = batch_size as u64;
+
+    let counter = metrics::counter!("te_request_success", "method" => "batch");
+    counter.increment(1);
+
+    let metadata = ResponseMetadata::new(
+        compute_chars,
+        total_compute_tokens,
+        start_time,
+        Duration::from_nanos(total_tokenization_time / batch_size),
+        Duration::from_nanos(total_queue_time / batch_size),
+        Duration::from_nanos(total_inference_time / batch_size),
+    )
+    .with_cache_hits(total_cache_hits);
+    Ok((embeddings, metadata))
+}
+
+#[cfg(feature = "index")]
+fn index_error(message: String) -> ErrorResponse {
+    tracing::error!("{message}");
+    let counter = metrics::counter!("te_request_failure", "err" => "validation");
+    counter.increment(1);
+    ErrorResponse {
+        error: message,
+        error_type: ErrorType::Validation,
+    }
+}
+
+/// Submit an embedding job. Every line of the JSONL file at `path`, or of the request body, is
+/// an `{"inputs": "..."}` object. Jobs are embedded in the background at low priority.
+#[utoipa::path(
+post,
+tag = "Text Embeddings Inference",
+path = "/jobs",
+params(JobParameters),
+request_body(content = String, content_type = "application/x-ndjson",
+description = "Lines to embed, if `path` is not set"),
+responses(
+(status = 202, description = "Queued job", body = Job),
+(status = 413, description = "Validation error", body = ErrorResponse,
+example = json ! ({"error": "`path` or a JSONL request body must be given", "error_type": "validation"})),
+)
+)]
+#[instrument(skip_all)]
+async fn create_job(
+    jobs: Extension<JobStore>,
+    Query(parameters): Query<JobParameters>,
+    body: Body,
+) -> Result<(StatusCode, Json<Job>), (StatusCode, Json<ErrorResponse>)> {
+    let counter = metrics::counter!("te_request_count", "method" => "job");
+    counter.increment(1);
+
+    let job = jobs.submit(parameters, body).await?;
+    tracing::info!("Queued job {}", job.id);
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// List the embedding jobs
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/jobs",
+responses((status = 200, description = "Jobs, from the oldest to the most recent", body = JobsResponse))
+)]
+#[instrument(skip_all)]
+async fn list_jobs(jobs: Extension<JobStore>) -> Json<JobsResponse> {
+    Json(JobsResponse(jobs.list()))
 }
 
-/// Generate embeddings from a Vertex request
+/// Get the status and progress of an embedding job
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/jobs/{id}",
+params(("id" = String, Path, description = "Job id")),
+responses(
+(status = 200, description = "Job", body = Job),
+(status = 404, description = "Unknown job", body = ErrorResponse,
+example = json ! ({"error": "job `0` does not exist", "error_type": "validation"})),
+)
+)]
+#[instrument(skip_all)]
+async fn get_job(
+    jobs: Extension<JobStore>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<Job>, (StatusCode, Json<ErrorResponse>)> {
+    jobs.get(&id).map(Json).ok_or_else(|| job_not_found(&id))
+}
+
+/// Cancel a queued or running embedding job. The results embedded so far are kept.
 #[utoipa::path(
 post,
 tag = "Text Embeddings Inference",
-path = "/vertex",
-request_body = VertexRequest,
+path = "/jobs/{id}/cancel",
+params(("id" = String, Path, description = "Job id")),
 responses(
-(status = 200, description = "Results"),
-(status = 424, description = "Error", body = ErrorResponse,
-example = json ! ({"error": "Inference failed", "error_type": "backend"})),
-(status = 429, description = "Model is overloaded", body = ErrorResponse,
-example = json ! ({"error": "Model is overloaded", "error_type": "overloaded"})),
-(status = 422, description = "Tokenization error", body = ErrorResponse,
-example = json ! ({"error": "Tokenization error", "error_type": "tokenizer"})),
-(status = 400, description = "Batch is empty", body = ErrorResponse,
-example = json ! ({"error": "Batch is empty", "error_type": "empty"})),
-(status = 413, description = "Batch size error", body = ErrorResponse,
-example = json ! ({"error": "Batch size error", "error_type": "validation"})),
+(status = 200, description = "Job", body = Job),
+(status = 404, description = "Unknown job", body = ErrorResponse,
+example = json ! ({"error": "job `0` does not exist", "error_type": "validation"})),
 )
 )]
 #[instrument(skip_all)]
-async fn vertex_compatibility(
-    infer: Extension<Infer>,
-    info: Extension<Info>,
-    Json(req): Json<VertexRequest>,
-) -> Result<Json<VertexResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let embed_future = move |infer: Extension<Infer>, info: Extension<Info>, req: EmbedRequest| async move {
-        let result = embed(infer, info, Json(req)).await?;
-        Ok(VertexPrediction::Embed(result.1 .0))
+async fn cancel_job(
+    jobs: Extension<JobStore>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<Job>, (StatusCode, Json<ErrorResponse>)> {
+    let job = jobs
+        .cancel(&id)
+        .await
+        .ok_or_else(|| job_not_found(&id))?
+        .map_err(|err| ErrorResponse {
+            error: format!("Failed to save the job: {err}"),
+            error_type: ErrorType::Backend,
+        })?;
+    tracing::info!("Job {id} is {:?}", job.status);
+    Ok(Json(job))
+}
+
+/// Download the results of a completed or cancelled embedding job
+#[utoipa::path(
+get,
+tag = "Text Embeddings Inference",
+path = "/jobs/{id}/results",
+params(("id" = String, Path, description = "Job id"), JobResultsQuery),
+responses(
+(status = 200, description = "Embeddings", body = EmbedStreamResponse, content_type = "application/x-ndjson"),
+(status = 404, description = "Unknown job", body = ErrorResponse,
+example = json ! ({"error": "job `0` does not exist", "error_type": "validation"})),
+(status = 413, description = "Unfinished job", body = ErrorResponse,
+example = json ! ({"error": "job `0` is Running: results are available once it is completed or cancelled", "error_type": "validation"})),
+)
+)]
+#[instrument(skip_all)]
+async fn job_results(
+    jobs: Extension<JobStore>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(query): Query<JobResultsQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let (path, length) = jobs
+        .results(&id, query.format)
+        .await
+        .ok_or_else(|| job_not_found(&id))??;
+    let file = tokio::fs::File::open(&path)
+        .await
+        .map_err(|err| ErrorResponse {
+            error: format!("Failed to open the results: {err}"),
+            error_type: ErrorType::Backend,
+        })?;
+
+    // Stream the file without loading it in memory
+    let chunks = futures::stream::try_unfold(file.take(length), |mut file| async move {
+        let mut buffer = vec![0; 64 * 1024];
+        let size = file.read(&mut buffer).await?;
+        if size == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        buffer.truncate(size);
+        Ok(Some((Bytes::from(buffer), file)))
+    });
+
+    let content_type = match query.format {
+        JobResultsFormat::Jsonl => "application/x-ndjson",
+        JobResultsFormat::Npy => "application/octet-stream",
     };
-    let embed_sparse_future =
-        move |infer: Extension<Infer>, info: Extension<Info>, req: EmbedSparseRequest| async move {
-            let result = embed_sparse(infer, info, Json(req)).await?;
-            Ok(VertexPrediction::EmbedSparse(result.1 .0))
-        };
-    let predict_future =
-        move |infer: Extension<Infer>, info: Extension<Info>, req: PredictRequest| async move {
-            let result = predict(infer, info, Json(req)).await?;
-            Ok(VertexPrediction::Predict(result.1 .0))
-        };
-    let rerank_future =
-        move |infer: Extension<Infer>, info: Extension<Info>, req: RerankRequest| async move {
-            let result = rerank(infer, info, Json(req)).await?;
-            Ok(VertexPrediction::Rerank(result.1 .0))
-        };
+    Ok(([(CONTENT_TYPE, content_type)], Body::from_stream(chunks)).into_response())
+}
 
-    let mut futures = Vec::with_capacity(req.instances.len());
-    for instance in req.instances {
-        let local_infer = infer.clone();
-        let local_info = info.clone();
+fn job_not_found(id: &str) -> (StatusCode, Json<ErrorResponse>) {
+    let message = format!("job `{id}` does not exist");
+    tracing::error!("{message}");
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        }),
+    )
+}
 
-        // Rerank is the only payload that can me matched safely
-        if let Ok(instance) = serde_json::from_value::<RerankRequest>(instance.clone()) {
-            futures.push(rerank_future(local_infer, local_info, instance).boxed());
-            continue;
-        }
+/// Get the deadline of a request from its `x-request-timeout-ms` header
+fn request_deadline(headers: &HeaderMap) -> Result<Option<Instant>, ErrorResponse> {
+    let Some(value) = headers.get(REQUEST_TIMEOUT_HEADER) else {
+        return Ok(None);
+    };
 
-        match info.model_type {
-            ModelType::Classifier(_) | ModelType::Reranker(_) => {
-                let instance = serde_json::from_value::<PredictRequest>(instance)
-                    .map_err(ErrorResponse::from)?;
-                futures.push(predict_future(local_infer, local_info, instance).boxed());
-            }
-            ModelType::Embedding(_) => {
-                if infer.is_splade() {
-                    let instance = serde_json::from_value::<EmbedSparseRequest>(instance)
-                        .map_err(ErrorResponse::from)?;
-                    futures.push(embed_sparse_future(local_infer, local_info, instance).boxed());
-                } else {
-                    let instance = serde_json::from_value::<EmbedRequest>(instance)
-                        .map_err(ErrorResponse::from)?;
-                    futures.push(embed_future(local_infer, local_info, instance).boxed());
-                }
-            }
+    match value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+    {
+        Some(timeout_ms) => Ok(Some(Instant::now() + Duration::from_millis(timeout_ms))),
+        None => {
+            let message = format!("`{REQUEST_TIMEOUT_HEADER}` must be a number of milliseconds");
+            tracing::error!("{message}");
+            let counter = metrics::counter!("te_request_failure", "err" => "validation");
+            counter.increment(1);
+            Err(ErrorResponse {
+                error: message,
+                error_type: ErrorType::Validation,
+            })
         }
     }
-
-    let predictions = join_all(futures)
-        .await
-        .into_iter()
-        .collect::<Result<Vec<VertexPrediction>, (StatusCode, Json<ErrorResponse>)>>()?;
-
-    Ok(Json(VertexResponse { predictions }))
 }
 
 /// Prometheus metrics scrape endpoint
@@ -1559,14 +3495,19 @@ async fn metrics(prom_handle: Extension<PrometheusHandle>) -> String {
 }
 
 /// Serving method
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
-    infer: Infer,
-    info: Info,
+    models: Vec<(String, Infer, Info)>,
     addr: SocketAddr,
-    prom_builder: PrometheusBuilder,
+    prom_handle: PrometheusHandle,
     payload_limit: usize,
     api_key: Option<String>,
     cors_allow_origin: Option<Vec<String>>,
+    index_path: Option<String>,
+    index_metric: IndexMetric,
+    jobs_dir: Option<String>,
+    jobs_input_dir: Option<String>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
 ) -> Result<(), anyhow::Error> {
     // OpenAPI documentation
     #[derive(OpenApi)]
@@ -1578,8 +3519,18 @@ pub async fn run(
     rerank,
     embed,
     embed_all,
+    embed_multivector,
     embed_sparse,
     openai_embed,
+    cohere_embed,
+    compat_rerank,
+    ollama_embed,
+    embed_stream,
+    create_job,
+    list_jobs,
+    get_job,
+    cancel_job,
+    job_results,
     similarity,
     tokenize,
     decode,
@@ -1590,6 +3541,8 @@ pub async fn run(
     PredictInput,
     Input,
     Info,
+    OnnxRuntimeInfo,
+    ServedModel,
     ModelType,
     ClassifierModel,
     Embedding,
@@ -1604,6 +3557,8 @@ pub async fn run(
     OpenAICompatResponse,
     EmbedAllRequest,
     EmbedAllResponse,
+    EmbedMultiVectorRequest,
+    EmbedMultiVectorResponse,
     EmbedSparseRequest,
     SparseValue,
     EmbedSparseResponse,
@@ -1612,12 +3567,44 @@ pub async fn run(
     RerankResponse,
     EmbedRequest,
     EmbedResponse,
+    EmbedOutput,
+    HybridEmbedding,
+    ChunkingParameters,
+    ChunkAggregation,
+    ChunkedEmbedding,
+    EmbeddingChunk,
     ErrorResponse,
     OpenAICompatErrorResponse,
+    CohereInputType,
+    CohereEmbeddingType,
+    CohereTruncate,
+    CohereEmbedRequest,
+    CohereEmbeddingsByType,
+    CohereEmbeddings,
+    CohereApiVersion,
+    CohereBilledUnits,
+    CohereMeta,
+    CohereEmbedResponse,
+    CompatRerankDocument,
+    CompatRerankRequest,
+    CompatRerankText,
+    CompatRerankResult,
+    CompatRerankUsage,
+    CompatRerankResponse,
+    OllamaEmbedRequest,
+    OllamaEmbedResponse,
+    EmbedStreamRequest,
+    EmbedStreamResponse,
+    JobStatus,
+    JobParameters,
+    Job,
+    JobsResponse,
+    JobResultsFormat,
     TokenizeInput,
     TokenizeRequest,
     TokenizeResponse,
     TruncationDirection,
+    Priority,
     SimilarityInput,
     SimilarityParameters,
     SimilarityRequest,
@@ -1658,13 +3645,6 @@ pub async fn run(
         }
     });
 
-    // See: https://github.com/metrics-rs/metrics/issues/467#issuecomment-2022755151
-    let (recorder, _) = prom_builder
-        .build()
-        .context("failed to build prometheus recorder")?;
-    let prom_handle = recorder.handle();
-    metrics::set_global_recorder(recorder).context("Failed to set global recorder")?;
-
     // CORS layer
     let allow_origin = allow_origin.unwrap_or(AllowOrigin::any());
     let cors_layer = CorsLayer::new()
@@ -1692,23 +3672,50 @@ pub async fn run(
         #[cfg(not(feature = "google"))]
         ApiDoc::openapi()
     };
+    #[cfg(feature = "index")]
+    let doc = {
+        #[derive(OpenApi)]
+        #[openapi(
+            paths(index_upsert, index_delete, search),
+            components(schemas(
+                IndexDocument,
+                IndexUpsertRequest,
+                IndexUpsertResponse,
+                IndexDeleteRequest,
+                IndexDeleteResponse,
+                SearchMode,
+                SearchRequest,
+                SearchHit,
+                SearchResponse
+            ))
+        )]
+        struct IndexApiDoc;
 
-    let mut routes = Router::new()
-        // Base routes
-        .route("/info", get(get_model_info))
-        .route("/embed", post(embed))
-        .route("/embed_all", post(embed_all))
-        .route("/embed_sparse", post(embed_sparse))
-        .route("/predict", post(predict))
-        .route("/rerank", post(rerank))
-        .route("/similarity", post(similarity))
-        .route("/tokenize", post(tokenize))
-        .route("/decode", post(decode))
-        // OpenAI compat route
-        .route("/embeddings", post(openai_embed))
-        .route("/v1/embeddings", post(openai_embed))
-        // Vertex compat route
-        .route("/vertex", post(vertex_compatibility));
+        let mut doc = doc;
+        doc.merge(IndexApiDoc::openapi());
+        doc
+    };
+
+    // The first model serves the requests that do not name a model
+    let (_, infer, info) = models
+        .first()
+        .cloned()
+        .context("at least one model must be served")?;
+    let served_models = ServedModels::new(&models);
+
+    let mut routes = model_routes(&info.model_type);
+    // Every model is also served under its own prefix
+    for (name, model_infer, model_info) in &models {
+        let prefix = format!("/models/{name}");
+        tracing::info!("Serving model `{}` on {prefix}", model_info.model_id);
+        routes = routes.nest(
+            &prefix,
+            model_routes(&model_info.model_type)
+                .route("/health", get(health))
+                .layer(Extension(model_infer.clone()))
+                .layer(Extension(model_info.clone())),
+        );
+    }
 
     #[allow(unused_mut)]
     let mut public_routes = Router::new()
@@ -1735,41 +3742,53 @@ pub async fn run(
             public_routes = public_routes.route(&env_health_route, get(health));
         }
     }
-    #[cfg(not(feature = "google"))]
-    {
-        // Set default routes
-        routes = match &info.model_type {
-            ModelType::Classifier(_) => {
-                routes
-                    .route("/", post(predict))
-                    // AWS Sagemaker route
-                    .route("/invocations", post(predict))
-            }
-            ModelType::Reranker(_) => {
-                routes
-                    .route("/", post(rerank))
-                    // AWS Sagemaker route
-                    .route("/invocations", post(rerank))
-            }
-            ModelType::Embedding(model) => {
-                if std::env::var("TASK").ok() == Some("sentence-similarity".to_string()) {
-                    routes
-                        .route("/", post(similarity))
-                        // AWS Sagemaker route
-                        .route("/invocations", post(similarity))
-                } else if model.pooling == "splade" {
-                    routes
-                        .route("/", post(embed_sparse))
-                        // AWS Sagemaker route
-                        .route("/invocations", post(embed_sparse))
-                } else {
-                    routes
-                        .route("/", post(embed))
-                        // AWS Sagemaker route
-                        .route("/invocations", post(embed))
-                }
-            }
+    #[cfg(feature = "index")]
+    let index = {
+        let index = match &index_path {
+            Some(path) => VectorIndex::load(Path::new(path), index_metric)?,
+            None => VectorIndex::new(index_metric),
         };
+        let gauge = metrics::gauge!("te_index_size");
+        gauge.set(index.len() as f64);
+        let index = Arc::new(RwLock::new(index));
+
+        // The index embeds with the default model
+        routes = routes
+            .route("/index/upsert", post(index_upsert))
+            .route("/index/delete", post(index_delete))
+            .route("/search", post(search))
+            .layer(Extension(index.clone()));
+        index
+    };
+    #[cfg(not(feature = "index"))]
+    {
+        if index_path.is_some() {
+            tracing::warn!(
+                "`index-path` is ignored: the router was built without the `index` feature"
+            );
+        }
+        let _ = index_metric;
+    }
+
+    if let Some(jobs_dir) = &jobs_dir {
+        if !matches!(info.model_type, ModelType::Embedding(_)) {
+            anyhow::bail!("`jobs-dir` requires an embedding model");
+        }
+        let jobs = JobStore::load(
+            Path::new(jobs_dir),
+            jobs_input_dir.as_deref().map(Path::new),
+            infer.clone(),
+            info.clone(),
+        )?;
+        tracing::info!("Serving jobs from {jobs_dir}");
+
+        // Jobs embed with the default model
+        routes = routes
+            .route("/jobs", post(create_job).get(list_jobs))
+            .route("/jobs/:id", get(get_job))
+            .route("/jobs/:id/cancel", post(cancel_job))
+            .route("/jobs/:id/results", get(job_results))
+            .layer(Extension(jobs));
     }
 
     if let Some(api_key) = api_key {
@@ -1799,6 +3818,8 @@ pub async fn run(
         .merge(public_routes)
         .layer(Extension(infer))
         .layer(Extension(info))
+        .layer(Extension(served_models))
+        .layer(Extension(PayloadLimit(payload_limit)))
         .layer(Extension(prom_handle.clone()))
         .layer(OtelAxumLayer::default())
         .layer(DefaultBodyLimit::max(payload_limit))
@@ -1814,12 +3835,88 @@ pub async fn run(
 
     axum::serve(listener, app)
         // Wait until all requests are finished to shut down
-        .with_graceful_shutdown(shutdown::shutdown_signal())
+        .with_graceful_shutdown(shutdown)
         .await?;
 
+    #[cfg(feature = "index")]
+    if let Some(path) = index_path {
+        let index = index.read().await;
+        index.snapshot(Path::new(&path))?;
+        tracing::info!("Saved {} documents to {path}", index.len());
+    }
+
     Ok(())
 }
 
+/// Inference routes of a model
+fn model_routes(model_type: &ModelType) -> Router {
+    #[allow(unused_mut)]
+    let mut routes = Router::new()
+        // Base routes
+        .route("/info", get(get_model_info))
+        .route("/embed", post(embed))
+        .route("/embed_all", post(embed_all))
+        .route("/embed_multivector", post(embed_multivector))
+        .route("/embed_sparse", post(embed_sparse))
+        .route("/embed/stream", post(embed_stream))
+        .route("/predict", post(predict))
+        .route("/rerank", post(rerank))
+        .route("/similarity", post(similarity))
+        .route("/tokenize", post(tokenize))
+        .route("/decode", post(decode))
+        // OpenAI compat route
+        .route("/embeddings", post(openai_embed))
+        .route("/v1/embeddings", post(openai_embed))
+        // Cohere and Jina compat routes
+        .route("/v1/embed", post(cohere_embed))
+        .route("/v1/rerank", post(compat_rerank))
+        // Ollama compat route
+        .route("/api/embed", post(ollama_embed))
+        // Vertex compat route
+        .route("/vertex", post(vertex_compatibility));
+
+    #[cfg(not(feature = "google"))]
+    {
+        // Set default routes
+        routes = match model_type {
+            ModelType::Classifier(_) => {
+                routes
+                    .route("/", post(predict))
+                    // AWS Sagemaker route
+                    .route("/invocations", post(predict))
+            }
+            ModelType::Reranker(_) => {
+                routes
+                    .route("/", post(rerank))
+                    // AWS Sagemaker route
+                    .route("/invocations", post(rerank))
+            }
+            ModelType::Embedding(model) => {
+                if std::env::var("TASK").ok() == Some("sentence-similarity".to_string()) {
+                    routes
+                        .route("/", post(similarity))
+                        // AWS Sagemaker route
+                        .route("/invocations", post(similarity))
+                } else if model.pooling == "splade" {
+                    routes
+                        .route("/", post(embed_sparse))
+                        // AWS Sagemaker route
+                        .route("/invocations", post(embed_sparse))
+                } else {
+                    routes
+                        .route("/", post(embed))
+                        // AWS Sagemaker route
+                        .route("/invocations", post(embed))
+                }
+            }
+        };
+    }
+    #[cfg(feature = "google")]
+    let _ = model_type;
+
+    routes
+}
+
 impl From<&ErrorType> for StatusCode {
     fn from(value: &ErrorType) -> Self {
         match value {
@@ -1829,6 +3926,7 @@ impl From<&ErrorType> for StatusCode {
             ErrorType::Tokenizer => StatusCode::UNPROCESSABLE_ENTITY,
             ErrorType::Validation => StatusCode::PAYLOAD_TOO_LARGE,
             ErrorType::Empty => StatusCode::BAD_REQUEST,
+            ErrorType::Deadline => StatusCode::GATEWAY_TIMEOUT,
         }
     }
 }
@@ -1864,3 +3962,114 @@ impl From<serde_json::Error> for ErrorResponse {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::http::server::{cohere_embeddings, compat_rerank_results};
+    use crate::http::types::CohereEmbeddingType;
+    use crate::{EmbeddingModel, Info, ModelType};
+    use serde_json::json;
+
+    fn info() -> Info {
+        Info {
+            model_id: "model".to_string(),
+            model_sha: None,
+            model_dtype: "float32".to_string(),
+            model_type: ModelType::Embedding(EmbeddingModel {
+                pooling: "mean".to_string(),
+            }),
+            max_concurrent_requests: 1,
+            max_input_length: 512,
+            max_batch_tokens: 512,
+            max_batch_requests: None,
+            max_client_batch_size: 1,
+            auto_truncate: false,
+            tokenization_workers: 1,
+            hidden_size: Some(5),
+            default_dimensions: None,
+            quantization_calibration: None,
+            query_prompt_name: None,
+            document_prompt_name: None,
+            onnx_runtime: None,
+            models: vec![],
+            version: "0.0.0",
+            sha: None,
+            docker_label: None,
+        }
+    }
+
+    #[test]
+    fn test_cohere_embeddings() {
+        let embeddings = vec![vec![0.1, -0.3, 0.25, 0.7, -0.05]];
+        let floats = serde_json::to_value(&embeddings).unwrap();
+
+        let (response_type, output) = cohere_embeddings(embeddings.clone(), None, &info()).unwrap();
+        assert_eq!(response_type, "embeddings_floats");
+        assert_eq!(serde_json::to_value(output).unwrap(), floats);
+
+        let embedding_types = vec![
+            CohereEmbeddingType::Float,
+            CohereEmbeddingType::Int8,
+            CohereEmbeddingType::Uint8,
+            CohereEmbeddingType::Binary,
+            CohereEmbeddingType::Ubinary,
+        ];
+        let (response_type, output) =
+            cohere_embeddings(embeddings.clone(), Some(embedding_types), &info()).unwrap();
+        assert_eq!(response_type, "embeddings_by_type");
+        // Without calibration, scalar quantization uses the range of each embedding
+        assert_eq!(
+            serde_json::to_value(output).unwrap(),
+            json!({
+                "float": floats,
+                "int8": [[-26, -128, 12, 126, -64]],
+                "uint8": [[101, 0, 140, 254, 63]],
+                "binary": [[48]],
+                "ubinary": [[176]],
+            })
+        );
+
+        // Only the requested types are returned
+        let (_, output) = cohere_embeddings(
+            embeddings,
+            Some(vec![CohereEmbeddingType::Ubinary]),
+            &info(),
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(output).unwrap(),
+            json!({ "ubinary": [[176]] })
+        );
+    }
+
+    #[test]
+    fn test_compat_rerank_results() {
+        let scores = vec![(2, 0.5), (0, 0.25), (1, 0.125)];
+        let texts = ["a", "b", "c"].map(String::from);
+
+        let results = compat_rerank_results(scores.clone(), &texts, None, false);
+        assert_eq!(
+            serde_json::to_value(results).unwrap(),
+            json!([
+                {"index": 2, "relevance_score": 0.5},
+                {"index": 0, "relevance_score": 0.25},
+                {"index": 1, "relevance_score": 0.125},
+            ])
+        );
+
+        let results = compat_rerank_results(scores.clone(), &texts, Some(2), true);
+        assert_eq!(
+            serde_json::to_value(results).unwrap(),
+            json!([
+                {"index": 2, "relevance_score": 0.5, "document": {"text": "c"}},
+                {"index": 0, "relevance_score": 0.25, "document": {"text": "a"}},
+            ])
+        );
+
+        assert_eq!(
+            compat_rerank_results(scores.clone(), &texts, Some(10), false).len(),
+            3
+        );
+        assert!(compat_rerank_results(scores, &texts, Some(0), true).is_empty());
+    }
+}
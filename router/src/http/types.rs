@@ -5,7 +5,7 @@ use serde_json::json;
 use std::fmt::Formatter;
 use text_embeddings_core::tokenization::EncodingInput;
 use utoipa::openapi::{RefOr, Schema};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Debug)]
 pub(crate) enum Sequence {
@@ -193,13 +193,34 @@ impl<'__s> ToSchema<'__s> for PredictInput {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema, Eq, Default)]
 pub(crate) enum TruncationDirection {
     Left,
     #[default]
     Right,
 }
 
+/// Scheduling class of the request. Batches are filled from the higher classes first and
+/// requests waiting for too long are promoted to the next class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Priority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl From<Priority> for text_embeddings_core::queue::Priority {
+    fn from(value: Priority) -> Self {
+        match value {
+            Priority::High => Self::High,
+            Priority::Normal => Self::Normal,
+            Priority::Low => Self::Low,
+        }
+    }
+}
+
 impl From<TruncationDirection> for tokenizers::TruncationDirection {
     fn from(value: TruncationDirection) -> Self {
         match value {
@@ -220,6 +241,9 @@ pub(crate) struct PredictRequest {
     #[serde(default)]
     #[schema(default = "false", example = "false")]
     pub raw_scores: bool,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -255,6 +279,9 @@ pub(crate) struct RerankRequest {
     #[serde(default)]
     #[schema(default = "false", example = "false")]
     pub return_text: bool,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -303,18 +330,30 @@ pub(crate) enum Input {
     Batch(Vec<InputType>),
 }
 
-#[derive(Deserialize, ToSchema, Default)]
+#[derive(Deserialize, ToSchema, Default, Debug, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum EncodingFormat {
     #[default]
     Float,
+    /// Base64 of the little-endian float32 values
     Base64,
+    /// Base64 of the little-endian float16 values
+    Float16,
+    /// Scalar quantization to [-128, 127]
+    Int8,
+    /// Scalar quantization to [0, 255]
+    Uint8,
+    /// Sign bits packed in bytes, shifted to [-128, 127]
+    Binary,
+    /// Sign bits packed in bytes
+    Ubinary,
 }
 
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct OpenAICompatRequest {
     pub input: Input,
-    #[allow(dead_code)]
+    /// Name of the served model to use. Ignored if a single model is served, otherwise unknown names
+    /// are rejected.
     #[schema(nullable = true, example = "null")]
     pub model: Option<String>,
     #[allow(dead_code)]
@@ -323,6 +362,11 @@ pub(crate) struct OpenAICompatRequest {
     #[schema(default = "float", example = "float")]
     #[serde(default)]
     pub encoding_format: EncodingFormat,
+    /// Truncate the embeddings of Matryoshka models to this many dimensions. Defaults to the
+    /// server `default-dimensions`.
+    #[serde(default)]
+    #[schema(nullable = true, example = "null")]
+    pub dimensions: Option<usize>,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -330,6 +374,8 @@ pub(crate) struct OpenAICompatRequest {
 pub(crate) enum Embedding {
     Float(Vec<f32>),
     Base64(String),
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
 }
 
 #[derive(Serialize, ToSchema)]
@@ -360,6 +406,215 @@ pub(crate) struct OpenAICompatResponse {
     pub usage: OpenAICompatUsage,
 }
 
+/// Cohere input type. Queries and documents use the Sentence Transformers prompts of the model.
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CohereInputType {
+    SearchQuery,
+    SearchDocument,
+    Classification,
+    Clustering,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CohereEmbeddingType {
+    Float,
+    Int8,
+    Uint8,
+    Binary,
+    Ubinary,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum CohereTruncate {
+    /// Return an error for inputs longer than the maximum input length
+    None,
+    Start,
+    #[default]
+    End,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct CohereEmbedRequest {
+    #[schema(example = json!(["What is Deep Learning?"]))]
+    pub texts: Vec<String>,
+    /// Name of the served model to use. Ignored if a single model is served, otherwise unknown names
+    /// are rejected.
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(nullable = true, example = "search_query")]
+    pub input_type: Option<CohereInputType>,
+    /// Return the embeddings in these types. Defaults to a plain list of float embeddings.
+    #[schema(nullable = true, example = json!(["float"]))]
+    pub embedding_types: Option<Vec<CohereEmbeddingType>>,
+    #[serde(default)]
+    #[schema(default = "END", example = "END")]
+    pub truncate: CohereTruncate,
+    /// Truncate the embeddings of Matryoshka models to this many dimensions
+    #[schema(nullable = true, example = "null")]
+    pub output_dimension: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema, Default)]
+pub(crate) struct CohereEmbeddingsByType {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub float: Option<Vec<Vec<f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub int8: Option<Vec<Vec<i8>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uint8: Option<Vec<Vec<u8>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<Vec<Vec<i8>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ubinary: Option<Vec<Vec<u8>>>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum CohereEmbeddings {
+    Floats(Vec<Vec<f32>>),
+    ByType(CohereEmbeddingsByType),
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereApiVersion {
+    #[schema(example = "1")]
+    pub version: &'static str,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereBilledUnits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true, example = "512")]
+    pub input_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true, example = "null")]
+    pub search_units: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereMeta {
+    pub api_version: CohereApiVersion,
+    pub billed_units: CohereBilledUnits,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CohereEmbedResponse {
+    #[schema(example = "5d9fc2bb3d1b4ac8a2a4bd8b1ba8d87f")]
+    pub id: String,
+    /// `embeddings_floats` or `embeddings_by_type` when `embedding_types` is set
+    #[schema(example = "embeddings_floats")]
+    pub response_type: &'static str,
+    pub embeddings: CohereEmbeddings,
+    #[schema(example = json!(["What is Deep Learning?"]))]
+    pub texts: Vec<String>,
+    pub meta: CohereMeta,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub(crate) enum CompatRerankDocument {
+    Text(String),
+    Object { text: String },
+}
+
+impl CompatRerankDocument {
+    pub(crate) fn into_text(self) -> String {
+        match self {
+            CompatRerankDocument::Text(text) | CompatRerankDocument::Object { text } => text,
+        }
+    }
+}
+
+/// Jina and Cohere re-ranking request
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct CompatRerankRequest {
+    /// Name of the served model to use. Ignored if a single model is served, otherwise unknown names
+    /// are rejected.
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    #[schema(example = "What is Deep Learning?")]
+    pub query: String,
+    #[schema(example = json!(["Deep Learning is ..."]))]
+    pub documents: Vec<CompatRerankDocument>,
+    /// Only return the `top_n` most relevant documents
+    #[schema(nullable = true, example = "null")]
+    pub top_n: Option<usize>,
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub return_documents: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CompatRerankText {
+    #[schema(example = "Deep Learning is ...")]
+    pub text: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CompatRerankResult {
+    #[schema(example = "0")]
+    pub index: usize,
+    #[schema(example = "1.0")]
+    pub relevance_score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true)]
+    pub document: Option<CompatRerankText>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CompatRerankUsage {
+    #[schema(example = "512")]
+    pub total_tokens: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CompatRerankResponse {
+    #[schema(example = "5d9fc2bb3d1b4ac8a2a4bd8b1ba8d87f")]
+    pub id: String,
+    #[schema(example = "BAAI/bge-reranker-large")]
+    pub model: String,
+    pub results: Vec<CompatRerankResult>,
+    pub usage: CompatRerankUsage,
+    pub meta: CohereMeta,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct OllamaEmbedRequest {
+    /// Name of the served model to use. Ignored if a single model is served, otherwise unknown names
+    /// are rejected.
+    #[schema(nullable = true, example = "null")]
+    pub model: Option<String>,
+    pub input: Input,
+    /// Truncate the inputs that are longer than the maximum input length instead of returning an
+    /// error
+    #[serde(default = "default_truncate")]
+    #[schema(default = "true", example = "true")]
+    pub truncate: bool,
+}
+
+fn default_truncate() -> bool {
+    true
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct OllamaEmbedResponse {
+    #[schema(example = "thenlper/gte-base")]
+    pub model: String,
+    #[schema(example = json!([[0.0, 1.0, 2.0]]))]
+    pub embeddings: Vec<Vec<f32>>,
+    /// Nanoseconds spent serving the request
+    #[schema(example = "14143917")]
+    pub total_duration: u64,
+    /// Models are loaded at startup
+    #[schema(example = "0")]
+    pub load_duration: u64,
+    #[schema(example = "8")]
+    pub prompt_eval_count: usize,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct SimilarityInput {
     /// The string that you wish to compare the other strings with. This can be a phrase, sentence,
@@ -426,15 +681,170 @@ pub(crate) struct EmbedRequest {
     #[serde(default = "default_normalize")]
     #[schema(default = "true", example = "true")]
     pub normalize: bool,
+    /// Truncate the embeddings of Matryoshka models to this many dimensions before normalizing
+    /// them. Defaults to the server `default-dimensions`.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub dimensions: Option<usize>,
+    /// The outputs to compute in a single forward pass. If not set, only the dense embeddings
+    /// are returned.
+    ///
+    /// `sparse` returns the lexical weights and `multivector` the per-token (ColBERT) embeddings
+    /// of models that provide them, such as BGE-M3.
+    #[serde(default)]
+    #[schema(default = "null", example = json!(["dense", "sparse"]), nullable = true)]
+    pub outputs: Option<Vec<EmbedOutput>>,
+    /// Split inputs into overlapping token windows that are embedded separately and combined
+    /// into a single embedding. If not set, inputs longer than the maximum input length are
+    /// truncated or rejected.
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub chunking: Option<ChunkingParameters>,
+    /// Encoding of the dense embeddings. `int8` and `uint8` are scalar quantized with the server
+    /// `quantization-calibration` ranges, or the range of each embedding if it is not set.
+    #[serde(default)]
+    #[schema(default = "float", example = "float")]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
+}
+
+/// A line of an `/embed/stream` request body
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct EmbedStreamRequest {
+    pub inputs: InputType,
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+    #[serde(default = "default_normalize")]
+    #[schema(default = "true", example = "true")]
+    pub normalize: bool,
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub dimensions: Option<usize>,
+    #[serde(default)]
+    #[schema(default = "float", example = "float")]
+    pub encoding_format: EncodingFormat,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
+}
+
+/// A line of an `/embed/stream` response body. Failed requests have an `error` instead of an
+/// `embedding`.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct EmbedStreamResponse {
+    /// Position of the request in the stream
+    #[schema(example = "0")]
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true)]
+    pub embedding: Option<Embedding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true, example = "null")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true, example = "null")]
+    pub error_type: Option<ErrorType>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct ChunkingParameters {
+    /// Maximum number of tokens in a window, including the prompt and the special tokens.
+    /// Defaults to the maximum input length of the model.
+    #[serde(default)]
+    #[schema(default = "null", example = "256", nullable = true)]
+    pub window_size: Option<usize>,
+    /// Number of tokens shared by two consecutive windows
+    #[serde(default)]
+    #[schema(default = "0", example = "32")]
+    pub stride: usize,
+    #[serde(default)]
+    #[schema(default = "mean", example = "mean")]
+    pub aggregation: ChunkAggregation,
+    /// Also return the embedding and the character offsets of each window
+    #[serde(default)]
+    #[schema(default = "false", example = "false")]
+    pub return_chunks: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ChunkAggregation {
+    #[default]
+    Mean,
+    Max,
+    /// Mean weighted by the number of tokens of each window
+    WeightedMean,
+}
+
+impl From<ChunkAggregation> for text_embeddings_core::infer::ChunkAggregation {
+    fn from(value: ChunkAggregation) -> Self {
+        match value {
+            ChunkAggregation::Mean => Self::Mean,
+            ChunkAggregation::Max => Self::Max,
+            ChunkAggregation::WeightedMean => Self::WeightedMean,
+        }
+    }
 }
 
 fn default_normalize() -> bool {
     true
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EmbedOutput {
+    Dense,
+    Sparse,
+    Multivector,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct HybridEmbedding {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true, example = json!([0.0, 1.0, 2.0]))]
+    pub dense: Option<Embedding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true)]
+    pub sparse: Option<Vec<SparseValue>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable = true, example = json!([[0.0, 1.0, 2.0]]))]
+    pub multivector: Option<Vec<Vec<f32>>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct EmbeddingChunk {
+    #[schema(example = json!([0.0, 1.0, 2.0]))]
+    pub embedding: Embedding,
+    /// Character offsets of the window in the input
+    #[schema(example = "0")]
+    pub start: usize,
+    #[schema(example = "1024")]
+    pub end: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ChunkedEmbedding {
+    #[schema(example = json!([0.0, 1.0, 2.0]))]
+    pub embedding: Embedding,
+    pub chunks: Vec<EmbeddingChunk>,
+}
+
 #[derive(Serialize, ToSchema)]
+#[serde(untagged)]
 #[schema(example = json!([[0.0, 1.0, 2.0]]))]
-pub(crate) struct EmbedResponse(pub Vec<Vec<f32>>);
+pub(crate) enum EmbedResponse {
+    Dense(Vec<Embedding>),
+    Hybrid(Vec<HybridEmbedding>),
+    Chunked(Vec<ChunkedEmbedding>),
+}
 
 #[derive(Deserialize, ToSchema)]
 pub(crate) struct EmbedSparseRequest {
@@ -456,6 +866,9 @@ pub(crate) struct EmbedSparseRequest {
     /// any text to encode.
     #[schema(default = "null", example = "null", nullable = true)]
     pub prompt_name: Option<String>,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -487,12 +900,44 @@ pub(crate) struct EmbedAllRequest {
     /// any text to encode.
     #[schema(default = "null", example = "null", nullable = true)]
     pub prompt_name: Option<String>,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
 }
 
 #[derive(Serialize, ToSchema)]
 #[schema(example = json!([[[0.0, 1.0, 2.0]]]))]
 pub(crate) struct EmbedAllResponse(pub Vec<Vec<Vec<f32>>>);
 
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct EmbedMultiVectorRequest {
+    pub inputs: Input,
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    /// The name of the prompt that should be used by for encoding. If not set, no prompt
+    /// will be applied.
+    ///
+    /// Must be a key in the `sentence-transformers` configuration `prompts` dictionary.
+    ///
+    /// For example if ``prompt_name`` is "query" and the ``prompts`` is {"query": "query: ", ...},
+    /// then the sentence "What is the capital of France?" will be encoded as
+    /// "query: What is the capital of France?" because the prompt text will be prepended before
+    /// any text to encode.
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(example = json!([[[0.0, 1.0, 2.0]]]))]
+pub(crate) struct EmbedMultiVectorResponse(pub Vec<Vec<Vec<f32>>>);
+
 #[derive(Serialize, ToSchema)]
 pub(crate) struct OpenAICompatErrorResponse {
     pub message: String,
@@ -590,3 +1035,224 @@ pub(crate) enum VertexPrediction {
 pub(crate) struct VertexResponse {
     pub predictions: Vec<VertexPrediction>,
 }
+
+#[cfg(feature = "index")]
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct IndexDocument {
+    #[schema(example = "faq-1")]
+    pub id: String,
+    #[schema(example = "What is Deep Learning?")]
+    pub text: String,
+    /// Arbitrary JSON returned with the search results
+    #[serde(default)]
+    #[schema(value_type = Object, nullable = true, example = json!({"url": "https://example.com"}))]
+    pub metadata: serde_json::Value,
+}
+
+#[cfg(feature = "index")]
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct IndexUpsertRequest {
+    /// Documents to embed and index. Documents with an indexed id are replaced.
+    pub documents: Vec<IndexDocument>,
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    /// The name of the prompt that should be used by for encoding. If not set, no prompt
+    /// will be applied.
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
+}
+
+#[cfg(feature = "index")]
+#[derive(Serialize, ToSchema)]
+pub(crate) struct IndexUpsertResponse {
+    #[schema(example = "1")]
+    pub upserted: usize,
+    /// Number of indexed documents
+    #[schema(example = "128")]
+    pub size: usize,
+}
+
+#[cfg(feature = "index")]
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct IndexDeleteRequest {
+    #[schema(example = json!(["faq-1"]))]
+    pub ids: Vec<String>,
+}
+
+#[cfg(feature = "index")]
+#[derive(Serialize, ToSchema)]
+pub(crate) struct IndexDeleteResponse {
+    /// Number of ids that were indexed
+    #[schema(example = "1")]
+    pub deleted: usize,
+    /// Number of indexed documents
+    #[schema(example = "127")]
+    pub size: usize,
+}
+
+#[cfg(feature = "index")]
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SearchMode {
+    /// Score every indexed document
+    #[default]
+    Exact,
+    /// Approximate search in the HNSW graph
+    Hnsw,
+}
+
+#[cfg(feature = "index")]
+impl From<SearchMode> for crate::index::SearchMode {
+    fn from(value: SearchMode) -> Self {
+        match value {
+            SearchMode::Exact => Self::Exact,
+            SearchMode::Hnsw => Self::Hnsw,
+        }
+    }
+}
+
+#[cfg(feature = "index")]
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SearchRequest {
+    #[schema(example = "What is Deep Learning?")]
+    pub query: String,
+    #[serde(default = "default_top_k")]
+    #[schema(default = "10", example = "10")]
+    pub top_k: usize,
+    #[serde(default)]
+    #[schema(default = "exact", example = "exact")]
+    pub mode: SearchMode,
+    /// Size of the candidate list of `hnsw` searches. Higher values are slower but more
+    /// accurate.
+    #[serde(default)]
+    #[schema(default = "64", example = "null", nullable = true)]
+    pub ef_search: Option<usize>,
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    #[schema(default = "right", example = "right")]
+    pub truncation_direction: TruncationDirection,
+    /// The name of the prompt that should be used by for encoding. If not set, no prompt
+    /// will be applied.
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+    #[serde(default)]
+    #[schema(default = "normal", example = "normal")]
+    pub priority: Priority,
+}
+
+#[cfg(feature = "index")]
+fn default_top_k() -> usize {
+    10
+}
+
+#[cfg(feature = "index")]
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SearchHit {
+    #[schema(example = "faq-1")]
+    pub id: String,
+    #[schema(example = "0.87")]
+    pub score: f32,
+    #[schema(value_type = Object, example = json!({"url": "https://example.com"}))]
+    pub metadata: serde_json::Value,
+}
+
+#[cfg(feature = "index")]
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SearchResponse(pub Vec<SearchHit>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// Parameters of a job, given in the query string of `POST /jobs`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct JobParameters {
+    /// JSONL file to embed, in the `--jobs-input-dir` directory of the server. Relative paths
+    /// start from this directory. If not set, the lines are read from the request body.
+    #[serde(default)]
+    #[schema(default = "null", example = "corpus.jsonl", nullable = true)]
+    pub path: Option<String>,
+    #[serde(default)]
+    #[schema(default = "false", example = "false", nullable = true)]
+    pub truncate: Option<bool>,
+    #[serde(default)]
+    #[schema(default = "Right", example = "Right")]
+    pub truncation_direction: TruncationDirection,
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub prompt_name: Option<String>,
+    #[serde(default = "default_normalize")]
+    #[schema(default = "true", example = "true")]
+    pub normalize: bool,
+    #[serde(default)]
+    #[schema(default = "null", example = "null", nullable = true)]
+    pub dimensions: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub(crate) struct Job {
+    #[schema(example = "5f1c0b8e4d2a4c6f9e3b7a1d0c8e2f4a")]
+    pub id: String,
+    pub status: JobStatus,
+    /// Submission time, in seconds since the Unix epoch
+    #[schema(example = "1718000000")]
+    pub created_at: u64,
+    /// End time, in seconds since the Unix epoch
+    #[schema(example = "null", nullable = true)]
+    pub finished_at: Option<u64>,
+    pub parameters: JobParameters,
+    /// Number of lines to embed. Set when the job starts.
+    #[schema(example = "1000000", nullable = true)]
+    pub total: Option<usize>,
+    /// Number of embedded lines
+    #[schema(example = "250000")]
+    pub completed: usize,
+    /// Number of lines that could not be embedded
+    #[schema(example = "0")]
+    pub failed: usize,
+    /// Reason of a `failed` job
+    #[schema(example = "null", nullable = true)]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct JobsResponse(pub Vec<Job>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobResultsFormat {
+    /// One JSON line per input line, with the same fields as the `/embed/stream` responses
+    #[default]
+    Jsonl,
+    /// `float32` array of shape `[lines, dimensions]`. The rows of failed lines are `NaN`.
+    Npy,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct JobResultsQuery {
+    #[serde(default)]
+    pub format: JobResultsFormat,
+}
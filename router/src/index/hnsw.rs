@@ -0,0 +1,291 @@
+/// Hierarchical Navigable Small World graph
+///
+/// See: https://arxiv.org/abs/1603.09320
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+
+/// Maximum number of neighbors of a node in the upper layers
+const M: usize = 16;
+/// Maximum number of neighbors of a node in the bottom layer
+const M0: usize = 2 * M;
+/// Size of the candidate list when inserting a node
+const EF_CONSTRUCTION: usize = 128;
+
+/// Node of the graph, ranked by similarity
+#[derive(Debug, Clone, Copy)]
+struct Scored {
+    similarity: f32,
+    node: usize,
+}
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity
+            .total_cmp(&other.similarity)
+            .then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+/// Graph over the vectors of the index. Nodes are the positions of the vectors and the
+/// similarity is the dot product.
+#[derive(Debug, Default)]
+pub(crate) struct Hnsw {
+    /// Neighbors of every node, by layer
+    neighbors: Vec<Vec<Vec<usize>>>,
+    entry_point: Option<usize>,
+    /// State of the level generator
+    seed: u64,
+}
+
+impl Hnsw {
+    /// Link the next node, the vector at position `neighbors.len()` in `vectors`
+    pub fn insert(&mut self, vectors: &[Vec<f32>]) {
+        let node = self.neighbors.len();
+        let level = self.random_level();
+        self.neighbors.push(vec![Vec::new(); level + 1]);
+
+        let Some(entry_point) = self.entry_point else {
+            self.entry_point = Some(node);
+            return;
+        };
+        let query = &vectors[node];
+        let top_level = self.neighbors[entry_point].len() - 1;
+
+        let mut entry_point = Scored {
+            similarity: dot(query, &vectors[entry_point]),
+            node: entry_point,
+        };
+        for layer in (level + 1..=top_level).rev() {
+            entry_point = self.greedy_search(query, entry_point, layer, vectors);
+        }
+
+        for layer in (0..=level.min(top_level)).rev() {
+            let candidates = self.search_layer(query, entry_point, EF_CONSTRUCTION, layer, vectors);
+            let max_neighbors = if layer == 0 { M0 } else { M };
+
+            let selected: Vec<usize> = candidates
+                .iter()
+                .take(max_neighbors)
+                .map(|c| c.node)
+                .collect();
+            for &neighbor in &selected {
+                let links = &mut self.neighbors[neighbor][layer];
+                links.push(node);
+                if links.len() > max_neighbors {
+                    // Keep the closest neighbors
+                    let base = &vectors[neighbor];
+                    links.sort_by(|a, b| {
+                        dot(base, &vectors[*b]).total_cmp(&dot(base, &vectors[*a]))
+                    });
+                    links.truncate(max_neighbors);
+                }
+            }
+            self.neighbors[node][layer] = selected;
+            entry_point = candidates[0];
+        }
+
+        if level > top_level {
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// Approximate `ef` nearest nodes of `query`, most similar first
+    pub fn search(&self, query: &[f32], ef: usize, vectors: &[Vec<f32>]) -> Vec<(usize, f32)> {
+        let Some(entry_point) = self.entry_point else {
+            return Vec::new();
+        };
+        let top_level = self.neighbors[entry_point].len() - 1;
+
+        let mut entry_point = Scored {
+            similarity: dot(query, &vectors[entry_point]),
+            node: entry_point,
+        };
+        for layer in (1..=top_level).rev() {
+            entry_point = self.greedy_search(query, entry_point, layer, vectors);
+        }
+        self.search_layer(query, entry_point, ef, 0, vectors)
+            .into_iter()
+            .map(|c| (c.node, c.similarity))
+            .collect()
+    }
+
+    /// Move to the most similar neighbor until reaching a local maximum
+    fn greedy_search(
+        &self,
+        query: &[f32],
+        mut current: Scored,
+        layer: usize,
+        vectors: &[Vec<f32>],
+    ) -> Scored {
+        loop {
+            let best = self.neighbors[current.node][layer]
+                .iter()
+                .map(|&node| Scored {
+                    similarity: dot(query, &vectors[node]),
+                    node,
+                })
+                .max();
+            match best {
+                Some(best) if best > current => current = best,
+                _ => return current,
+            }
+        }
+    }
+
+    /// Beam search of a layer, most similar first
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_point: Scored,
+        ef: usize,
+        layer: usize,
+        vectors: &[Vec<f32>],
+    ) -> Vec<Scored> {
+        let mut visited = HashSet::from([entry_point.node]);
+        // Max-heap of the nodes to expand
+        let mut candidates = BinaryHeap::from([entry_point]);
+        // Min-heap of the `ef` best nodes
+        let mut results = BinaryHeap::from([std::cmp::Reverse(entry_point)]);
+
+        while let Some(candidate) = candidates.pop() {
+            // Unwrap is safe here: results is never empty
+            let worst = results.peek().unwrap().0;
+            if candidate < worst && results.len() >= ef {
+                break;
+            }
+            for &node in &self.neighbors[candidate.node][layer] {
+                if !visited.insert(node) {
+                    continue;
+                }
+                let scored = Scored {
+                    similarity: dot(query, &vectors[node]),
+                    node,
+                };
+                // Unwrap is safe here: results is never empty
+                if results.len() < ef || scored > results.peek().unwrap().0 {
+                    candidates.push(scored);
+                    results.push(std::cmp::Reverse(scored));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        let mut results: Vec<Scored> = results.into_iter().map(|r| r.0).collect();
+        results.sort_by(|a, b| b.cmp(a));
+        results
+    }
+
+    /// Draw the top layer of a new node from an exponential distribution
+    fn random_level(&mut self) -> usize {
+        // splitmix64
+        self.seed = self.seed.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.seed;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+
+        let uniform = ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        (-uniform.ln() / (M as f64).ln()) as usize
+    }
+}
+
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::index::hnsw::{dot, Hnsw};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Unit vectors with uniform directions
+    pub(crate) fn random_vectors(count: usize, dimensions: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| {
+                let mut vector: Vec<f32> =
+                    (0..dimensions).map(|_| rng.gen_range(-1.0..1.0)).collect();
+                let norm = dot(&vector, &vector).sqrt();
+                vector.iter_mut().for_each(|v| *v /= norm);
+                vector
+            })
+            .collect()
+    }
+
+    fn exact_search(query: &[f32], top_k: usize, vectors: &[Vec<f32>]) -> Vec<usize> {
+        let mut scored: Vec<(usize, f32)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(node, vector)| (node, dot(query, vector)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+            .into_iter()
+            .take(top_k)
+            .map(|(node, _)| node)
+            .collect()
+    }
+
+    #[test]
+    fn test_recall() {
+        let vectors = random_vectors(2000, 16, 0);
+        let mut graph = Hnsw::default();
+        for _ in 0..vectors.len() {
+            graph.insert(&vectors);
+        }
+
+        let top_k = 10;
+        let mut found = 0;
+        for query in random_vectors(100, 16, 1) {
+            let expected = exact_search(&query, top_k, &vectors);
+            let results = graph.search(&query, 64, &vectors);
+
+            // Results are sorted and their similarities are exact
+            assert_eq!(results.len(), 64);
+            assert!(results.windows(2).all(|w| w[0].1 >= w[1].1));
+            for (node, similarity) in &results {
+                assert_eq!(*similarity, dot(&query, &vectors[*node]));
+            }
+
+            found += results
+                .iter()
+                .take(top_k)
+                .filter(|(node, _)| expected.contains(node))
+                .count();
+        }
+        let recall = found as f32 / (100 * top_k) as f32;
+        assert!(recall >= 0.95, "recall@{top_k} is {recall}");
+    }
+
+    #[test]
+    fn test_search_small_graph() {
+        let mut graph = Hnsw::default();
+        assert!(graph.search(&[1.0, 0.0], 10, &[]).is_empty());
+
+        let vectors = random_vectors(5, 2, 2);
+        for _ in 0..vectors.len() {
+            graph.insert(&vectors);
+        }
+        // Every node is returned when `ef` is larger than the graph
+        let results = graph.search(&vectors[3], 10, &vectors);
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].0, 3);
+    }
+}
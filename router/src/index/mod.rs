@@ -0,0 +1,371 @@
+/// In-memory vector index of embedded documents
+mod hnsw;
+
+use crate::IndexMetric;
+use anyhow::{Context, Result};
+use hnsw::{dot, Hnsw};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Search strategy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SearchMode {
+    /// Score every document
+    #[default]
+    Exact,
+    /// Approximate search in the HNSW graph
+    Hnsw,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Document {
+    pub id: String,
+    pub vector: Vec<f32>,
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SearchResult {
+    pub id: String,
+    pub score: f32,
+    pub metadata: serde_json::Value,
+}
+
+/// Content of a snapshot file
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    metric: IndexMetric,
+    documents: Vec<Document>,
+}
+
+/// Documents indexed by id. Deleted and replaced documents stay in the HNSW graph until the
+/// index is compacted.
+#[derive(Debug)]
+pub(crate) struct VectorIndex {
+    metric: IndexMetric,
+    /// Vectors of every node of the graph, normalized for the cosine metric
+    vectors: Vec<Vec<f32>>,
+    /// Live document of every node
+    documents: Vec<Option<(String, serde_json::Value)>>,
+    ids: HashMap<String, usize>,
+    graph: Hnsw,
+}
+
+impl VectorIndex {
+    pub fn new(metric: IndexMetric) -> Self {
+        Self {
+            metric,
+            vectors: Vec::new(),
+            documents: Vec::new(),
+            ids: HashMap::new(),
+            graph: Hnsw::default(),
+        }
+    }
+
+    /// Load the snapshot at `path`, or create an empty index if it does not exist
+    pub fn load(path: &Path, metric: IndexMetric) -> Result<Self> {
+        let mut index = Self::new(metric);
+        if !path.exists() {
+            return Ok(index);
+        }
+
+        let content = std::fs::read(path)
+            .with_context(|| format!("Failed to read index snapshot `{}`", path.display()))?;
+        let snapshot: Snapshot = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse index snapshot `{}`", path.display()))?;
+        if snapshot.metric != metric {
+            anyhow::bail!(
+                "index snapshot `{}` uses the `{:?}` metric but `index-metric` is `{metric:?}`",
+                path.display(),
+                snapshot.metric
+            );
+        }
+
+        for document in snapshot.documents {
+            index
+                .upsert(document.id, document.vector, document.metadata)
+                .map_err(anyhow::Error::msg)?;
+        }
+        tracing::info!("Loaded {} documents from {}", index.len(), path.display());
+        Ok(index)
+    }
+
+    /// Write the live documents to `path`
+    pub fn snapshot(&self, path: &Path) -> Result<()> {
+        let documents = self
+            .documents
+            .iter()
+            .zip(&self.vectors)
+            .filter_map(|(document, vector)| {
+                document.as_ref().map(|(id, metadata)| Document {
+                    id: id.clone(),
+                    vector: vector.clone(),
+                    metadata: metadata.clone(),
+                })
+            })
+            .collect();
+        let snapshot = Snapshot {
+            metric: self.metric,
+            documents,
+        };
+
+        // Write to a temporary file first to never leave a truncated snapshot behind
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&snapshot)?)
+            .with_context(|| format!("Failed to write `{}`", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to write index snapshot `{}`", path.display()))?;
+        Ok(())
+    }
+
+    pub fn metric(&self) -> IndexMetric {
+        self.metric
+    }
+
+    /// Number of live documents
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Insert a document or replace the document with the same id
+    pub fn upsert(
+        &mut self,
+        id: String,
+        mut vector: Vec<f32>,
+        metadata: serde_json::Value,
+    ) -> Result<(), String> {
+        self.check_dimensions(&vector)?;
+        if self.metric == IndexMetric::Cosine {
+            normalize(&mut vector);
+        }
+
+        self.delete(&id);
+        let node = self.vectors.len();
+        self.vectors.push(vector);
+        self.documents.push(Some((id.clone(), metadata)));
+        self.ids.insert(id, node);
+        self.graph.insert(&self.vectors);
+        Ok(())
+    }
+
+    /// Remove a document. Returns false if the id is not indexed.
+    pub fn delete(&mut self, id: &str) -> bool {
+        let Some(node) = self.ids.remove(id) else {
+            return false;
+        };
+        self.documents[node] = None;
+
+        // Rebuild the graph once most of its nodes are stale
+        if self.documents.len() > 2 * self.ids.len() + 1024 {
+            self.compact();
+        }
+        true
+    }
+
+    /// `top_k` most similar documents to `query`, most similar first
+    pub fn search(
+        &self,
+        mut query: Vec<f32>,
+        top_k: usize,
+        mode: SearchMode,
+        ef_search: Option<usize>,
+    ) -> Result<Vec<SearchResult>, String> {
+        if self.vectors.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.check_dimensions(&query)?;
+        if self.metric == IndexMetric::Cosine {
+            normalize(&mut query);
+        }
+
+        let mut scored: Vec<(usize, f32)> = match mode {
+            SearchMode::Exact => self
+                .ids
+                .values()
+                .map(|&node| (node, dot(&query, &self.vectors[node])))
+                .collect(),
+            SearchMode::Hnsw => {
+                // Stale nodes are skipped, so more candidates than `top_k` are needed
+                let stale = self.documents.len() - self.ids.len();
+                let ef = ef_search.unwrap_or(64).max(top_k) + stale.min(top_k);
+                self.graph.search(&query, ef, &self.vectors)
+            }
+        };
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Ok(scored
+            .into_iter()
+            .filter_map(|(node, score)| {
+                self.documents[node]
+                    .as_ref()
+                    .map(|(id, metadata)| SearchResult {
+                        id: id.clone(),
+                        score,
+                        metadata: metadata.clone(),
+                    })
+            })
+            .take(top_k)
+            .collect())
+    }
+
+    fn check_dimensions(&self, vector: &[f32]) -> Result<(), String> {
+        match self.vectors.first() {
+            Some(first) if first.len() != vector.len() => Err(format!(
+                "vector has {} dimensions but the index has {}",
+                vector.len(),
+                first.len()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Rebuild the graph from the live documents
+    fn compact(&mut self) {
+        let vectors = std::mem::take(&mut self.vectors);
+        let documents = std::mem::take(&mut self.documents);
+        self.ids.clear();
+        self.graph = Hnsw::default();
+
+        for (vector, document) in vectors.into_iter().zip(documents) {
+            if let Some((id, metadata)) = document {
+                let node = self.vectors.len();
+                self.vectors.push(vector);
+                self.documents.push(Some((id.clone(), metadata)));
+                self.ids.insert(id, node);
+                self.graph.insert(&self.vectors);
+            }
+        }
+    }
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = dot(vector, vector).sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::hnsw::tests::random_vectors;
+    use crate::index::{SearchMode, VectorIndex};
+    use crate::IndexMetric;
+
+    fn index(count: usize) -> VectorIndex {
+        let mut index = VectorIndex::new(IndexMetric::Cosine);
+        for (i, vector) in random_vectors(count, 8, 0).into_iter().enumerate() {
+            index
+                .upsert(format!("doc-{i}"), vector, serde_json::json!({ "i": i }))
+                .unwrap();
+        }
+        index
+    }
+
+    fn ids(index: &VectorIndex, query: &[f32], top_k: usize, mode: SearchMode) -> Vec<String> {
+        index
+            .search(query.to_vec(), top_k, mode, None)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_delete_then_search() {
+        let mut index = index(500);
+        let query = random_vectors(1, 8, 1).remove(0);
+
+        // Delete the documents closest to the query
+        let closest = ids(&index, &query, 10, SearchMode::Exact);
+        for id in &closest {
+            assert!(index.delete(id));
+        }
+        assert!(!index.delete(&closest[0]));
+        assert_eq!(index.len(), 490);
+
+        let exact = ids(&index, &query, 5, SearchMode::Exact);
+        let approximate = ids(&index, &query, 5, SearchMode::Hnsw);
+        assert_eq!(exact.len(), 5);
+        assert_eq!(approximate, exact);
+        assert!(exact.iter().all(|id| !closest.contains(id)));
+    }
+
+    #[test]
+    fn test_upsert_replaces() {
+        let mut index = index(10);
+        index
+            .upsert("doc-3".to_string(), vec![1.0; 8], serde_json::json!("new"))
+            .unwrap();
+        assert_eq!(index.len(), 10);
+
+        for mode in [SearchMode::Exact, SearchMode::Hnsw] {
+            let results = index.search(vec![2.0; 8], 2, mode, None).unwrap();
+            assert_eq!(results[0].id, "doc-3");
+            assert_eq!(results[0].metadata, serde_json::json!("new"));
+            assert!((results[0].score - 1.0).abs() < 1e-6);
+            assert_ne!(results[1].id, "doc-3");
+        }
+
+        let err = index
+            .upsert("doc-3".to_string(), vec![1.0; 4], serde_json::Value::Null)
+            .unwrap_err();
+        assert_eq!(err, "vector has 4 dimensions but the index has 8");
+    }
+
+    #[test]
+    fn test_compaction() {
+        let mut index = index(1100);
+        for i in 0..1100 {
+            if i % 100 != 0 {
+                index.delete(&format!("doc-{i}"));
+            }
+        }
+        // The graph was rebuilt from the live documents
+        assert!(index.vectors.len() < 1100);
+        assert_eq!(index.len(), 11);
+
+        let query = random_vectors(1, 8, 1).remove(0);
+        let exact = ids(&index, &query, 11, SearchMode::Exact);
+        assert_eq!(ids(&index, &query, 11, SearchMode::Hnsw), exact);
+        assert!(exact.iter().all(|id| id.ends_with("00") || id == "doc-0"));
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.json");
+
+        // A missing snapshot is an empty index
+        let empty = VectorIndex::load(&path, IndexMetric::Cosine).unwrap();
+        assert_eq!(empty.len(), 0);
+
+        let mut index = index(200);
+        index.delete("doc-7");
+        index.snapshot(&path).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let loaded = VectorIndex::load(&path, IndexMetric::Cosine).unwrap();
+        assert_eq!(loaded.len(), 199);
+        for query in random_vectors(10, 8, 1) {
+            let expected = index
+                .search(query.clone(), 5, SearchMode::Exact, None)
+                .unwrap();
+            let results = loaded.search(query, 5, SearchMode::Hnsw, None).unwrap();
+            assert_eq!(results.len(), 5);
+            for (result, expected) in results.iter().zip(&expected) {
+                assert_eq!(result.id, expected.id);
+                assert_eq!(result.score, expected.score);
+                assert_eq!(result.metadata, expected.metadata);
+            }
+        }
+        let deleted = loaded
+            .search(index.vectors[7].clone(), 1, SearchMode::Exact, None)
+            .unwrap();
+        assert_ne!(deleted[0].id, "doc-7");
+
+        let err = VectorIndex::load(&path, IndexMetric::Dot).unwrap_err();
+        assert!(err.to_string().contains("uses the `Cosine` metric"));
+    }
+}
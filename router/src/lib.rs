@@ -1,19 +1,18 @@
 /// Text Embedding Inference Webserver
+pub mod batch;
 mod logging;
+#[cfg(feature = "index")]
+mod index;
+mod npy;
 mod prometheus;
+mod quantization;
 
 #[cfg(feature = "http")]
 mod http;
 
-#[cfg(feature = "http")]
-use ::http::HeaderMap;
-
 #[cfg(feature = "grpc")]
 mod grpc;
 
-#[cfg(feature = "grpc")]
-use tonic::codegen::http::HeaderMap;
-
 mod shutdown;
 
 use anyhow::{anyhow, Context, Result};
@@ -21,15 +20,18 @@ use hf_hub::api::tokio::ApiBuilder;
 use hf_hub::{Repo, RepoType};
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::path::Path;
-use std::time::{Duration, Instant};
-use text_embeddings_backend::{DType, Pool};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use text_embeddings_backend::{DType, OrtSessionConfig, Pool};
+use text_embeddings_core::cache::EmbeddingCache;
 use text_embeddings_core::download::{download_artifacts, ST_CONFIG_NAMES};
-use text_embeddings_core::infer::Infer;
-use text_embeddings_core::queue::Queue;
+use text_embeddings_core::infer::{BatchWait, Infer};
+use text_embeddings_core::queue::{Priority, Queue};
 use text_embeddings_core::tokenization::Tokenization;
 use text_embeddings_core::TextEmbeddingsError;
 use tokenizers::processors::sequence::Sequence;
@@ -38,25 +40,41 @@ use tokenizers::{PostProcessorWrapper, Tokenizer};
 use tracing::Span;
 
 pub use logging::init_logging;
+use quantization::Calibration;
 
 /// Create entrypoint
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
     model_id: String,
     revision: Option<String>,
+    additional_models: Vec<(String, String, Option<String>)>,
     tokenization_workers: Option<usize>,
     dtype: Option<DType>,
     pooling: Option<text_embeddings_backend::Pool>,
+    ort_session_config: OrtSessionConfig,
     max_concurrent_requests: usize,
     max_batch_tokens: usize,
     max_batch_requests: Option<usize>,
     max_client_batch_size: usize,
+    max_chunks: usize,
     auto_truncate: bool,
+    embedding_cache_size: usize,
+    embedding_cache_dir: Option<String>,
+    priority_aging_ms: u64,
+    reserved_concurrent_requests: Vec<(Priority, usize)>,
+    length_bucketing_window: usize,
+    max_batch_wait_ms: u64,
+    min_batch_tokens: Option<usize>,
+    min_batch_requests: Option<usize>,
+    adaptive_batch_wait: bool,
     default_prompt: Option<String>,
     default_prompt_name: Option<String>,
+    default_dimensions: Option<usize>,
+    quantization_calibration: Option<String>,
     hf_api_token: Option<String>,
     hostname: Option<String>,
     port: u16,
+    grpc_port: Option<u16>,
     uds_path: Option<String>,
     huggingface_hub_cache: Option<String>,
     payload_limit: usize,
@@ -64,7 +82,370 @@ pub async fn run(
     otlp_endpoint: Option<String>,
     otlp_service_name: String,
     cors_allow_origin: Option<Vec<String>>,
+    index_path: Option<String>,
+    index_metric: IndexMetric,
+    jobs_dir: Option<String>,
+    jobs_input_dir: Option<String>,
 ) -> Result<()> {
+    let reserved_concurrent_requests: HashMap<Priority, usize> =
+        reserved_concurrent_requests.into_iter().collect();
+    let total_reserved: usize = reserved_concurrent_requests.values().sum();
+    if total_reserved >= max_concurrent_requests {
+        anyhow::bail!("`reserved-concurrent-requests` ({total_reserved}) must be lower than `max-concurrent-requests` ({max_concurrent_requests})");
+    }
+    // The windows of an input are only taken from the capacity that is not reserved
+    let shared_concurrent_requests = max_concurrent_requests - total_reserved;
+    if max_chunks == 0 || max_chunks > shared_concurrent_requests {
+        anyhow::bail!("`max-chunks` ({max_chunks}) must be between 1 and the {shared_concurrent_requests} concurrent requests that are not reserved");
+    }
+    let min_batch_tokens = min_batch_tokens.unwrap_or(max_batch_tokens);
+    if min_batch_tokens > max_batch_tokens {
+        anyhow::bail!("`min-batch-tokens` ({min_batch_tokens}) must be lower than or equal to `max-batch-tokens` ({max_batch_tokens})");
+    }
+    let quantization_calibration = quantization_calibration
+        .map(|path| Calibration::load(Path::new(&path)).map(Arc::new))
+        .transpose()?;
+
+    if ort_session_config.max_batch_size == 0 {
+        anyhow::bail!("`ort-max-batch-size` must be greater than 0");
+    }
+
+    let settings = ModelSettings {
+        tokenization_workers,
+        dtype,
+        pooling,
+        ort_session_config,
+        max_concurrent_requests,
+        max_batch_tokens,
+        max_batch_requests,
+        max_client_batch_size,
+        max_chunks,
+        auto_truncate,
+        embedding_cache_size,
+        embedding_cache_dir,
+        priority_aging_ms,
+        reserved_concurrent_requests,
+        length_bucketing_window,
+        batch_wait: BatchWait {
+            max_wait: Duration::from_millis(max_batch_wait_ms),
+            min_batch_tokens,
+            min_batch_requests,
+            adaptive: adaptive_batch_wait,
+        },
+        default_prompt,
+        default_prompt_name,
+        default_dimensions,
+        quantization_calibration,
+        hf_api_token,
+        uds_path: uds_path.unwrap_or("/tmp/text-embeddings-inference-server".to_string()),
+        huggingface_hub_cache,
+        otlp_endpoint,
+        otlp_service_name,
+    };
+
+    // The primary model serves the requests that do not name a model
+    let mut models = Vec::with_capacity(additional_models.len() + 1);
+    let (infer, info) = load_model(model_id.clone(), revision, settings.clone()).await?;
+    models.push((model_id, infer, info));
+
+    for (i, (name, model_id, revision)) in additional_models.into_iter().enumerate() {
+        if models.iter().any(|(n, _, _)| n == &name) {
+            anyhow::bail!("model name `{name}` is used by more than one model");
+        }
+        tracing::info!("Loading additional model `{name}`: {model_id}");
+
+        // Pooling, prompts, dimensions and calibration are specific to the primary model
+        let optimized_model_path = settings
+            .ort_session_config
+            .optimized_model_path
+            .as_deref()
+            .map(|path| suffixed_path(path, i + 1));
+        let settings = ModelSettings {
+            pooling: None,
+            default_prompt: None,
+            default_prompt_name: None,
+            default_dimensions: None,
+            quantization_calibration: None,
+            uds_path: format!("{}-{}", settings.uds_path, i + 1),
+            ort_session_config: OrtSessionConfig {
+                optimized_model_path,
+                ..settings.ort_session_config.clone()
+            },
+            ..settings.clone()
+        };
+        let (infer, info) = load_model(model_id, revision, settings).await?;
+        models.push((name, infer, info));
+    }
+
+    // Every model lists all the served models
+    let served_models: Vec<ServedModel> = models
+        .iter()
+        .map(|(name, _, info)| ServedModel {
+            name: name.clone(),
+            model_id: info.model_id.clone(),
+            model_type: info.model_type.clone(),
+        })
+        .collect();
+    for (_, _, info) in models.iter_mut() {
+        info.models = served_models.clone();
+    }
+
+    // use AIP_HTTP_PORT if google feature is enabled
+    let port = if cfg!(feature = "google") {
+        std::env::var("AIP_HTTP_PORT")
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .map(|p| {
+                tracing::info!("`AIP_HTTP_PORT` is set: overriding port {port} by port {p}");
+                p
+            })
+            .unwrap_or(port)
+    } else {
+        port
+    };
+
+    let ip = match hostname.unwrap_or("0.0.0.0".to_string()).parse() {
+        Ok(ip) => ip,
+        Err(_) => {
+            tracing::warn!("Invalid hostname, defaulting to 0.0.0.0");
+            IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))
+        }
+    };
+
+    let max_input_length = models
+        .iter()
+        .map(|(_, _, info)| info.max_input_length)
+        .max()
+        .unwrap_or_default();
+    let prom_builder = prometheus::prometheus_builer(max_input_length)?;
+    let prom_handle = prometheus::install(prom_builder)?;
+
+    #[cfg(all(feature = "grpc", feature = "google"))]
+    compile_error!("Features `grpc` and `google` cannot be enabled at the same time.");
+
+    #[cfg(not(any(feature = "http", feature = "grpc")))]
+    compile_error!("Either feature `http` or `grpc` must be enabled.");
+
+    let shutdown = shutdown::Shutdown::new();
+
+    #[cfg(all(feature = "http", feature = "grpc"))]
+    {
+        // Both APIs share the models and their queues
+        let grpc_port = match grpc_port {
+            Some(grpc_port) => grpc_port,
+            None => port
+                .checked_add(1)
+                .context("`grpc-port` must be set when `port` is 65535")?,
+        };
+        if grpc_port == port {
+            anyhow::bail!("`grpc-port` must be different from `port` ({port})");
+        }
+
+        // The exit of one server shuts down the other one
+        let http = {
+            let shutdown = shutdown.clone();
+            let models = models.clone();
+            let api_key = api_key.clone();
+            async move {
+                let result = http::server::run(
+                    models,
+                    SocketAddr::new(ip, port),
+                    prom_handle,
+                    payload_limit,
+                    api_key,
+                    cors_allow_origin,
+                    index_path,
+                    index_metric,
+                    jobs_dir,
+                    jobs_input_dir,
+                    shutdown.wait(),
+                )
+                .await;
+                shutdown.trigger();
+                result
+            }
+        };
+        let grpc = async move {
+            let result =
+                grpc::server::run(models, SocketAddr::new(ip, grpc_port), api_key, shutdown.wait())
+                    .await;
+            shutdown.trigger();
+            result
+        };
+
+        let (http, grpc) = tokio::join!(http, grpc);
+        http.and(grpc)
+    }
+
+    #[cfg(all(feature = "http", not(feature = "grpc")))]
+    {
+        if grpc_port.is_some() {
+            tracing::warn!("`grpc-port` is ignored: the router is built without the gRPC API");
+        }
+        http::server::run(
+            models,
+            SocketAddr::new(ip, port),
+            prom_handle,
+            payload_limit,
+            api_key,
+            cors_allow_origin,
+            index_path,
+            index_metric,
+            jobs_dir,
+            jobs_input_dir,
+            shutdown.wait(),
+        )
+        .await
+    }
+
+    #[cfg(all(feature = "grpc", not(feature = "http")))]
+    {
+        // cors_allow_origin, payload_limit, the index and the jobs are not used for gRPC servers
+        let _ = cors_allow_origin;
+        let _ = payload_limit;
+        let _ = prom_handle;
+        if index_path.is_some() {
+            tracing::warn!("`index-path` is ignored: the vector index is only served over HTTP");
+        }
+        let _ = index_metric;
+        if jobs_dir.is_some() {
+            tracing::warn!("`jobs-dir` is ignored: jobs are only served over HTTP");
+        }
+        let _ = jobs_input_dir;
+        let port = grpc_port.unwrap_or(port);
+        grpc::server::run(models, SocketAddr::new(ip, port), api_key, shutdown.wait()).await
+    }
+}
+
+/// Offline entrypoint: load the model without serving it and embed the rows of a file
+#[allow(clippy::too_many_arguments)]
+pub async fn run_batch(
+    model_id: String,
+    revision: Option<String>,
+    tokenization_workers: Option<usize>,
+    dtype: Option<DType>,
+    pooling: Option<text_embeddings_backend::Pool>,
+    ort_session_config: OrtSessionConfig,
+    max_concurrent_requests: usize,
+    max_batch_tokens: usize,
+    max_batch_requests: Option<usize>,
+    auto_truncate: bool,
+    default_prompt: Option<String>,
+    default_prompt_name: Option<String>,
+    hf_api_token: Option<String>,
+    uds_path: Option<String>,
+    huggingface_hub_cache: Option<String>,
+    options: batch::BatchOptions,
+) -> Result<()> {
+    if ort_session_config.max_batch_size == 0 {
+        anyhow::bail!("`ort-max-batch-size` must be greater than 0");
+    }
+
+    let settings = ModelSettings {
+        tokenization_workers,
+        dtype,
+        pooling,
+        ort_session_config,
+        max_concurrent_requests,
+        max_batch_tokens,
+        max_batch_requests,
+        max_client_batch_size: max_concurrent_requests,
+        max_chunks: max_concurrent_requests,
+        auto_truncate,
+        embedding_cache_size: 0,
+        embedding_cache_dir: None,
+        priority_aging_ms: 0,
+        reserved_concurrent_requests: HashMap::new(),
+        length_bucketing_window: 0,
+        batch_wait: BatchWait {
+            max_wait: Duration::ZERO,
+            min_batch_tokens: max_batch_tokens,
+            min_batch_requests: None,
+            adaptive: false,
+        },
+        default_prompt,
+        default_prompt_name,
+        // `options.dimensions` is validated against the model by the batch runner
+        default_dimensions: None,
+        quantization_calibration: None,
+        hf_api_token,
+        uds_path: uds_path.unwrap_or("/tmp/text-embeddings-inference-batch".to_string()),
+        huggingface_hub_cache,
+        otlp_endpoint: None,
+        otlp_service_name: "text-embeddings-inference.batch".to_string(),
+    };
+
+    let (infer, info) = load_model(model_id, revision, settings).await?;
+    if !matches!(info.model_type, ModelType::Embedding(_)) {
+        anyhow::bail!("only embedding models can be used to embed files");
+    }
+    batch::run(infer, info, options).await
+}
+
+/// Settings shared by all the served models
+#[derive(Debug, Clone)]
+struct ModelSettings {
+    tokenization_workers: Option<usize>,
+    dtype: Option<DType>,
+    pooling: Option<text_embeddings_backend::Pool>,
+    ort_session_config: OrtSessionConfig,
+    max_concurrent_requests: usize,
+    max_batch_tokens: usize,
+    max_batch_requests: Option<usize>,
+    max_client_batch_size: usize,
+    max_chunks: usize,
+    auto_truncate: bool,
+    embedding_cache_size: usize,
+    embedding_cache_dir: Option<String>,
+    priority_aging_ms: u64,
+    reserved_concurrent_requests: HashMap<Priority, usize>,
+    length_bucketing_window: usize,
+    batch_wait: BatchWait,
+    default_prompt: Option<String>,
+    default_prompt_name: Option<String>,
+    default_dimensions: Option<usize>,
+    quantization_calibration: Option<Arc<Calibration>>,
+    hf_api_token: Option<String>,
+    uds_path: String,
+    huggingface_hub_cache: Option<String>,
+    otlp_endpoint: Option<String>,
+    otlp_service_name: String,
+}
+
+/// Download a model and start its backend, queue and batching tasks
+async fn load_model(
+    model_id: String,
+    revision: Option<String>,
+    settings: ModelSettings,
+) -> Result<(Infer, Info)> {
+    let ModelSettings {
+        tokenization_workers,
+        dtype,
+        pooling,
+        ort_session_config,
+        max_concurrent_requests,
+        max_batch_tokens,
+        max_batch_requests,
+        max_client_batch_size,
+        max_chunks,
+        auto_truncate,
+        embedding_cache_size,
+        embedding_cache_dir,
+        priority_aging_ms,
+        reserved_concurrent_requests,
+        length_bucketing_window,
+        batch_wait,
+        default_prompt,
+        default_prompt_name,
+        default_dimensions,
+        quantization_calibration,
+        hf_api_token,
+        uds_path,
+        huggingface_hub_cache,
+        otlp_endpoint,
+        otlp_service_name,
+    } = settings;
+
     let model_id_path = Path::new(&model_id);
     let (model_root, api_repo) = if model_id_path.exists() && model_id_path.is_dir() {
         // Using a local model
@@ -103,6 +484,16 @@ pub async fn run(
     // Set model type from config
     let backend_model_type = get_backend_model_type(&config, &model_root, pooling)?;
 
+    // Sentence Transformers `Dense` modules change the size of the embeddings
+    let hidden_size = match &backend_model_type {
+        text_embeddings_backend::ModelType::Embedding(_) => {
+            let st_modules = text_embeddings_backend::load_st_modules(&model_root)
+                .context("Failed to load the Sentence Transformers modules")?;
+            text_embeddings_backend::st_modules_dimensions(&st_modules).or(config.hidden_size)
+        }
+        text_embeddings_backend::ModelType::Classifier => config.hidden_size,
+    };
+
     // Info model type
     let model_type = match &backend_model_type {
         text_embeddings_backend::ModelType::Classifier => {
@@ -199,6 +590,7 @@ pub async fn run(
         );
     }
     let prompts = new_st_config.and_then(|c| c.prompts);
+    let (query_prompt_name, document_prompt_name) = rerank_prompt_names(prompts.as_ref());
     let default_prompt = if let Some(default_prompt_name) = default_prompt_name.as_ref() {
         match &prompts {
             None => {
@@ -213,6 +605,51 @@ pub async fn run(
         default_prompt
     };
 
+    // Get dtype
+    let dtype = dtype.unwrap_or_default();
+
+    match (default_dimensions, hidden_size) {
+        (Some(0), _) => anyhow::bail!("`default-dimensions` must be greater than 0"),
+        (Some(dimensions), Some(hidden_size)) if dimensions > hidden_size => {
+            anyhow::bail!("`default-dimensions` ({dimensions}) must be lower than or equal to the model hidden size ({hidden_size})")
+        }
+        _ => {}
+    }
+    if let (Some(calibration), Some(hidden_size)) = (&quantization_calibration, hidden_size)
+    {
+        if calibration.dimensions() < hidden_size {
+            anyhow::bail!("`quantization-calibration` has {} dimensions but the model hidden size is {hidden_size}", calibration.dimensions())
+        }
+    }
+
+    // Embedding cache
+    let embedding_cache = if embedding_cache_size > 0 || embedding_cache_dir.is_some() {
+        // Hub snapshots are stored in a directory named after the commit sha
+        let model_sha = match api_repo {
+            Some(_) => model_root
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string()),
+            None => None,
+        };
+        // Everything that changes the embeddings of a given input must be part of the namespace.
+        // Local models have no sha and can change in place, so their files are part of it too.
+        let files_digest = model_files_digest(&model_root)?;
+        let namespace = format!(
+            "{model_id}@{}#{files_digest}/{dtype}/{backend_model_type:?}/{default_prompt:?}/{max_input_length}",
+            model_sha.as_deref().unwrap_or("local")
+        );
+        Some(
+            EmbeddingCache::new(
+                namespace,
+                embedding_cache_size,
+                embedding_cache_dir.map(PathBuf::from),
+            )
+            .context("Could not create embedding cache")?,
+        )
+    } else {
+        None
+    };
+
     // Tokenization logic
     let tokenization = Tokenization::new(
         tokenization_workers,
@@ -223,9 +660,6 @@ pub async fn run(
         prompts,
     );
 
-    // Get dtype
-    let dtype = dtype.unwrap_or_default();
-
     // Create backend
     tracing::info!("Starting model backend");
     let backend = text_embeddings_backend::Backend::new(
@@ -233,9 +667,10 @@ pub async fn run(
         api_repo,
         dtype.clone(),
         backend_model_type,
-        uds_path.unwrap_or("/tmp/text-embeddings-inference-server".to_string()),
-        otlp_endpoint.clone(),
-        otlp_service_name.clone(),
+        ort_session_config,
+        uds_path,
+        otlp_endpoint,
+        otlp_service_name,
     )
     .await
     .context("Could not create backend")?;
@@ -252,6 +687,7 @@ pub async fn run(
             .context("Model backend is not healthy")?;
     }
 
+    let onnx_runtime = backend.ort_session_config.as_ref().map(OnnxRuntimeInfo::from);
     let max_batch_requests = backend
         .max_batch_size
         .map(|s| {
@@ -267,10 +703,24 @@ pub async fn run(
         max_batch_tokens,
         max_batch_requests,
         max_concurrent_requests,
+        Duration::from_millis(priority_aging_ms),
+        length_bucketing_window,
     );
 
     // Create infer task
-    let infer = Infer::new(tokenization, queue, max_concurrent_requests, backend);
+    let infer = Infer::new(
+        tokenization,
+        queue,
+        max_concurrent_requests,
+        reserved_concurrent_requests,
+        max_chunks,
+        backend,
+        embedding_cache,
+        BatchWait {
+            min_batch_requests: batch_wait.min_batch_requests.or(max_batch_requests),
+            ..batch_wait
+        },
+    );
 
     // Endpoint info
     let info = Info {
@@ -285,65 +735,105 @@ pub async fn run(
         max_batch_requests,
         max_client_batch_size,
         auto_truncate,
+        hidden_size,
+        default_dimensions,
+        quantization_calibration,
+        query_prompt_name,
+        document_prompt_name,
+        onnx_runtime,
+        models: vec![],
         version: env!("CARGO_PKG_VERSION"),
         sha: option_env!("VERGEN_GIT_SHA"),
         docker_label: option_env!("DOCKER_LABEL"),
     };
 
-    // use AIP_HTTP_PORT if google feature is enabled
-    let port = if cfg!(feature = "google") {
-        std::env::var("AIP_HTTP_PORT")
-            .ok()
-            .and_then(|p| p.parse().ok())
-            .map(|p| {
-                tracing::info!("`AIP_HTTP_PORT` is set: overriding port {port} by port {p}");
-                p
-            })
-            .unwrap_or(port)
-    } else {
-        port
-    };
+    Ok((infer, info))
+}
 
-    let addr = match hostname.unwrap_or("0.0.0.0".to_string()).parse() {
-        Ok(ip) => SocketAddr::new(ip, port),
-        Err(_) => {
-            tracing::warn!("Invalid hostname, defaulting to 0.0.0.0");
-            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port)
+/// Add `-<suffix>` to the file stem of `path`
+/// Digest of the files of a model that change its embeddings. The configurations, including
+/// the configurations of the Sentence Transformers modules and `onnx_io.json`, are hashed with
+/// their content. The weights are hashed with their name, size and modification time.
+fn model_files_digest(model_root: &Path) -> Result<String> {
+    let mut directories = vec![model_root.to_path_buf(), model_root.join("onnx")];
+    if let Ok(modules) = fs::read_to_string(model_root.join("modules.json")) {
+        let modules: Vec<serde_json::Value> =
+            serde_json::from_str(&modules).context("Failed to parse `modules.json`")?;
+        for module in modules {
+            match module.get("path").and_then(|path| path.as_str()) {
+                Some(path) if !path.is_empty() => directories.push(model_root.join(path)),
+                _ => {}
+            }
         }
-    };
-
-    let prom_builder = prometheus::prometheus_builer(info.max_input_length)?;
-
-    #[cfg(all(feature = "grpc", feature = "http"))]
-    compile_error!("Features `http` and `grpc` cannot be enabled at the same time.");
-
-    #[cfg(all(feature = "grpc", feature = "google"))]
-    compile_error!("Features `http` and `google` cannot be enabled at the same time.");
+    }
 
-    #[cfg(not(any(feature = "http", feature = "grpc")))]
-    compile_error!("Either feature `http` or `grpc` must be enabled.");
+    let mut hasher = Sha256::new();
+    let mut update_bytes = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    for directory in directories {
+        let Ok(entries) = fs::read_dir(&directory) else {
+            continue;
+        };
+        let mut paths = entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
 
-    #[cfg(feature = "http")]
-    {
-        http::server::run(
-            infer,
-            info,
-            addr,
-            prom_builder,
-            payload_limit,
-            api_key,
-            cors_allow_origin,
-        )
-        .await
+        for path in paths.into_iter().filter(|path| path.is_file()) {
+            let name = path.strip_prefix(model_root).unwrap_or(&path);
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            match extension {
+                Some("json") => {
+                    update_bytes(name.to_string_lossy().as_bytes());
+                    update_bytes(&fs::read(&path)?);
+                }
+                Some("safetensors" | "bin" | "pt" | "pth" | "onnx" | "onnx_data" | "data") => {
+                    let metadata = fs::metadata(&path)?;
+                    let modified = metadata
+                        .modified()
+                        .ok()
+                        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                        .unwrap_or_default();
+                    update_bytes(name.to_string_lossy().as_bytes());
+                    update_bytes(&metadata.len().to_le_bytes());
+                    update_bytes(&modified.as_nanos().to_le_bytes());
+                }
+                _ => {}
+            }
+        }
     }
+    Ok(format!("{:x}", hasher.finalize())[..16].to_string())
+}
 
-    #[cfg(feature = "grpc")]
-    {
-        // cors_allow_origin and payload_limit are not used for gRPC servers
-        let _ = cors_allow_origin;
-        let _ = payload_limit;
-        grpc::server::run(infer, info, addr, prom_builder, api_key).await
-    }
+fn suffixed_path(path: &Path, suffix: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}-{suffix}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{suffix}"),
+    };
+    path.with_file_name(file_name)
+}
+
+/// Names of the Sentence Transformers prompts used to embed the queries and the texts of
+/// re-ranking requests
+fn rerank_prompt_names(
+    prompts: Option<&HashMap<String, String>>,
+) -> (Option<String>, Option<String>) {
+    let Some(prompts) = prompts else {
+        return (None, None);
+    };
+    let find = |names: &[&str]| {
+        names
+            .iter()
+            .find(|name| prompts.contains_key(**name))
+            .map(|name| name.to_string())
+    };
+    (
+        find(&["query"]),
+        find(&["document", "passage", "text", "corpus"]),
+    )
 }
 
 fn get_backend_model_type(
@@ -406,6 +896,8 @@ pub struct ModelConfig {
     pub max_position_embeddings: usize,
     #[serde(default)]
     pub pad_token_id: usize,
+    #[serde(alias = "d_model", alias = "n_embd")]
+    pub hidden_size: Option<usize>,
     pub id2label: Option<HashMap<String, String>>,
     pub label2id: Option<HashMap<String, usize>>,
 }
@@ -415,6 +907,12 @@ pub struct PoolConfig {
     pooling_mode_cls_token: bool,
     pooling_mode_mean_tokens: bool,
     #[serde(default)]
+    pooling_mode_max_tokens: bool,
+    #[serde(default)]
+    pooling_mode_weightedmean_tokens: bool,
+    #[serde(default)]
+    pooling_mode_mean_sqrt_len_tokens: bool,
+    #[serde(default)]
     pooling_mode_lasttoken: bool,
 }
 
@@ -428,6 +926,15 @@ impl TryFrom<PoolConfig> for Pool {
         if config.pooling_mode_mean_tokens {
             return Ok(Pool::Mean);
         }
+        if config.pooling_mode_max_tokens {
+            return Ok(Pool::Max);
+        }
+        if config.pooling_mode_weightedmean_tokens {
+            return Ok(Pool::WeightedMean);
+        }
+        if config.pooling_mode_mean_sqrt_len_tokens {
+            return Ok(Pool::MeanSqrtLen);
+        }
         if config.pooling_mode_lasttoken {
             return Ok(Pool::LastToken);
         }
@@ -501,6 +1008,24 @@ pub struct Info {
     pub auto_truncate: bool,
     #[cfg_attr(feature = "http", schema(example = "4"))]
     pub tokenization_workers: usize,
+    #[cfg_attr(feature = "http", schema(nullable = true, example = "768"))]
+    pub hidden_size: Option<usize>,
+    /// Matryoshka dimensions of the requests that do not set them
+    #[cfg_attr(feature = "http", schema(nullable = true, example = "null"))]
+    pub default_dimensions: Option<usize>,
+    /// Ranges used for the scalar quantization of the embeddings
+    #[serde(skip)]
+    pub quantization_calibration: Option<Arc<Calibration>>,
+    /// Prompts applied to the queries and texts of re-ranking requests served by embedding models
+    #[serde(skip)]
+    pub query_prompt_name: Option<String>,
+    #[serde(skip)]
+    pub document_prompt_name: Option<String>,
+    /// ONNX Runtime session options, if the model runs on the ORT backend
+    #[cfg_attr(feature = "http", schema(nullable = true))]
+    pub onnx_runtime: Option<OnnxRuntimeInfo>,
+    /// Models served by the router
+    pub models: Vec<ServedModel>,
     /// Router Info
     #[cfg_attr(feature = "http", schema(example = "0.5.0"))]
     pub version: &'static str,
@@ -510,7 +1035,64 @@ pub struct Info {
     pub docker_label: Option<&'static str>,
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
+pub struct ServedModel {
+    /// Name used to route requests to the model
+    #[cfg_attr(feature = "http", schema(example = "gte-base"))]
+    pub name: String,
+    #[cfg_attr(feature = "http", schema(example = "thenlper/gte-base"))]
+    pub model_id: String,
+    pub model_type: ModelType,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
+pub struct OnnxRuntimeInfo {
+    #[cfg_attr(feature = "http", schema(example = "8"))]
+    pub intra_threads: usize,
+    #[cfg_attr(feature = "http", schema(nullable = true, example = "null"))]
+    pub inter_threads: Option<usize>,
+    #[cfg_attr(feature = "http", schema(example = "sequential"))]
+    pub execution_mode: String,
+    #[cfg_attr(feature = "http", schema(example = "all"))]
+    pub optimization_level: String,
+    #[cfg_attr(feature = "http", schema(nullable = true, example = "null"))]
+    pub optimized_model_path: Option<String>,
+    pub memory_arena: bool,
+    pub memory_pattern: bool,
+    #[cfg_attr(feature = "http", schema(example = "8"))]
+    pub max_batch_size: usize,
+}
+
+impl From<&OrtSessionConfig> for OnnxRuntimeInfo {
+    fn from(config: &OrtSessionConfig) -> Self {
+        Self {
+            intra_threads: config.intra_threads.unwrap_or_else(num_cpus::get),
+            inter_threads: config.inter_threads,
+            execution_mode: config.execution_mode.to_string(),
+            optimization_level: config.optimization_level.to_string(),
+            optimized_model_path: config
+                .optimized_model_path
+                .as_ref()
+                .map(|path| path.display().to_string()),
+            memory_arena: config.memory_arena,
+            memory_pattern: config.memory_pattern,
+            max_batch_size: config.max_batch_size,
+        }
+    }
+}
+
+/// Similarity metric of the vector index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexMetric {
+    #[default]
+    Cosine,
+    Dot,
+}
+
+#[derive(Debug, Serialize)]
 #[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
 pub enum ErrorType {
     Unhealthy,
@@ -519,9 +1101,10 @@ pub enum ErrorType {
     Validation,
     Tokenizer,
     Empty,
+    Deadline,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Serialize)]
 #[cfg_attr(feature = "http", derive(utoipa::ToSchema))]
 pub struct ErrorResponse {
     pub error: String,
@@ -535,6 +1118,7 @@ impl From<TextEmbeddingsError> for ErrorResponse {
             TextEmbeddingsError::Validation(_) => ErrorType::Validation,
             TextEmbeddingsError::Overloaded(_) => ErrorType::Overloaded,
             TextEmbeddingsError::Backend(_) => ErrorType::Backend,
+            TextEmbeddingsError::DeadlineExceeded => ErrorType::Deadline,
         };
         Self {
             error: err.to_string(),
@@ -543,6 +1127,34 @@ impl From<TextEmbeddingsError> for ErrorResponse {
     }
 }
 
+impl Info {
+    /// Matryoshka dimensions of a request, defaulting to the server default
+    pub(crate) fn dimensions(
+        &self,
+        dimensions: Option<usize>,
+    ) -> Result<Option<usize>, ErrorResponse> {
+        let dimensions = dimensions.or(self.default_dimensions);
+        match (dimensions, self.hidden_size) {
+            (Some(0), _) => {}
+            (Some(dimensions), Some(hidden_size)) if dimensions > hidden_size => {}
+            _ => return Ok(dimensions),
+        }
+
+        let counter = metrics::counter!("te_request_failure", "err" => "validation");
+        counter.increment(1);
+        let message = format!(
+            "`dimensions` must be between 1 and the model hidden size ({}). Given: {}",
+            self.hidden_size.unwrap_or_default(),
+            dimensions.unwrap_or_default()
+        );
+        tracing::error!("{message}");
+        Err(ErrorResponse {
+            error: message,
+            error_type: ErrorType::Validation,
+        })
+    }
+}
+
 struct ResponseMetadata {
     compute_chars: usize,
     compute_tokens: usize,
@@ -550,6 +1162,8 @@ struct ResponseMetadata {
     tokenization_time: Duration,
     queue_time: Duration,
     inference_time: Duration,
+    /// Number of inputs served from the embedding cache
+    cache_hits: usize,
 }
 
 impl ResponseMetadata {
@@ -568,9 +1182,15 @@ impl ResponseMetadata {
             tokenization_time,
             queue_time,
             inference_time,
+            cache_hits: 0,
         }
     }
 
+    fn with_cache_hits(mut self, cache_hits: usize) -> Self {
+        self.cache_hits = cache_hits;
+        self
+    }
+
     fn record_span(&self, span: &Span) {
         // Tracing metadata
         span.record("compute_chars", self.compute_chars);
@@ -594,61 +1214,91 @@ impl ResponseMetadata {
     }
 }
 
-impl From<ResponseMetadata> for HeaderMap {
+impl ResponseMetadata {
+    /// Response headers as `(name, value)` pairs
+    fn headers(&self) -> [(&'static str, String); 9] {
+        let total_time = self.start_time.elapsed().as_millis().to_string();
+        [
+            ("x-compute-type", "gpu+optimized".to_string()),
+            ("x-compute-time", total_time.clone()),
+            ("x-compute-characters", self.compute_chars.to_string()),
+            ("x-compute-tokens", self.compute_tokens.to_string()),
+            ("x-total-time", total_time),
+            (
+                "x-tokenization-time",
+                self.tokenization_time.as_millis().to_string(),
+            ),
+            ("x-queue-time", self.queue_time.as_millis().to_string()),
+            ("x-inference-time", self.inference_time.as_millis().to_string()),
+            ("x-cache-hits", self.cache_hits.to_string()),
+        ]
+    }
+}
+
+#[cfg(feature = "http")]
+impl From<ResponseMetadata> for ::http::HeaderMap {
     fn from(value: ResponseMetadata) -> Self {
-        // Headers
-        let mut headers = HeaderMap::new();
-        headers.insert("x-compute-type", "gpu+optimized".parse().unwrap());
-        headers.insert(
-            "x-compute-time",
-            value
-                .start_time
-                .elapsed()
-                .as_millis()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(
-            "x-compute-characters",
-            value.compute_chars.to_string().parse().unwrap(),
-        );
-        headers.insert(
-            "x-compute-tokens",
-            value.compute_tokens.to_string().parse().unwrap(),
-        );
-        headers.insert(
-            "x-total-time",
-            value
-                .start_time
-                .elapsed()
-                .as_millis()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(
-            "x-tokenization-time",
-            value
-                .tokenization_time
-                .as_millis()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(
-            "x-queue-time",
-            value.queue_time.as_millis().to_string().parse().unwrap(),
-        );
-        headers.insert(
-            "x-inference-time",
-            value
-                .inference_time
-                .as_millis()
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
+        let mut headers = Self::new();
+        for (name, value) in value.headers() {
+            headers.insert(name, value.parse().unwrap());
+        }
         headers
     }
 }
+
+// tonic uses an older version of the `http` crate
+#[cfg(feature = "grpc")]
+impl From<ResponseMetadata> for tonic::codegen::http::HeaderMap {
+    fn from(value: ResponseMetadata) -> Self {
+        let mut headers = Self::new();
+        for (name, value) in value.headers() {
+            headers.insert(name, value.parse().unwrap());
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model_files_digest;
+    use std::fs;
+
+    #[test]
+    fn test_model_files_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("config.json"), r#"{"hidden_size": 4}"#).unwrap();
+        fs::write(
+            root.join("modules.json"),
+            r#"[{"path": ""}, {"path": "1_Pooling"}, {"path": "2_Dense"}]"#,
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("2_Dense")).unwrap();
+        fs::write(root.join("2_Dense/config.json"), r#"{"out_features": 2}"#).unwrap();
+        fs::write(root.join("model.safetensors"), [0; 8]).unwrap();
+
+        let digest = model_files_digest(root).unwrap();
+        assert_eq!(model_files_digest(root).unwrap(), digest);
+
+        // Other files are ignored
+        fs::write(root.join("README.md"), "model card").unwrap();
+        assert_eq!(model_files_digest(root).unwrap(), digest);
+
+        let mut digests = vec![digest];
+        let changes: [&dyn Fn(); 4] = [
+            &|| fs::write(root.join("2_Dense/config.json"), r#"{"out_features": 3}"#).unwrap(),
+            &|| fs::write(root.join("2_Dense/model.safetensors"), [0; 8]).unwrap(),
+            &|| {
+                fs::create_dir_all(root.join("onnx")).unwrap();
+                fs::write(root.join("onnx/onnx_io.json"), "{}").unwrap();
+            },
+            &|| fs::write(root.join("model.safetensors"), [0; 16]).unwrap(),
+        ];
+        for change in changes {
+            change();
+            let digest = model_files_digest(root).unwrap();
+            assert!(!digests.contains(&digest));
+            digests.push(digest);
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use clap::Parser;
 use opentelemetry::global;
-use text_embeddings_backend::DType;
+use std::path::PathBuf;
+use text_embeddings_backend::{DType, OrtExecutionMode, OrtOptimizationLevel, OrtSessionConfig};
+use text_embeddings_core::queue::Priority;
+use text_embeddings_router::IndexMetric;
 use veil::Redact;
 
 #[cfg(not(target_os = "linux"))]
@@ -26,6 +29,17 @@ struct Args {
     #[clap(long, env)]
     revision: Option<String>,
 
+    /// Additional models served by the same router, as comma separated
+    /// `<name>=<model_id>[@<revision>]` entries.
+    ///
+    /// Requests are routed to a model with the `model` field of OpenAI, Cohere, Jina and Ollama
+    /// requests, the `/models/<name>` path prefix for HTTP or the `model` metadata key for gRPC.
+    /// Other requests are served by `model-id`, which is also reachable under its own id. Unknown
+    /// model names are rejected when several models are served. `pooling` and the default prompt
+    /// only apply to `model-id`.
+    #[clap(long, env, value_delimiter = ',', value_parser = parse_additional_model)]
+    additional_models: Vec<(String, String, Option<String>)>,
+
     /// Optionally control the number of tokenizer workers used for payload tokenization, validation
     /// and truncation.
     /// Default to the number of CPU cores on the machine.
@@ -33,6 +47,12 @@ struct Args {
     tokenization_workers: Option<usize>,
 
     /// The dtype to be forced upon the model.
+    ///
+    /// With the `ort` backend, `float16` loads `model_fp16.onnx` and `int8` loads a dynamically
+    /// quantized export, preferring the one built for the instruction set of the CPU
+    /// (`model_qint8_avx512_vnni.onnx`, `model_qint8_avx512.onnx`, `model_qint8_avx2.onnx`,
+    /// `model_qint8_arm64.onnx`) over `model_quantized.onnx`, `model_int8.onnx` and
+    /// `model_uint8.onnx`.
     #[clap(long, env, value_enum)]
     dtype: Option<DType>,
 
@@ -45,6 +65,44 @@ struct Args {
     #[clap(long, env, value_enum)]
     pooling: Option<text_embeddings_backend::Pool>,
 
+    /// The number of threads ONNX Runtime uses to run a single operator.
+    /// Default to the number of CPU cores on the machine.
+    #[clap(long, env)]
+    ort_intra_threads: Option<usize>,
+
+    /// The number of threads ONNX Runtime uses to run independent operators with the `parallel`
+    /// execution mode. Default to the ONNX Runtime default.
+    #[clap(long, env)]
+    ort_inter_threads: Option<usize>,
+
+    /// The ONNX Runtime execution mode
+    #[clap(default_value = "sequential", long, env, value_enum)]
+    ort_execution_mode: OrtExecutionMode,
+
+    /// The ONNX Runtime graph optimization level
+    #[clap(default_value = "all", long, env, value_enum)]
+    ort_optimization_level: OrtOptimizationLevel,
+
+    /// Save the model optimized by ONNX Runtime to this path and load it from there on the next
+    /// start instead of optimizing it again.
+    ///
+    /// The model is optimized again when its ONNX export, dtype or optimization level change.
+    /// Additional models use `<stem>-<i>.<extension>`.
+    #[clap(long, env)]
+    ort_optimized_model_path: Option<PathBuf>,
+
+    /// Disable the ONNX Runtime CPU memory arena
+    #[clap(long, env)]
+    ort_disable_memory_arena: bool,
+
+    /// Disable the ONNX Runtime memory pattern optimization
+    #[clap(long, env)]
+    ort_disable_memory_pattern: bool,
+
+    /// The maximum number of requests in a batch for the ONNX Runtime backend
+    #[clap(default_value = "8", long, env)]
+    ort_max_batch_size: usize,
+
     /// The maximum amount of concurrent requests for this particular deployment.
     /// Having a low limit will refuse clients requests instead of having them
     /// wait for too long and is usually good to handle backpressure correctly.
@@ -73,12 +131,72 @@ struct Args {
     #[clap(default_value = "32", long, env)]
     max_client_batch_size: usize,
 
+    /// The maximum number of windows an input can be split into with `chunking`.
+    ///
+    /// Every window counts as a request against `max-concurrent-requests`.
+    #[clap(default_value = "64", long, env)]
+    max_chunks: usize,
+
     /// Automatically truncate inputs that are longer than the maximum supported size
     ///
     /// Unused for gRPC servers
     #[clap(long, env)]
     auto_truncate: bool,
 
+    /// The maximum number of pooled embeddings kept in the in-memory embedding cache.
+    ///
+    /// Repeated inputs with the same prompt and truncation parameters are served from the cache
+    /// without being tokenized or queued. Set to 0 to disable the in-memory tier.
+    #[clap(default_value = "0", long, env)]
+    embedding_cache_size: usize,
+
+    /// Optionally persist the embedding cache in this directory.
+    ///
+    /// Entries are keyed by the model revision and the configuration and weight files of the model,
+    /// and survive restarts. The directory is never pruned.
+    #[clap(long, env)]
+    embedding_cache_dir: Option<String>,
+
+    /// Requests waiting in the queue for this long are promoted to the next priority class so
+    /// that low priority requests still make progress. Set to 0 to disable aging.
+    #[clap(default_value = "2000", long, env)]
+    priority_aging_ms: u64,
+
+    /// Concurrent requests reserved to a priority class, as comma separated `<priority>=<count>`
+    /// pairs. Priorities are `high`, `normal` and `low`.
+    ///
+    /// For example `high=64` keeps 64 of the `max-concurrent-requests` for high priority requests.
+    /// The other requests share the remaining capacity.
+    #[clap(long, env, value_delimiter = ',', value_parser = parse_reserved_concurrent_requests)]
+    reserved_concurrent_requests: Vec<(Priority, usize)>,
+
+    /// For backends that pad the inputs of a batch to the same length, take up to this many
+    /// queued requests into account when building a batch and only batch requests of the same
+    /// power of two length bucket to bound padding. Set to 0 to build batches in queue order.
+    #[clap(default_value = "0", long, env)]
+    length_bucketing_window: usize,
+
+    /// Hold a batch for up to this long to let more requests join it before running it. Set to 0
+    /// to run batches as soon as the backend is available.
+    #[clap(default_value = "0", long, env)]
+    max_batch_wait_ms: u64,
+
+    /// A held batch is run as soon as this many tokens are queued.
+    ///
+    /// Defaults to `max-batch-tokens`.
+    #[clap(long, env)]
+    min_batch_tokens: Option<usize>,
+
+    /// A held batch is run as soon as this many requests are queued.
+    ///
+    /// Defaults to `max-batch-requests`.
+    #[clap(long, env)]
+    min_batch_requests: Option<usize>,
+
+    /// Derive the batch wait from the observed inference durations, up to `max-batch-wait-ms`.
+    #[clap(long, env)]
+    adaptive_batch_wait: bool,
+
     /// The name of the prompt that should be used by default for encoding. If not set, no prompt
     /// will be applied.
     ///
@@ -106,6 +224,17 @@ struct Args {
     #[clap(long, env, conflicts_with = "default_prompt_name")]
     default_prompt: Option<String>,
 
+    /// Truncate the embeddings of Matryoshka models to this many dimensions when requests do not
+    /// set `dimensions`. Must be lower than or equal to the model hidden size.
+    #[clap(long, env)]
+    default_dimensions: Option<usize>,
+
+    /// Path to a JSON file `{"min": [...], "max": [...]}` with the per dimension ranges used to
+    /// quantize the embeddings to `int8` and `uint8`. If not set, the ranges are computed for
+    /// every embedding.
+    #[clap(long, env)]
+    quantization_calibration: Option<String>,
+
     /// Your HuggingFace hub token
     #[clap(long, env)]
     #[redact(partial)]
@@ -119,6 +248,11 @@ struct Args {
     #[clap(default_value = "3000", long, short, env)]
     port: u16,
 
+    /// The port the gRPC API listens on when the router is built with both the HTTP and gRPC APIs.
+    /// Default to `port + 1`. gRPC-only builds listen on `port` unless it is set
+    #[clap(long, env)]
+    grpc_port: Option<u16>,
+
     /// The name of the unix socket some text-embeddings-inference backends will use as they
     /// communicate internally with gRPC.
     #[clap(default_value = "/tmp/text-embeddings-inference-server", long, env)]
@@ -158,6 +292,31 @@ struct Args {
     /// Unused for gRPC servers
     #[clap(long, env)]
     cors_allow_origin: Option<Vec<String>>,
+
+    /// Snapshot file of the vector index served on `/index/upsert`, `/index/delete` and
+    /// `/search`. The index is reloaded from this file on start and written to it on shutdown.
+    ///
+    /// Requires the `index` feature.
+    #[clap(long, env)]
+    index_path: Option<String>,
+
+    /// Similarity metric of the vector index
+    #[clap(default_value = "cosine", long, env, value_enum)]
+    index_metric: IndexMetric,
+
+    /// Directory of the embedding jobs served on `/jobs`. Jobs are kept in this directory and
+    /// the unfinished ones resume on start.
+    ///
+    /// Unused for gRPC servers
+    #[clap(long, env)]
+    jobs_dir: Option<String>,
+
+    /// Directory of the server files that jobs can embed with the `path` parameter. Jobs can only
+    /// embed the lines of their request body if it is not set.
+    ///
+    /// Unused for gRPC servers
+    #[clap(long, env)]
+    jobs_input_dir: Option<String>,
 }
 
 #[tokio::main]
@@ -188,22 +347,48 @@ async fn main() -> Result<()> {
         }
     });
 
+    let ort_session_config = OrtSessionConfig {
+        intra_threads: args.ort_intra_threads,
+        inter_threads: args.ort_inter_threads,
+        execution_mode: args.ort_execution_mode,
+        optimization_level: args.ort_optimization_level,
+        optimized_model_path: args.ort_optimized_model_path,
+        memory_arena: !args.ort_disable_memory_arena,
+        memory_pattern: !args.ort_disable_memory_pattern,
+        max_batch_size: args.ort_max_batch_size,
+    };
+
     text_embeddings_router::run(
         args.model_id,
         args.revision,
+        args.additional_models,
         args.tokenization_workers,
         args.dtype,
         args.pooling,
+        ort_session_config,
         args.max_concurrent_requests,
         args.max_batch_tokens,
         args.max_batch_requests,
         args.max_client_batch_size,
+        args.max_chunks,
         args.auto_truncate,
+        args.embedding_cache_size,
+        args.embedding_cache_dir,
+        args.priority_aging_ms,
+        args.reserved_concurrent_requests,
+        args.length_bucketing_window,
+        args.max_batch_wait_ms,
+        args.min_batch_tokens,
+        args.min_batch_requests,
+        args.adaptive_batch_wait,
         args.default_prompt,
         args.default_prompt_name,
+        args.default_dimensions,
+        args.quantization_calibration,
         args.hf_api_token,
         Some(args.hostname),
         args.port,
+        args.grpc_port,
         Some(args.uds_path),
         args.huggingface_hub_cache,
         args.payload_limit,
@@ -211,6 +396,10 @@ async fn main() -> Result<()> {
         args.otlp_endpoint,
         args.otlp_service_name,
         args.cors_allow_origin,
+        args.index_path,
+        args.index_metric,
+        args.jobs_dir,
+        args.jobs_input_dir,
     )
     .await?;
 
@@ -220,3 +409,27 @@ async fn main() -> Result<()> {
     }
     Ok(())
 }
+
+fn parse_reserved_concurrent_requests(s: &str) -> Result<(Priority, usize), String> {
+    let (priority, count) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `<priority>=<count>`, got `{s}`"))?;
+    let count = count
+        .parse()
+        .map_err(|err| format!("invalid count `{count}`: {err}"))?;
+    Ok((priority.parse()?, count))
+}
+
+fn parse_additional_model(s: &str) -> Result<(String, String, Option<String>), String> {
+    let (name, model) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `<name>=<model_id>[@<revision>]`, got `{s}`"))?;
+    if name.is_empty() {
+        return Err(format!("empty model name in `{s}`"));
+    }
+    let (model_id, revision) = match model.split_once('@') {
+        Some((model_id, revision)) => (model_id, Some(revision.to_string())),
+        None => (model, None),
+    };
+    Ok((name.to_string(), model_id.to_string(), revision))
+}
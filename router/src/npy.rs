@@ -0,0 +1,29 @@
+/// NumPy `.npy` files of `float32` embeddings
+use std::io::{Result, Write};
+
+/// Size of the header, including the magic string, is a multiple of this alignment
+const HEADER_ALIGNMENT: usize = 64;
+
+/// Write the header of a `rows x columns` little-endian `float32` array. The values follow in
+/// row-major order.
+pub(crate) fn write_header(writer: &mut impl Write, rows: usize, columns: usize) -> Result<()> {
+    let mut header =
+        format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({rows}, {columns}), }}");
+    // Magic string, version and header length take 10 bytes. The header ends with a line break.
+    let length = 10 + header.len() + 1;
+    let padding = (HEADER_ALIGNMENT - length % HEADER_ALIGNMENT) % HEADER_ALIGNMENT;
+    header.extend(std::iter::repeat(' ').take(padding));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())
+}
+
+/// Write a row of the array
+pub(crate) fn write_row(writer: &mut impl Write, values: &[f32]) -> Result<()> {
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
@@ -1,4 +1,5 @@
-use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder};
+use anyhow::Context;
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
 
 pub(crate) fn prometheus_builer(max_input_length: usize) -> Result<PrometheusBuilder, BuildError> {
     // Duration buckets
@@ -28,10 +29,36 @@ pub(crate) fn prometheus_builer(max_input_length: usize) -> Result<PrometheusBui
     let batch_tokens_matcher = Matcher::Full(String::from("te_batch_next_tokens"));
     let batch_tokens_buckets: Vec<f64> = (0..21).map(|x| 2.0_f64.powi(x)).collect();
 
+    // Batch padding buckets
+    let batch_padding_matcher = Matcher::Full(String::from("te_batch_next_padding_tokens"));
+
     // Prometheus handler
     PrometheusBuilder::new()
         .set_buckets_for_metric(duration_matcher, &duration_buckets)?
         .set_buckets_for_metric(input_length_matcher, &input_length_buckets)?
         .set_buckets_for_metric(batch_size_matcher, &batch_size_buckets)?
-        .set_buckets_for_metric(batch_tokens_matcher, &batch_tokens_buckets)
+        .set_buckets_for_metric(batch_tokens_matcher, &batch_tokens_buckets)?
+        .set_buckets_for_metric(batch_padding_matcher, &batch_tokens_buckets)
+}
+
+/// Install the global recorder shared by the HTTP and gRPC servers
+pub(crate) fn install(prom_builder: PrometheusBuilder) -> anyhow::Result<PrometheusHandle> {
+    // See: https://github.com/metrics-rs/metrics/issues/467#issuecomment-2022755151
+    let (recorder, exporter) = prom_builder
+        .build()
+        .context("failed to build prometheus recorder")?;
+    let prom_handle = recorder.handle();
+    metrics::set_global_recorder(recorder).context("Failed to set global recorder")?;
+
+    // gRPC has no `/metrics` route: serve the metrics on their own port
+    #[cfg(feature = "grpc")]
+    {
+        tokio::spawn(exporter);
+        tracing::info!("Serving Prometheus metrics: 0.0.0.0:9000");
+    }
+    // HTTP servers serve the metrics on `/metrics`
+    #[cfg(not(feature = "grpc"))]
+    drop(exporter);
+
+    Ok(prom_handle)
 }
@@ -0,0 +1,299 @@
+/// Quantization of the embeddings returned to the clients
+use anyhow::{anyhow, Context, Result};
+use half::f16;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Numeric precision of the embeddings returned to the clients
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    #[default]
+    Float32,
+    Float16,
+    /// Scalar quantization to [-128, 127]
+    Int8,
+    /// Scalar quantization to [0, 255]
+    Uint8,
+    /// Sign bits packed in bytes, shifted by -128
+    Binary,
+    /// Sign bits packed in bytes
+    Ubinary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuantizedEmbedding {
+    Float32(Vec<f32>),
+    Float16(Vec<f16>),
+    Int8(Vec<i8>),
+    Uint8(Vec<u8>),
+}
+
+impl QuantizedEmbedding {
+    /// Little-endian bytes of the embedding
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        match self {
+            QuantizedEmbedding::Float32(values) => {
+                values.iter().flat_map(|v| v.to_le_bytes()).collect()
+            }
+            QuantizedEmbedding::Float16(values) => {
+                values.iter().flat_map(|v| v.to_le_bytes()).collect()
+            }
+            QuantizedEmbedding::Int8(values) => values.iter().map(|v| *v as u8).collect(),
+            QuantizedEmbedding::Uint8(values) => values.clone(),
+        }
+    }
+}
+
+/// Per dimension ranges used for scalar quantization
+///
+/// Loaded from a JSON file `{"min": [...], "max": [...]}`, usually computed on a sample of the
+/// embeddings of the corpus. Matryoshka embeddings use the first dimensions of the ranges.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Calibration {
+    min: Vec<f32>,
+    max: Vec<f32>,
+}
+
+impl Calibration {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read `{}`", path.display()))?;
+        let calibration: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+
+        if calibration.min.len() != calibration.max.len() {
+            return Err(anyhow!(
+                "calibration `min` has {} dimensions but `max` has {}",
+                calibration.min.len(),
+                calibration.max.len()
+            ));
+        }
+        if calibration
+            .min
+            .iter()
+            .zip(&calibration.max)
+            .any(|(min, max)| min > max)
+        {
+            return Err(anyhow!("calibration `min` must be lower than `max`"));
+        }
+        Ok(calibration)
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.min.len()
+    }
+}
+
+/// Encode `embedding` with `precision`
+///
+/// Scalar quantization uses the `calibration` ranges when they are given and the range of the
+/// values of the embedding otherwise.
+pub fn quantize(
+    embedding: Vec<f32>,
+    precision: Precision,
+    calibration: Option<&Calibration>,
+) -> Result<QuantizedEmbedding, String> {
+    let quantized = match precision {
+        Precision::Float32 => QuantizedEmbedding::Float32(embedding),
+        Precision::Float16 => {
+            QuantizedEmbedding::Float16(embedding.into_iter().map(f16::from_f32).collect())
+        }
+        Precision::Int8 => QuantizedEmbedding::Int8(
+            scalar_quantize(&embedding, calibration)?
+                .into_iter()
+                .map(|v| (v - 128.0) as i8)
+                .collect(),
+        ),
+        Precision::Uint8 => QuantizedEmbedding::Uint8(
+            scalar_quantize(&embedding, calibration)?
+                .into_iter()
+                .map(|v| v as u8)
+                .collect(),
+        ),
+        Precision::Binary => QuantizedEmbedding::Int8(
+            pack_sign_bits(&embedding)
+                .into_iter()
+                .map(|v| (v as i16 - 128) as i8)
+                .collect(),
+        ),
+        Precision::Ubinary => QuantizedEmbedding::Uint8(pack_sign_bits(&embedding)),
+    };
+    Ok(quantized)
+}
+
+/// Position of the values in 255 uniform steps between the lower and upper bounds
+///
+/// Like `sentence_transformers.quantize_embeddings`, the callers truncate the positions toward
+/// zero. Values out of the bounds saturate.
+fn scalar_quantize(
+    embedding: &[f32],
+    calibration: Option<&Calibration>,
+) -> Result<Vec<f32>, String> {
+    let bucket = |value: f32, min: f32, max: f32| {
+        let step = (max - min) / 255.0;
+        if step > 0.0 {
+            (value - min) / step
+        } else {
+            0.0
+        }
+    };
+
+    match calibration {
+        Some(calibration) => {
+            if embedding.len() > calibration.dimensions() {
+                return Err(format!(
+                    "embedding has {} dimensions but the quantization calibration only has {}",
+                    embedding.len(),
+                    calibration.dimensions()
+                ));
+            }
+            Ok(embedding
+                .iter()
+                .zip(calibration.min.iter().zip(&calibration.max))
+                .map(|(value, (min, max))| bucket(*value, *min, *max))
+                .collect())
+        }
+        None => {
+            let min = embedding.iter().copied().fold(f32::INFINITY, f32::min);
+            let max = embedding.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            Ok(embedding.iter().map(|v| bucket(*v, min, max)).collect())
+        }
+    }
+}
+
+/// Pack the sign bits of the values, most significant bit first
+fn pack_sign_bits(embedding: &[f32]) -> Vec<u8> {
+    embedding
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, v)| byte | (((*v > 0.0) as u8) << (7 - i)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quantization::{quantize, Calibration, Precision, QuantizedEmbedding};
+    use std::io::Write;
+
+    // Expected values computed in float32 with the formulas of
+    // `sentence_transformers.quantize_embeddings(embeddings, precision, ranges=[min, max])`
+    fn calibration() -> Calibration {
+        Calibration {
+            min: vec![-1.0, -1.0, 0.0, -0.5, -0.25, -2.0, -1.0, 0.0, -0.1, -1.0],
+            max: vec![1.0, 0.5, 1.0, 0.5, 0.75, 2.0, 1.0, 0.2, 0.1, 3.0],
+        }
+    }
+
+    fn embedding() -> Vec<f32> {
+        vec![0.3, -0.7, 0.5, -0.2, 0.0, 1.5, -0.99, 0.05, 0.07, 2.9]
+    }
+
+    #[test]
+    fn test_int8() {
+        let quantized = quantize(embedding(), Precision::Int8, Some(&calibration())).unwrap();
+        // -0.99 is at 1.27 steps: truncating -126.73 toward zero gives -126
+        assert_eq!(
+            quantized,
+            QuantizedEmbedding::Int8(vec![37, -77, 0, -51, -64, 95, -126, -64, 88, 120])
+        );
+    }
+
+    #[test]
+    fn test_uint8() {
+        let quantized = quantize(embedding(), Precision::Uint8, Some(&calibration())).unwrap();
+        assert_eq!(
+            quantized,
+            QuantizedEmbedding::Uint8(vec![165, 51, 127, 76, 63, 223, 1, 63, 216, 248])
+        );
+    }
+
+    #[test]
+    fn test_binary() {
+        // `0.0` is not positive and the last byte is padded with zeros
+        let quantized = quantize(embedding(), Precision::Binary, None).unwrap();
+        assert_eq!(quantized, QuantizedEmbedding::Int8(vec![37, 64]));
+        assert_eq!(quantized.to_le_bytes(), vec![37, 64]);
+    }
+
+    #[test]
+    fn test_ubinary() {
+        let quantized = quantize(embedding(), Precision::Ubinary, None).unwrap();
+        assert_eq!(
+            quantized,
+            QuantizedEmbedding::Uint8(vec![0b10100101, 0b11000000])
+        );
+    }
+
+    #[test]
+    fn test_scalar_quantization_without_calibration() {
+        let embedding = vec![0.1, -0.3, 0.25, 0.7, -0.05];
+
+        let quantized = quantize(embedding.clone(), Precision::Uint8, None).unwrap();
+        assert_eq!(
+            quantized,
+            QuantizedEmbedding::Uint8(vec![101, 0, 140, 254, 63])
+        );
+
+        let quantized = quantize(embedding, Precision::Int8, None).unwrap();
+        assert_eq!(
+            quantized,
+            QuantizedEmbedding::Int8(vec![-26, -128, 12, 126, -64])
+        );
+
+        // A constant embedding has no range
+        let quantized = quantize(vec![0.5; 3], Precision::Int8, None).unwrap();
+        assert_eq!(quantized, QuantizedEmbedding::Int8(vec![-128; 3]));
+    }
+
+    #[test]
+    fn test_scalar_quantization_saturates() {
+        let calibration = Calibration {
+            min: vec![0.0, 0.0],
+            max: vec![1.0, 1.0],
+        };
+        let quantized = quantize(vec![-0.5, 1.5], Precision::Uint8, Some(&calibration)).unwrap();
+        assert_eq!(quantized, QuantizedEmbedding::Uint8(vec![0, 255]));
+
+        let quantized = quantize(vec![-0.5, 1.5], Precision::Int8, Some(&calibration)).unwrap();
+        assert_eq!(quantized, QuantizedEmbedding::Int8(vec![-128, 127]));
+    }
+
+    #[test]
+    fn test_calibration_dimensions() {
+        // Matryoshka embeddings use the first dimensions of the ranges
+        let quantized = quantize(vec![0.3, -0.7], Precision::Uint8, Some(&calibration())).unwrap();
+        assert_eq!(quantized, QuantizedEmbedding::Uint8(vec![165, 51]));
+
+        let err = quantize(vec![0.0; 11], Precision::Uint8, Some(&calibration())).unwrap_err();
+        assert_eq!(
+            err,
+            "embedding has 11 dimensions but the quantization calibration only has 10"
+        );
+    }
+
+    #[test]
+    fn test_calibration_load() {
+        let load = |content: &str| {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            file.write_all(content.as_bytes()).unwrap();
+            Calibration::load(file.path()).map_err(|err| err.to_string())
+        };
+
+        let calibration = load(r#"{"min": [-1.0, 0.0], "max": [1.0, 0.5]}"#).unwrap();
+        assert_eq!(calibration.dimensions(), 2);
+
+        assert_eq!(
+            load(r#"{"min": [-1.0, 0.0], "max": [1.0]}"#).unwrap_err(),
+            "calibration `min` has 2 dimensions but `max` has 1"
+        );
+        assert_eq!(
+            load(r#"{"min": [1.0], "max": [-1.0]}"#).unwrap_err(),
+            "calibration `min` must be lower than `max`"
+        );
+        assert!(load(r#"{"min": [1.0]}"#).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use tokio::signal;
+use tokio::sync::watch;
 
 /// Shutdown signal handler
 pub(crate) async fn shutdown_signal() {
@@ -26,3 +27,34 @@ pub(crate) async fn shutdown_signal() {
 
     tracing::info!("signal received, starting graceful shutdown");
 }
+
+/// Shutdown shared by the servers running side by side: a signal or the exit of one server stops
+/// all of them
+#[derive(Clone)]
+pub(crate) struct Shutdown(watch::Sender<bool>);
+
+impl Shutdown {
+    pub(crate) fn new() -> Self {
+        let shutdown = Self(watch::Sender::new(false));
+
+        let signal = shutdown.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            signal.trigger();
+        });
+
+        shutdown
+    }
+
+    pub(crate) fn trigger(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// Resolves once the shutdown is triggered
+    pub(crate) fn wait(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut receiver = self.0.subscribe();
+        async move {
+            let _ = receiver.wait_for(|shutdown| *shutdown).await;
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
-use text_embeddings_backend::DType;
-use text_embeddings_router::run;
+use text_embeddings_backend::{DType, OrtSessionConfig};
+use text_embeddings_router::{run, IndexMetric};
 use tokio::time::Instant;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -48,26 +48,45 @@ pub async fn start_server(model_id: String, revision: Option<String>, dtype: DTy
         run(
             model_id,
             revision,
+            vec![],
             Some(1),
             Some(dtype),
             None,
+            OrtSessionConfig::default(),
             4,
             1024,
             None,
             32,
+            4,
+            false,
+            0,
+            None,
+            2000,
+            vec![],
+            0,
+            0,
+            None,
+            None,
             false,
             None,
             None,
             None,
             None,
+            None,
+            None,
             8090,
             None,
             None,
+            None,
             2_000_000,
             None,
             None,
             "text-embeddings-inference.server".to_owned(),
             None,
+            None,
+            IndexMetric::Cosine,
+            None,
+            None,
         )
     });
 